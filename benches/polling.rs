@@ -0,0 +1,43 @@
+//! Poll strategies against the simulator with its LAN latency model.
+//!
+//! `cargo bench --bench polling`
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use spd3303x_control::instrument::{Channel, Spd3303x};
+use spd3303x_control::mock::{LatencyModel, MockDevice};
+use spd3303x_control::monitor::{PollStrategy, Poller};
+use tokio::sync::Mutex;
+
+fn polling(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().expect("failed to start runtime");
+    let device = MockDevice::new().with_latency(LatencyModel::lan());
+    let inst = Arc::new(Mutex::new(Spd3303x::mock(&device)));
+
+    let mut group = c.benchmark_group("poll");
+    group.sample_size(20);
+    for (name, strategy) in [
+        ("sequential", PollStrategy::Sequential),
+        ("batched", PollStrategy::Batched),
+        ("cached", PollStrategy::Cached { setpoint_every: 5 }),
+    ] {
+        let mut poller = Poller::new(strategy, Channel::programmable().collect());
+        group.bench_function(name, |b| {
+            b.iter_custom(|iters| {
+                runtime.block_on(async {
+                    let started = Instant::now();
+                    for _ in 0..iters {
+                        poller.poll(&inst).await.expect("poll failed");
+                    }
+                    started.elapsed()
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, polling);
+criterion_main!(benches);
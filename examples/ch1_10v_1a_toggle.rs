@@ -1,9 +1,11 @@
 use std::time::Duration;
 
 use anyhow::Result;
-use spd3303x_control::instrument::{Channel, OutputState, Spd3303x};
+use spd3303x_control::instrument::{Channel, OutputState};
 use tokio::time::{sleep, timeout};
 
+mod common;
+
 #[tokio::main]
 async fn main() -> Result<()> {
     tracing_subscriber::fmt()
@@ -14,7 +16,7 @@ async fn main() -> Result<()> {
     let host = args.get(1).map(String::as_str).unwrap_or("192.168.0.232");
     let resource = args.get(2).map(String::as_str).unwrap_or("inst0");
 
-    let mut inst = match timeout(Duration::from_secs(5), Spd3303x::connect(host, resource)).await {
+    let mut inst = match timeout(Duration::from_secs(5), common::connect(host, resource)).await {
         Ok(Ok(client)) => client,
         Ok(Err(e)) => return Err(e),
         Err(_) => {
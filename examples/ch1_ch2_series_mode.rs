@@ -1,9 +1,11 @@
 use std::time::Duration;
 
 use anyhow::Result;
-use spd3303x_control::instrument::{Channel, OutputState, Spd3303x, TrackMode};
+use spd3303x_control::instrument::{Channel, OutputState, TrackMode};
 use tokio::time::{sleep, timeout};
 
+mod common;
+
 #[tokio::main]
 async fn main() -> Result<()> {
     tracing_subscriber::fmt()
@@ -14,7 +16,7 @@ async fn main() -> Result<()> {
     let host = args.get(1).map(String::as_str).unwrap_or("192.168.0.232");
     let resource = args.get(2).map(String::as_str).unwrap_or("inst0");
 
-    let mut inst = match timeout(Duration::from_secs(5), Spd3303x::connect(host, resource)).await {
+    let mut inst = match timeout(Duration::from_secs(5), common::connect(host, resource)).await {
         Ok(Ok(client)) => client,
         Ok(Err(e)) => return Err(e),
         Err(_) => {
@@ -40,22 +42,9 @@ async fn main() -> Result<()> {
 
     sleep(Duration::from_secs(3)).await;
 
-    // Some firmware variants may not support total MEAS queries like `MEAS:VOLT?`
-    // without an explicit channel. Measure CH1/CH2 separately and combine them
-    // to avoid empty or unsupported responses.
-    let v_ch1 = inst.measure_voltage(Some(Channel::Ch1)).await?;
-    let v_ch2 = inst.measure_voltage(Some(Channel::Ch2)).await?;
-    let v_total = v_ch1 + v_ch2;
-
-    let i_ch1 = inst.measure_current(Some(Channel::Ch1)).await?;
-    // In series tracking, CH1/CH2 share the same current; use CH1 as reference.
-    let i_total = i_ch1;
-
-    let p_total = v_total * i_total;
-    println!(
-        "Total measured -> {:.3} V / {:.3} A / {:.3} W",
-        v_total, i_total, p_total
-    );
+    // 串联模式下总电压为 CH1+CH2，电流以 CH1 为准，由库统一计算。
+    let total = inst.series_measurements().await?;
+    print!("{}", total.render_table());
 
     inst.set_output(Channel::Ch1, OutputState::Off).await?;
     inst.set_output(Channel::Ch2, OutputState::Off).await?;
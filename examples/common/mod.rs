@@ -0,0 +1,13 @@
+//! Shared by the examples that talk to a supply.
+
+use anyhow::Result;
+use spd3303x_control::instrument::Spd3303x;
+
+/// Connect over VXI-11, or to the raw SCPI socket named by `SPD3303X_TCP`,
+/// e.g. a `spd3303x-sim`.
+pub async fn connect(host: &str, resource: &str) -> Result<Spd3303x> {
+    match std::env::var("SPD3303X_TCP") {
+        Ok(addr) => Spd3303x::connect_tcp(addr).await,
+        Err(_) => Spd3303x::connect(host, resource).await,
+    }
+}
@@ -1,9 +1,11 @@
 use std::time::Duration;
 
 use anyhow::Result;
-use spd3303x_control::instrument::{Channel, Spd3303x};
+use spd3303x_control::instrument::Channel;
 use tokio::time::timeout;
 
+mod common;
+
 #[tokio::main]
 async fn main() -> Result<()> {
     tracing_subscriber::fmt()
@@ -14,7 +16,7 @@ async fn main() -> Result<()> {
     let host = args.get(1).map(String::as_str).unwrap_or("192.168.0.232");
     let resource = args.get(2).map(String::as_str).unwrap_or("inst0");
 
-    let mut inst = match timeout(Duration::from_secs(5), Spd3303x::connect(host, resource)).await {
+    let mut inst = match timeout(Duration::from_secs(5), common::connect(host, resource)).await {
         Ok(Ok(client)) => client,
         Ok(Err(e)) => return Err(e),
         Err(_) => {
@@ -30,22 +32,14 @@ async fn main() -> Result<()> {
     println!("Firmware version: {}", version.trim());
 
     let status = inst.system_status().await?;
-    println!("System status: 0x{:04X}", status.raw);
+    println!("System status:");
+    print!("{}", status.render_table());
 
     // CH1 / CH2: fully programmable, support SCPI queries for V/I/P.
-    for channel in [Channel::Ch1, Channel::Ch2] {
+    for channel in Channel::programmable() {
         let status = inst.channel_status(channel).await?;
         println!("{}:", channel.label());
-        println!(
-            "  Set      : {:.3} V / {:.3} A",
-            status.set_voltage_v, status.set_current_a
-        );
-        println!(
-            "  Measured : {:.3} V / {:.3} A / {:.3} W",
-            status.measured_voltage_v,
-            status.measured_current_a,
-            status.measured_power_w
-        );
+        print!("{}", status.render_table());
         let output_on = inst.query_output(channel).await?;
         println!("  Output   : {}", if output_on { "ON" } else { "OFF" });
     }
@@ -1,9 +1,10 @@
 use std::time::Duration;
 
 use anyhow::Result;
-use spd3303x_control::instrument::Spd3303x;
 use tokio::time::timeout;
 
+mod common;
+
 #[tokio::main]
 async fn main() -> Result<()> {
     tracing_subscriber::fmt()
@@ -14,7 +15,7 @@ async fn main() -> Result<()> {
     let host = args.get(1).map(String::as_str).unwrap_or("192.168.0.232");
     let resource = args.get(2).map(String::as_str).unwrap_or("inst0");
 
-    let mut inst = match timeout(Duration::from_secs(5), Spd3303x::connect(host, resource)).await {
+    let mut inst = match timeout(Duration::from_secs(5), common::connect(host, resource)).await {
         Ok(Ok(client)) => client,
         Ok(Err(e)) => return Err(e),
         Err(_) => {
@@ -25,10 +26,7 @@ async fn main() -> Result<()> {
 
     let cfg = inst.network_config().await?;
 
-    println!("IP      : {}", cfg.ip.trim());
-    println!("Mask    : {}", cfg.mask.trim());
-    println!("Gateway : {}", cfg.gateway.trim());
-    println!("DHCP    : {}", if cfg.dhcp { "ON" } else { "OFF" });
+    print!("{}", cfg.render_table());
 
     // 读取型示例：最后做一次软复位，保证用完后设备处于安全状态。
     inst.soft_reset().await?;
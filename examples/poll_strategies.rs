@@ -0,0 +1,41 @@
+//! Compare monitor poll strategies against the simulator, no hardware
+//! needed: queries per poll and mean poll time with the LAN latency model.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use spd3303x_control::instrument::{Channel, Spd3303x};
+use spd3303x_control::mock::{LatencyModel, MockDevice};
+use spd3303x_control::monitor::{PollStrategy, Poller};
+use tokio::sync::Mutex;
+
+const POLLS: u32 = 50;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let device = MockDevice::new().with_latency(LatencyModel::lan());
+    let inst = Arc::new(Mutex::new(Spd3303x::mock(&device)));
+
+    for strategy in [
+        PollStrategy::Sequential,
+        PollStrategy::Batched,
+        PollStrategy::Cached { setpoint_every: 5 },
+    ] {
+        let mut poller = Poller::new(strategy, Channel::programmable().collect());
+        // A fresh transcript counts this strategy's queries.
+        inst.lock().await.enable_transcript(10_000);
+
+        let started = Instant::now();
+        for _ in 0..POLLS {
+            poller.poll(&inst).await?;
+        }
+        let mean: Duration = started.elapsed() / POLLS;
+        let queries = inst.lock().await.transcript().map_or(0, |t| t.len());
+        println!(
+            "{strategy:?}: {:.1} queries/poll, {mean:?} per poll",
+            queries as f64 / POLLS as f64
+        );
+    }
+    Ok(())
+}
@@ -1,9 +1,11 @@
 use std::time::Duration;
 
 use anyhow::Result;
-use spd3303x_control::instrument::{Channel, Spd3303x, TimerState};
+use spd3303x_control::instrument::{Channel, TimerState};
 use tokio::time::timeout;
 
+mod common;
+
 #[tokio::main]
 async fn main() -> Result<()> {
     tracing_subscriber::fmt()
@@ -14,7 +16,7 @@ async fn main() -> Result<()> {
     let host = args.get(1).map(String::as_str).unwrap_or("192.168.0.232");
     let resource = args.get(2).map(String::as_str).unwrap_or("inst0");
 
-    let mut inst = match timeout(Duration::from_secs(5), Spd3303x::connect(host, resource)).await {
+    let mut inst = match timeout(Duration::from_secs(5), common::connect(host, resource)).await {
         Ok(Ok(client)) => client,
         Ok(Err(e)) => return Err(e),
         Err(_) => {
@@ -28,22 +30,20 @@ async fn main() -> Result<()> {
     let channel = Channel::Ch1;
 
     let steps = [
-        (1_u8, 3.3_f64, 1.0_f64, 2.0_f64),
-        (2_u8, 5.0_f64, 1.0_f64, 2.0_f64),
-        (3_u8, 9.0_f64, 1.0_f64, 2.0_f64),
+        (1_u8, 3.3_f64, 1.0_f64, Duration::from_secs(2)),
+        (2_u8, 5.0_f64, 1.0_f64, Duration::from_secs(2)),
+        (3_u8, 9.0_f64, 1.0_f64, Duration::from_secs(2)),
     ];
 
-    for (group, volts, amps, seconds) in steps {
-        inst.timer_set(channel, group, volts, amps, seconds).await?;
+    for (group, volts, amps, duration) in steps {
+        inst.timer_set(channel, group, volts, amps, duration).await?;
     }
 
     println!("{} timer groups programmed:", channel.label());
-    for group in 1_u8..=3_u8 {
-        let entry = inst.timer_query(channel, group).await?;
-        println!(
-            "  Group {} -> {:.3} V / {:.3} A / {:.3} s",
-            entry.group, entry.voltage_v, entry.current_a, entry.duration_s
-        );
+    let timers = inst.read_all_timers(channel).await?;
+    print!("{}", timers.render_table());
+    for (group, error) in &timers.errors {
+        eprintln!("定时组 {group} 读取失败：{error}");
     }
 
     inst.timer_state(channel, TimerState::On).await?;
@@ -0,0 +1,273 @@
+//! Alarm state derived from monitor events.
+//!
+//! Events are transitions; an operator UI needs to know what is wrong right
+//! now and what has been seen to. [`Alarms`] turns the events of a
+//! [`Monitor`](crate::monitor::Monitor) into alarms that are raised and
+//! cleared with their condition and latched until acknowledged:
+//!
+//! - raised and not acknowledged: shown, needs attention;
+//! - acknowledged while still raised: shown until the condition clears;
+//! - cleared but not acknowledged: still shown, so a short trip is not
+//!   missed;
+//! - cleared and acknowledged: gone.
+//!
+//! Sources outside the instrument, such as an external interlock, raise
+//! and clear their alarms through [`Alarms::raise`] and [`Alarms::clear`].
+
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::SystemTime;
+
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::events::{Event, EventBus, EventFilter};
+use crate::instrument::{Channel, RegulationMode};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AlarmKind {
+    /// Polls are failing.
+    LinkLoss,
+    /// The channel is current-limiting (CC).
+    CurrentLimit(Channel),
+    /// The channel's tracking error is above the monitor's threshold.
+    TrackingError(Channel),
+    /// The instrument appears to have rebooted; clears at once and stays
+    /// latched until acknowledged.
+    PowerCycle,
+    /// A reading exceeded the safety limits and the channel was switched
+    /// off; clears at once and stays latched until acknowledged.
+    SafetyLimit(Channel),
+    /// An external interlock, by name.
+    Interlock(String),
+    /// No successful poll within the daemon's watchdog timeout; the
+    /// outputs were switched off.
+    Watchdog,
+}
+
+impl fmt::Display for AlarmKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlarmKind::LinkLoss => write!(f, "link loss"),
+            AlarmKind::CurrentLimit(channel) => write!(f, "{} current limit", channel.label()),
+            AlarmKind::TrackingError(channel) => {
+                write!(f, "{} tracking error", channel.label())
+            }
+            AlarmKind::PowerCycle => write!(f, "power cycle"),
+            AlarmKind::SafetyLimit(channel) => write!(f, "{} safety limit", channel.label()),
+            AlarmKind::Interlock(name) => write!(f, "interlock {name}"),
+            AlarmKind::Watchdog => write!(f, "watchdog"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Alarm {
+    pub kind: AlarmKind,
+    pub message: String,
+    pub raised_at: SystemTime,
+    /// When the condition went away; `None` while it is present.
+    pub cleared_at: Option<SystemTime>,
+    pub acknowledged: bool,
+}
+
+impl Alarm {
+    pub fn is_raised(&self) -> bool {
+        self.cleared_at.is_none()
+    }
+}
+
+/// An operation was stopped because an alarm is raised.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SafetyTrip {
+    pub kind: AlarmKind,
+    pub message: String,
+}
+
+impl fmt::Display for SafetyTrip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "stopped on {}: {}", self.kind, self.message)
+    }
+}
+
+impl std::error::Error for SafetyTrip {}
+
+/// Shared alarm list; clones see the same alarms.
+#[derive(Debug, Clone, Default)]
+pub struct Alarms(Arc<Mutex<Vec<Alarm>>>);
+
+impl Alarms {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Follow the events published on `bus` until it closes.
+    pub fn track(&self, bus: &EventBus) -> JoinHandle<()> {
+        let alarms = self.clone();
+        let mut events = bus.subscribe(EventFilter::default());
+        tokio::spawn(async move {
+            while let Some(event) = events.next().await {
+                alarms.observe(&event);
+            }
+        })
+    }
+
+    /// Update the alarms from one event.
+    pub fn observe(&self, event: &Event) {
+        match event {
+            Event::Sample(_) | Event::Recovered { .. } => self.clear(&AlarmKind::LinkLoss),
+            Event::PollFailed { error } => self.raise(AlarmKind::LinkLoss, error.clone()),
+            Event::RegulationChanged {
+                channel,
+                mode: RegulationMode::ConstantCurrent,
+            } => self.raise(
+                AlarmKind::CurrentLimit(*channel),
+                "output is current-limiting".to_string(),
+            ),
+            Event::RegulationChanged { channel, .. } => {
+                self.clear(&AlarmKind::CurrentLimit(*channel))
+            }
+            Event::TrackingErrorExceeded {
+                channel,
+                error_v,
+                threshold_v,
+            } => self.raise(
+                AlarmKind::TrackingError(*channel),
+                format!("{error_v:.3} V above the {threshold_v:.3} V threshold"),
+            ),
+            Event::TrackingErrorCleared { channel, .. } => {
+                self.clear(&AlarmKind::TrackingError(*channel))
+            }
+            Event::SafetyLimitExceeded {
+                exceeded,
+                switched_off,
+            } => {
+                let channel = AlarmKind::SafetyLimit(exceeded.channel);
+                let action = if *switched_off {
+                    "output switched off"
+                } else {
+                    "switching the output off failed"
+                };
+                self.raise(channel.clone(), format!("{exceeded}; {action}"));
+                if *switched_off {
+                    self.clear(&channel);
+                }
+            }
+            Event::PowerCycleSuspected => {
+                self.raise(
+                    AlarmKind::PowerCycle,
+                    "outputs and timers went off across failed polls".to_string(),
+                );
+                self.clear(&AlarmKind::PowerCycle);
+            }
+            Event::OutputChanged { .. } | Event::Degraded { .. } | Event::TimerProgress(_) => {}
+        }
+    }
+
+    /// Raise `kind`; an alarm already raised keeps its time and
+    /// acknowledgement, a latched one is raised again unacknowledged.
+    pub fn raise(&self, kind: AlarmKind, message: impl Into<String>) {
+        let message = message.into();
+        let mut alarms = self.lock();
+        match alarms.iter_mut().find(|alarm| alarm.kind == kind) {
+            Some(alarm) if alarm.is_raised() => alarm.message = message,
+            Some(alarm) => {
+                warn!("alarm: {kind} raised again: {message}");
+                alarm.message = message;
+                alarm.raised_at = SystemTime::now();
+                alarm.cleared_at = None;
+                alarm.acknowledged = false;
+            }
+            None => {
+                warn!("alarm: {kind} raised: {message}");
+                alarms.push(Alarm {
+                    kind,
+                    message,
+                    raised_at: SystemTime::now(),
+                    cleared_at: None,
+                    acknowledged: false,
+                });
+            }
+        }
+    }
+
+    /// The condition of `kind` is gone; the alarm stays until acknowledged.
+    pub fn clear(&self, kind: &AlarmKind) {
+        let mut alarms = self.lock();
+        let Some(index) = alarms.iter().position(|alarm| alarm.kind == *kind) else {
+            return;
+        };
+        let alarm = &mut alarms[index];
+        if !alarm.is_raised() {
+            return;
+        }
+        info!("alarm: {kind} cleared");
+        if alarm.acknowledged {
+            alarms.remove(index);
+        } else {
+            alarm.cleared_at = Some(SystemTime::now());
+        }
+    }
+
+    /// Acknowledge `kind`; returns whether there was such an alarm.
+    pub fn acknowledge(&self, kind: &AlarmKind) -> bool {
+        let mut alarms = self.lock();
+        let Some(index) = alarms.iter().position(|alarm| alarm.kind == *kind) else {
+            return false;
+        };
+        if alarms[index].is_raised() {
+            alarms[index].acknowledged = true;
+        } else {
+            alarms.remove(index);
+        }
+        true
+    }
+
+    pub fn acknowledge_all(&self) {
+        let mut alarms = self.lock();
+        alarms.retain(Alarm::is_raised);
+        for alarm in alarms.iter_mut() {
+            alarm.acknowledged = true;
+        }
+    }
+
+    /// Alarms raised or latched, oldest first.
+    pub fn list(&self) -> Vec<Alarm> {
+        self.lock().clone()
+    }
+
+    /// Alarms that need attention.
+    pub fn unacknowledged(&self) -> Vec<Alarm> {
+        self.lock()
+            .iter()
+            .filter(|alarm| !alarm.acknowledged)
+            .cloned()
+            .collect()
+    }
+
+    /// Fail with a [`SafetyTrip`] if any alarm is raised, or latched and
+    /// not acknowledged, so an operation can refuse to start or stop while
+    /// something is wrong. A trip that cleared at once, like
+    /// [`AlarmKind::SafetyLimit`], counts until it is acknowledged.
+    pub fn ensure_clear(&self) -> Result<(), SafetyTrip> {
+        match self.lock().iter().find(|alarm| alarm.is_raised() || !alarm.acknowledged) {
+            Some(alarm) => Err(SafetyTrip {
+                kind: alarm.kind.clone(),
+                message: alarm.message.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_raised(&self, kind: &AlarmKind) -> bool {
+        self.lock()
+            .iter()
+            .any(|alarm| alarm.kind == *kind && alarm.is_raised())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Alarm>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
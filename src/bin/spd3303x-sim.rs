@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use spd3303x_control::mock::{LatencyModel, MockDevice};
+use spd3303x_control::sniff::DEVICE_PORT;
+use spd3303x_control::units;
+use spd3303x_control::Channel;
+use tokio::net::TcpListener;
+
+/// Simulated SPD3303X on a raw SCPI socket, for demos and CI without a
+/// bench supply. Prologix `++` commands are accepted as well.
+#[derive(Debug, Parser)]
+#[command(name = "spd3303x-sim")]
+struct Args {
+    /// Address to listen on.
+    #[arg(long, default_value_t = format!("127.0.0.1:{DEVICE_PORT}"))]
+    listen: String,
+    /// Delay added to every exchange, in milliseconds.
+    #[arg(long, default_value_t = 0)]
+    latency_ms: u64,
+    /// Resistive load on an output, e.g. `CH1=10` or `CH1=4R7`. May be
+    /// repeated.
+    #[arg(long, value_parser = parse_load)]
+    load: Vec<(Channel, f64)>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .init();
+
+    let args = Args::parse();
+    let device = MockDevice::new().with_latency(LatencyModel {
+        round_trip: Duration::from_millis(args.latency_ms),
+        ..LatencyModel::none()
+    });
+    for (channel, ohms) in args.load {
+        device.set_load(channel, Some(ohms))?;
+    }
+    let listener = TcpListener::bind(&args.listen).await?;
+    device.serve(listener).await
+}
+
+fn parse_load(s: &str) -> Result<(Channel, f64)> {
+    let (channel, ohms) = s
+        .split_once('=')
+        .ok_or_else(|| anyhow!("expected CHn=<ohms>, got {s:?}"))?;
+    let channel = Channel::all()
+        .find(|ch| ch.label().eq_ignore_ascii_case(channel.trim()))
+        .ok_or_else(|| anyhow!("unknown channel {channel:?}"))?;
+    let ohms = units::parse_resistance(ohms)?;
+    if ohms <= 0.0 {
+        return Err(anyhow!("load must be a positive resistance, got {ohms}"));
+    }
+    Ok((channel, ohms))
+}
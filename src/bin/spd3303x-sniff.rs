@@ -0,0 +1,32 @@
+use anyhow::Result;
+use clap::Parser;
+use spd3303x_control::sniff::{Sniffer, DEVICE_PORT};
+use tokio::net::TcpListener;
+
+/// Forward SCPI traffic to an SPD3303X and print every frame with timing.
+#[derive(Debug, Parser)]
+#[command(name = "spd3303x-sniff")]
+struct Args {
+    /// Instrument address.
+    host: String,
+    /// Raw socket port of the instrument.
+    #[arg(long, default_value_t = DEVICE_PORT)]
+    port: u16,
+    /// Address the monitored software connects to.
+    #[arg(long, default_value_t = format!("127.0.0.1:{DEVICE_PORT}"))]
+    listen: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .init();
+
+    let args = Args::parse();
+    let sniffer = Sniffer::new(format!("{}:{}", args.host, args.port), |frame| {
+        println!("{frame}")
+    });
+    let listener = TcpListener::bind(&args.listen).await?;
+    sniffer.serve(listener).await
+}
@@ -0,0 +1,354 @@
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use spd3303x_control::clock::Ticker;
+use spd3303x_control::exit::ErrorReport;
+use spd3303x_control::inventory::{self, InventoryOptions, Subnet};
+use spd3303x_control::monitor::{Monitor, MonitorConfig};
+use spd3303x_control::safety::SafetyLimits;
+use spd3303x_control::validate::{Limits, Violation};
+use spd3303x_control::{units, Channel, ChannelSelector, OutputState, Spd3303x};
+use tokio::sync::Mutex;
+use tracing::warn;
+
+/// Poll interval while watching an output against `--max-voltage` and
+/// `--max-current`.
+const WATCH_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Command-line tools for Siglent SPD3303X supplies.
+///
+/// Exit codes: 0 success, 1 other failure, 2 validation, 3 transport,
+/// 4 device error, 5 safety trip.
+#[derive(Debug, Parser)]
+#[command(name = "spd3303x")]
+struct Args {
+    /// How errors are printed on stderr (`json` needs the `json` feature).
+    #[arg(long, value_enum, default_value_t = ErrorFormat::Text, global = true)]
+    error_format: ErrorFormat,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ErrorFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// List the supplies on a subnet with model, serial, firmware, IP and MAC.
+    Inventory {
+        /// Network to scan, e.g. 192.168.0.0/24.
+        #[arg(long)]
+        subnet: Subnet,
+        /// VXI-11 device name.
+        #[arg(long, default_value = "inst0")]
+        resource: String,
+        /// Per-host timeout in milliseconds.
+        #[arg(long, default_value_t = 500)]
+        timeout_ms: u64,
+        /// Print JSON instead of a table (needs the `json` feature).
+        #[arg(long)]
+        json: bool,
+    },
+    /// Program the voltage and/or current setpoint of a channel or of all.
+    Set {
+        #[command(flatten)]
+        target: Target,
+        /// A channel, or `all` for CH1 and CH2.
+        #[arg(long, value_enum)]
+        channel: ChannelSelector,
+        /// Voltage setpoint, e.g. `3v3`, `3.3V` or `500mV`.
+        #[arg(long, value_parser = units::parse_voltage)]
+        voltage: Option<f64>,
+        /// Current limit, e.g. `500mA` or `1.2A`.
+        #[arg(long, value_parser = units::parse_current)]
+        current: Option<f64>,
+    },
+    /// Switch the output of a channel, or of all, on or off.
+    ///
+    /// With `--max-voltage` or `--max-current` the output is watched while
+    /// it is on: a reading above them switches it off, and any alarm ends
+    /// the command with exit code 5.
+    Output {
+        #[command(flatten)]
+        target: Target,
+        /// A channel, or `all` for CH1 and CH2.
+        #[arg(long, value_enum)]
+        channel: ChannelSelector,
+        #[arg(value_enum)]
+        state: OutputState,
+        /// Switch the output off again after this long, e.g. `2m30s`.
+        #[arg(long, value_parser = units::parse_duration)]
+        time: Option<Duration>,
+        /// Switch off if the output measures above this voltage.
+        #[arg(long, value_parser = units::parse_voltage, requires = "time")]
+        max_voltage: Option<f64>,
+        /// Switch off if the output draws more than this current.
+        #[arg(long, value_parser = units::parse_current, requires = "time")]
+        max_current: Option<f64>,
+    },
+    /// Print the programmed setpoints of a channel, or of all, next to
+    /// the measured output.
+    Status {
+        #[command(flatten)]
+        target: Target,
+        /// A channel, or `all` for CH1 and CH2.
+        #[arg(long, value_enum)]
+        channel: ChannelSelector,
+    },
+    /// Send one SCPI command, printing the reply of a query, and fail with
+    /// the instrument's error if it queued one.
+    Raw {
+        #[command(flatten)]
+        target: Target,
+        /// The command, e.g. `CH1:VOLT?` or `'OUTPut CH1,ON'`.
+        command: String,
+    },
+}
+
+/// The supply a command talks to.
+#[derive(Debug, clap::Args)]
+struct Target {
+    /// Instrument address, reached over VXI-11.
+    #[arg(long, required_unless_present = "tcp", conflicts_with = "tcp")]
+    host: Option<String>,
+    /// VXI-11 device name.
+    #[arg(long, default_value = "inst0")]
+    resource: String,
+    /// Address of a raw SCPI socket instead, e.g. `192.168.1.50:5025` or
+    /// a `spd3303x-sim`.
+    #[arg(long)]
+    tcp: Option<String>,
+    /// Refuse to control this channel, e.g. a rail switched by hand. May be
+    /// repeated.
+    #[arg(long, value_enum)]
+    forbid: Vec<Channel>,
+}
+
+impl Target {
+    async fn connect(&self) -> Result<Spd3303x> {
+        let mut inst = match (&self.host, &self.tcp) {
+            (_, Some(addr)) => Spd3303x::connect_tcp(addr.as_str()).await?,
+            (Some(host), None) => Spd3303x::connect(host, &self.resource).await?,
+            (None, None) => return Err(anyhow!("no instrument given; use --host or --tcp")),
+        };
+        for &channel in &self.forbid {
+            inst.forbid(channel);
+        }
+        Ok(inst)
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::WARN)
+        .init();
+
+    let args = Args::parse();
+    if matches!(args.error_format, ErrorFormat::Json) && !cfg!(feature = "json") {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::InvalidValue,
+                "--error-format json needs spd3303x built with the `json` feature",
+            )
+            .exit();
+    }
+    let format = args.error_format;
+    match run(args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            let report = ErrorReport::new(&e);
+            match format {
+                ErrorFormat::Text => eprintln!("{}", report.render_text()),
+                ErrorFormat::Json => print_error_json(&report),
+            }
+            report.kind.exit_code()
+        }
+    }
+}
+
+async fn run(args: Args) -> Result<()> {
+    match args.command {
+        Command::Inventory {
+            subnet,
+            resource,
+            timeout_ms,
+            json,
+        } => {
+            let options = InventoryOptions {
+                resource,
+                timeout: Duration::from_millis(timeout_ms),
+                ..InventoryOptions::default()
+            };
+            let entries = inventory::scan(subnet, &options).await;
+            if json {
+                return print_json(&entries);
+            }
+            print!("{}", inventory::render_table(&entries));
+            eprintln!("{} supplies found on {subnet}", entries.len());
+        }
+        Command::Set {
+            target,
+            channel,
+            voltage,
+            current,
+        } => {
+            if voltage.is_none() && current.is_none() {
+                return Err(anyhow!("nothing to set; give --voltage and/or --current"));
+            }
+            let mut inst = target.connect().await?;
+            for channel in channel.channels() {
+                if let Some(volts) = voltage {
+                    inst.set_voltage(channel, volts).await?;
+                }
+                if let Some(amps) = current {
+                    inst.set_current(channel, amps).await?;
+                }
+            }
+            inst.close().await?;
+        }
+        Command::Output {
+            target,
+            channel,
+            state,
+            time,
+            max_voltage,
+            max_current,
+        } => {
+            if time.is_some() && matches!(state, OutputState::Off) {
+                return Err(anyhow!("--time only applies to switching on"));
+            }
+            let channels: Vec<Channel> = channel.channels().collect();
+            let limits = (max_voltage.is_some() || max_current.is_some()).then(|| {
+                let limits = Limits {
+                    max_voltage_v: max_voltage,
+                    max_current_a: max_current,
+                    ..Limits::default()
+                };
+                channels
+                    .iter()
+                    .fold(SafetyLimits::new(), |safety, &channel| safety.channel(channel, limits))
+                    .trip_on_measurement(true)
+            });
+            if limits.is_some() && channels.contains(&Channel::Ch3) {
+                return Err(Violation::UnsupportedChannel(Channel::Ch3).into());
+            }
+            let mut inst = target.connect().await?;
+            if let Some(limits) = &limits {
+                inst.apply_safety_limits(limits)?;
+            }
+            for &channel in &channels {
+                inst.set_output(channel, state).await?;
+            }
+            match (time, limits) {
+                (Some(time), Some(limits)) => {
+                    return hold_watched(inst, channels, time, limits).await;
+                }
+                (Some(time), None) => {
+                    inst.clock().sleep(time).await;
+                    for &channel in &channels {
+                        inst.set_output(channel, OutputState::Off).await?;
+                    }
+                }
+                (None, _) => {}
+            }
+            inst.close().await?;
+        }
+        Command::Status { target, channel } => {
+            let mut inst = target.connect().await?;
+            for channel in channel.channels() {
+                let status = inst.channel_status(channel).await?;
+                println!("{}:", channel.label());
+                print!("{}", status.render_table());
+            }
+            inst.close().await?;
+        }
+        Command::Raw { target, command } => {
+            let mut inst = target.connect().await?;
+            if command.trim_end().ends_with('?') {
+                println!("{}", inst.query_raw(&command).await?);
+            } else {
+                inst.write_raw(&command).await?;
+            }
+            inst.ensure_no_device_error().await?;
+            inst.close().await?;
+        }
+    }
+    Ok(())
+}
+
+/// Keep `channel` on for `time` under a monitor enforcing `limits`, then
+/// switch it off. Fails with the first alarm raised meanwhile.
+async fn hold_watched(
+    inst: Spd3303x,
+    channels: Vec<Channel>,
+    time: Duration,
+    limits: SafetyLimits,
+) -> Result<()> {
+    let clock = inst.clock();
+    let inst = Arc::new(Mutex::new(inst));
+    let config = MonitorConfig {
+        interval: WATCH_INTERVAL,
+        channels: channels.clone(),
+        safety_limits: Some(limits),
+        ..MonitorConfig::default()
+    };
+    let monitor = Monitor::start(inst.clone(), config);
+    let deadline = clock.now() + time;
+    let mut ticker = Ticker::new(clock.clone(), WATCH_INTERVAL);
+    let watched = loop {
+        ticker.tick().await;
+        if let Err(trip) = monitor.alarms().ensure_clear() {
+            break Err(trip);
+        }
+        if clock.now() >= deadline {
+            break Ok(());
+        }
+    };
+    monitor.stop().await?;
+    let mut inst = inst.lock().await;
+    let mut off = Ok(());
+    for &channel in &channels {
+        if let Err(e) = inst.set_output(channel, OutputState::Off).await {
+            if watched.is_ok() && off.is_ok() {
+                off = Err(e);
+            } else {
+                warn!("switching {} off failed: {e:#}", channel.label());
+            }
+        }
+    }
+    watched?;
+    off?;
+    inst.close().await
+}
+
+#[cfg(feature = "json")]
+fn print_json<T: serde::Serialize>(value: &T) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+#[cfg(not(feature = "json"))]
+fn print_json<T>(_value: &T) -> Result<()> {
+    anyhow::bail!("spd3303x was built without the `json` feature")
+}
+
+#[cfg(feature = "json")]
+fn print_error_json(report: &ErrorReport) {
+    match serde_json::to_string(report) {
+        Ok(json) => eprintln!("{json}"),
+        Err(_) => eprintln!("{}", report.render_text()),
+    }
+}
+
+#[cfg(not(feature = "json"))]
+fn print_error_json(report: &ErrorReport) {
+    // Refused when the arguments are parsed.
+    eprintln!("{}", report.render_text());
+}
@@ -0,0 +1,122 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use spd3303x_control::daemon::{
+    self, DaemonConfig, HttpSection, MonitorSection, WatchdogSection,
+};
+use spd3303x_control::{units, Channel};
+
+/// Share one SPD3303X connection between several local clients, optionally
+/// polling it, logging its events and serving metrics and alarms.
+#[derive(Debug, Parser)]
+#[command(name = "spd3303xd")]
+struct Args {
+    /// Instrument address; overrides the config file.
+    host: Option<String>,
+    /// Daemon config file; the other options override its settings.
+    #[cfg(feature = "config")]
+    #[arg(long)]
+    config: Option<std::path::PathBuf>,
+    /// VXI-11 device name.
+    #[arg(long)]
+    resource: Option<String>,
+    /// TCP address clients connect to [default: 127.0.0.1:5026].
+    #[arg(long)]
+    listen: Option<String>,
+    /// Additionally listen on a Unix socket at this path.
+    #[cfg(unix)]
+    #[arg(long)]
+    unix: Option<std::path::PathBuf>,
+    /// Connect timeout in seconds.
+    #[arg(long)]
+    timeout: Option<u64>,
+    /// Deadline of a command's first attempt in milliseconds. Timed-out
+    /// commands are retried, then the link is cleared and reopened.
+    #[arg(long)]
+    command_timeout_ms: Option<u64>,
+    /// Poll the instrument at this interval, e.g. `500ms` or `2s`.
+    #[arg(long, value_parser = units::parse_duration)]
+    monitor_interval: Option<Duration>,
+    /// Deprecated: use `--monitor-interval`.
+    #[arg(long, hide = true, conflicts_with = "monitor_interval")]
+    monitor_ms: Option<u64>,
+    /// Append monitor events and client writes to this file.
+    #[arg(long)]
+    audit_log: Option<std::path::PathBuf>,
+    /// Serve `/metrics` and `/alarms` over HTTP at this address, e.g.
+    /// `0.0.0.0:9330`. Needs `--monitor-interval`.
+    #[arg(long)]
+    http: Option<String>,
+    /// Switch every output off after this long without a successful poll,
+    /// e.g. `5s`. Needs `--monitor-interval`.
+    #[arg(long, value_parser = units::parse_duration)]
+    watchdog: Option<Duration>,
+    /// Refuse client commands that control this channel, e.g. a rail
+    /// switched by hand. May be repeated.
+    #[arg(long, value_enum)]
+    forbid: Vec<Channel>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .init();
+
+    let args = Args::parse();
+    #[cfg(feature = "config")]
+    let mut config = match &args.config {
+        Some(path) => DaemonConfig::load(path)?,
+        None => DaemonConfig::default(),
+    };
+    #[cfg(not(feature = "config"))]
+    let mut config = DaemonConfig::default();
+
+    if let Some(host) = args.host {
+        config.instrument.host = host;
+    }
+    if config.instrument.host.is_empty() {
+        return Err(anyhow!("no instrument address given"));
+    }
+    if let Some(resource) = args.resource {
+        config.instrument.resource = resource;
+    }
+    if let Some(listen) = args.listen {
+        config.broker.listen = listen;
+    }
+    #[cfg(unix)]
+    if let Some(unix) = args.unix {
+        config.broker.unix = Some(unix);
+    }
+    if let Some(timeout) = args.timeout {
+        config.instrument.connect_timeout_s = timeout;
+    }
+    if let Some(timeout_ms) = args.command_timeout_ms {
+        config.instrument.command_timeout_ms = timeout_ms;
+    }
+    let monitor_interval = args.monitor_interval.or_else(|| {
+        let interval_ms = args.monitor_ms?;
+        tracing::warn!("--monitor-ms is deprecated; use --monitor-interval {interval_ms}ms");
+        Some(Duration::from_millis(interval_ms))
+    });
+    if let Some(interval) = monitor_interval {
+        let monitor = config.monitor.get_or_insert_with(MonitorSection::default);
+        monitor.interval_ms = interval.as_millis().try_into().unwrap_or(u64::MAX);
+    }
+    if let Some(path) = args.audit_log {
+        config.audit_log = Some(path);
+    }
+    if let Some(listen) = args.http {
+        config.http = Some(HttpSection { listen });
+    }
+    if let Some(timeout) = args.watchdog {
+        config.watchdog = Some(WatchdogSection {
+            timeout_ms: timeout.as_millis().try_into().unwrap_or(u64::MAX),
+        });
+    }
+    for channel in args.forbid {
+        config.channel_policy = config.channel_policy.forbid(channel);
+    }
+    daemon::run(config).await
+}
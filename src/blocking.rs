@@ -0,0 +1,207 @@
+//! Synchronous wrapper for scripts that do not use async Rust.
+//!
+//! [`Spd3303xBlocking`] owns a small tokio runtime and drives an async
+//! [`Spd3303x`] on it, so a plain `fn main` can talk to the supply:
+//!
+//! ```ignore
+//! let mut inst = Spd3303xBlocking::connect("192.168.1.50", "inst0")?;
+//! inst.set_voltage(Channel::Ch1, 5.0)?;
+//! inst.set_output(Channel::Ch1, OutputState::On)?;
+//! println!("{:.3} A", inst.measured_current(Some(Channel::Ch1))?);
+//! ```
+//!
+//! The common operations have blocking counterparts here; anything else
+//! runs through [`Spd3303xBlocking::run`]. Background tasks started on the
+//! handle (monitors, keep-alives, timed outputs) keep running on the
+//! runtime's worker thread between calls.
+//!
+//! The wrapper must not be used from inside an async runtime: blocking on
+//! one from another panics.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use tokio::runtime::Runtime;
+
+use crate::instrument::{
+    Channel, ChannelStatus, Identity, NetworkConfig, OutputState, Spd3303x, SystemStatus,
+    TimerEntry, TimerState, TrackMode,
+};
+use crate::mock::MockDevice;
+
+pub struct Spd3303xBlocking {
+    // Declared first so the handle is dropped while its runtime still
+    // exists.
+    inner: Spd3303x,
+    runtime: Runtime,
+}
+
+impl Spd3303xBlocking {
+    pub fn connect(host: &str, resource: &str) -> Result<Self> {
+        let runtime = runtime()?;
+        let inner = runtime.block_on(Spd3303x::connect(host, resource))?;
+        Ok(Self { inner, runtime })
+    }
+
+    pub fn connect_with_timeout(host: &str, resource: &str, timeout: Duration) -> Result<Self> {
+        let runtime = runtime()?;
+        let inner = runtime.block_on(Spd3303x::connect_with_timeout(host, resource, timeout))?;
+        Ok(Self { inner, runtime })
+    }
+
+    /// See [`Spd3303x::connect_tcp`].
+    pub fn connect_tcp(addr: &str) -> Result<Self> {
+        let runtime = runtime()?;
+        let inner = runtime.block_on(Spd3303x::connect_tcp(addr))?;
+        Ok(Self { inner, runtime })
+    }
+
+    pub fn mock(device: &MockDevice) -> Result<Self> {
+        let runtime = runtime()?;
+        Ok(Self {
+            inner: Spd3303x::mock(device),
+            runtime,
+        })
+    }
+
+    /// The async handle, for its synchronous configuration methods.
+    pub fn get_ref(&self) -> &Spd3303x {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut Spd3303x {
+        &mut self.inner
+    }
+
+    /// Run an async operation on the handle to completion:
+    ///
+    /// ```ignore
+    /// let report = inst.run(|inst| Box::pin(inst.wait_for_settled(Channel::Ch1, spec)))?;
+    /// ```
+    pub fn run<T>(
+        &mut self,
+        operation: impl for<'a> FnOnce(&'a mut Spd3303x) -> Pin<Box<dyn Future<Output = T> + 'a>>,
+    ) -> T {
+        self.runtime.block_on(operation(&mut self.inner))
+    }
+
+    pub fn close(mut self) -> Result<()> {
+        self.runtime.block_on(self.inner.close())
+    }
+
+    pub fn reconnect(&mut self) -> Result<()> {
+        self.runtime.block_on(self.inner.reconnect())
+    }
+
+    pub fn identity(&mut self) -> Result<Identity> {
+        self.runtime.block_on(self.inner.identity())
+    }
+
+    pub fn set_voltage(&mut self, channel: Channel, volts: f64) -> Result<()> {
+        self.runtime.block_on(self.inner.set_voltage(channel, volts))
+    }
+
+    pub fn setpoint_voltage(&mut self, channel: Channel) -> Result<f64> {
+        self.runtime.block_on(self.inner.setpoint_voltage(channel))
+    }
+
+    pub fn set_current(&mut self, channel: Channel, amps: f64) -> Result<()> {
+        self.runtime.block_on(self.inner.set_current(channel, amps))
+    }
+
+    pub fn setpoint_current(&mut self, channel: Channel) -> Result<f64> {
+        self.runtime.block_on(self.inner.setpoint_current(channel))
+    }
+
+    pub fn set_output(&mut self, channel: Channel, state: OutputState) -> Result<()> {
+        self.runtime.block_on(self.inner.set_output(channel, state))
+    }
+
+    pub fn query_output(&mut self, channel: Channel) -> Result<bool> {
+        self.runtime.block_on(self.inner.query_output(channel))
+    }
+
+    pub fn set_track_mode(&mut self, mode: TrackMode) -> Result<()> {
+        self.runtime.block_on(self.inner.set_track_mode(mode))
+    }
+
+    pub fn query_track_mode(&mut self) -> Result<TrackMode> {
+        self.runtime.block_on(self.inner.query_track_mode())
+    }
+
+    pub fn measured_voltage(&mut self, channel: Option<Channel>) -> Result<f64> {
+        self.runtime.block_on(self.inner.measured_voltage(channel))
+    }
+
+    pub fn measured_current(&mut self, channel: Option<Channel>) -> Result<f64> {
+        self.runtime.block_on(self.inner.measured_current(channel))
+    }
+
+    pub fn measured_power(&mut self, channel: Option<Channel>) -> Result<f64> {
+        self.runtime.block_on(self.inner.measured_power(channel))
+    }
+
+    pub fn channel_status(&mut self, channel: Channel) -> Result<ChannelStatus> {
+        self.runtime.block_on(self.inner.channel_status(channel))
+    }
+
+    pub fn system_status(&mut self) -> Result<SystemStatus> {
+        self.runtime.block_on(self.inner.system_status())
+    }
+
+    pub fn system_error(&mut self) -> Result<String> {
+        self.runtime.block_on(self.inner.system_error())
+    }
+
+    pub fn save_state(&mut self, slot: u8) -> Result<()> {
+        self.runtime.block_on(self.inner.save_state(slot))
+    }
+
+    pub fn recall_state(&mut self, slot: u8) -> Result<()> {
+        self.runtime.block_on(self.inner.recall_state(slot))
+    }
+
+    pub fn timer_set(
+        &mut self,
+        channel: Channel,
+        group: u8,
+        voltage: f64,
+        current: f64,
+        duration: Duration,
+    ) -> Result<()> {
+        self.runtime
+            .block_on(self.inner.timer_set(channel, group, voltage, current, duration))
+    }
+
+    pub fn timer_query(&mut self, channel: Channel, group: u8) -> Result<TimerEntry> {
+        self.runtime.block_on(self.inner.timer_query(channel, group))
+    }
+
+    pub fn timer_state(&mut self, channel: Channel, state: TimerState) -> Result<()> {
+        self.runtime.block_on(self.inner.timer_state(channel, state))
+    }
+
+    pub fn network_config(&mut self) -> Result<NetworkConfig> {
+        self.runtime.block_on(self.inner.network_config())
+    }
+
+    pub fn write_raw(&mut self, command: &str) -> Result<()> {
+        self.runtime.block_on(self.inner.write_raw(command))
+    }
+
+    pub fn query_raw(&mut self, command: &str) -> Result<String> {
+        self.runtime.block_on(self.inner.query_raw(command))
+    }
+}
+
+/// One worker thread, so that background tasks of the handle keep running
+/// while the caller is not inside a call.
+fn runtime() -> Result<Runtime> {
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .build()
+        .context("failed to start the tokio runtime")
+}
@@ -0,0 +1,278 @@
+//! Session multiplexing: one instrument connection, many clients.
+//!
+//! The SPD3303X accepts a single controller at a time. The broker
+//! (`spd3303xd`) owns that connection and serves any number of local
+//! clients, executing their requests one at a time so command/response
+//! pairs never interleave on the wire.
+//!
+//! The protocol is line based, one request and one response per line:
+//!
+//! | Request       | Response                    |
+//! |---------------|-----------------------------|
+//! | `W <command>` | `OK` or `E <message>`       |
+//! | `Q <command>` | `R <reply>` or `E <message>`|
+//!
+//! Clients get the same checks as the typed methods of a local handle:
+//! `CHn:VOLT`/`CHn:CURR` writes run through `set_voltage`/`set_current`
+//! and `OUTPut CHn,ON|OFF` through `set_output`, so the limits, strict
+//! mode, the channel policy and the middleware all apply. Other writes go
+//! through `write_raw` and its channel policy check. Compound commands
+//! (`;`) are refused, and a `Q` request must be a single query, so a state
+//! change cannot be smuggled in as one.
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::sync::Mutex;
+use tracing::{debug, info, warn};
+
+use crate::instrument::{output_change, setpoint_change, Spd3303x};
+use crate::pipeline::Quantity;
+use crate::transport::{Transport, TransportFuture};
+
+pub const DEFAULT_PORT: u16 = 5026;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    Write(String),
+    Query(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Response {
+    Ok,
+    Reply(String),
+    Error(String),
+}
+
+impl Request {
+    pub fn encode(&self) -> String {
+        match self {
+            Request::Write(command) => format!("W {}\n", single_line(command)),
+            Request::Query(command) => format!("Q {}\n", single_line(command)),
+        }
+    }
+
+    pub fn decode(line: &str) -> Result<Self> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        match line.split_once(' ') {
+            Some(("W", command)) => Ok(Request::Write(command.to_string())),
+            Some(("Q", command)) => Ok(Request::Query(command.to_string())),
+            _ => Err(anyhow!("malformed broker request {line:?}")),
+        }
+    }
+}
+
+impl Response {
+    pub fn encode(&self) -> String {
+        match self {
+            Response::Ok => "OK\n".to_string(),
+            Response::Reply(reply) => format!("R {}\n", single_line(reply)),
+            Response::Error(message) => format!("E {}\n", single_line(message)),
+        }
+    }
+
+    pub fn decode(line: &str) -> Result<Self> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line == "OK" {
+            return Ok(Response::Ok);
+        }
+        match line.split_once(' ') {
+            Some(("R", reply)) => Ok(Response::Reply(reply.to_string())),
+            Some(("E", message)) => Ok(Response::Error(message.to_string())),
+            _ => Err(anyhow!("malformed broker response {line:?}")),
+        }
+    }
+}
+
+/// Serves clients on behalf of one shared instrument.
+#[derive(Clone)]
+pub struct Broker {
+    inst: Arc<Mutex<Spd3303x>>,
+}
+
+impl Broker {
+    pub fn new(inst: Spd3303x) -> Self {
+        Self::from_shared(Arc::new(Mutex::new(inst)))
+    }
+
+    pub fn from_shared(inst: Arc<Mutex<Spd3303x>>) -> Self {
+        Self { inst }
+    }
+
+    /// Accept TCP clients forever.
+    pub async fn serve_tcp(&self, listener: TcpListener) -> Result<()> {
+        info!("broker: listening on {}", listener.local_addr()?);
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let broker = self.clone();
+            let peer = peer.to_string();
+            tokio::spawn(async move { broker.serve_logged(stream, &peer).await });
+        }
+    }
+
+    /// Accept Unix socket clients forever.
+    #[cfg(unix)]
+    pub async fn serve_unix(&self, listener: tokio::net::UnixListener) -> Result<()> {
+        info!("broker: listening on {:?}", listener.local_addr()?);
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let broker = self.clone();
+            tokio::spawn(async move { broker.serve_logged(stream, "unix client").await });
+        }
+    }
+
+    /// Serve one client until it disconnects.
+    pub async fn serve_connection<S>(&self, stream: S) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut lines = BufReader::new(reader).lines();
+        while let Some(line) = lines.next_line().await? {
+            let response = match Request::decode(&line) {
+                Ok(request) => self.execute(request).await,
+                Err(e) => Response::Error(e.to_string()),
+            };
+            writer.write_all(response.encode().as_bytes()).await?;
+        }
+        Ok(())
+    }
+
+    async fn serve_logged<S>(&self, stream: S, peer: &str)
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        debug!("broker: {peer} connected");
+        match self.serve_connection(stream).await {
+            Ok(()) => debug!("broker: {peer} disconnected"),
+            Err(e) => warn!("broker: {peer} dropped: {e:#}"),
+        }
+    }
+
+    async fn execute(&self, request: Request) -> Response {
+        let mut inst = self.inst.lock().await;
+        let result = match &request {
+            Request::Write(command) => write(&mut inst, command).await.map(|()| Response::Ok),
+            Request::Query(command) => query(&mut inst, command).await.map(Response::Reply),
+        };
+        result.unwrap_or_else(|e| Response::Error(format!("{e:#}")))
+    }
+}
+
+/// A client's write, through the typed method where there is one.
+async fn write(inst: &mut Spd3303x, command: &str) -> Result<()> {
+    if command.contains(';') {
+        return Err(anyhow!("compound commands are not accepted from broker clients"));
+    }
+    if let Some((channel, quantity, value)) = setpoint_change(command) {
+        let (Some(channel), Some(value)) = (channel, value) else {
+            return Err(anyhow!(
+                "{:?}: give setpoints as CHn:VOLT/CHn:CURR with a plain number",
+                command.trim()
+            ));
+        };
+        return match quantity {
+            Quantity::Current => inst.set_current(channel, value).await,
+            _ => inst.set_voltage(channel, value).await,
+        };
+    }
+    if let Some((channel, state)) = output_change(command) {
+        return inst.set_output(channel, state).await;
+    }
+    inst.write_raw(command).await
+}
+
+/// A client's query, which must be a single one.
+async fn query(inst: &mut Spd3303x, command: &str) -> Result<String> {
+    let header = command.split_whitespace().next().unwrap_or_default();
+    if command.contains(';') || !header.ends_with('?') {
+        return Err(anyhow!("{:?} is not a single query", command.trim()));
+    }
+    inst.query_raw(command).await
+}
+
+trait BrokerStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> BrokerStream for T {}
+
+/// Client side of the broker protocol, used by `Spd3303x::connect_broker`.
+pub(crate) struct BrokerClient {
+    stream: BufReader<Box<dyn BrokerStream>>,
+}
+
+impl BrokerClient {
+    pub(crate) async fn connect_tcp(addr: impl ToSocketAddrs) -> Result<Self> {
+        let stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(true)?;
+        Ok(Self::new(Box::new(stream)))
+    }
+
+    #[cfg(unix)]
+    pub(crate) async fn connect_unix(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let stream = tokio::net::UnixStream::connect(path).await?;
+        Ok(Self::new(Box::new(stream)))
+    }
+
+    fn new(stream: Box<dyn BrokerStream>) -> Self {
+        Self {
+            stream: BufReader::new(stream),
+        }
+    }
+
+    pub(crate) async fn write(&mut self, command: &str) -> Result<()> {
+        match self.call(Request::Write(command.to_string())).await? {
+            Response::Ok => Ok(()),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    pub(crate) async fn query(&mut self, command: &str) -> Result<String> {
+        match self.call(Request::Query(command.to_string())).await? {
+            Response::Reply(reply) => Ok(reply),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    pub(crate) async fn close(&mut self) -> Result<()> {
+        self.stream.shutdown().await?;
+        Ok(())
+    }
+
+    async fn call(&mut self, request: Request) -> Result<Response> {
+        self.stream.write_all(request.encode().as_bytes()).await?;
+        self.stream.flush().await?;
+        let mut line = String::new();
+        if self.stream.read_line(&mut line).await? == 0 {
+            return Err(anyhow!("broker closed the connection"));
+        }
+        Response::decode(&line)
+    }
+}
+
+impl Transport for BrokerClient {
+    fn write<'a>(&'a mut self, command: &'a str) -> TransportFuture<'a, ()> {
+        Box::pin(BrokerClient::write(self, command))
+    }
+
+    fn query<'a>(&'a mut self, command: &'a str) -> TransportFuture<'a, String> {
+        Box::pin(BrokerClient::query(self, command))
+    }
+
+    fn close(&mut self) -> TransportFuture<'_, ()> {
+        Box::pin(BrokerClient::close(self))
+    }
+}
+
+fn unexpected(response: Response) -> anyhow::Error {
+    match response {
+        Response::Error(message) => anyhow!("broker: {message}"),
+        other => anyhow!("unexpected broker response {other:?}"),
+    }
+}
+
+fn single_line(text: &str) -> String {
+    text.trim_end_matches(['\r', '\n']).replace(['\r', '\n'], " ")
+}
@@ -0,0 +1,255 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use tracing::{debug, warn};
+
+use crate::clock::Clock;
+use crate::instrument::{Channel, Identity, OutputState, Spd3303x};
+use crate::operation;
+use crate::retry::RetryPolicy;
+#[cfg(feature = "config")]
+use crate::unit_settings::SettingsStore;
+
+const DEFAULT_RESOURCE: &str = "inst0";
+
+/// Connection options for [`Spd3303x`], created by [`Spd3303x::builder`].
+///
+/// ```ignore
+/// let inst = Spd3303x::builder("192.168.0.232")
+///     .expect_model("SPD3303X-E")
+///     .expect_serial("SPD3XIDX000000")
+///     .initial(Channel::Ch1, 3.3, 0.5, OutputState::Off)
+///     .connect()
+///     .await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Spd3303xBuilder {
+    host: String,
+    resource: String,
+    timeout: Option<Duration>,
+    models: Vec<String>,
+    serials: Vec<String>,
+    profiles: Vec<(Channel, ChannelProfile)>,
+    clock: Option<Arc<dyn Clock>>,
+    retry: Option<RetryPolicy>,
+    #[cfg(feature = "config")]
+    settings_store: Option<SettingsStore>,
+}
+
+/// State a channel is put into right after connecting.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChannelProfile {
+    pub voltage_v: f64,
+    pub current_a: f64,
+    pub output: OutputState,
+}
+
+impl Spd3303xBuilder {
+    pub fn new(host: impl Into<String>) -> Self {
+        Self {
+            host: host.into(),
+            resource: DEFAULT_RESOURCE.to_string(),
+            timeout: None,
+            models: Vec::new(),
+            serials: Vec::new(),
+            profiles: Vec::new(),
+            clock: None,
+            retry: None,
+            #[cfg(feature = "config")]
+            settings_store: None,
+        }
+    }
+
+    /// VXI-11 device name, `inst0` by default.
+    pub fn resource(mut self, resource: impl Into<String>) -> Self {
+        self.resource = resource.into();
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Only accept an instrument reporting this model (case-insensitive).
+    /// May be given several times to allow any of the listed models.
+    pub fn expect_model(mut self, model: impl Into<String>) -> Self {
+        self.models.push(model.into());
+        self
+    }
+
+    /// Only accept an instrument reporting this serial number. May be given
+    /// several times to allow any of the listed units.
+    pub fn expect_serial(mut self, serial: impl Into<String>) -> Self {
+        self.serials.push(serial.into());
+        self
+    }
+
+    /// Put `channel` (CH1 or CH2) into this state after connecting.
+    /// Replaces an earlier profile for the same channel.
+    pub fn initial(
+        mut self,
+        channel: Channel,
+        volts: f64,
+        amps: f64,
+        output: OutputState,
+    ) -> Self {
+        let profile = ChannelProfile {
+            voltage_v: volts,
+            current_a: amps,
+            output,
+        };
+        self.profiles.retain(|(ch, _)| *ch != channel);
+        self.profiles.push((channel, profile));
+        self
+    }
+
+    /// Time source of the connected handle, see [`clock`](crate::clock).
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Retry transient command failures, see [`retry`](crate::retry). The
+    /// policy is in place from the identity check on.
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Apply the connected unit's settings from `store`, see
+    /// [`unit_settings`](crate::unit_settings).
+    #[cfg(feature = "config")]
+    pub fn settings_store(mut self, store: SettingsStore) -> Self {
+        self.settings_store = Some(store);
+        self
+    }
+
+    /// Connect, check the instrument's identity if any model or serial was
+    /// pinned, apply its unit settings if a store was given, then apply the
+    /// initial profiles. The connection is closed
+    /// again if any of this fails.
+    pub async fn connect(self) -> Result<Spd3303x> {
+        if let Some((channel, _)) = self
+            .profiles
+            .iter()
+            .find(|(channel, _)| *channel == Channel::Ch3)
+        {
+            return Err(anyhow!(
+                "{} has no programmable setpoints for an initial profile",
+                channel.label()
+            ));
+        }
+
+        let mut inst = match self.timeout {
+            Some(timeout) => {
+                Spd3303x::connect_with_timeout(&self.host, &self.resource, timeout).await?
+            }
+            None => Spd3303x::connect(&self.host, &self.resource).await?,
+        };
+        if let Some(clock) = &self.clock {
+            inst.set_clock(clock.clone());
+        }
+        inst.set_retry_policy(self.retry.clone());
+
+        let checked = match self.self_check(&mut inst).await {
+            Ok(()) => operation::run("initial_profiles", self.apply_profiles(&mut inst)).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = checked {
+            if let Err(close_err) = inst.close().await {
+                warn!("failed to close rejected instrument: {close_err:#}");
+            }
+            return Err(e);
+        }
+        Ok(inst)
+    }
+
+    async fn self_check(&self, inst: &mut Spd3303x) -> Result<()> {
+        #[cfg(feature = "config")]
+        let load_settings = self.settings_store.is_some();
+        #[cfg(not(feature = "config"))]
+        let load_settings = false;
+        if self.models.is_empty() && self.serials.is_empty() && !load_settings {
+            return Ok(());
+        }
+        let identity = inst.identity().await?;
+        self.check_identity(&identity)?;
+        #[cfg(feature = "config")]
+        if let Some(store) = &self.settings_store
+            && let Some(settings) = store.load(&identity.serial)?
+        {
+            debug!("unit settings: applying {}", store.path(&identity.serial).display());
+            inst.apply_unit_settings(&settings)?;
+        }
+        Ok(())
+    }
+
+    /// Outputs that end up off are switched off before any setpoint
+    /// changes, and outputs are only switched on once all setpoints are in.
+    async fn apply_profiles(&self, inst: &mut Spd3303x) -> Result<()> {
+        for (channel, profile) in &self.profiles {
+            if matches!(profile.output, OutputState::Off) {
+                inst.set_output(*channel, OutputState::Off).await?;
+            }
+        }
+        for (channel, profile) in &self.profiles {
+            debug!(
+                "initial profile: {} {} V / {} A",
+                channel.label(),
+                profile.voltage_v,
+                profile.current_a
+            );
+            inst.set_voltage(*channel, profile.voltage_v).await?;
+            inst.set_current(*channel, profile.current_a).await?;
+        }
+        for (channel, profile) in &self.profiles {
+            if matches!(profile.output, OutputState::On) {
+                inst.set_output(*channel, OutputState::On).await?;
+            }
+        }
+        Ok(())
+    }
+
+    fn check_identity(&self, identity: &Identity) -> Result<()> {
+        debug!(
+            "connected to {} {} (serial {}, firmware {})",
+            identity.manufacturer, identity.model, identity.serial, identity.firmware
+        );
+        if !self.models.is_empty()
+            && !self
+                .models
+                .iter()
+                .any(|model| model.trim().eq_ignore_ascii_case(&identity.model))
+        {
+            return Err(anyhow!(
+                "instrument at {} is a {}, expected {}",
+                self.host,
+                identity.model,
+                self.models.join(" or ")
+            ));
+        }
+        if !self.serials.is_empty()
+            && !self
+                .serials
+                .iter()
+                .any(|serial| serial.trim() == identity.serial)
+        {
+            return Err(anyhow!(
+                "instrument at {} has serial {}, expected {}",
+                self.host,
+                identity.serial,
+                self.serials.join(" or ")
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Spd3303x {
+    pub fn builder(host: impl Into<String>) -> Spd3303xBuilder {
+        Spd3303xBuilder::new(host)
+    }
+}
@@ -0,0 +1,317 @@
+//! Detaching a running sequence and reattaching from another process.
+//!
+//! With [`SequenceRunner::checkpoint_to`] set, the runner writes a
+//! [`SequenceCheckpoint`] each time a step is issued and each time a step's
+//! condition is met. If the controller restarts during a multi-hour run, or
+//! the run is stopped on purpose with a [`DetachHandle`], a new process
+//! loads the file and continues with [`SequenceRunner::resume`].
+//!
+//! Steps are identified by their index, so resuming is only safe against
+//! the very same sequence; the checkpoint carries a
+//! [fingerprint](Sequence::fingerprint) of it and a different sequence is
+//! refused. On resume:
+//!
+//! - the setpoints and output states the sequence had established up to
+//!   the interrupted step are applied again, setpoints before outputs, so
+//!   a supply that was touched or paused at a safe voltage in the meantime
+//!   is back where the sequence left it;
+//! - a step interrupted while waiting for its condition waits again with
+//!   its full timeout;
+//! - a step interrupted during its hold only holds for what is left, with
+//!   the time the controller was away counted as held, since the supply
+//!   kept its outputs meanwhile.
+//!
+//! A run that ends, whether completed or aborted, removes its checkpoint;
+//! one that fails with an error or is detached leaves it in place.
+//!
+//! [`SequenceRunner::checkpoint_to`]: crate::sequence::SequenceRunner::checkpoint_to
+//! [`SequenceRunner::resume`]: crate::sequence::SequenceRunner::resume
+//! [`DetachHandle`]: crate::sequence::DetachHandle
+
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, Result};
+
+use crate::instrument::{Channel, OutputState, RegulationMode};
+use crate::pipeline::Quantity;
+use crate::sequence::{Action, Condition, Sequence, StepReport};
+use crate::source_sink::LoadSetting;
+
+/// Where the interrupted step was.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CheckpointPhase {
+    /// Actions applied, waiting for the step's condition.
+    Waiting,
+    /// Holding since `since`.
+    Holding { since: SystemTime },
+}
+
+/// State of a running sequence, enough to continue it elsewhere.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SequenceCheckpoint {
+    /// [`Sequence::fingerprint`] of the sequence being run.
+    pub fingerprint: u64,
+    /// Index of the step in progress.
+    pub step: usize,
+    pub phase: CheckpointPhase,
+    /// Reports of the steps before `step`.
+    pub completed: Vec<StepReport>,
+    /// Time spent paused so far.
+    pub paused: Duration,
+    pub saved_at: SystemTime,
+}
+
+impl SequenceCheckpoint {
+    /// Time the step in progress still has to hold, or its whole hold if it
+    /// is still waiting for its condition.
+    pub fn remaining_hold(&self, sequence: &Sequence) -> Duration {
+        let hold = sequence.steps.get(self.step).map_or(Duration::ZERO, |step| step.hold);
+        match self.phase {
+            CheckpointPhase::Waiting => hold,
+            CheckpointPhase::Holding { since } => {
+                hold.saturating_sub(since.elapsed().unwrap_or_default())
+            }
+        }
+    }
+
+    /// Check that this checkpoint can continue `sequence`, and is no older
+    /// than `max_age` if given.
+    pub fn validate(&self, sequence: &Sequence, max_age: Option<Duration>) -> Result<()> {
+        if self.fingerprint != sequence.fingerprint() {
+            return Err(anyhow!("checkpoint was taken on a different sequence"));
+        }
+        if self.step >= sequence.steps.len() {
+            return Err(anyhow!(
+                "checkpoint is at step {} of a {}-step sequence",
+                self.step,
+                sequence.steps.len()
+            ));
+        }
+        if self.completed.len() != self.step {
+            return Err(anyhow!(
+                "checkpoint at step {} carries {} step reports",
+                self.step,
+                self.completed.len()
+            ));
+        }
+        let age = self.saved_at.elapsed().unwrap_or_default();
+        if let Some(max_age) = max_age
+            && age > max_age
+        {
+            return Err(anyhow!("checkpoint is {age:?} old, more than {max_age:?}"));
+        }
+        Ok(())
+    }
+
+    /// Write the checkpoint to `path`, replacing it in one step so that a
+    /// crash mid-write leaves the previous checkpoint intact.
+    #[cfg(feature = "json")]
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        use anyhow::Context;
+
+        let path = path.as_ref();
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        std::fs::write(&temp, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("failed to write checkpoint {}", path.display()))?;
+        std::fs::rename(&temp, path)
+            .with_context(|| format!("failed to replace checkpoint {}", path.display()))
+    }
+
+    #[cfg(feature = "json")]
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        use anyhow::Context;
+
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read checkpoint {}", path.display()))?;
+        serde_json::from_str(&text)
+            .with_context(|| format!("failed to parse checkpoint {}", path.display()))
+    }
+}
+
+impl Sequence {
+    /// Hash of the steps, the same in every process and build, identifying
+    /// the sequence a [`SequenceCheckpoint`] belongs to.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hash = Fnv::new();
+        hash.usize(self.steps.len());
+        for step in &self.steps {
+            match &step.label {
+                Some(label) => {
+                    hash.u8(1);
+                    hash.str(label);
+                }
+                None => hash.u8(0),
+            }
+            hash.usize(step.actions.len());
+            for action in &step.actions {
+                hash.action(action);
+            }
+            match &step.wait {
+                Some(wait) => {
+                    hash.u8(1);
+                    hash.condition(&wait.condition);
+                    hash.duration(wait.timeout);
+                    hash.duration(wait.poll_interval);
+                }
+                None => hash.u8(0),
+            }
+            hash.duration(step.hold);
+        }
+        hash.0
+    }
+
+    /// The last action of each kind and target in steps `0..=index`,
+    /// setpoints first, then outputs and load input.
+    pub(crate) fn state_through(&self, index: usize) -> Vec<Action> {
+        let mut state: Vec<Action> = Vec::new();
+        for action in self.steps.iter().take(index + 1).flat_map(|step| &step.actions) {
+            state.retain(|earlier| !same_target(earlier, action));
+            state.push(*action);
+        }
+        state.sort_by_key(|action| matches!(action, Action::Output(..) | Action::LoadInput(_)));
+        state
+    }
+}
+
+/// FNV-1a over an explicit encoding of the steps: unlike `DefaultHasher`
+/// or a `Debug` form, it does not change with the compiler or this crate's
+/// formatting. Enums are written as a fixed tag per variant, strings with
+/// their length, numbers little-endian.
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.bytes(&[value]);
+    }
+
+    fn usize(&mut self, value: usize) {
+        self.bytes(&(value as u64).to_le_bytes());
+    }
+
+    fn f64(&mut self, value: f64) {
+        self.bytes(&value.to_bits().to_le_bytes());
+    }
+
+    fn str(&mut self, value: &str) {
+        self.usize(value.len());
+        self.bytes(value.as_bytes());
+    }
+
+    fn duration(&mut self, value: Duration) {
+        self.bytes(&value.as_nanos().to_le_bytes());
+    }
+
+    fn channel(&mut self, channel: Channel) {
+        self.str(channel.label());
+    }
+
+    fn quantity(&mut self, quantity: Quantity) {
+        self.u8(match quantity {
+            Quantity::Voltage => 0,
+            Quantity::Current => 1,
+            Quantity::Power => 2,
+        });
+    }
+
+    fn action(&mut self, action: &Action) {
+        match *action {
+            Action::SetVoltage(channel, volts) => {
+                self.u8(0);
+                self.channel(channel);
+                self.f64(volts);
+            }
+            Action::SetCurrent(channel, amps) => {
+                self.u8(1);
+                self.channel(channel);
+                self.f64(amps);
+            }
+            Action::Output(channel, state) => {
+                self.u8(2);
+                self.channel(channel);
+                self.u8(u8::from(matches!(state, OutputState::On)));
+            }
+            Action::Load(setting) => {
+                self.u8(3);
+                let (mode, value) = match setting {
+                    LoadSetting::ConstantCurrent(value) => (0, value),
+                    LoadSetting::ConstantVoltage(value) => (1, value),
+                    LoadSetting::ConstantResistance(value) => (2, value),
+                    LoadSetting::ConstantPower(value) => (3, value),
+                };
+                self.u8(mode);
+                self.f64(value);
+            }
+            Action::LoadInput(on) => {
+                self.u8(4);
+                self.u8(u8::from(on));
+            }
+        }
+    }
+
+    fn condition(&mut self, condition: &Condition) {
+        match *condition {
+            Condition::CurrentBelow(channel, limit) => {
+                self.u8(0);
+                self.channel(channel);
+                self.f64(limit);
+            }
+            Condition::CurrentAbove(channel, limit) => {
+                self.u8(1);
+                self.channel(channel);
+                self.f64(limit);
+            }
+            Condition::VoltageBelow(channel, limit) => {
+                self.u8(2);
+                self.channel(channel);
+                self.f64(limit);
+            }
+            Condition::VoltageAbove(channel, limit) => {
+                self.u8(3);
+                self.channel(channel);
+                self.f64(limit);
+            }
+            Condition::Regulation(channel, mode) => {
+                self.u8(4);
+                self.channel(channel);
+                self.u8(u8::from(matches!(mode, RegulationMode::ConstantCurrent)));
+            }
+            Condition::Settled {
+                channel,
+                quantity,
+                tolerance,
+                window,
+            } => {
+                self.u8(5);
+                self.channel(channel);
+                self.quantity(quantity);
+                self.f64(tolerance);
+                self.duration(window);
+            }
+        }
+    }
+}
+
+fn same_target(a: &Action, b: &Action) -> bool {
+    match (a, b) {
+        (Action::SetVoltage(a, _), Action::SetVoltage(b, _))
+        | (Action::SetCurrent(a, _), Action::SetCurrent(b, _))
+        | (Action::Output(a, _), Action::Output(b, _)) => a == b,
+        (Action::Load(_), Action::Load(_)) | (Action::LoadInput(_), Action::LoadInput(_)) => true,
+        _ => false,
+    }
+}
@@ -0,0 +1,149 @@
+//! Time source of the time-based subsystems.
+//!
+//! Sequences, settling waits, control loops, monitors and the timed-output
+//! watchdog take the time and sleep through the [`Clock`] of the handle
+//! they work on, [`TokioClock`] unless one is set with
+//! `Spd3303x::set_clock` or `Spd3303xBuilder::clock`. Tests swap in a
+//! [`MockClock`] and move time forward by hand, so a multi-hour sequence
+//! runs in milliseconds and every step lands exactly on its deadline:
+//!
+//! ```ignore
+//! let clock = MockClock::new();
+//! let mut inst = Spd3303x::mock(&device);
+//! inst.set_clock(Arc::new(clock.clone()));
+//! let run = tokio::spawn(async move { runner.run(&mut inst, &sequence).await });
+//! clock.advance(Duration::from_secs(3600)).await;
+//! ```
+//!
+//! [`TokioClock`] also follows `tokio::time::pause`, for tests that would
+//! rather drive tokio's own clock.
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+pub type ClockFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// Resolves once [`now`](Self::now) has reached `deadline`.
+    fn sleep_until(&self, deadline: Instant) -> ClockFuture;
+
+    fn sleep(&self, duration: Duration) -> ClockFuture {
+        self.sleep_until(self.now() + duration)
+    }
+
+    fn elapsed(&self, since: Instant) -> Duration {
+        self.now().saturating_duration_since(since)
+    }
+}
+
+/// The tokio timer; the default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioClock;
+
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> ClockFuture {
+        Box::pin(tokio::time::sleep_until(deadline))
+    }
+}
+
+/// A clock that only moves when told to. Clones share the same time.
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    now: Arc<watch::Sender<Instant>>,
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockClock {
+    /// A clock standing at the current instant.
+    pub fn new() -> Self {
+        let (now, _) = watch::channel(Instant::now());
+        Self { now: Arc::new(now) }
+    }
+
+    /// Move time forward by `duration`, waking every sleep that is due,
+    /// then yield so that the woken tasks get to run before the caller
+    /// moves on.
+    pub async fn advance(&self, duration: Duration) {
+        self.now.send_modify(|now| *now += duration);
+        tokio::task::yield_now().await;
+    }
+
+    /// Advance in steps of `step` until `duration` has passed, yielding
+    /// after each, so that loops which sleep repeatedly see every step.
+    pub async fn advance_by_steps(&self, duration: Duration, step: Duration) {
+        let mut left = duration;
+        while !left.is_zero() {
+            let step = if step.is_zero() { left } else { step.min(left) };
+            self.advance(step).await;
+            left -= step;
+        }
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        *self.now.borrow()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> ClockFuture {
+        let mut now = self.now.subscribe();
+        Box::pin(async move {
+            // The sender lives as long as any clone of the clock; once it
+            // is gone, time can no longer reach the deadline.
+            if now.wait_for(|now| *now >= deadline).await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        })
+    }
+}
+
+/// Periodic ticks on a [`Clock`]. The first tick is immediate; a late tick
+/// pushes the following ones back, like tokio's `MissedTickBehavior::Delay`.
+#[derive(Debug)]
+pub struct Ticker {
+    clock: Arc<dyn Clock>,
+    period: Duration,
+    next: Instant,
+}
+
+impl Ticker {
+    pub fn new(clock: Arc<dyn Clock>, period: Duration) -> Self {
+        let next = clock.now();
+        Self::starting_at(clock, next, period)
+    }
+
+    pub fn starting_at(clock: Arc<dyn Clock>, start: Instant, period: Duration) -> Self {
+        Self {
+            clock,
+            period,
+            next: start,
+        }
+    }
+
+    /// Wait for the next tick and return the instant it was due. Safe to
+    /// cancel: a dropped call leaves the schedule as it was.
+    pub async fn tick(&mut self) -> Instant {
+        let due = self.next;
+        self.clock.sleep_until(due).await;
+        let now = self.clock.now();
+        self.next = now.max(due) + self.period;
+        due
+    }
+}
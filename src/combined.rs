@@ -0,0 +1,264 @@
+//! Readings of CH1 and CH2 combined into the single output they form in
+//! the tracking modes.
+//!
+//! The helpers check the track mode in the status word first, so the
+//! combination is never applied to independent channels.
+
+use anyhow::{anyhow, Result};
+
+use crate::instrument::{Channel, Spd3303x, TrackMode};
+use crate::operation;
+use crate::table::Table;
+
+/// Combined output of CH1 and CH2 plus the per-channel readbacks it was
+/// computed from.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CombinedStatus {
+    pub track_mode: TrackMode,
+    pub voltage_v: f64,
+    pub current_a: f64,
+    pub power_w: f64,
+    pub ch1_voltage_v: f64,
+    pub ch1_current_a: f64,
+    pub ch2_voltage_v: f64,
+    pub ch2_current_a: f64,
+}
+
+impl CombinedStatus {
+    pub fn render_table(&self) -> Table {
+        Table::new(["Quantity", "CH1", "CH2", self.track_mode.label()])
+            .row([
+                "Voltage".to_string(),
+                format!("{:.3} V", self.ch1_voltage_v),
+                format!("{:.3} V", self.ch2_voltage_v),
+                format!("{:.3} V", self.voltage_v),
+            ])
+            .row([
+                "Current".to_string(),
+                format!("{:.3} A", self.ch1_current_a),
+                format!("{:.3} A", self.ch2_current_a),
+                format!("{:.3} A", self.current_a),
+            ])
+            .row([
+                "Power".to_string(),
+                String::new(),
+                String::new(),
+                format!("{:.3} W", self.power_w),
+            ])
+    }
+}
+
+/// Imbalance above which the parallel share is flagged: the busier channel
+/// carries more than 60% of the load.
+pub const MAX_SHARE_IMBALANCE: f64 = 0.2;
+
+/// Fraction of a channel's current limit above which it is flagged as
+/// close to its limit.
+pub const NEAR_LIMIT_RATIO: f64 = 0.9;
+
+/// How evenly CH1 and CH2 share the load in parallel mode.
+///
+/// A contact resistance or a missing lead on one channel pushes the load
+/// onto the other, which then runs into its limit while the total is well
+/// inside the 6.4 A of the parallel output.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BalanceReport {
+    pub samples: usize,
+    /// Mean current readbacks.
+    pub ch1_current_a: f64,
+    pub ch2_current_a: f64,
+    pub total_current_a: f64,
+    /// `|I1 - I2| / (I1 + I2)`: 0 is an even share, 1 is one channel
+    /// carrying all of it. `None` below the readback resolution.
+    pub imbalance: Option<f64>,
+    pub ch1_limit_a: f64,
+    pub ch2_limit_a: f64,
+}
+
+impl BalanceReport {
+    /// Readback over limit of `channel` (CH1 or CH2).
+    pub fn limit_ratio(&self, channel: Channel) -> Option<f64> {
+        let (current, limit) = match channel {
+            Channel::Ch1 => (self.ch1_current_a, self.ch1_limit_a),
+            Channel::Ch2 => (self.ch2_current_a, self.ch2_limit_a),
+            Channel::Ch3 => return None,
+        };
+        (limit > 0.0).then(|| current / limit)
+    }
+
+    /// Problems worth checking the wiring for; empty when the share looks
+    /// healthy.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if let Some(imbalance) = self.imbalance
+            && imbalance > MAX_SHARE_IMBALANCE
+        {
+            let busier = if self.ch1_current_a >= self.ch2_current_a {
+                Channel::Ch1
+            } else {
+                Channel::Ch2
+            };
+            warnings.push(format!(
+                "{} carries {:.0}% of the load; check the leads and contacts of both channels",
+                busier.label(),
+                (1.0 + imbalance) / 2.0 * 100.0
+            ));
+        }
+        for channel in [Channel::Ch1, Channel::Ch2] {
+            if let Some(ratio) = self.limit_ratio(channel)
+                && ratio > NEAR_LIMIT_RATIO
+            {
+                warnings.push(format!(
+                    "{} is at {:.0}% of its current limit",
+                    channel.label(),
+                    ratio * 100.0
+                ));
+            }
+        }
+        warnings
+    }
+
+    pub fn is_balanced(&self) -> bool {
+        self.warnings().is_empty()
+    }
+
+    pub fn render_table(&self) -> Table {
+        let ratio = |channel| {
+            self.limit_ratio(channel)
+                .map(|ratio| format!("{:.0}%", ratio * 100.0))
+                .unwrap_or_default()
+        };
+        let share = |current: f64| {
+            if self.total_current_a > 0.0 {
+                format!("{:.0}%", current / self.total_current_a * 100.0)
+            } else {
+                String::new()
+            }
+        };
+        Table::new(["Quantity", "CH1", "CH2", "Parallel"])
+            .row([
+                "Current".to_string(),
+                format!("{:.3} A", self.ch1_current_a),
+                format!("{:.3} A", self.ch2_current_a),
+                format!("{:.3} A", self.total_current_a),
+            ])
+            .row([
+                "Share".to_string(),
+                share(self.ch1_current_a),
+                share(self.ch2_current_a),
+                self.imbalance
+                    .map(|imbalance| format!("{:.0}% imbalance", imbalance * 100.0))
+                    .unwrap_or_default(),
+            ])
+            .row([
+                "Of limit".to_string(),
+                ratio(Channel::Ch1),
+                ratio(Channel::Ch2),
+                String::new(),
+            ])
+    }
+}
+
+/// Total current below which the share is not judged, as the readbacks
+/// resolve 1 mA.
+const MIN_BALANCE_CURRENT_A: f64 = 0.01;
+
+impl Spd3303x {
+    /// Average `samples` current readbacks of both channels in parallel
+    /// mode and report how the load is shared; see
+    /// [`BalanceReport::warnings`]. Fails unless the instrument reports
+    /// parallel mode. Only reads, so it can run under a live load.
+    pub async fn parallel_balance_report(&mut self, samples: usize) -> Result<BalanceReport> {
+        operation::run("parallel_balance", async {
+            self.ensure_track_mode(TrackMode::Parallel).await?;
+            let samples = samples.max(1);
+            let (mut i1, mut i2) = (0.0, 0.0);
+            for _ in 0..samples {
+                i1 += self.measured_current(Some(Channel::Ch1)).await?;
+                i2 += self.measured_current(Some(Channel::Ch2)).await?;
+            }
+            let (i1, i2) = (i1 / samples as f64, i2 / samples as f64);
+            let total = i1 + i2;
+            Ok(BalanceReport {
+                samples,
+                ch1_current_a: i1,
+                ch2_current_a: i2,
+                total_current_a: total,
+                imbalance: (total >= MIN_BALANCE_CURRENT_A).then(|| (i1 - i2).abs() / total),
+                ch1_limit_a: self.setpoint_current(Channel::Ch1).await?,
+                ch2_limit_a: self.setpoint_current(Channel::Ch2).await?,
+            })
+        })
+        .await
+    }
+
+    /// Measurements of the parallel output (0–32 V / 0–6.4 A at the CH1
+    /// terminals).
+    ///
+    /// In parallel mode CH1 sets the voltage and CH2 runs in CC mode
+    /// sharing the load, so the output voltage is CH1's readback and the
+    /// output current is the sum of both channels' readbacks. Fails unless
+    /// the instrument reports parallel mode.
+    pub async fn parallel_measurements(&mut self) -> Result<CombinedStatus> {
+        self.ensure_track_mode(TrackMode::Parallel).await?;
+        let (v1, i1, v2, i2) = self.read_both().await?;
+        let current = i1 + i2;
+        Ok(CombinedStatus {
+            track_mode: TrackMode::Parallel,
+            voltage_v: v1,
+            current_a: current,
+            power_w: v1 * current,
+            ch1_voltage_v: v1,
+            ch1_current_a: i1,
+            ch2_voltage_v: v2,
+            ch2_current_a: i2,
+        })
+    }
+
+    /// Measurements of the series output (0–64 V / 0–3.2 A between CH2−
+    /// and CH1+).
+    ///
+    /// Both channels carry the same current, so the output voltage is the
+    /// sum of the two voltage readbacks (each positive at its own `+`
+    /// terminal) and the output current is CH1's readback. Fails unless the
+    /// instrument reports series mode.
+    pub async fn series_measurements(&mut self) -> Result<CombinedStatus> {
+        self.ensure_track_mode(TrackMode::Series).await?;
+        let (v1, i1, v2, i2) = self.read_both().await?;
+        let voltage = v1 + v2;
+        Ok(CombinedStatus {
+            track_mode: TrackMode::Series,
+            voltage_v: voltage,
+            current_a: i1,
+            power_w: voltage * i1,
+            ch1_voltage_v: v1,
+            ch1_current_a: i1,
+            ch2_voltage_v: v2,
+            ch2_current_a: i2,
+        })
+    }
+
+    async fn ensure_track_mode(&mut self, expected: TrackMode) -> Result<()> {
+        let actual = self.system_status().await?.track_mode;
+        if actual != Some(expected) {
+            let actual = actual.map(TrackMode::label).unwrap_or("unknown");
+            return Err(anyhow!(
+                "instrument is in {actual} mode, not {} mode",
+                expected.label()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Per-channel voltage and current, through the measurement pipelines.
+    async fn read_both(&mut self) -> Result<(f64, f64, f64, f64)> {
+        Ok((
+            self.measured_voltage(Some(Channel::Ch1)).await?,
+            self.measured_current(Some(Channel::Ch1)).await?,
+            self.measured_voltage(Some(Channel::Ch2)).await?,
+            self.measured_current(Some(Channel::Ch2)).await?,
+        ))
+    }
+}
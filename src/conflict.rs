@@ -0,0 +1,167 @@
+//! Detecting other sessions controlling the same instrument.
+//!
+//! The SPD3303X accepts commands from its web page, the front panel and
+//! several remote sessions at once. When something else is driving it,
+//! writes from this handle fail now and then or are undone, which is hard
+//! to tell from a flaky link. Two signs are watched for:
+//!
+//! - a write failing with a lock error, as VXI-11 reports while another
+//!   link holds the device lock;
+//! - entries in the error queue that this handle did not cause, found by
+//!   [`Spd3303x::check_control`].
+//!
+//! Either is kept as the handle's [`ControlConflict`]. With
+//! [`Spd3303x::set_observer_fallback`] enabled, the handle then drops to
+//! observer-only mode: queries still go out, writes fail right away with
+//! the conflict as their error until [`Spd3303x::regain_control`] finds the
+//! instrument quiet again. Switching an output off is the exception, so
+//! failsafes, watchdogs and safety trips are never locked out.
+
+use std::fmt;
+use std::time::SystemTime;
+
+use anyhow::Result;
+use tracing::{info, warn};
+
+use crate::display::{is_no_error, MAX_QUEUED_ERRORS};
+use crate::instrument::Spd3303x;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ConflictEvidence {
+    /// A write failed because another link holds the device lock.
+    LockHeld { command: String, error: String },
+    /// The error queue held entries this handle did not cause.
+    ForeignErrors { entries: Vec<String> },
+}
+
+/// Another session appears to be controlling the instrument.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ControlConflict {
+    pub detected_at: SystemTime,
+    pub evidence: ConflictEvidence,
+}
+
+impl fmt::Display for ControlConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "another session appears to control the instrument: ")?;
+        match &self.evidence {
+            ConflictEvidence::LockHeld { command, error } => {
+                write!(f, "{command:?} failed on a held lock ({error})")
+            }
+            ConflictEvidence::ForeignErrors { entries } => {
+                write!(f, "unexpected errors queued: {}", entries.join("; "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for ControlConflict {}
+
+/// Conflict bookkeeping of one handle.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ConflictState {
+    pub(crate) observer_fallback: bool,
+    pub(crate) observer_only: bool,
+    pub(crate) last: Option<ControlConflict>,
+    /// Writes and queries failed since the last check, whose errors may
+    /// legitimately be in the queue.
+    pub(crate) own_errors: u64,
+}
+
+impl Spd3303x {
+    /// Drop to observer-only mode when a conflict is detected.
+    pub fn set_observer_fallback(&mut self, enabled: bool) {
+        self.conflict_state_mut().observer_fallback = enabled;
+    }
+
+    /// Whether writes are held back because of a conflict.
+    pub fn is_observer_only(&self) -> bool {
+        self.conflict_state().observer_only
+    }
+
+    /// Most recently detected conflict, if any.
+    pub fn control_conflict(&self) -> Option<&ControlConflict> {
+        self.conflict_state().last.as_ref()
+    }
+
+    /// Read the error queue and report a conflict if it holds errors while
+    /// none of this handle's commands failed since the last check. Errors
+    /// of this handle are drained without being reported; a command the
+    /// instrument rejected without failing the exchange still looks
+    /// foreign, so check after commands known to be valid.
+    pub async fn check_control(&mut self) -> Result<Option<ControlConflict>> {
+        let own_errors = std::mem::take(&mut self.conflict_state_mut().own_errors);
+        let mut entries = Vec::new();
+        for _ in 0..MAX_QUEUED_ERRORS {
+            let reply = self.system_error().await?;
+            if is_no_error(&reply) {
+                break;
+            }
+            entries.push(reply.trim().to_string());
+        }
+        if entries.is_empty() || own_errors > 0 {
+            return Ok(None);
+        }
+        let conflict = ControlConflict {
+            detected_at: SystemTime::now(),
+            evidence: ConflictEvidence::ForeignErrors { entries },
+        };
+        self.note_conflict(conflict.clone());
+        Ok(Some(conflict))
+    }
+
+    /// Leave observer-only mode if [`check_control`](Self::check_control)
+    /// finds nothing; returns whether writes are allowed again.
+    pub async fn regain_control(&mut self) -> Result<bool> {
+        if self.check_control().await?.is_some() {
+            return Ok(false);
+        }
+        let state = self.conflict_state_mut();
+        if state.observer_only {
+            info!("conflict: no other session detected, leaving observer-only mode");
+        }
+        state.observer_only = false;
+        state.last = None;
+        Ok(true)
+    }
+
+    /// Fail a write up front while in observer-only mode.
+    pub(crate) fn ensure_in_control(&self) -> Result<()> {
+        let state = self.conflict_state();
+        match &state.last {
+            Some(conflict) if state.observer_only => Err(conflict.clone().into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Look at a failed command for signs of another session.
+    pub(crate) fn observe_command_error(&mut self, command: &str, error: &anyhow::Error) {
+        let message = format!("{error:#}");
+        let lock_error = message
+            .to_ascii_lowercase()
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .any(|word| word == "lock" || word == "locked");
+        if !lock_error {
+            return;
+        }
+        self.note_conflict(ControlConflict {
+            detected_at: SystemTime::now(),
+            evidence: ConflictEvidence::LockHeld {
+                command: command.trim_end_matches('\n').to_string(),
+                error: message,
+            },
+        });
+    }
+
+    fn note_conflict(&mut self, conflict: ControlConflict) {
+        warn!("conflict: {conflict}");
+        let state = self.conflict_state_mut();
+        if state.observer_fallback && !state.observer_only {
+            warn!("conflict: switching to observer-only mode, writes are held back");
+            state.observer_only = true;
+        }
+        state.last = Some(conflict);
+    }
+}
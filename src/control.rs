@@ -0,0 +1,594 @@
+//! Closed-loop setpoint control run from the host.
+//!
+//! A [`ControlLoop`] turns a measurement into the next setpoint;
+//! [`Spd3303x::run_control_loop`] runs it at a fixed rate and clamps every
+//! setpoint it produces to the channel's rated range and host-side limits.
+//! [`Pid`] is the general-purpose loop; [`ConstantPower`] and
+//! [`SourceResistance`] back `hold_constant_power` and
+//! `hold_constant_resistance`.
+//!
+//! By default the loop sees the supply's own readbacks. With a [`Feedback`]
+//! one of them is replaced by an external reading, e.g. a DMM at the DUT,
+//! which turns a voltage loop into software remote sense.
+
+use std::future::Future;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use tokio::sync::watch;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+use crate::clock::Ticker;
+use crate::instrument::{Channel, Spd3303x};
+use crate::operation;
+use crate::pipeline::Quantity;
+use crate::validate::Violation;
+
+/// Below this load current the load resistance is treated as unknown.
+const MIN_LOAD_CURRENT_A: f64 = 0.001;
+/// Voltage increase per iteration while no load current flows yet.
+const STARTUP_STEP_V: f64 = 0.5;
+/// Highest voltage [`ConstantPower`] ramps to without seeing a load, unless
+/// set otherwise.
+pub const DEFAULT_STARTUP_CEILING_V: f64 = 5.0;
+
+/// Setpoint driven by a [`ControlLoop`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Actuator {
+    Voltage,
+    Current,
+}
+
+/// Readings taken at the start of one control iteration.
+#[derive(Debug, Clone, Copy)]
+pub struct Measurement {
+    pub voltage_v: f64,
+    pub current_a: f64,
+    /// Time since the previous iteration; the configured interval on the
+    /// first one.
+    pub dt: Duration,
+}
+
+impl Measurement {
+    pub fn power_w(&self) -> f64 {
+        self.voltage_v * self.current_a
+    }
+
+    pub fn get(&self, quantity: Quantity) -> f64 {
+        match quantity {
+            Quantity::Voltage => self.voltage_v,
+            Quantity::Current => self.current_a,
+            Quantity::Power => self.power_w(),
+        }
+    }
+}
+
+/// What a loop learns about the channel before its first iteration.
+#[derive(Debug, Clone, Copy)]
+pub struct LoopContext {
+    /// Setpoint programmed when the loop started.
+    pub initial_setpoint: f64,
+    /// Setpoint range the executor clamps to.
+    pub min: f64,
+    pub max: f64,
+}
+
+/// A control law: measurement in, setpoint out.
+pub trait ControlLoop: Send {
+    fn actuator(&self) -> Actuator {
+        Actuator::Voltage
+    }
+
+    fn start(&mut self, _context: &LoopContext) {}
+
+    /// Next setpoint given the latest readings and the setpoint currently
+    /// programmed. The executor clamps the result before sending it.
+    fn update(&mut self, measurement: &Measurement, setpoint: f64) -> f64;
+
+    /// Deviation from the loop's target, reported in [`ControlReport`].
+    fn error(&self, _measurement: &Measurement) -> Option<f64> {
+        None
+    }
+
+    /// Setpoint to program after the last iteration, if any. Also
+    /// programmed when an iteration fails.
+    fn finish(&mut self) -> Option<f64> {
+        None
+    }
+}
+
+/// Source of an external reading, polled once per control iteration.
+///
+/// Implemented for async closures returning `Result<f64>` and for
+/// `watch::Receiver<f64>`, which yields the latest value published by a
+/// separate acquisition task.
+pub trait FeedbackSource: Send {
+    fn read(&mut self) -> impl Future<Output = Result<f64>> + Send;
+}
+
+impl<F, Fut> FeedbackSource for F
+where
+    F: FnMut() -> Fut + Send,
+    Fut: Future<Output = Result<f64>> + Send,
+{
+    fn read(&mut self) -> impl Future<Output = Result<f64>> + Send {
+        self()
+    }
+}
+
+impl FeedbackSource for watch::Receiver<f64> {
+    fn read(&mut self) -> impl Future<Output = Result<f64>> + Send {
+        std::future::ready(Ok(*self.borrow_and_update()))
+    }
+}
+
+/// Replaces the supply's reading of `quantity` with `source`.
+pub struct Feedback<S> {
+    pub quantity: Quantity,
+    pub source: S,
+}
+
+impl<S: FeedbackSource> Feedback<S> {
+    /// External voltage, typically measured at the load.
+    pub fn voltage(source: S) -> Self {
+        Self {
+            quantity: Quantity::Voltage,
+            source,
+        }
+    }
+
+    pub fn current(source: S) -> Self {
+        Self {
+            quantity: Quantity::Current,
+            source,
+        }
+    }
+}
+
+/// Used where a loop runs on the supply's own readbacks.
+enum NoFeedback {}
+
+impl FeedbackSource for NoFeedback {
+    fn read(&mut self) -> impl Future<Output = Result<f64>> + Send {
+        std::future::ready(match *self {})
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ControlOptions {
+    /// Time between iterations.
+    pub interval: Duration,
+    /// Total run time.
+    pub duration: Duration,
+}
+
+/// Outcome of a control loop run.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ControlReport {
+    pub iterations: usize,
+    /// Setpoint programmed by the last iteration.
+    pub setpoint: f64,
+    /// Error reported by the loop in the last iteration.
+    pub last_error: Option<f64>,
+    /// Largest absolute error seen after the first iteration.
+    pub max_abs_error: f64,
+}
+
+/// PID controller on one measured quantity.
+///
+/// The output is `initial setpoint + Kp·e + Ki·∫e dt + Kd·de/dt` with
+/// `e = target - measured`. The integral term is frozen while the output
+/// is saturated in the direction of the error, so it does not wind up while
+/// the setpoint sits at a limit.
+#[derive(Debug, Clone)]
+pub struct Pid {
+    pub kp: f64,
+    pub ki: f64,
+    pub kd: f64,
+    pub target: f64,
+    pub quantity: Quantity,
+    pub actuator: Actuator,
+    bias: f64,
+    min: f64,
+    max: f64,
+    integral: f64,
+    previous_error: Option<f64>,
+}
+
+impl Pid {
+    /// Drive `actuator` so that `quantity` reaches `target`. All gains start
+    /// at zero.
+    pub fn new(quantity: Quantity, target: f64, actuator: Actuator) -> Self {
+        Self {
+            kp: 0.0,
+            ki: 0.0,
+            kd: 0.0,
+            target,
+            quantity,
+            actuator,
+            bias: 0.0,
+            min: 0.0,
+            max: f64::INFINITY,
+            integral: 0.0,
+            previous_error: None,
+        }
+    }
+
+    pub fn gains(mut self, kp: f64, ki: f64, kd: f64) -> Self {
+        self.kp = kp;
+        self.ki = ki;
+        self.kd = kd;
+        self
+    }
+
+    /// Clear the integral and derivative history.
+    pub fn reset(&mut self) {
+        self.integral = 0.0;
+        self.previous_error = None;
+    }
+}
+
+impl ControlLoop for Pid {
+    fn actuator(&self) -> Actuator {
+        self.actuator
+    }
+
+    fn start(&mut self, context: &LoopContext) {
+        self.bias = context.initial_setpoint;
+        self.min = context.min;
+        self.max = context.max;
+        self.reset();
+    }
+
+    fn update(&mut self, measurement: &Measurement, _setpoint: f64) -> f64 {
+        let dt = measurement.dt.as_secs_f64();
+        let error = self.target - measurement.get(self.quantity);
+        let derivative = match self.previous_error {
+            Some(previous) if dt > 0.0 => (error - previous) / dt,
+            _ => 0.0,
+        };
+        self.previous_error = Some(error);
+
+        let proportional = self.bias + self.kp * error + self.kd * derivative;
+        let integral = self.integral + error * dt;
+        let output = proportional + self.ki * integral;
+        let winding_up =
+            (output > self.max && error > 0.0) || (output < self.min && error < 0.0);
+        if !winding_up {
+            self.integral = integral;
+        }
+        (proportional + self.ki * self.integral).clamp(self.min, self.max)
+    }
+
+    fn error(&self, measurement: &Measurement) -> Option<f64> {
+        Some(self.target - measurement.get(self.quantity))
+    }
+}
+
+/// Hold the power delivered to the load at `watts`.
+///
+/// Each iteration estimates the load resistance from the readbacks and
+/// programs `V = sqrt(P·R)`, which settles in one step for resistive loads
+/// such as heaters.
+///
+/// While no load current flows the voltage is raised step by step until a
+/// load shows up, but never past
+/// [`startup_ceiling_v`](Self::startup_ceiling_v) nor, with
+/// [`min_load_ohms`](Self::min_load_ohms) set, past `sqrt(P·R_min)`, the
+/// voltage that delivers the target power into the smallest expected load.
+/// An open output is therefore held there instead of being driven to the
+/// channel maximum.
+#[derive(Debug, Clone)]
+pub struct ConstantPower {
+    pub watts: f64,
+    /// Highest voltage of the ramp while no load current flows.
+    pub startup_ceiling_v: f64,
+    /// Smallest load resistance expected.
+    pub min_load_ohms: Option<f64>,
+}
+
+impl ConstantPower {
+    pub fn new(watts: f64) -> Self {
+        Self {
+            watts,
+            startup_ceiling_v: DEFAULT_STARTUP_CEILING_V,
+            min_load_ohms: None,
+        }
+    }
+
+    pub fn startup_ceiling(mut self, volts: f64) -> Self {
+        self.startup_ceiling_v = volts;
+        self
+    }
+
+    pub fn min_load_ohms(mut self, ohms: f64) -> Self {
+        self.min_load_ohms = Some(ohms);
+        self
+    }
+
+    /// Voltage the startup ramp holds at.
+    pub fn startup_limit(&self) -> f64 {
+        let ceiling = self.startup_ceiling_v.max(0.0);
+        match self.min_load_ohms {
+            Some(ohms) => ceiling.min((self.watts * ohms).sqrt()),
+            None => ceiling,
+        }
+    }
+}
+
+impl ControlLoop for ConstantPower {
+    fn update(&mut self, measurement: &Measurement, setpoint: f64) -> f64 {
+        if measurement.current_a >= MIN_LOAD_CURRENT_A {
+            let ohms = measurement.voltage_v / measurement.current_a;
+            (self.watts * ohms).sqrt()
+        } else {
+            (setpoint + STARTUP_STEP_V).min(self.startup_limit())
+        }
+    }
+
+    fn error(&self, measurement: &Measurement) -> Option<f64> {
+        Some(measurement.power_w() - self.watts)
+    }
+}
+
+/// Emulate a source with internal resistance `ohms`.
+///
+/// The setpoint at start is taken as the open-circuit voltage `V0` and the
+/// setpoint follows `V0 - I·R`; `V0` is restored when the loop ends, also
+/// when it ends with an error. The reported error is the apparent source
+/// resistance `(V0 - V) / I` minus `ohms`.
+#[derive(Debug, Clone)]
+pub struct SourceResistance {
+    pub ohms: f64,
+    open_circuit_v: f64,
+}
+
+impl SourceResistance {
+    pub fn new(ohms: f64) -> Self {
+        Self {
+            ohms,
+            open_circuit_v: 0.0,
+        }
+    }
+}
+
+impl ControlLoop for SourceResistance {
+    fn start(&mut self, context: &LoopContext) {
+        self.open_circuit_v = context.initial_setpoint;
+    }
+
+    fn update(&mut self, measurement: &Measurement, _setpoint: f64) -> f64 {
+        (self.open_circuit_v - measurement.current_a * self.ohms).clamp(0.0, self.open_circuit_v)
+    }
+
+    fn error(&self, measurement: &Measurement) -> Option<f64> {
+        (measurement.current_a >= MIN_LOAD_CURRENT_A).then(|| {
+            (self.open_circuit_v - measurement.voltage_v) / measurement.current_a - self.ohms
+        })
+    }
+
+    fn finish(&mut self) -> Option<f64> {
+        Some(self.open_circuit_v)
+    }
+}
+
+impl Spd3303x {
+    /// Run `control` on `channel` every `options.interval` for
+    /// `options.duration`.
+    ///
+    /// Each iteration reads voltage and current through the channel's
+    /// measurement pipeline, asks the loop for the next setpoint, clamps it
+    /// to the rated range and the channel's limits, and programs it if it
+    /// changed. The output must already be on.
+    pub async fn run_control_loop<L: ControlLoop>(
+        &mut self,
+        channel: Channel,
+        control: &mut L,
+        options: ControlOptions,
+    ) -> Result<ControlReport> {
+        let fut = self.control_iterations::<_, NoFeedback>(channel, control, options, None);
+        operation::run("control_loop", fut).await
+    }
+
+    /// Like [`run_control_loop`](Self::run_control_loop), but the loop sees
+    /// `feedback` in place of the supply's own reading of that quantity.
+    ///
+    /// The safety clamp still applies to the setpoint, so a faulty sensor
+    /// reading low cannot push the output past the channel's limits.
+    pub async fn run_control_loop_with_feedback<L: ControlLoop, S: FeedbackSource>(
+        &mut self,
+        channel: Channel,
+        control: &mut L,
+        options: ControlOptions,
+        feedback: &mut Feedback<S>,
+    ) -> Result<ControlReport> {
+        if feedback.quantity == Quantity::Power {
+            return Err(anyhow!("external feedback must be a voltage or current reading"));
+        }
+        let fut = self.control_iterations(channel, control, options, Some(feedback));
+        operation::run("control_loop", fut).await
+    }
+
+    /// Hold the power delivered to the load at `watts` for `duration`; see
+    /// [`ConstantPower`].
+    ///
+    /// The current limit is left as configured, so it still caps the power
+    /// if the load shorts. Without a load the voltage stops at
+    /// [`DEFAULT_STARTUP_CEILING_V`]; run a configured [`ConstantPower`]
+    /// through [`run_control_loop`](Self::run_control_loop) to change that.
+    pub async fn hold_constant_power(
+        &mut self,
+        channel: Channel,
+        watts: f64,
+        control_interval: Duration,
+        duration: Duration,
+    ) -> Result<ControlReport> {
+        if !watts.is_finite() || watts < 0.0 {
+            return Err(anyhow!("target power must be a finite, non-negative value"));
+        }
+        let options = ControlOptions {
+            interval: control_interval,
+            duration,
+        };
+        let mut control = ConstantPower::new(watts);
+        let fut = self.control_iterations::<_, NoFeedback>(channel, &mut control, options, None);
+        operation::run("hold_constant_power", fut).await
+    }
+
+    /// Emulate a source resistance of `ohms` for `duration`, so the terminal
+    /// voltage sags with load like a battery or a long supply line; see
+    /// [`SourceResistance`].
+    pub async fn hold_constant_resistance(
+        &mut self,
+        channel: Channel,
+        ohms: f64,
+        control_interval: Duration,
+        duration: Duration,
+    ) -> Result<ControlReport> {
+        if !ohms.is_finite() || ohms < 0.0 {
+            return Err(anyhow!("source resistance must be a finite, non-negative value"));
+        }
+        let options = ControlOptions {
+            interval: control_interval,
+            duration,
+        };
+        let mut control = SourceResistance::new(ohms);
+        let fut = self.control_iterations::<_, NoFeedback>(channel, &mut control, options, None);
+        operation::run("hold_constant_resistance", fut).await
+    }
+
+    async fn control_iterations<L: ControlLoop, S: FeedbackSource>(
+        &mut self,
+        channel: Channel,
+        control: &mut L,
+        options: ControlOptions,
+        feedback: Option<&mut Feedback<S>>,
+    ) -> Result<ControlReport> {
+        let limits = self.limits(channel).ok_or(Violation::UnsupportedChannel(channel))?;
+        let actuator = control.actuator();
+        let (initial_setpoint, max) = match actuator {
+            Actuator::Voltage => (
+                self.setpoint_voltage(channel).await?,
+                limits.effective_max_voltage(self.capabilities()),
+            ),
+            Actuator::Current => (
+                self.setpoint_current(channel).await?,
+                limits.effective_max_current(self.capabilities()),
+            ),
+        };
+        control.start(&LoopContext {
+            initial_setpoint,
+            min: 0.0,
+            max,
+        });
+
+        let result = self
+            .iterate(channel, control, options, feedback, initial_setpoint, max)
+            .await;
+        if let Some(last) = control.finish() {
+            let restored = self.program(channel, actuator, last.clamp(0.0, max)).await;
+            match (&result, restored) {
+                (Ok(_), Err(e)) => return Err(e),
+                (Err(_), Err(e)) => {
+                    warn!("control loop: restoring {} failed: {e:#}", channel.label());
+                }
+                _ => {}
+            }
+        }
+        result
+    }
+
+    /// The iterations of [`control_iterations`](Self::control_iterations),
+    /// starting from `initial_setpoint`.
+    async fn iterate<L: ControlLoop, S: FeedbackSource>(
+        &mut self,
+        channel: Channel,
+        control: &mut L,
+        options: ControlOptions,
+        mut feedback: Option<&mut Feedback<S>>,
+        initial_setpoint: f64,
+        max: f64,
+    ) -> Result<ControlReport> {
+        let actuator = control.actuator();
+        let mut setpoint = initial_setpoint;
+        let mut report = ControlReport {
+            iterations: 0,
+            setpoint,
+            last_error: None,
+            max_abs_error: 0.0,
+        };
+
+        let clock = self.clock();
+        let end = clock.now() + options.duration;
+        let mut ticker = Ticker::new(clock.clone(), options.interval);
+        let mut last_tick: Option<Instant> = None;
+        while clock.now() < end {
+            let tick = ticker.tick().await;
+            let dt = last_tick.map_or(options.interval, |last| tick - last);
+            last_tick = Some(tick);
+
+            let mut measurement = Measurement {
+                voltage_v: self.measured_voltage(Some(channel)).await?,
+                current_a: self.measured_current(Some(channel)).await?,
+                dt,
+            };
+            if let Some(feedback) = feedback.as_deref_mut() {
+                let value = feedback
+                    .source
+                    .read()
+                    .await
+                    .context("failed to read external feedback")?;
+                if !value.is_finite() {
+                    return Err(anyhow!("external feedback returned {value}"));
+                }
+                match feedback.quantity {
+                    Quantity::Current => measurement.current_a = value,
+                    _ => measurement.voltage_v = value,
+                }
+            }
+            let next = control.update(&measurement, setpoint).clamp(0.0, max);
+            debug!(
+                "control loop: {} {:.3} V at {:.3} A, {:?} setpoint {:.4} -> {:.4}",
+                channel.label(),
+                measurement.voltage_v,
+                measurement.current_a,
+                actuator,
+                setpoint,
+                next
+            );
+
+            let error = control.error(&measurement);
+            if let Some(error) = error
+                && report.iterations > 0
+            {
+                report.max_abs_error = report.max_abs_error.max(error.abs());
+            }
+            report.last_error = error;
+            report.iterations += 1;
+            if next != setpoint {
+                self.program(channel, actuator, next).await?;
+                setpoint = next;
+            }
+            report.setpoint = setpoint;
+        }
+        Ok(report)
+    }
+
+    /// Program a computed setpoint, rounded to the setting resolution so
+    /// that loops also run in strict mode.
+    async fn program(&mut self, channel: Channel, actuator: Actuator, value: f64) -> Result<()> {
+        match actuator {
+            Actuator::Voltage => {
+                let volts = self.capabilities().quantize(Quantity::Voltage, value);
+                self.set_voltage(channel, volts).await
+            }
+            Actuator::Current => {
+                let amps = self.capabilities().quantize(Quantity::Current, value);
+                self.set_current(channel, amps).await
+            }
+        }
+    }
+}
@@ -0,0 +1,263 @@
+//! Characterizing the current limit against a known load.
+//!
+//! With a resistor of known value on the output, a channel with current
+//! limit `I` should go from CV to CC when the voltage setpoint passes
+//! `I·R`. [`Spd3303x::characterize_current_limit`] raises the voltage in
+//! small steps for each limit to test, notes where the instrument reports
+//! CC and reads the current it actually limits at. The resulting
+//! [`CurrentLimitTable`] maps programmed limits to actual ones and back.
+//!
+//! The load dissipates up to `(1.3·I)²·R`; size it accordingly.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use tracing::{debug, warn};
+
+use crate::instrument::{Channel, OutputState, RegulationMode, Spd3303x};
+use crate::operation;
+use crate::pipeline::Quantity;
+use crate::settle::SettleSpec;
+use crate::table::Table;
+
+/// Highest voltage tried, as a multiple of the expected `I·R`.
+const OVERDRIVE: f64 = 1.3;
+/// Lowest voltage tried, as a multiple of the expected `I·R`.
+const START: f64 = 0.8;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CurrentLimitSpec {
+    /// Resistance of the attached load.
+    pub load_ohms: f64,
+    /// Current limits to characterize.
+    pub setpoints_a: Vec<f64>,
+    pub voltage_step_v: f64,
+    /// What the current readback has to settle to after each voltage step
+    /// before the status is read.
+    pub settle: SettleSpec,
+}
+
+impl CurrentLimitSpec {
+    pub fn new(load_ohms: f64, setpoints_a: impl IntoIterator<Item = f64>) -> Self {
+        Self {
+            load_ohms,
+            setpoints_a: setpoints_a.into_iter().collect(),
+            voltage_step_v: 0.05,
+            settle: SettleSpec::current(
+                Duration::from_millis(200),
+                0.002,
+                Duration::from_secs(3),
+            ),
+        }
+    }
+
+    pub fn voltage_step(mut self, volts: f64) -> Self {
+        self.voltage_step_v = volts;
+        self
+    }
+
+    pub fn settle(mut self, settle: SettleSpec) -> Self {
+        self.settle = settle;
+        self
+    }
+
+    #[deprecated(note = "use `settle`; the dwell is now the window the current must hold still")]
+    pub fn dwell(mut self, dwell: Duration) -> Self {
+        self.settle.window = dwell;
+        self
+    }
+}
+
+/// One programmed limit and where the channel actually limited.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LimitPoint {
+    pub programmed_a: f64,
+    /// `programmed_a · R`, where CC should start.
+    pub expected_transition_v: f64,
+    /// Voltage setpoint at which CC was first reported; `None` if it was
+    /// not reached within the search range.
+    pub transition_v: Option<f64>,
+    /// Current measured once in CC.
+    pub measured_a: Option<f64>,
+}
+
+impl LimitPoint {
+    /// Actual minus programmed limit.
+    pub fn error_a(&self) -> Option<f64> {
+        Some(self.measured_a? - self.programmed_a)
+    }
+}
+
+/// Result of [`Spd3303x::characterize_current_limit`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CurrentLimitTable {
+    pub channel: Channel,
+    pub load_ohms: f64,
+    /// In the order of the spec's setpoints.
+    pub points: Vec<LimitPoint>,
+}
+
+impl CurrentLimitTable {
+    /// Measured points, sorted by programmed limit.
+    fn measured(&self) -> Vec<(f64, f64)> {
+        let mut points: Vec<(f64, f64)> = self
+            .points
+            .iter()
+            .filter_map(|point| Some((point.programmed_a, point.measured_a?)))
+            .collect();
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        points
+    }
+
+    /// Actual limit expected for `programmed_a`, interpolated linearly
+    /// between the measured points and extrapolated beyond them.
+    pub fn actual_for(&self, programmed_a: f64) -> Option<f64> {
+        interpolate(&self.measured(), programmed_a)
+    }
+
+    /// Limit to program for the channel to actually limit at `actual_a`.
+    pub fn programmed_for(&self, actual_a: f64) -> Option<f64> {
+        let mut inverse: Vec<(f64, f64)> = self
+            .measured()
+            .into_iter()
+            .map(|(programmed, actual)| (actual, programmed))
+            .collect();
+        inverse.sort_by(|a, b| a.0.total_cmp(&b.0));
+        interpolate(&inverse, actual_a)
+    }
+
+    pub fn render_table(&self) -> Table {
+        let mut table = Table::new(["Programmed", "CC at", "Expected at", "Actual", "Error"]);
+        for point in &self.points {
+            table.push_row([
+                format!("{:.3} A", point.programmed_a),
+                point
+                    .transition_v
+                    .map_or_else(|| "-".to_string(), |volts| format!("{volts:.3} V")),
+                format!("{:.3} V", point.expected_transition_v),
+                point
+                    .measured_a
+                    .map_or_else(|| "-".to_string(), |amps| format!("{amps:.3} A")),
+                point
+                    .error_a()
+                    .map_or_else(|| "-".to_string(), |amps| format!("{:+.1} mA", amps * 1e3)),
+            ]);
+        }
+        table
+    }
+}
+
+/// Piecewise-linear `y(x)` through `points` sorted by `x`.
+fn interpolate(points: &[(f64, f64)], x: f64) -> Option<f64> {
+    match points {
+        [] => None,
+        [(_, y)] => Some(*y),
+        _ => {
+            let index = points
+                .windows(2)
+                .position(|pair| x <= pair[1].0)
+                .unwrap_or(points.len() - 2);
+            let ((x0, y0), (x1, y1)) = (points[index], points[index + 1]);
+            if x1 == x0 {
+                return Some(y0);
+            }
+            Some(y0 + (x - x0) * (y1 - y0) / (x1 - x0))
+        }
+    }
+}
+
+impl Spd3303x {
+    /// Find where `channel` goes into CC for each limit of `spec`, with a
+    /// load of `spec.load_ohms` attached; see the [module docs](self).
+    ///
+    /// The output is switched on for the sweep and left off at 0 V.
+    pub async fn characterize_current_limit(
+        &mut self,
+        channel: Channel,
+        spec: &CurrentLimitSpec,
+    ) -> Result<CurrentLimitTable> {
+        if !(spec.load_ohms.is_finite() && spec.load_ohms > 0.0 && spec.voltage_step_v > 0.0) {
+            return Err(anyhow!("load resistance and voltage step must be positive"));
+        }
+        let max_v = self
+            .limits(channel)
+            .ok_or_else(|| anyhow!("{} has no programmable current limit", channel.label()))?
+            .effective_max_voltage(self.capabilities());
+        operation::run("characterize_current_limit", async {
+            self.set_voltage(channel, 0.0).await?;
+            self.set_output(channel, OutputState::On).await?;
+            let mut points = Vec::new();
+            let mut result = Ok(());
+            for &programmed_a in &spec.setpoints_a {
+                match self.find_limit(channel, spec, programmed_a, max_v).await {
+                    Ok(point) => points.push(point),
+                    Err(e) => {
+                        result = Err(e);
+                        break;
+                    }
+                }
+            }
+            if let Err(e) = self.set_voltage(channel, 0.0).await {
+                warn!("current_limit: zeroing {} failed: {e:#}", channel.label());
+            }
+            if let Err(e) = self.set_output(channel, OutputState::Off).await {
+                warn!("current_limit: switching {} off failed: {e:#}", channel.label());
+            }
+            result?;
+            Ok(CurrentLimitTable {
+                channel,
+                load_ohms: spec.load_ohms,
+                points,
+            })
+        })
+        .await
+    }
+
+    async fn find_limit(
+        &mut self,
+        channel: Channel,
+        spec: &CurrentLimitSpec,
+        programmed_a: f64,
+        max_v: f64,
+    ) -> Result<LimitPoint> {
+        let expected_v = programmed_a * spec.load_ohms;
+        let mut point = LimitPoint {
+            programmed_a,
+            expected_transition_v: expected_v,
+            transition_v: None,
+            measured_a: None,
+        };
+        self.set_current(channel, programmed_a).await?;
+        let stop_v = (expected_v * OVERDRIVE).min(max_v);
+        let mut volts = expected_v * START;
+        while volts <= stop_v {
+            let setpoint = self.capabilities().quantize(Quantity::Voltage, volts);
+            self.set_voltage(channel, setpoint).await?;
+            self.wait_for_settled(channel, spec.settle).await?;
+            let mode = self.system_status().await?.regulation_mode(channel);
+            if mode == Some(RegulationMode::ConstantCurrent) {
+                let amps = self.measure_raw(Quantity::Current, Some(channel)).await?;
+                debug!(
+                    "current_limit: {} in CC at {setpoint:.3} V, {amps:.4} A \
+                     for a {programmed_a:.3} A limit",
+                    channel.label()
+                );
+                point.transition_v = Some(setpoint);
+                point.measured_a = Some(amps);
+                break;
+            }
+            volts += spec.voltage_step_v;
+        }
+        if point.transition_v.is_none() {
+            warn!(
+                "current_limit: {} did not reach CC at {programmed_a:.3} A below {stop_v:.3} V",
+                channel.label()
+            );
+        }
+        self.set_voltage(channel, 0.0).await?;
+        Ok(point)
+    }
+}
@@ -0,0 +1,458 @@
+//! Long-running service around one instrument, as run by `spd3303xd`.
+//!
+//! [`run`] connects, installs limits and an optional failsafe state, then
+//! shares the connection through a [`Broker`] while a [`Monitor`] polls it
+//! and every non-sample event is logged. If an audit file is configured,
+//! an [`AuditTrail`] appends those events and every write sent to it.
+//!
+//! With `[http]`, the daemon also answers plain HTTP:
+//!
+//! - `GET /metrics`: the latest poll in the Prometheus text format, see
+//!   [`metrics::render`];
+//! - `GET /alarms`: the [`Alarms`](crate::alarms::Alarms) of the monitor,
+//!   as JSON with the `json` feature, else one tab-separated line each;
+//! - `POST /alarms/acknowledge`: acknowledge them all.
+//!
+//! With `[watchdog]`, every output is switched off once no poll has
+//! succeeded for `timeout_ms` and the failsafe state, if any, is applied
+//! again; [`AlarmKind::Watchdog`] is raised until polls succeed again. Both
+//! need `[monitor]`.
+//!
+//! With the `config` feature the whole setup comes from a file:
+//!
+//! ```toml
+//! audit_log = "/var/log/spd3303xd.log"
+//!
+//! [instrument]
+//! host = "192.168.1.50"
+//! command_timeout_ms = 2000
+//!
+//! [broker]
+//! listen = "127.0.0.1:5026"
+//!
+//! [limits]
+//! trip_on_measurement = true
+//!
+//! [limits.ch1]
+//! max_voltage_v = 5.0
+//!
+//! [channel_policy]
+//! denied = ["Ch3"]
+//!
+//! [[channel_policy.dependencies]]
+//! channel = "Ch2"
+//! requires = "Ch1"
+//!
+//! [monitor]
+//! interval_ms = 500
+//! debounce = { regulation_polls = 2 }
+//!
+//! [http]
+//! listen = "0.0.0.0:9330"
+//!
+//! [watchdog]
+//! timeout_ms = 5000
+//! ```
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+use crate::alarms::{Alarm, AlarmKind};
+use crate::broker::{Broker, DEFAULT_PORT};
+use crate::clock::{Clock, Ticker};
+use crate::escalation::EscalationPolicy;
+use crate::events::{Event, EventFilter, EventStream};
+use crate::failsafe::FailsafeConfig;
+use crate::instrument::{Channel, Spd3303x};
+use crate::metrics;
+use crate::middleware::AuditTrail;
+use crate::monitor::{Debounce, Monitor, MonitorConfig};
+use crate::safety::SafetyLimits;
+use crate::validate::{ChannelPolicy, Limits};
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct DaemonConfig {
+    pub instrument: InstrumentSection,
+    pub broker: BrokerSection,
+    pub limits: LimitsSection,
+    /// Channels clients may control; see [`ChannelPolicy`].
+    pub channel_policy: ChannelPolicy,
+    /// State applied once after connecting, before `limits`.
+    pub failsafe: Option<FailsafeConfig>,
+    /// Polling; `None` runs the broker alone.
+    pub monitor: Option<MonitorSection>,
+    /// File events and writes are appended to, one line each.
+    pub audit_log: Option<PathBuf>,
+    /// Metrics and alarms over HTTP; needs `monitor`.
+    pub http: Option<HttpSection>,
+    /// Outputs off when polls stop succeeding; needs `monitor`.
+    pub watchdog: Option<WatchdogSection>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct InstrumentSection {
+    pub host: String,
+    /// VXI-11 device name.
+    pub resource: String,
+    pub connect_timeout_s: u64,
+    /// Deadline of a command's first attempt, see
+    /// [`EscalationPolicy::timeout`].
+    pub command_timeout_ms: u64,
+}
+
+impl Default for InstrumentSection {
+    fn default() -> Self {
+        Self {
+            host: String::new(),
+            resource: "inst0".to_string(),
+            connect_timeout_s: 5,
+            command_timeout_ms: 2000,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct BrokerSection {
+    /// TCP address clients connect to.
+    pub listen: String,
+    /// Additionally listen on a Unix socket at this path.
+    pub unix: Option<PathBuf>,
+}
+
+impl Default for BrokerSection {
+    fn default() -> Self {
+        Self {
+            listen: format!("127.0.0.1:{DEFAULT_PORT}"),
+            unix: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct LimitsSection {
+    pub ch1: Option<Limits>,
+    pub ch2: Option<Limits>,
+    /// Have the monitor switch off a channel measuring above its limits,
+    /// see [`safety`](crate::safety).
+    pub trip_on_measurement: bool,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct MonitorSection {
+    pub interval_ms: u64,
+    pub tracking_error_threshold_v: Option<f64>,
+    pub debounce: Debounce,
+}
+
+impl Default for MonitorSection {
+    fn default() -> Self {
+        Self {
+            interval_ms: 1000,
+            tracking_error_threshold_v: None,
+            debounce: Debounce::default(),
+        }
+    }
+}
+
+/// Default port of the HTTP endpoints.
+pub const DEFAULT_HTTP_PORT: u16 = 9330;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct HttpSection {
+    pub listen: String,
+}
+
+impl Default for HttpSection {
+    fn default() -> Self {
+        Self {
+            listen: format!("127.0.0.1:{DEFAULT_HTTP_PORT}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct WatchdogSection {
+    /// Longest time without a successful poll; checked every quarter of it.
+    pub timeout_ms: u64,
+}
+
+impl Default for WatchdogSection {
+    fn default() -> Self {
+        Self { timeout_ms: 10_000 }
+    }
+}
+
+impl DaemonConfig {
+    #[cfg(feature = "config")]
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        use anyhow::Context;
+
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read daemon config {}", path.display()))?;
+        toml::from_str(&text)
+            .with_context(|| format!("failed to parse daemon config {}", path.display()))
+    }
+}
+
+/// Run the service described by `config` until the broker stops.
+pub async fn run(config: DaemonConfig) -> Result<()> {
+    if config.monitor.is_none() && (config.http.is_some() || config.watchdog.is_some()) {
+        return Err(anyhow!("the HTTP endpoints and the watchdog need a [monitor] section"));
+    }
+    let section = &config.instrument;
+    let mut inst = Spd3303x::connect_with_timeout(
+        &section.host,
+        &section.resource,
+        Duration::from_secs(section.connect_timeout_s),
+    )
+    .await?;
+    inst.set_escalation(Some(
+        EscalationPolicy::new().timeout(Duration::from_millis(section.command_timeout_ms)),
+    ));
+    inst.set_channel_policy(config.channel_policy.clone());
+    let audit = match &config.audit_log {
+        Some(path) => AuditTrail::to_file(path)?,
+        None => AuditTrail::new(),
+    };
+    inst.add_middleware(Arc::new(audit.clone()));
+    // Limits first, so that the failsafe setpoints are checked against them.
+    for (channel, limits) in [
+        (Channel::Ch1, config.limits.ch1),
+        (Channel::Ch2, config.limits.ch2),
+    ] {
+        if let Some(limits) = limits {
+            inst.set_limits(channel, limits)?;
+        }
+    }
+    if let Some(failsafe) = &config.failsafe {
+        inst.apply_failsafe_config(failsafe).await?;
+    }
+
+    let clock = inst.clock();
+    let inst = Arc::new(Mutex::new(inst));
+    let broker = Broker::from_shared(inst.clone());
+    let monitor = config.monitor.as_ref().map(|section| {
+        let monitor = Monitor::start(
+            inst.clone(),
+            MonitorConfig {
+                interval: Duration::from_millis(section.interval_ms),
+                tracking_error_threshold_v: section.tracking_error_threshold_v,
+                debounce: section.debounce,
+                safety_limits: Some(SafetyLimits {
+                    ch1: config.limits.ch1.unwrap_or_default(),
+                    ch2: config.limits.ch2.unwrap_or_default(),
+                    trip_on_measurement: config.limits.trip_on_measurement,
+                }),
+                ..MonitorConfig::default()
+            },
+        );
+        let events = monitor.events(EventFilter::default());
+        let audit = audit.clone();
+        tokio::spawn(async move {
+            if let Err(e) = log_events(events, audit).await {
+                error!("daemon: audit log stopped: {e:#}");
+            }
+        });
+        Arc::new(monitor)
+    });
+
+    if let (Some(section), Some(monitor)) = (&config.http, &monitor) {
+        let listener = TcpListener::bind(&section.listen).await?;
+        info!("daemon: serving metrics and alarms on {}", listener.local_addr()?);
+        let monitor = monitor.clone();
+        let instrument = config.instrument.host.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve_http(listener, monitor, instrument).await {
+                error!("http listener stopped: {e:#}");
+            }
+        });
+    }
+    if let (Some(section), Some(monitor)) = (&config.watchdog, &monitor) {
+        let timeout = Duration::from_millis(section.timeout_ms);
+        let failsafe = config.failsafe.clone();
+        tokio::spawn(watchdog(inst.clone(), monitor.clone(), clock, timeout, failsafe));
+    }
+
+    #[cfg(unix)]
+    if let Some(path) = &config.broker.unix {
+        // A stale socket from a previous run would make bind fail.
+        let _ = std::fs::remove_file(path);
+        let listener = tokio::net::UnixListener::bind(path)?;
+        let broker = broker.clone();
+        tokio::spawn(async move {
+            if let Err(e) = broker.serve_unix(listener).await {
+                error!("unix listener stopped: {e:#}");
+            }
+        });
+    }
+
+    let listener = TcpListener::bind(&config.broker.listen).await?;
+    broker.serve_tcp(listener).await
+}
+
+/// Log every event but samples, and append them to `audit`.
+async fn log_events(mut events: EventStream, audit: AuditTrail) -> Result<()> {
+    while let Some(event) = events.next().await {
+        if matches!(event, Event::Sample(_)) {
+            continue;
+        }
+        info!("daemon: {event:?}");
+        audit.record(&format!("{:?} {event:?}", event.severity()))?;
+    }
+    Ok(())
+}
+
+/// Switch every output off once no poll has succeeded for `timeout`, then
+/// apply `failsafe` if set, and keep [`AlarmKind::Watchdog`] raised until a
+/// poll succeeds.
+async fn watchdog(
+    inst: Arc<Mutex<Spd3303x>>,
+    monitor: Arc<Monitor>,
+    clock: Arc<dyn Clock>,
+    timeout: Duration,
+    failsafe: Option<FailsafeConfig>,
+) {
+    let started = clock.now();
+    let mut ticker = Ticker::new(clock.clone(), timeout / 4);
+    let mut tripped = false;
+    loop {
+        ticker.tick().await;
+        match monitor.latest_within(timeout) {
+            Ok(_) if tripped => {
+                info!("daemon: watchdog: polls succeed again");
+                monitor.alarms().clear(&AlarmKind::Watchdog);
+                tripped = false;
+            }
+            Ok(_) => {}
+            // The first poll gets the whole timeout too.
+            Err(e) if !tripped && clock.elapsed(started) >= timeout => {
+                error!("daemon: watchdog: {e:#}; switching every output off");
+                monitor.alarms().raise(AlarmKind::Watchdog, format!("{e:#}"));
+                tripped = true;
+                let locked = tokio::select! {
+                    inst = inst.lock() => Some(inst),
+                    () = clock.sleep(timeout) => None,
+                };
+                let Some(mut inst) = locked else {
+                    error!("daemon: watchdog: the instrument stayed busy; outputs left on");
+                    continue;
+                };
+                for channel in Channel::all() {
+                    if let Err(e) = inst.force_output_off(channel).await {
+                        warn!("daemon: watchdog: switching {} off failed: {e:#}", channel.label());
+                    }
+                }
+                if let Some(failsafe) = &failsafe
+                    && let Err(e) = inst.apply_failsafe_config(failsafe).await
+                {
+                    warn!("daemon: watchdog: applying the failsafe failed: {e:#}");
+                }
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+/// Answer HTTP requests for the metrics and alarms of `monitor`, one
+/// request per connection, until the listener fails.
+async fn serve_http(
+    listener: TcpListener,
+    monitor: Arc<Monitor>,
+    instrument: String,
+) -> Result<()> {
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let monitor = monitor.clone();
+        let instrument = instrument.clone();
+        tokio::spawn(async move {
+            if let Err(e) = answer_http(stream, &monitor, &instrument).await {
+                warn!("http: request from {peer} failed: {e:#}");
+            }
+        });
+    }
+}
+
+async fn answer_http(stream: TcpStream, monitor: &Monitor, instrument: &str) -> Result<()> {
+    let mut stream = BufReader::new(stream);
+    let mut request = String::new();
+    stream.read_line(&mut request).await?;
+    // Nothing is taken from the headers, but they have to be read.
+    let mut header = String::new();
+    while stream.read_line(&mut header).await? > 0 && !header.trim().is_empty() {
+        header.clear();
+    }
+    let mut words = request.split_whitespace();
+    let method = words.next().unwrap_or_default();
+    let path = words.next().unwrap_or_default();
+    let (status, content_type, body) = match (method, path) {
+        ("GET", "/metrics") => {
+            let latest = monitor.latest();
+            let samples = latest.as_deref().map(|sample| (instrument, sample));
+            ("200 OK", "text/plain; version=0.0.4", metrics::render(samples))
+        }
+        ("GET", "/alarms") => {
+            let (content_type, body) = render_alarms(&monitor.alarms().list());
+            ("200 OK", content_type, body)
+        }
+        ("POST", "/alarms/acknowledge") => {
+            monitor.alarms().acknowledge_all();
+            ("204 No Content", "text/plain", String::new())
+        }
+        (_, "/metrics" | "/alarms" | "/alarms/acknowledge") => {
+            ("405 Method Not Allowed", "text/plain", "method not allowed\n".to_string())
+        }
+        _ => ("404 Not Found", "text/plain", "not found\n".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    );
+    let stream = stream.get_mut();
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(feature = "json")]
+fn render_alarms(alarms: &[Alarm]) -> (&'static str, String) {
+    let body = serde_json::to_string(alarms).unwrap_or_else(|_| "[]".to_string());
+    ("application/json", body)
+}
+
+/// One line per alarm: kind, state and message, separated by tabs.
+#[cfg(not(feature = "json"))]
+fn render_alarms(alarms: &[Alarm]) -> (&'static str, String) {
+    let mut body = String::new();
+    for alarm in alarms {
+        let state = match (alarm.is_raised(), alarm.acknowledged) {
+            (true, false) => "raised",
+            (true, true) => "acknowledged",
+            (false, _) => "cleared",
+        };
+        body.push_str(&format!("{}\t{state}\t{}\n", alarm.kind, alarm.message));
+    }
+    ("text/plain", body)
+}
@@ -0,0 +1,76 @@
+//! Operator messages on the front-panel display.
+//!
+//! The documented SCPI set of the SPD3303X has no `DISPlay` commands; some
+//! firmware may accept the common `DISPlay:TEXT` form anyway. The first
+//! [`Spd3303x::show_message`] probes for it by sending the command and
+//! checking the error queue, and remembers the answer. Where it is not
+//! supported, messages are dropped with a warning so fixtures run the same
+//! on every unit.
+
+use anyhow::Result;
+use tracing::{debug, warn};
+
+use crate::instrument::Spd3303x;
+use crate::quirks::Feature;
+
+/// Error queue entries read in one go, so a device that keeps adding
+/// errors cannot hold a drain forever.
+pub(crate) const MAX_QUEUED_ERRORS: usize = 16;
+
+impl Spd3303x {
+    /// Show `text` on the display, e.g. "TEST RUNNING - DO NOT TOUCH".
+    /// Returns whether the firmware displayed it; unsupported firmware
+    /// makes this a no-op.
+    pub async fn show_message(&mut self, text: &str) -> Result<bool> {
+        self.display_text(&format!("DISPlay:TEXT \"{}\"", text.replace('"', "\"\"")))
+            .await
+    }
+
+    /// Remove a message shown with [`show_message`](Self::show_message).
+    pub async fn clear_message(&mut self) -> Result<bool> {
+        self.display_text("DISPlay:TEXT:CLEar").await
+    }
+
+    /// Whether `DISPlay:TEXT` works on this unit; `None` until probed.
+    pub fn display_text_supported(&self) -> Option<bool> {
+        self.display_support()
+    }
+
+    async fn display_text(&mut self, command: &str) -> Result<bool> {
+        self.gate(Feature::DisplayText)?;
+        match self.display_support() {
+            Some(true) => {
+                self.write_raw(command).await?;
+                Ok(true)
+            }
+            Some(false) => {
+                debug!("display: text not supported, dropped {command:?}");
+                Ok(false)
+            }
+            None => {
+                let supported = self.probe_display_text(command).await?;
+                self.set_display_support(supported);
+                if !supported {
+                    warn!("display: firmware does not accept DISPlay:TEXT; messages are dropped");
+                }
+                Ok(supported)
+            }
+        }
+    }
+
+    /// Send `command` and tell from the error queue whether it was accepted.
+    async fn probe_display_text(&mut self, command: &str) -> Result<bool> {
+        for _ in 0..MAX_QUEUED_ERRORS {
+            if is_no_error(&self.system_error().await?) {
+                break;
+            }
+        }
+        self.write_raw(command).await?;
+        Ok(is_no_error(&self.system_error().await?))
+    }
+}
+
+/// `SYST:ERR?` replies `0, No Error` (or just `0`) when the queue is empty.
+pub(crate) fn is_no_error(reply: &str) -> bool {
+    reply.trim().split(',').next().map(str::trim) == Some("0")
+}
@@ -0,0 +1,313 @@
+//! Typed errors for callers that need to tell failures apart.
+//!
+//! The crate's functions return `anyhow::Result`: the typed error of the
+//! failure (a [`Violation`], a [`CommandTimedOut`], ...) sits under the
+//! context added on the way up, and the context is what ends up in logs.
+//! Moving every signature to a typed result would drop that context at
+//! each public call, so the functions keep `anyhow::Result` and
+//! [`Spd3303xError`] is a view of their errors instead: it collects the
+//! typed errors in one enum, so a caller can match on the failure mode.
+//! [`Spd3303xError::classify`] leaves the original error in place for its
+//! message:
+//!
+//! ```ignore
+//! if let Err(e) = inst.set_voltage(Channel::Ch1, 5.0).await {
+//!     match Spd3303xError::classify(&e) {
+//!         Some(Spd3303xError::Timeout(timeout)) => retry_later(timeout),
+//!         Some(Spd3303xError::Validation(violation)) => reject(violation),
+//!         _ => return Err(e),
+//!     }
+//! }
+//! ```
+//!
+//! `Spd3303xError::from(error)` takes the error instead; it keeps the
+//! outermost typed error of the chain and drops the context messages around
+//! it, and an error of none of the known types becomes
+//! [`Spd3303xError::Other`] unchanged. The same table sorts failures into
+//! the exit codes of the command-line tools ([`FailureKind`]).
+//! `Spd3303xError` is a `std::error::Error`, so `?` turns it back into an
+//! `anyhow::Error`.
+
+use std::fmt;
+
+use anyhow::Result;
+
+use crate::alarms::SafetyTrip;
+use crate::conflict::ControlConflict;
+use crate::escalation::CommandTimedOut;
+use crate::exit::FailureKind;
+use crate::instrument::{Channel, Spd3303x};
+use crate::monitor::StaleData;
+use crate::parse::ParseError;
+use crate::quirks::FirmwareUnsupported;
+use crate::reconnect::ReconnectFailed;
+use crate::response::{EmptyResponse, UnexpectedResponse};
+use crate::safety::LimitExceeded;
+use crate::snapshot::StateMismatch;
+use crate::training::Declined;
+use crate::validate::Violation;
+
+#[derive(Debug)]
+pub enum Spd3303xError {
+    /// The link failed: connection refused or reset, device node gone, ...
+    Io(std::io::Error),
+    /// A command got no reply in time.
+    Timeout(CommandTimedOut),
+    /// Every reconnect attempt failed.
+    ReconnectFailed(ReconnectFailed),
+    /// A reply could not be parsed.
+    Parse(ParseError),
+    /// A reply did not have its registered shape.
+    UnexpectedResponse(UnexpectedResponse),
+    /// A query was answered with nothing.
+    EmptyResponse(EmptyResponse),
+    /// The command is not available on this channel, e.g. a setpoint on
+    /// CH3.
+    UnsupportedChannel(Channel),
+    /// An argument was refused before anything was sent.
+    Validation(Violation),
+    /// The feature is known to be broken on the connected firmware.
+    FirmwareUnsupported(FirmwareUnsupported),
+    /// The command was declined in training mode and not sent.
+    Declined(Declined),
+    /// The instrument queued an error.
+    Device(DeviceError),
+    /// The instrument is not in the expected state.
+    StateMismatch(StateMismatch),
+    /// Another session is controlling the instrument.
+    ControlConflict(ControlConflict),
+    /// Monitor data is too old to act on.
+    StaleData(StaleData),
+    /// A reading exceeded the safety limits.
+    LimitExceeded(LimitExceeded),
+    /// An alarm stopped the operation.
+    SafetyTrip(SafetyTrip),
+    Other(anyhow::Error),
+}
+
+impl Spd3303xError {
+    /// The exit code category of the command-line tools.
+    pub fn kind(&self) -> FailureKind {
+        match self {
+            Spd3303xError::UnsupportedChannel(_)
+            | Spd3303xError::Validation(_)
+            | Spd3303xError::FirmwareUnsupported(_)
+            | Spd3303xError::Declined(_) => FailureKind::Validation,
+            Spd3303xError::Io(_)
+            | Spd3303xError::Timeout(_)
+            | Spd3303xError::ReconnectFailed(_)
+            | Spd3303xError::StaleData(_) => FailureKind::Transport,
+            Spd3303xError::Parse(_)
+            | Spd3303xError::UnexpectedResponse(_)
+            | Spd3303xError::EmptyResponse(_)
+            | Spd3303xError::Device(_)
+            | Spd3303xError::StateMismatch(_)
+            | Spd3303xError::ControlConflict(_) => FailureKind::Device,
+            Spd3303xError::LimitExceeded(_) | Spd3303xError::SafetyTrip(_) => {
+                FailureKind::SafetyTrip
+            }
+            Spd3303xError::Other(e) => FailureKind::classify(e),
+        }
+    }
+
+    /// `cause` as a variant if it is one of the crate's error types, an I/O
+    /// error or a tokio timeout. The one table of error types behind both
+    /// the conversion from `anyhow::Error` and [`FailureKind::classify`].
+    pub(crate) fn of(cause: &(dyn std::error::Error + 'static)) -> Option<Self> {
+        typed(cause)
+            .map(Spd3303xError::from_violation)
+            .or_else(|| typed(cause).map(Spd3303xError::Timeout))
+            .or_else(|| typed(cause).map(Spd3303xError::ReconnectFailed))
+            .or_else(|| typed(cause).map(Spd3303xError::Parse))
+            .or_else(|| typed(cause).map(Spd3303xError::UnexpectedResponse))
+            .or_else(|| typed(cause).map(Spd3303xError::EmptyResponse))
+            .or_else(|| typed(cause).map(Spd3303xError::FirmwareUnsupported))
+            .or_else(|| typed(cause).map(Spd3303xError::Declined))
+            .or_else(|| typed(cause).map(Spd3303xError::Device))
+            .or_else(|| typed(cause).map(Spd3303xError::StateMismatch))
+            .or_else(|| typed(cause).map(Spd3303xError::ControlConflict))
+            .or_else(|| typed(cause).map(Spd3303xError::StaleData))
+            .or_else(|| typed(cause).map(Spd3303xError::LimitExceeded))
+            .or_else(|| typed(cause).map(Spd3303xError::SafetyTrip))
+            .or_else(|| {
+                // Not `Clone`; a copy keeps the kind and the message.
+                let e = cause.downcast_ref::<std::io::Error>()?;
+                Some(Spd3303xError::Io(std::io::Error::new(e.kind(), e.to_string())))
+            })
+            .or_else(|| {
+                cause.is::<tokio::time::error::Elapsed>().then(|| {
+                    Spd3303xError::Io(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        cause.to_string(),
+                    ))
+                })
+            })
+    }
+
+    /// The outermost typed error in the chain of `error`, leaving `error`
+    /// with its context to the caller; `None` if it holds none of the
+    /// known types.
+    pub fn classify(error: &anyhow::Error) -> Option<Self> {
+        error.chain().find_map(|cause| match cause.downcast_ref::<Spd3303xError>() {
+            Some(Spd3303xError::UnsupportedChannel(channel)) => {
+                Some(Spd3303xError::UnsupportedChannel(*channel))
+            }
+            Some(Spd3303xError::Other(_)) => None,
+            Some(typed) => Spd3303xError::of(typed.inner()?),
+            None => Spd3303xError::of(cause),
+        })
+    }
+
+    /// The wrapped error of a typed variant.
+    fn inner(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(match self {
+            Spd3303xError::Io(e) => e,
+            Spd3303xError::Timeout(e) => e,
+            Spd3303xError::ReconnectFailed(e) => e,
+            Spd3303xError::Parse(e) => e,
+            Spd3303xError::UnexpectedResponse(e) => e,
+            Spd3303xError::EmptyResponse(e) => e,
+            Spd3303xError::Validation(e) => e,
+            Spd3303xError::FirmwareUnsupported(e) => e,
+            Spd3303xError::Declined(e) => e,
+            Spd3303xError::Device(e) => e,
+            Spd3303xError::StateMismatch(e) => e,
+            Spd3303xError::ControlConflict(e) => e,
+            Spd3303xError::StaleData(e) => e,
+            Spd3303xError::LimitExceeded(e) => e,
+            Spd3303xError::SafetyTrip(e) => e,
+            Spd3303xError::UnsupportedChannel(_) | Spd3303xError::Other(_) => return None,
+        })
+    }
+
+    /// Back to an `anyhow::Error`, unwrapping [`Other`](Self::Other).
+    pub fn into_anyhow(self) -> anyhow::Error {
+        match self {
+            Spd3303xError::Other(e) => e,
+            e => e.into(),
+        }
+    }
+
+    fn from_violation(violation: Violation) -> Self {
+        match violation {
+            Violation::UnsupportedChannel(channel) => Spd3303xError::UnsupportedChannel(channel),
+            violation => Spd3303xError::Validation(violation),
+        }
+    }
+}
+
+impl fmt::Display for Spd3303xError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Spd3303xError::Io(e) => write!(f, "{e}"),
+            Spd3303xError::Timeout(e) => write!(f, "{e}"),
+            Spd3303xError::ReconnectFailed(e) => write!(f, "{e}"),
+            Spd3303xError::Parse(e) => write!(f, "{e}"),
+            Spd3303xError::UnexpectedResponse(e) => write!(f, "{e}"),
+            Spd3303xError::EmptyResponse(e) => write!(f, "{e}"),
+            Spd3303xError::UnsupportedChannel(channel) => {
+                write!(f, "{}", Violation::UnsupportedChannel(*channel))
+            }
+            Spd3303xError::Validation(e) => write!(f, "{e}"),
+            Spd3303xError::FirmwareUnsupported(e) => write!(f, "{e}"),
+            Spd3303xError::Declined(e) => write!(f, "{e}"),
+            Spd3303xError::Device(e) => write!(f, "{e}"),
+            Spd3303xError::StateMismatch(e) => write!(f, "{e}"),
+            Spd3303xError::ControlConflict(e) => write!(f, "{e}"),
+            Spd3303xError::StaleData(e) => write!(f, "{e}"),
+            Spd3303xError::LimitExceeded(e) => write!(f, "{e}"),
+            Spd3303xError::SafetyTrip(e) => write!(f, "{e}"),
+            Spd3303xError::Other(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for Spd3303xError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Spd3303xError::Io(e) => std::error::Error::source(e),
+            Spd3303xError::Other(e) => e.chain().nth(1),
+            _ => None,
+        }
+    }
+}
+
+impl From<anyhow::Error> for Spd3303xError {
+    fn from(error: anyhow::Error) -> Self {
+        let error = match error.downcast::<Spd3303xError>() {
+            Ok(error) => return error,
+            Err(error) => error,
+        };
+        let typed = error.chain().find_map(Spd3303xError::of);
+        typed.unwrap_or(Spd3303xError::Other(error))
+    }
+}
+
+impl From<std::io::Error> for Spd3303xError {
+    fn from(error: std::io::Error) -> Self {
+        Spd3303xError::Io(error)
+    }
+}
+
+impl From<Violation> for Spd3303xError {
+    fn from(violation: Violation) -> Self {
+        Spd3303xError::from_violation(violation)
+    }
+}
+
+/// A copy of `cause` if it is a `T`.
+fn typed<T: std::error::Error + Clone + 'static>(
+    cause: &(dyn std::error::Error + 'static),
+) -> Option<T> {
+    cause.downcast_ref::<T>().cloned()
+}
+
+/// An entry of the instrument's error queue, as read by `SYSTem:ERRor?`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceError {
+    /// SCPI error code, negative for the standard ones.
+    pub code: i32,
+    pub message: String,
+}
+
+impl DeviceError {
+    /// Parse a `<code>, <message>` reply; `None` for `0, No Error` and
+    /// replies without a code.
+    pub fn parse(reply: &str) -> Option<Self> {
+        let (code, message) = reply.trim().split_once(',').unwrap_or((reply.trim(), ""));
+        let code: i32 = code.trim().parse().ok()?;
+        if code == 0 {
+            return None;
+        }
+        Some(Self {
+            code,
+            message: message.trim().trim_matches('"').to_string(),
+        })
+    }
+}
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "instrument reported error {}: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for DeviceError {}
+
+impl Spd3303x {
+    /// Pop the oldest entry of the instrument's error queue, `None` if it
+    /// is empty.
+    pub async fn next_device_error(&mut self) -> Result<Option<DeviceError>> {
+        Ok(DeviceError::parse(&self.system_error().await?))
+    }
+
+    /// Fail with the oldest queued [`DeviceError`], if any, e.g. after a
+    /// batch of writes to find out whether the instrument accepted them.
+    pub async fn ensure_no_device_error(&mut self) -> Result<()> {
+        match self.next_device_error().await? {
+            Some(error) => Err(error.into()),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,167 @@
+//! Timeout escalation for SCPI commands.
+//!
+//! Without a policy a command waits as long as the link lets it, which for
+//! a wedged instrument can be forever. With an [`EscalationPolicy`]
+//! installed through `Spd3303x::set_escalation`, every command, and so
+//! every high-level operation, climbs a ladder on timeouts:
+//!
+//! 1. the first timeout clears the link and retries the command with the
+//!    longer [`retry_timeout`](EscalationPolicy::retry_timeout);
+//! 2. the second clears the link again and tries a last time;
+//! 3. the third marks the connection unhealthy, reconnects if enabled and
+//!    fails with [`CommandTimedOut`].
+//!
+//! The clear before each retry drops the reply the abandoned attempt may
+//! still get, which the retry would otherwise read as its own. With
+//! [`clear`](EscalationPolicy::clear) off, queries are therefore not
+//! retried, only writes; a failed clear ends the ladder early.
+//!
+//! Clearing is a GPIB Selected Device Clear on Prologix links. The VXI-11
+//! client exposes no `device_abort`/`device_clear`, so there, as on the
+//! other links, the connection is reopened instead, which drops the stuck
+//! transaction.
+//!
+//! A retried write is sent again; the SPD3303X commands are all safe to
+//! repeat.
+//!
+//! Without the ladder, plain [`CommandTimeouts`] bound each write and query
+//! with one deadline and fail with [`CommandTimedOut`] right away. A query
+//! abandoned this way may still be answered later, so the link is cleared
+//! or reopened as on the ladder before the error is returned. If that
+//! fails too, the connection is marked unhealthy and the next command
+//! tries again first, failing without being sent until the link is back
+//! in step.
+
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct EscalationPolicy {
+    /// Deadline of the first attempt.
+    pub timeout: Duration,
+    /// Deadline of the second and third attempt.
+    pub retry_timeout: Duration,
+    /// Clear the link before each retry.
+    pub clear: bool,
+    /// Reconnect after the third timeout, and before the next command
+    /// while the connection is unhealthy.
+    pub reconnect: bool,
+}
+
+impl Default for EscalationPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(2),
+            retry_timeout: Duration::from_secs(5),
+            clear: true,
+            reconnect: true,
+        }
+    }
+}
+
+impl EscalationPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn retry_timeout(mut self, timeout: Duration) -> Self {
+        self.retry_timeout = timeout;
+        self
+    }
+
+    pub fn clear(mut self, clear: bool) -> Self {
+        self.clear = clear;
+        self
+    }
+
+    pub fn reconnect(mut self, reconnect: bool) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    /// Deadlines of the three attempts.
+    pub(crate) fn deadlines(&self) -> [Duration; 3] {
+        [self.timeout, self.retry_timeout, self.retry_timeout]
+    }
+}
+
+/// Deadlines of single commands on a handle without an escalation ladder,
+/// set with `Spd3303x::set_command_timeouts`. `None` waits as long as the
+/// link does.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct CommandTimeouts {
+    pub write: Option<Duration>,
+    pub query: Option<Duration>,
+}
+
+impl CommandTimeouts {
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    /// The same deadline for writes and queries.
+    pub fn uniform(timeout: Duration) -> Self {
+        Self {
+            write: Some(timeout),
+            query: Some(timeout),
+        }
+    }
+
+    pub fn write(mut self, timeout: Option<Duration>) -> Self {
+        self.write = timeout;
+        self
+    }
+
+    pub fn query(mut self, timeout: Option<Duration>) -> Self {
+        self.query = timeout;
+        self
+    }
+
+    pub(crate) fn deadline(&self, reply: bool) -> Option<Duration> {
+        if reply { self.query } else { self.write }
+    }
+}
+
+/// A command timed out: on every rung of the ladder, or once under
+/// [`CommandTimeouts`].
+#[derive(Debug, Clone)]
+pub struct CommandTimedOut {
+    /// The command, redacted.
+    pub command: String,
+    pub attempts: u32,
+    /// Sum of the deadlines of all attempts.
+    pub waited: Duration,
+}
+
+impl CommandTimedOut {
+    /// A single attempt at `command` that gave up after `waited`.
+    pub(crate) fn once(command: &str, waited: Duration) -> Self {
+        Self {
+            command: command.trim_end_matches(['\r', '\n']).to_string(),
+            attempts: 1,
+            waited,
+        }
+    }
+}
+
+impl fmt::Display for CommandTimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.attempts <= 1 {
+            return write!(f, "{:?} timed out after {:?}", self.command, self.waited);
+        }
+        write!(
+            f,
+            "{:?} timed out {} times ({:?} in total); connection marked unhealthy",
+            self.command, self.attempts, self.waited
+        )
+    }
+}
+
+impl std::error::Error for CommandTimedOut {}
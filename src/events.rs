@@ -0,0 +1,218 @@
+//! Typed events published by background tasks such as the
+//! [`Monitor`](crate::monitor::Monitor).
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::broadcast;
+use tracing::warn;
+
+use crate::instrument::{Channel, RegulationMode};
+use crate::monitor::MonitorSample;
+use crate::safety::LimitExceeded;
+use crate::timer_capture::TimerProgress;
+
+const DEFAULT_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Severity {
+    Info,
+    Warn,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EventClass {
+    /// Periodic readings.
+    Measurement,
+    /// CV/CC transitions.
+    Regulation,
+    /// Output enable changes.
+    Output,
+    /// Communication with the instrument.
+    Link,
+}
+
+#[derive(Debug, Clone)]
+pub enum Event {
+    Sample(Arc<MonitorSample>),
+    RegulationChanged {
+        channel: Channel,
+        mode: RegulationMode,
+    },
+    OutputChanged {
+        channel: Channel,
+        on: bool,
+    },
+    PollFailed {
+        error: String,
+    },
+    /// The monitor slowed down to `interval` because polls fail or are slow.
+    Degraded {
+        interval: Duration,
+        reason: String,
+    },
+    /// Polls are healthy again; back at the configured `interval`.
+    Recovered {
+        interval: Duration,
+    },
+    /// Set minus measured voltage of a channel in CV rose above the
+    /// configured threshold, e.g. from IR drop in a failing cable.
+    TrackingErrorExceeded {
+        channel: Channel,
+        error_v: f64,
+        threshold_v: f64,
+    },
+    /// The tracking error is back below the threshold, or no longer
+    /// defined because the channel left CV or its output went off; `error_v`
+    /// is `None` then.
+    TrackingErrorCleared {
+        channel: Channel,
+        error_v: Option<f64>,
+    },
+    /// Outputs or timers that were on are all off after polls failed, as
+    /// after a reboot; see [`uptime`](crate::uptime).
+    PowerCycleSuspected,
+    /// A reading exceeded the monitor's
+    /// [`SafetyLimits`](crate::safety::SafetyLimits) and the channel was
+    /// switched off, unless `switched_off` is false because that failed.
+    SafetyLimitExceeded {
+        exceeded: LimitExceeded,
+        switched_off: bool,
+    },
+    /// Estimated position of a timer this handle started, published with
+    /// each poll while the timer is on.
+    TimerProgress(TimerProgress),
+}
+
+impl Event {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Event::Sample(_) => Severity::Info,
+            Event::RegulationChanged {
+                mode: RegulationMode::ConstantCurrent,
+                ..
+            } => Severity::Warn,
+            Event::RegulationChanged { .. } => Severity::Info,
+            Event::OutputChanged { .. } => Severity::Info,
+            Event::PollFailed { .. } => Severity::Error,
+            Event::Degraded { .. } => Severity::Warn,
+            Event::Recovered { .. } => Severity::Info,
+            Event::TrackingErrorExceeded { .. } => Severity::Warn,
+            Event::TrackingErrorCleared { .. } => Severity::Info,
+            Event::PowerCycleSuspected => Severity::Warn,
+            Event::SafetyLimitExceeded { .. } => Severity::Error,
+            Event::TimerProgress(_) => Severity::Info,
+        }
+    }
+
+    pub fn class(&self) -> EventClass {
+        match self {
+            Event::Sample(_)
+            | Event::TrackingErrorExceeded { .. }
+            | Event::TrackingErrorCleared { .. }
+            | Event::TimerProgress(_) => EventClass::Measurement,
+            Event::RegulationChanged { .. } => EventClass::Regulation,
+            Event::OutputChanged { .. } | Event::SafetyLimitExceeded { .. } => EventClass::Output,
+            Event::PollFailed { .. }
+            | Event::Degraded { .. }
+            | Event::Recovered { .. }
+            | Event::PowerCycleSuspected => EventClass::Link,
+        }
+    }
+}
+
+/// Which events an [`EventStream`] yields. The default passes everything.
+#[derive(Debug, Clone)]
+pub struct EventFilter {
+    min_severity: Severity,
+    classes: Vec<EventClass>,
+}
+
+impl Default for EventFilter {
+    fn default() -> Self {
+        Self {
+            min_severity: Severity::Info,
+            classes: Vec::new(),
+        }
+    }
+}
+
+impl EventFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn min_severity(mut self, severity: Severity) -> Self {
+        self.min_severity = severity;
+        self
+    }
+
+    /// Restrict to the given class; may be called several times. Without
+    /// any call, all classes pass.
+    pub fn class(mut self, class: EventClass) -> Self {
+        self.classes.push(class);
+        self
+    }
+
+    pub fn matches(&self, event: &Event) -> bool {
+        event.severity() >= self.min_severity
+            && (self.classes.is_empty() || self.classes.contains(&event.class()))
+    }
+}
+
+/// Fan-out of events to any number of subscribers.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    tx: broadcast::Sender<Event>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl EventBus {
+    /// `capacity` events are buffered per subscriber; slower subscribers
+    /// skip the oldest ones.
+    pub fn new(capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity);
+        Self { tx }
+    }
+
+    pub fn publish(&self, event: Event) {
+        // No subscribers is not an error.
+        let _ = self.tx.send(event);
+    }
+
+    pub fn subscribe(&self, filter: EventFilter) -> EventStream {
+        EventStream {
+            rx: self.tx.subscribe(),
+            filter,
+        }
+    }
+}
+
+pub struct EventStream {
+    rx: broadcast::Receiver<Event>,
+    filter: EventFilter,
+}
+
+impl EventStream {
+    /// Next event passing the filter, or `None` once the publisher is gone.
+    pub async fn next(&mut self) -> Option<Event> {
+        loop {
+            match self.rx.recv().await {
+                Ok(event) if self.filter.matches(&event) => return Some(event),
+                Ok(_) => continue,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("event subscriber lagged, {skipped} events skipped");
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
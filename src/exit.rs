@@ -0,0 +1,113 @@
+//! Exit codes and error output of the command-line tools.
+//!
+//! The tools exit with a code that tells what kind of failure ended them,
+//! so scripts can branch on it without parsing messages:
+//!
+//! | Code | Kind | Meaning |
+//! |------|------|---------|
+//! | 0 | | success |
+//! | 1 | `other` | a failure not classified below |
+//! | 2 | `validation` | a bad argument or setpoint, refused before anything was sent |
+//! | 3 | `transport` | the link failed or timed out |
+//! | 4 | `device` | the instrument reported an error or replied unexpectedly |
+//! | 5 | `safety_trip` | stopped because an alarm was raised |
+//!
+//! These codes are stable. Command-line usage errors also exit with 2.
+//!
+//! The kind is taken from the first error in the chain that is one of the
+//! crate's error types, or an I/O error, as [`Spd3303xError::kind`] sorts
+//! them. Errors that only carry a message are `other`.
+
+use std::fmt;
+use std::process::ExitCode;
+
+use crate::error::Spd3303xError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum FailureKind {
+    Other,
+    Validation,
+    Transport,
+    Device,
+    SafetyTrip,
+}
+
+impl FailureKind {
+    pub fn classify(error: &anyhow::Error) -> Self {
+        error.chain().find_map(Self::of).unwrap_or(FailureKind::Other)
+    }
+
+    fn of(cause: &(dyn std::error::Error + 'static)) -> Option<Self> {
+        let kind = match cause.downcast_ref::<Spd3303xError>() {
+            Some(error) => error.kind(),
+            None => Spd3303xError::of(cause)?.kind(),
+        };
+        Some(kind).filter(|kind| *kind != FailureKind::Other)
+    }
+
+    pub fn code(self) -> u8 {
+        match self {
+            FailureKind::Other => 1,
+            FailureKind::Validation => 2,
+            FailureKind::Transport => 3,
+            FailureKind::Device => 4,
+            FailureKind::SafetyTrip => 5,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FailureKind::Other => "other",
+            FailureKind::Validation => "validation",
+            FailureKind::Transport => "transport",
+            FailureKind::Device => "device",
+            FailureKind::SafetyTrip => "safety_trip",
+        }
+    }
+
+    pub fn exit_code(self) -> ExitCode {
+        ExitCode::from(self.code())
+    }
+}
+
+impl fmt::Display for FailureKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A failure as the tools report it with `--error-format json`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ErrorReport {
+    pub kind: FailureKind,
+    pub code: u8,
+    /// The outermost message.
+    pub message: String,
+    /// The underlying causes, outermost first.
+    pub causes: Vec<String>,
+}
+
+impl ErrorReport {
+    pub fn new(error: &anyhow::Error) -> Self {
+        let kind = FailureKind::classify(error);
+        Self {
+            kind,
+            code: kind.code(),
+            message: error.to_string(),
+            causes: error.chain().skip(1).map(ToString::to_string).collect(),
+        }
+    }
+
+    /// One line, as the tools print by default.
+    pub fn render_text(&self) -> String {
+        let mut text = format!("error ({}): {}", self.kind, self.message);
+        for cause in &self.causes {
+            text.push_str(": ");
+            text.push_str(cause);
+        }
+        text
+    }
+}
@@ -0,0 +1,163 @@
+//! Known-safe instrument configuration for unattended controllers.
+//!
+//! A failsafe file describes the state the supply must fall back to at
+//! service start or after an unrecoverable error, e.g.:
+//!
+//! ```toml
+//! track_mode = "Independent"
+//!
+//! [ch1]
+//! voltage_v = 0.0
+//! current_a = 0.1
+//! limits = { max_voltage_v = 5.0, max_current_a = 0.5 }
+//!
+//! [ch2]
+//! output = false
+//! ```
+//!
+//! Channels that are not listed are left at 0 V / 0 A with the output off.
+
+use anyhow::{anyhow, Result};
+use tracing::{debug, warn};
+
+use crate::instrument::{Channel, OutputState, Spd3303x, TimerState, TrackMode};
+use crate::operation;
+use crate::validate::Limits;
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct FailsafeConfig {
+    pub track_mode: Option<TrackMode>,
+    pub ch1: FailsafeChannel,
+    pub ch2: FailsafeChannel,
+    pub ch3_output: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct FailsafeChannel {
+    pub voltage_v: f64,
+    pub current_a: f64,
+    /// Whether the output is left ON after applying. Defaults to OFF.
+    pub output: bool,
+    /// Host-side limits installed with `Spd3303x::set_limits`; without,
+    /// the limits already installed stay in place and bound the setpoints.
+    pub limits: Option<Limits>,
+}
+
+impl FailsafeConfig {
+    #[cfg(feature = "config")]
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        use anyhow::Context;
+
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read failsafe file {}", path.display()))?;
+        toml::from_str(&text)
+            .with_context(|| format!("failed to parse failsafe file {}", path.display()))
+    }
+}
+
+impl Spd3303x {
+    /// Load a failsafe file and apply it; see [`apply_failsafe_config`](Self::apply_failsafe_config).
+    #[cfg(feature = "config")]
+    pub async fn apply_failsafe(&mut self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        let config = FailsafeConfig::load(path)?;
+        self.apply_failsafe_config(&config).await
+    }
+
+    /// Drive the instrument into the state described by `config`.
+    ///
+    /// All outputs are switched off and timers disabled first, then track
+    /// mode, limits and setpoints are applied, and only then are the
+    /// requested outputs enabled. Channels the channel policy denies are
+    /// left alone, and so is the track mode if it denies CH1 or CH2. Every
+    /// step is attempted even if an earlier one fails, because a partially
+    /// applied failsafe is still better than none; the first error is
+    /// returned at the end. The one exception is enabling an output: a
+    /// channel whose output off, limits or setpoints failed (CH1 and CH2
+    /// also after a failed or skipped track mode) is left off.
+    pub async fn apply_failsafe_config(&mut self, config: &FailsafeConfig) -> Result<()> {
+        operation::run("apply_failsafe", self.apply_failsafe_steps(config)).await
+    }
+
+    async fn apply_failsafe_steps(&mut self, config: &FailsafeConfig) -> Result<()> {
+        let mut errors = Vec::new();
+        let mut note = |step: &str, result: Result<()>| match result {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("failsafe: {step} failed: {e:#}");
+                errors.push(anyhow!("failsafe: {step} failed: {e:#}"));
+                false
+            }
+        };
+        // Channels not to switch on because an earlier step failed.
+        let mut unsafe_channels: Vec<Channel> = Vec::new();
+
+        debug!("failsafe: turning all outputs OFF");
+        let controlled: Vec<Channel> = Channel::all()
+            .filter(|ch| self.channel_policy().permits(*ch))
+            .collect();
+        for &channel in &controlled {
+            if !note("output off", self.set_output(channel, OutputState::Off).await) {
+                unsafe_channels.push(channel);
+            }
+        }
+        for channel in Channel::programmable().filter(|ch| controlled.contains(ch)) {
+            note("timer off", self.timer_state(channel, TimerState::Off).await);
+        }
+
+        if let Some(mode) = config.track_mode {
+            // The track mode couples CH1 and CH2, so it needs both.
+            let track_mode = if Channel::programmable().all(|ch| controlled.contains(&ch)) {
+                note("track mode", self.set_track_mode(mode).await)
+            } else {
+                warn!("failsafe: track mode left as is, the channel policy denies a channel");
+                false
+            };
+            if !track_mode {
+                unsafe_channels.extend(Channel::programmable());
+            }
+        }
+
+        for (channel, settings) in [(Channel::Ch1, &config.ch1), (Channel::Ch2, &config.ch2)] {
+            if !controlled.contains(&channel) {
+                continue;
+            }
+            let limits = match settings.limits {
+                Some(limits) => note("limits", self.set_limits(channel, limits)),
+                None => true,
+            };
+            let voltage = note("voltage", self.set_voltage(channel, settings.voltage_v).await);
+            let current = note("current", self.set_current(channel, settings.current_a).await);
+            if !(limits && voltage && current) {
+                unsafe_channels.push(channel);
+            }
+        }
+
+        for (channel, on) in [
+            (Channel::Ch1, config.ch1.output),
+            (Channel::Ch2, config.ch2.output),
+            (Channel::Ch3, config.ch3_output),
+        ] {
+            if !on || !controlled.contains(&channel) {
+                continue;
+            }
+            if unsafe_channels.contains(&channel) {
+                warn!("failsafe: leaving {} off after the failed steps", channel.label());
+            } else {
+                note("output on", self.set_output(channel, OutputState::On).await);
+            }
+        }
+
+        match errors.into_iter().next() {
+            Some(e) => Err(e),
+            None => {
+                debug!("failsafe: applied");
+                Ok(())
+            }
+        }
+    }
+}
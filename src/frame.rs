@@ -0,0 +1,258 @@
+//! Fixed-size frames of equally spaced readings, for ripple and
+//! periodicity analysis off the instrument.
+//!
+//! [`Spd3303x::capture_frame`] reads a channel's voltage and current every
+//! `interval` until it has `n_samples` of them. [`Spd3303x::frames`] yields
+//! such frames back to back on one schedule, so consecutive frames join up
+//! into one long capture with no gap between them.
+//!
+//! Each reading is two SCPI round trips, which bounds the useful rate to a
+//! few tens of samples per second; anything faster than the link is
+//! aliased, not resolved. Samples are taken on a fixed schedule, and the
+//! actual offset of each is kept, so the spacing can be checked with
+//! [`MeasurementFrame::max_jitter`] before treating the frame as uniform.
+//!
+//! For numpy, [`MeasurementFrame::to_npy`] writes an `(n, 3)` float64
+//! array of offset (s), voltage (V) and current (A):
+//!
+//! ```text
+//! frame = numpy.load("ch1.npy")
+//! volts = frame[:, 1]
+//! ```
+
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, Result};
+
+use crate::clock::Ticker;
+use crate::instrument::{Channel, Spd3303x};
+use crate::operation;
+use crate::pipeline::Quantity;
+
+/// `n` readings of one channel taken `interval` apart.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MeasurementFrame {
+    pub channel: Channel,
+    pub started_at: SystemTime,
+    /// Intended spacing of the samples.
+    pub interval: Duration,
+    /// Time from the start of the frame to each sample, as taken.
+    pub offsets: Vec<Duration>,
+    pub voltage_v: Vec<f64>,
+    pub current_a: Vec<f64>,
+}
+
+impl MeasurementFrame {
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Intended sample rate in Hz.
+    pub fn sample_rate_hz(&self) -> f64 {
+        1.0 / self.interval.as_secs_f64()
+    }
+
+    /// `V·I` of each sample.
+    pub fn power_w(&self) -> Vec<f64> {
+        self.voltage_v
+            .iter()
+            .zip(&self.current_a)
+            .map(|(volts, amps)| volts * amps)
+            .collect()
+    }
+
+    /// Readings of `quantity`, one per sample.
+    pub fn values(&self, quantity: Quantity) -> Vec<f64> {
+        match quantity {
+            Quantity::Voltage => self.voltage_v.clone(),
+            Quantity::Current => self.current_a.clone(),
+            Quantity::Power => self.power_w(),
+        }
+    }
+
+    pub fn mean(&self, quantity: Quantity) -> Option<f64> {
+        let values = self.values(quantity);
+        (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+    }
+
+    /// Peak-to-peak spread of `quantity` over the frame.
+    pub fn ripple(&self, quantity: Quantity) -> Option<f64> {
+        let values = self.values(quantity);
+        let min = values.iter().copied().reduce(f64::min)?;
+        let max = values.iter().copied().reduce(f64::max)?;
+        Some(max - min)
+    }
+
+    /// Largest distance of a sample from its place on the schedule.
+    pub fn max_jitter(&self) -> Duration {
+        (0u32..)
+            .zip(&self.offsets)
+            .map(|(index, offset)| {
+                let due = self.interval * index;
+                offset.abs_diff(due)
+            })
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// `offset_s,voltage_v,current_a` with a header line.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("offset_s,voltage_v,current_a\n");
+        for ((offset, volts), amps) in self.offsets.iter().zip(&self.voltage_v).zip(&self.current_a)
+        {
+            csv.push_str(&format!("{:.6},{volts},{amps}\n", offset.as_secs_f64()));
+        }
+        csv
+    }
+
+    /// The frame as a NPY (format 1.0) file: a C-ordered `(n, 3)` array of
+    /// little-endian float64 offset, voltage and current.
+    pub fn to_npy(&self) -> Vec<u8> {
+        let mut header = format!(
+            "{{'descr': '<f8', 'fortran_order': False, 'shape': ({}, 3), }}",
+            self.len()
+        );
+        // Magic, version and length take 10 bytes; the header is padded
+        // with spaces and ends in a newline so that the data starts on a
+        // 64-byte boundary.
+        let unpadded = 10 + header.len() + 1;
+        header.push_str(&" ".repeat(unpadded.next_multiple_of(64) - unpadded));
+        header.push('\n');
+
+        let mut npy = Vec::with_capacity(10 + header.len() + self.len() * 3 * 8);
+        npy.extend_from_slice(b"\x93NUMPY\x01\x00");
+        npy.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        npy.extend_from_slice(header.as_bytes());
+        for ((offset, volts), amps) in self.offsets.iter().zip(&self.voltage_v).zip(&self.current_a)
+        {
+            for value in [offset.as_secs_f64(), *volts, *amps] {
+                npy.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        npy
+    }
+
+    /// Write [`to_npy`](Self::to_npy) to `path`, usually `*.npy`.
+    pub fn save_npy(&self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        use anyhow::Context;
+
+        let path = path.as_ref();
+        std::fs::write(path, self.to_npy())
+            .with_context(|| format!("failed to write frame {}", path.display()))
+    }
+}
+
+/// Back-to-back frames of one channel, see [`Spd3303x::frames`].
+pub struct FrameStream<'a> {
+    inst: &'a mut Spd3303x,
+    channel: Channel,
+    n_samples: usize,
+    interval: Duration,
+    ticker: Ticker,
+}
+
+impl FrameStream<'_> {
+    /// Capture the next frame. Never ends by itself; stop by dropping the
+    /// stream. After an error the next frame starts on the schedule where
+    /// the failed one stopped.
+    pub async fn next(&mut self) -> Option<Result<MeasurementFrame>> {
+        let frame = capture(
+            self.inst,
+            &mut self.ticker,
+            self.channel,
+            self.n_samples,
+            self.interval,
+        );
+        Some(operation::run("capture_frame", frame).await)
+    }
+}
+
+impl Spd3303x {
+    /// Read `channel` every `interval` until `n_samples` readings are in.
+    pub async fn capture_frame(
+        &mut self,
+        channel: Channel,
+        n_samples: usize,
+        interval: Duration,
+    ) -> Result<MeasurementFrame> {
+        check_frame(n_samples, interval)?;
+        let mut ticker = Ticker::new(self.clock(), interval);
+        operation::run(
+            "capture_frame",
+            capture(self, &mut ticker, channel, n_samples, interval),
+        )
+        .await
+    }
+
+    /// Frames of `n_samples` readings `interval` apart, one after another
+    /// on a single schedule.
+    ///
+    /// ```ignore
+    /// let mut frames = inst.frames(Channel::Ch1, 256, Duration::from_millis(50))?;
+    /// while let Some(frame) = frames.next().await {
+    ///     analyse(frame?);
+    /// }
+    /// ```
+    pub fn frames(
+        &mut self,
+        channel: Channel,
+        n_samples: usize,
+        interval: Duration,
+    ) -> Result<FrameStream<'_>> {
+        check_frame(n_samples, interval)?;
+        let ticker = Ticker::new(self.clock(), interval);
+        Ok(FrameStream {
+            inst: self,
+            channel,
+            n_samples,
+            interval,
+            ticker,
+        })
+    }
+}
+
+fn check_frame(n_samples: usize, interval: Duration) -> Result<()> {
+    if n_samples == 0 {
+        return Err(anyhow!("a frame needs at least one sample"));
+    }
+    if interval.is_zero() {
+        return Err(anyhow!("frame interval must be longer than zero"));
+    }
+    Ok(())
+}
+
+async fn capture(
+    inst: &mut Spd3303x,
+    ticker: &mut Ticker,
+    channel: Channel,
+    n_samples: usize,
+    interval: Duration,
+) -> Result<MeasurementFrame> {
+    let clock = inst.clock();
+    let mut frame = MeasurementFrame {
+        channel,
+        started_at: SystemTime::now(),
+        interval,
+        offsets: Vec::with_capacity(n_samples),
+        voltage_v: Vec::with_capacity(n_samples),
+        current_a: Vec::with_capacity(n_samples),
+    };
+    let mut start = None;
+    while frame.len() < n_samples {
+        let due = ticker.tick().await;
+        let taken = clock.now();
+        let start = *start.get_or_insert_with(|| {
+            frame.started_at = SystemTime::now();
+            due
+        });
+        frame.voltage_v.push(inst.measured_voltage(Some(channel)).await?);
+        frame.current_a.push(inst.measured_current(Some(channel)).await?);
+        frame.offsets.push(taken.saturating_duration_since(start));
+    }
+    Ok(frame)
+}
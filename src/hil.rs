@@ -0,0 +1,170 @@
+//! Fixture for hardware-in-the-loop tests.
+//!
+//! Tests written with [`spd3303x_test!`](crate::spd3303x_test) (or
+//! [`run`] directly) connect to the supply named by the environment, start
+//! and finish from `soft_reset`, and are skipped with a message instead of
+//! failing when no instrument is configured or reachable:
+//!
+//! ```ignore
+//! spd3303x_control::spd3303x_test! {
+//!     async fn ch1_accepts_setpoint(inst) {
+//!         inst.set_voltage(Channel::Ch1, 3.3).await?;
+//!         assert!((inst.setpoint_voltage(Channel::Ch1).await? - 3.3).abs() < 0.01);
+//!         Ok(())
+//!     }
+//! }
+//! ```
+//!
+//! A test may get the supply wired to real hardware, so the handle it
+//! receives is in strict mode and limited to `SPD3303X_MAX_V` /
+//! `SPD3303X_MAX_A` on CH1 and CH2. A test that needs more has to raise
+//! the limits itself with `set_limits`.
+//!
+//! Environment:
+//! - `SPD3303X_HOST`: instrument address; tests are skipped when unset
+//! - `SPD3303X_RESOURCE`: VXI-11 device name, defaults to `inst0`
+//! - `SPD3303X_MAX_V`: voltage limit for CH1/CH2, defaults to 5 V
+//! - `SPD3303X_MAX_A`: current limit for CH1/CH2, defaults to 0.5 A
+
+use std::time::Duration;
+
+use anyhow::Context;
+use tokio::sync::Mutex;
+use tracing::warn;
+
+use crate::instrument::{Channel, Spd3303x};
+use crate::units::{parse_current, parse_voltage};
+use crate::validate::Limits;
+
+pub use anyhow::Result;
+
+pub const HOST_ENV: &str = "SPD3303X_HOST";
+pub const RESOURCE_ENV: &str = "SPD3303X_RESOURCE";
+pub const MAX_VOLTAGE_ENV: &str = "SPD3303X_MAX_V";
+pub const MAX_CURRENT_ENV: &str = "SPD3303X_MAX_A";
+
+const DEFAULT_RESOURCE: &str = "inst0";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_MAX_VOLTAGE_V: f64 = 5.0;
+const DEFAULT_MAX_CURRENT_A: f64 = 0.5;
+
+/// The test harness runs tests in parallel, but there is only one supply.
+static INSTRUMENT_LOCK: Mutex<()> = Mutex::const_new(());
+
+#[derive(Debug, Clone)]
+pub struct HilConfig {
+    pub host: String,
+    pub resource: String,
+    pub connect_timeout: Duration,
+    /// Installed on CH1 and CH2 before every test.
+    pub limits: Limits,
+}
+
+impl HilConfig {
+    /// Read the configuration from the environment, or `None` when
+    /// `SPD3303X_HOST` is not set. Fails when a limit is set but does not
+    /// parse, rather than testing without it.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Some(host) = std::env::var(HOST_ENV)
+            .ok()
+            .filter(|h| !h.trim().is_empty())
+        else {
+            return Ok(None);
+        };
+        let resource =
+            std::env::var(RESOURCE_ENV).unwrap_or_else(|_| DEFAULT_RESOURCE.to_string());
+        let max_voltage_v = env_or(MAX_VOLTAGE_ENV, DEFAULT_MAX_VOLTAGE_V, parse_voltage)?;
+        let max_current_a = env_or(MAX_CURRENT_ENV, DEFAULT_MAX_CURRENT_A, parse_current)?;
+        Ok(Some(Self {
+            host,
+            resource,
+            connect_timeout: CONNECT_TIMEOUT,
+            limits: Limits {
+                max_voltage_v: Some(max_voltage_v),
+                max_current_a: Some(max_current_a),
+                ..Limits::unlimited()
+            },
+        }))
+    }
+}
+
+fn env_or(name: &str, default: f64, parse: fn(&str) -> Result<f64>) -> Result<f64> {
+    match std::env::var(name) {
+        Ok(text) => parse(&text).with_context(|| format!("{name}={text:?}")),
+        Err(_) => Ok(default),
+    }
+}
+
+/// Run `test` against the instrument configured in the environment.
+///
+/// The instrument is soft-reset before the test and again afterwards, also
+/// when the test fails, so every test starts with all outputs off and 0 V /
+/// 0 A setpoints. The handle is in strict mode with the configured limits
+/// on CH1 and CH2. Tests in the same process are serialized. Returns
+/// `Ok(())` without running `test` when no instrument is configured or it
+/// cannot be reached.
+pub async fn run<F>(name: &str, test: F) -> Result<()>
+where
+    F: AsyncFnOnce(&mut Spd3303x) -> Result<()>,
+{
+    let Some(config) = HilConfig::from_env()? else {
+        eprintln!("skipping {name}: {HOST_ENV} is not set");
+        return Ok(());
+    };
+
+    let _guard = INSTRUMENT_LOCK.lock().await;
+    let connect = Spd3303x::connect_with_timeout(
+        &config.host,
+        &config.resource,
+        config.connect_timeout,
+    );
+    let mut inst = match tokio::time::timeout(config.connect_timeout, connect).await {
+        Ok(Ok(inst)) => inst,
+        Ok(Err(e)) => {
+            eprintln!("skipping {name}: cannot connect to {}: {e:#}", config.host);
+            return Ok(());
+        }
+        Err(_) => {
+            eprintln!("skipping {name}: timed out connecting to {}", config.host);
+            return Ok(());
+        }
+    };
+
+    inst.set_strict(true);
+    for channel in Channel::programmable() {
+        inst.set_limits(channel, config.limits)?;
+    }
+    inst.soft_reset().await?;
+    let outcome = test(&mut inst).await;
+    if let Err(e) = inst.soft_reset().await {
+        warn!("{name}: soft_reset after test failed: {e:#}");
+        if outcome.is_ok() {
+            return Err(e);
+        }
+    }
+    if let Err(e) = inst.close().await {
+        warn!("{name}: closing the instrument failed: {e:#}");
+    }
+    outcome
+}
+
+/// Declare a `#[tokio::test]` that runs its body through [`hil::run`](run).
+///
+/// The calling crate needs `tokio` (with the `macros` and `rt` features) as
+/// a dev-dependency. The body receives `&mut Spd3303x` under the given name
+/// and returns `hil::Result<()>`.
+#[macro_export]
+macro_rules! spd3303x_test {
+    ($(#[$meta:meta])* async fn $name:ident($inst:ident) $body:block) => {
+        $(#[$meta])*
+        #[tokio::test]
+        async fn $name() {
+            $crate::hil::run(
+                stringify!($name),
+                async |$inst: &mut $crate::instrument::Spd3303x| -> $crate::hil::Result<()> $body,
+            )
+            .await
+            .unwrap();
+        }
+    };
+}
@@ -0,0 +1,172 @@
+//! Coarse inrush measurements.
+//!
+//! [`Spd3303x::capture_inrush`] switches an output on and reads its current
+//! back to back for a short window. Each reading is a full SCPI round
+//! trip, so the sample rate is that of the link (tens to a few hundred per
+//! second), far too slow to resolve a capacitor charging in microseconds.
+//! It does catch a DUT that draws heavily for tens of milliseconds, runs
+//! into the current limit at power-up or takes long to settle.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use tracing::debug;
+
+use crate::instrument::{Channel, OutputState, Spd3303x};
+use crate::operation;
+use crate::pipeline::Quantity;
+use crate::table::Table;
+
+/// Relative band around the final current that counts as steady.
+pub const STEADY_TOLERANCE: f64 = 0.05;
+
+/// Absolute floor of the steady band, as the readbacks resolve 1 mA.
+const STEADY_FLOOR_A: f64 = 0.002;
+
+/// Setpoints programmed before the output is switched on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InrushSettings {
+    pub voltage_v: f64,
+    pub current_limit_a: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InrushSample {
+    /// Time since the output-on command was sent.
+    pub offset: Duration,
+    pub current_a: f64,
+}
+
+/// Result of [`Spd3303x::capture_inrush`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InrushReport {
+    pub channel: Channel,
+    pub settings: InrushSettings,
+    pub samples: Vec<InrushSample>,
+    pub peak_current_a: f64,
+    pub peak_at: Duration,
+    /// Mean of the last quarter of the samples.
+    pub steady_current_a: f64,
+    /// Offset after which every sample stays within [`STEADY_TOLERANCE`]
+    /// of the steady current; `None` if the last sample is still outside.
+    pub time_to_steady: Option<Duration>,
+}
+
+impl InrushReport {
+    /// Achieved sample rate in Hz.
+    pub fn sample_rate_hz(&self) -> f64 {
+        match self.samples.last() {
+            Some(last) if self.samples.len() > 1 && !last.offset.is_zero() => {
+                (self.samples.len() - 1) as f64 / last.offset.as_secs_f64()
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Whether the peak reached the current limit, i.e. the channel went
+    /// into CC while starting the DUT.
+    pub fn hit_current_limit(&self) -> bool {
+        self.peak_current_a >= self.settings.current_limit_a - STEADY_FLOOR_A
+    }
+
+    pub fn render_table(&self) -> Table {
+        let steady = self
+            .time_to_steady
+            .map_or_else(|| "not reached".to_string(), |at| format!("{:.1} ms", ms(at)));
+        Table::new(["Quantity", self.channel.label()])
+            .row(["Peak".to_string(), format!("{:.3} A", self.peak_current_a)])
+            .row(["Peak at".to_string(), format!("{:.1} ms", ms(self.peak_at))])
+            .row(["Steady".to_string(), format!("{:.3} A", self.steady_current_a)])
+            .row(["Time to steady".to_string(), steady])
+            .row(["Samples".to_string(), self.samples.len().to_string()])
+            .row(["Rate".to_string(), format!("{:.0} Hz", self.sample_rate_hz())])
+    }
+}
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1e3
+}
+
+impl Spd3303x {
+    /// Program `settings`, switch the output of `channel` on and read its
+    /// current as fast as the link allows for `capture_duration`.
+    ///
+    /// The output must be off beforehand, otherwise there is no inrush to
+    /// see; it is left on afterwards. Readings bypass the channel's
+    /// measurement pipeline, so a filter cannot flatten the peak.
+    pub async fn capture_inrush(
+        &mut self,
+        channel: Channel,
+        settings: InrushSettings,
+        capture_duration: Duration,
+    ) -> Result<InrushReport> {
+        operation::run("capture_inrush", async {
+            if self.query_output(channel).await? {
+                return Err(anyhow!(
+                    "{} is already on; switch it off and let the DUT discharge first",
+                    channel.label()
+                ));
+            }
+            self.set_voltage(channel, settings.voltage_v).await?;
+            self.set_current(channel, settings.current_limit_a).await?;
+
+            let clock = self.clock();
+            let started = clock.now();
+            self.set_output(channel, OutputState::On).await?;
+            let mut samples = Vec::new();
+            loop {
+                let current_a = self.measure_raw(Quantity::Current, Some(channel)).await?;
+                let offset = clock.elapsed(started);
+                samples.push(InrushSample { offset, current_a });
+                if offset >= capture_duration {
+                    break;
+                }
+            }
+            debug!(
+                "capture_inrush: {} samples in {:?}",
+                samples.len(),
+                clock.elapsed(started)
+            );
+            Ok(summarize(channel, settings, samples))
+        })
+        .await
+    }
+}
+
+/// `samples` is never empty: the capture loop reads at least once.
+fn summarize(
+    channel: Channel,
+    settings: InrushSettings,
+    samples: Vec<InrushSample>,
+) -> InrushReport {
+    let peak = samples
+        .iter()
+        .copied()
+        .max_by(|a, b| a.current_a.total_cmp(&b.current_a))
+        .unwrap_or(InrushSample {
+            offset: Duration::ZERO,
+            current_a: 0.0,
+        });
+    let tail = &samples[samples.len() - samples.len().div_ceil(4)..];
+    let steady = tail.iter().map(|sample| sample.current_a).sum::<f64>() / tail.len() as f64;
+    let band = (steady.abs() * STEADY_TOLERANCE).max(STEADY_FLOOR_A);
+    let time_to_steady = match samples
+        .iter()
+        .rposition(|sample| (sample.current_a - steady).abs() > band)
+    {
+        None => samples.first().map(|sample| sample.offset),
+        Some(last_outside) => samples.get(last_outside + 1).map(|sample| sample.offset),
+    };
+    InrushReport {
+        channel,
+        settings,
+        peak_current_a: peak.current_a,
+        peak_at: peak.offset,
+        steady_current_a: steady,
+        time_to_steady,
+        samples,
+    }
+}
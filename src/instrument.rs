@@ -1,12 +1,64 @@
 use anyhow::{anyhow, Context, Result};
 use clap::ValueEnum;
-use std::time::Duration;
+use std::fmt;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::time::Instant;
 use tokio_vxi11::DeviceClient;
-use tracing::debug;
-
-const MAX_READ: u32 = 4096;
+use tracing::{debug, info, warn};
+
+use crate::broker::BrokerClient;
+use crate::clock::{Clock, TokioClock};
+use crate::conflict::ConflictState;
+use crate::escalation::{CommandTimedOut, CommandTimeouts, EscalationPolicy};
+use crate::mock::MockDevice;
+use crate::middleware::{self, Middleware};
+use crate::notes::NoteLog;
+use crate::operation;
+use crate::parse;
+use crate::pipeline::{Quantity, Sample, SamplePipeline};
+use crate::prologix::PrologixClient;
+use crate::quirks::{Feature, Quirk, Quirks};
+use crate::reconnect::ReconnectPolicy;
+use crate::redact::Redactor;
+use crate::response::{EmptyResponse, ResponseRules, ResponseShape};
+use crate::retry::RetryPolicy;
+#[cfg(feature = "serial")]
+use crate::serial::{SerialClient, SerialConfig};
+use crate::socket::SocketClient;
+use crate::table::Table;
+use crate::training::Training;
+use crate::transcript::{Direction, Transcript, TranscriptEntry};
+use crate::transport::{self, Transport};
+#[cfg(feature = "ssh")]
+use crate::tunnel::SshTunnel;
+use crate::undo::UndoStack;
+use crate::uptime::CommandCounters;
+#[cfg(target_os = "linux")]
+use crate::usbtmc::UsbtmcClient;
+use crate::validate::{self, Capabilities, ChannelPolicy, Limits, SafetyLayer, Violation};
+
+/// Rated output range of CH1/CH2 in independent mode (0–32 V / 0–3.2 A).
+pub const MAX_VOLTAGE_V: f64 = 32.0;
+pub const MAX_CURRENT_A: f64 = 3.2;
+
+/// Longest duration of one timer group, and the resolution durations are
+/// sent with.
+pub const MAX_TIMER_DURATION: Duration = Duration::from_secs(10_000);
+pub const TIMER_RESOLUTION: Duration = Duration::from_millis(1);
+/// Number of timer groups per channel.
+pub const TIMER_GROUPS: u8 = 5;
+
+/// Iteration bounds for wire-drop compensation in `set_load_voltage`.
+const COMPENSATION_MAX_ITERATIONS: usize = 5;
+const COMPENSATION_TOLERANCE_V: f64 = 0.001;
 
 #[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Channel {
     #[value(name = "CH1")]
     Ch1,
@@ -28,9 +80,57 @@ impl Channel {
     pub fn label(self) -> &'static str {
         self.as_scpi()
     }
+
+    /// CH1 and CH2, the channels with programmable setpoints.
+    pub fn programmable() -> impl Iterator<Item = Channel> {
+        [Channel::Ch1, Channel::Ch2].into_iter()
+    }
+
+    /// Every output, including the fixed CH3.
+    pub fn all() -> impl Iterator<Item = Channel> {
+        [Channel::Ch1, Channel::Ch2, Channel::Ch3].into_iter()
+    }
+}
+
+/// A single channel or `all` programmable channels, e.g. for a
+/// `--channel` option.
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChannelSelector {
+    #[value(name = "CH1")]
+    Ch1,
+    #[value(name = "CH2")]
+    Ch2,
+    #[value(name = "CH3")]
+    Ch3,
+    #[value(name = "all", alias = "ALL")]
+    All,
+}
+
+impl ChannelSelector {
+    pub fn channels(self) -> impl Iterator<Item = Channel> {
+        let channels: &'static [Channel] = match self {
+            ChannelSelector::Ch1 => &[Channel::Ch1],
+            ChannelSelector::Ch2 => &[Channel::Ch2],
+            ChannelSelector::Ch3 => &[Channel::Ch3],
+            ChannelSelector::All => &[Channel::Ch1, Channel::Ch2],
+        };
+        channels.iter().copied()
+    }
+}
+
+impl From<Channel> for ChannelSelector {
+    fn from(channel: Channel) -> Self {
+        match channel {
+            Channel::Ch1 => ChannelSelector::Ch1,
+            Channel::Ch2 => ChannelSelector::Ch2,
+            Channel::Ch3 => ChannelSelector::Ch3,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, ValueEnum)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum OutputState {
     On,
     Off,
@@ -45,7 +145,8 @@ impl OutputState {
     }
 }
 
-#[derive(Debug, Clone, Copy, ValueEnum)]
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TrackMode {
     Independent,
     Series,
@@ -69,9 +170,18 @@ impl TrackMode {
             other => Err(anyhow!("unknown track mode value {other}")),
         }
     }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            TrackMode::Independent => "Independent",
+            TrackMode::Series => "Series",
+            TrackMode::Parallel => "Parallel",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, ValueEnum)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TimerState {
     On,
     Off,
@@ -87,6 +197,7 @@ impl TimerState {
 }
 
 #[derive(Debug, Clone, Copy, ValueEnum)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DhcpState {
     On,
     Off,
@@ -101,13 +212,24 @@ impl DhcpState {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum RegulationMode {
     ConstantVoltage,
     ConstantCurrent,
 }
 
+impl RegulationMode {
+    pub fn label(self) -> &'static str {
+        match self {
+            RegulationMode::ConstantVoltage => "CV",
+            RegulationMode::ConstantCurrent => "CC",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SystemStatus {
     /// Raw status word as returned by `SYSTem:STATus?` (after hex decoding).
     pub raw: u32,
@@ -124,7 +246,7 @@ pub struct SystemStatus {
 }
 
 impl SystemStatus {
-    fn from_word(word: u32) -> Self {
+    pub(crate) fn from_word(word: u32) -> Self {
         let ch1_regulation_mode = if word & (1 << 0) == 0 {
             RegulationMode::ConstantVoltage
         } else {
@@ -168,26 +290,287 @@ impl SystemStatus {
             parallel_mode,
         }
     }
+
+    /// Regulation mode of CH1/CH2; `None` for CH3.
+    pub fn regulation_mode(&self, channel: Channel) -> Option<RegulationMode> {
+        match channel {
+            Channel::Ch1 => Some(self.ch1_regulation_mode),
+            Channel::Ch2 => Some(self.ch2_regulation_mode),
+            Channel::Ch3 => None,
+        }
+    }
+
+    /// Output state of CH1/CH2; `None` for CH3, which is not reported.
+    pub fn output_on(&self, channel: Channel) -> Option<bool> {
+        match channel {
+            Channel::Ch1 => Some(self.ch1_output_on),
+            Channel::Ch2 => Some(self.ch2_output_on),
+            Channel::Ch3 => None,
+        }
+    }
+
+    /// Waveform display state of CH1/CH2; `None` for CH3.
+    pub fn wave_display_on(&self, channel: Channel) -> Option<bool> {
+        match channel {
+            Channel::Ch1 => Some(self.ch1_waveform_display),
+            Channel::Ch2 => Some(self.ch2_waveform_display),
+            Channel::Ch3 => None,
+        }
+    }
+
+    /// Timer state of CH1/CH2; `None` for CH3.
+    pub fn timer_on(&self, channel: Channel) -> Option<bool> {
+        match channel {
+            Channel::Ch1 => Some(self.timer1_on),
+            Channel::Ch2 => Some(self.timer2_on),
+            Channel::Ch3 => None,
+        }
+    }
+
+    /// Render the decoded status word as a per-channel table.
+    pub fn render_table(&self) -> Table {
+        let track = self.track_mode.map(TrackMode::label).unwrap_or("Unknown");
+        Table::new(["Field", "CH1", "CH2"])
+            .row([
+                "Regulation",
+                self.ch1_regulation_mode.label(),
+                self.ch2_regulation_mode.label(),
+            ])
+            .row(["Output", on_off(self.ch1_output_on), on_off(self.ch2_output_on)])
+            .row(["Timer", on_off(self.timer1_on), on_off(self.timer2_on)])
+            .row([
+                "Waveform",
+                on_off(self.ch1_waveform_display),
+                on_off(self.ch2_waveform_display),
+            ])
+            .row(["Track mode", track, ""])
+            .row(["Status word", format!("0x{:04X}", self.raw).as_str(), ""])
+    }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ChannelStatus {
-    pub set_voltage_v: f64,
-    pub set_current_a: f64,
+    /// Programmed voltage. Serialized as `set_voltage_v`, the name before
+    /// the rename, until the next release; either name is read.
+    #[cfg_attr(
+        feature = "serde",
+        serde(rename = "set_voltage_v", alias = "setpoint_voltage_v")
+    )]
+    pub setpoint_voltage_v: f64,
+    /// Programmed current limit, serialized as `set_current_a` like
+    /// `setpoint_voltage_v`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(rename = "set_current_a", alias = "setpoint_current_a")
+    )]
+    pub setpoint_current_a: f64,
     pub measured_voltage_v: f64,
     pub measured_current_a: f64,
     pub measured_power_w: f64,
+    /// Same value as `setpoint_voltage_v`, kept for one release under the
+    /// old name. Code that builds a `ChannelStatus` has to fill both; the
+    /// field is not serialized and reads 0 after deserializing.
+    #[deprecated(note = "renamed to `setpoint_voltage_v`")]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub set_voltage_v: f64,
+    /// Same value as `setpoint_current_a`, see `set_voltage_v`.
+    #[deprecated(note = "renamed to `setpoint_current_a`")]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub set_current_a: f64,
+}
+
+impl ChannelStatus {
+
+    /// Measured minus programmed voltage, in volts.
+    pub fn voltage_error_v(&self) -> f64 {
+        self.measured_voltage_v - self.setpoint_voltage_v
+    }
+
+    /// Measured voltage error relative to the setpoint, in percent.
+    ///
+    /// Returns `None` when the voltage setpoint is zero.
+    pub fn voltage_error_pct(&self) -> Option<f64> {
+        if self.setpoint_voltage_v == 0.0 {
+            None
+        } else {
+            Some(self.voltage_error_v() / self.setpoint_voltage_v * 100.0)
+        }
+    }
+
+    /// Ratio of measured to programmed voltage in dB (`20·log10(Vmeas/Vset)`).
+    ///
+    /// Returns `None` when either value is not positive.
+    pub fn voltage_error_db(&self) -> Option<f64> {
+        if self.setpoint_voltage_v > 0.0 && self.measured_voltage_v > 0.0 {
+            Some(20.0 * (self.measured_voltage_v / self.setpoint_voltage_v).log10())
+        } else {
+            None
+        }
+    }
+
+    /// Current left before the channel reaches its current limit and drops
+    /// into CC mode. Clamped at zero.
+    pub fn headroom_a(&self) -> f64 {
+        (self.setpoint_current_a - self.measured_current_a).max(0.0)
+    }
+
+    /// Load current as a percentage of the current limit.
+    ///
+    /// Returns `None` when the current limit is zero.
+    pub fn current_utilization_pct(&self) -> Option<f64> {
+        if self.setpoint_current_a == 0.0 {
+            None
+        } else {
+            Some(self.measured_current_a / self.setpoint_current_a * 100.0)
+        }
+    }
+
+    /// Power delivered to the load computed from the V/I readbacks.
+    ///
+    /// This is independent of `measured_power_w`, which is the instrument's
+    /// own `MEAS:POWEr?` reading, so the two can be cross-checked.
+    pub fn load_w(&self) -> f64 {
+        self.measured_voltage_v * self.measured_current_a
+    }
+
+    /// Apparent load resistance (`Vmeas / Imeas`), or `None` with no load current.
+    pub fn load_ohms(&self) -> Option<f64> {
+        if self.measured_current_a > 0.0 {
+            Some(self.measured_voltage_v / self.measured_current_a)
+        } else {
+            None
+        }
+    }
+
+    /// Collect all derived values into one struct, e.g. for serialization
+    /// next to the raw status.
+    pub fn derived(&self) -> DerivedMetrics {
+        DerivedMetrics {
+            voltage_error_v: self.voltage_error_v(),
+            voltage_error_pct: self.voltage_error_pct(),
+            voltage_error_db: self.voltage_error_db(),
+            headroom_a: self.headroom_a(),
+            current_utilization_pct: self.current_utilization_pct(),
+            load_w: self.load_w(),
+            load_ohms: self.load_ohms(),
+        }
+    }
+
+    /// Render setpoints and readbacks as a table with one row per quantity.
+    pub fn render_table(&self) -> Table {
+        Table::new(["Quantity", "Setpoint", "Measured"])
+            .row([
+                "Voltage".to_string(),
+                format!("{:.3} V", self.setpoint_voltage_v),
+                format!("{:.3} V", self.measured_voltage_v),
+            ])
+            .row([
+                "Current".to_string(),
+                format!("{:.3} A", self.setpoint_current_a),
+                format!("{:.3} A", self.measured_current_a),
+            ])
+            .row([
+                "Power".to_string(),
+                String::new(),
+                format!("{:.3} W", self.measured_power_w),
+            ])
+    }
 }
 
+/// Values derived from a [`ChannelStatus`]; see the methods of the same name.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DerivedMetrics {
+    pub voltage_error_v: f64,
+    pub voltage_error_pct: Option<f64>,
+    pub voltage_error_db: Option<f64>,
+    pub headroom_a: f64,
+    pub current_utilization_pct: Option<f64>,
+    pub load_w: f64,
+    pub load_ohms: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TimerEntry {
     pub group: u8,
     pub voltage_v: f64,
     pub current_a: f64,
-    pub duration_s: f64,
+    pub duration: Duration,
+}
+
+impl TimerEntry {
+    pub fn duration_secs(&self) -> f64 {
+        self.duration.as_secs_f64()
+    }
 }
 
+/// All timer groups of a channel, as read by `Spd3303x::read_all_timers`.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TimerGroups {
+    pub channel: Channel,
+    /// Entry of group `i + 1`, `None` if it could not be read.
+    pub entries: [Option<TimerEntry>; TIMER_GROUPS as usize],
+    /// Groups that could not be read, with the error of the last attempt.
+    pub errors: Vec<(u8, String)>,
+}
+
+impl TimerGroups {
+    pub fn is_complete(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// All entries, or the first per-group error.
+    pub fn into_complete(self) -> Result<[TimerEntry; TIMER_GROUPS as usize]> {
+        if let Some((group, error)) = self.errors.first() {
+            return Err(anyhow!("timer group {group} could not be read: {error}"));
+        }
+        let entries: Vec<TimerEntry> = self.entries.into_iter().flatten().collect();
+        entries
+            .try_into()
+            .map_err(|_| anyhow!("incomplete timer readout"))
+    }
+
+    pub fn render_table(&self) -> Table {
+        let mut table = Table::new(["Group", "Voltage", "Current", "Duration"]);
+        for (group, entry) in (1..=TIMER_GROUPS).zip(&self.entries) {
+            match entry {
+                Some(entry) => table.push_row([
+                    group.to_string(),
+                    format!("{:.3} V", entry.voltage_v),
+                    format!("{:.3} A", entry.current_a),
+                    format!("{:.3} s", entry.duration_secs()),
+                ]),
+                None => table.push_row([
+                    group.to_string(),
+                    "?".to_string(),
+                    "?".to_string(),
+                    "?".to_string(),
+                ]),
+            }
+        }
+        table
+    }
+}
+
+/// Convert fractional seconds to a timer duration, rounded to
+/// [`TIMER_RESOLUTION`] and checked against [`MAX_TIMER_DURATION`].
+pub fn timer_duration_from_secs(seconds: f64) -> Result<Duration> {
+    if !seconds.is_finite() || seconds < 0.0 {
+        return Err(anyhow!(
+            "timer duration must be a finite, non-negative number of seconds, got {seconds}"
+        ));
+    }
+    let millis = (seconds * 1000.0).round();
+    let duration = Duration::from_millis(millis as u64);
+    validate::ensure(validate::check_timer_duration(duration))?;
+    Ok(duration)
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NetworkConfig {
     pub ip: String,
     pub mask: String,
@@ -195,8 +578,186 @@ pub struct NetworkConfig {
     pub dhcp: bool,
 }
 
+impl NetworkConfig {
+    pub fn render_table(&self) -> Table {
+        Table::new(["Setting", "Value"])
+            .row(["IP", self.ip.as_str()])
+            .row(["Mask", self.mask.as_str()])
+            .row(["Gateway", self.gateway.as_str()])
+            .row(["DHCP", on_off(self.dhcp)])
+    }
+}
+
+/// Decoded `*IDN?` response, e.g.
+/// `Siglent Technologies,SPD3303X-E,SPD3XIDX000000,1.01.01.02.05,V3.0`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Identity {
+    pub manufacturer: String,
+    pub model: String,
+    pub serial: String,
+    pub firmware: String,
+    pub hardware: String,
+}
+
+impl Identity {
+    pub fn parse(idn: &str) -> Result<Self> {
+        parse::identity(idn.as_bytes())
+            .map(|identity| identity.to_identity())
+            .map_err(|e| anyhow!("{e} in IDN response {idn:?}"))
+    }
+
+    pub fn firmware_version(&self) -> Result<FirmwareVersion> {
+        self.firmware.parse()
+    }
+}
+
+/// Firmware version such as `1.01.01.02.05`, ordered field by field.
+///
+/// ```ignore
+/// if inst.firmware_version().await? >= FirmwareVersion::new(1, 1, 1, 2, 5) { .. }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FirmwareVersion([u16; 5]);
+
+impl FirmwareVersion {
+    pub const fn new(major: u16, minor: u16, patch: u16, build: u16, revision: u16) -> Self {
+        Self([major, minor, patch, build, revision])
+    }
+
+    pub fn fields(&self) -> [u16; 5] {
+        self.0
+    }
+}
+
+impl FromStr for FirmwareVersion {
+    type Err = anyhow::Error;
+
+    /// Missing trailing fields count as 0, so `1.01` parses as
+    /// `1.01.00.00.00`.
+    fn from_str(s: &str) -> Result<Self> {
+        parse::firmware_version(s.as_bytes())
+            .map_err(|_| anyhow!("invalid firmware version {:?}", s.trim()))
+    }
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [major, minor, patch, build, revision] = self.0;
+        write!(f, "{major}.{minor:02}.{patch:02}.{build:02}.{revision:02}")
+    }
+}
+
+/// Host-side settings kept per programmable channel (CH1/CH2).
+#[derive(Debug, Clone, Default)]
+struct ChannelConfig {
+    pipeline: SamplePipeline,
+    wire_resistance_ohms: f64,
+    limits: Limits,
+    /// When this handle last switched the channel's timer on; cleared when
+    /// it switches it off.
+    timer_started: Option<Instant>,
+    /// Timer groups as last read back, until the next `timer_set`.
+    timer_groups: Option<[TimerEntry; TIMER_GROUPS as usize]>,
+}
+
+/// Where a link was opened, so that it can be reopened.
+#[derive(Debug, Clone)]
+enum Endpoint {
+    Vxi11 {
+        host: String,
+        resource: String,
+        timeout: Option<Duration>,
+    },
+    Socket(Vec<SocketAddr>),
+    BrokerTcp(Vec<SocketAddr>),
+    #[cfg(unix)]
+    BrokerUnix(std::path::PathBuf),
+    Prologix {
+        addrs: Vec<SocketAddr>,
+        gpib_address: u8,
+    },
+    #[cfg(feature = "serial")]
+    Serial(SerialConfig),
+    #[cfg(target_os = "linux")]
+    Usbtmc(std::path::PathBuf),
+    Mock(MockDevice),
+}
+
+impl Endpoint {
+    async fn open(&self) -> Result<Box<dyn Transport>> {
+        let link: Box<dyn Transport> = match self {
+            Endpoint::Vxi11 {
+                host,
+                resource,
+                timeout: None,
+            } => Box::new(DeviceClient::connect(host.as_str(), resource.as_str()).await?),
+            Endpoint::Vxi11 {
+                host,
+                resource,
+                timeout: Some(timeout),
+            } => Box::new(
+                DeviceClient::connect_with_timeout(host.as_str(), resource.as_str(), *timeout)
+                    .await?,
+            ),
+            Endpoint::Socket(addrs) => Box::new(SocketClient::connect(&addrs[..]).await?),
+            Endpoint::BrokerTcp(addrs) => Box::new(BrokerClient::connect_tcp(&addrs[..]).await?),
+            #[cfg(unix)]
+            Endpoint::BrokerUnix(path) => Box::new(BrokerClient::connect_unix(path).await?),
+            Endpoint::Prologix {
+                addrs,
+                gpib_address,
+            } => Box::new(PrologixClient::connect(&addrs[..], *gpib_address).await?),
+            #[cfg(feature = "serial")]
+            Endpoint::Serial(config) => Box::new(SerialClient::open(config)?),
+            #[cfg(target_os = "linux")]
+            Endpoint::Usbtmc(path) => Box::new(UsbtmcClient::open(path)?),
+            Endpoint::Mock(device) => Box::new(device.clone()),
+        };
+        Ok(link)
+    }
+}
+
+/// How long closing a link may take before it is abandoned on reconnect.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+/// Deadline of the device clear before a query is retried.
+const RETRY_CLEAR_TIMEOUT: Duration = Duration::from_secs(2);
+
 pub struct Spd3303x {
-    inner: DeviceClient,
+    inner: Box<dyn Transport>,
+    /// Where `inner` was opened; `None` if it cannot be reopened.
+    endpoint: Option<Endpoint>,
+    escalation: Option<EscalationPolicy>,
+    timeouts: CommandTimeouts,
+    auto_reconnect: Option<ReconnectPolicy>,
+    retry: Option<RetryPolicy>,
+    /// Cleared when the escalation ladder runs out, set again on reconnect.
+    healthy: bool,
+    capabilities: Capabilities,
+    /// Reject setpoints finer than the setting resolution.
+    strict: bool,
+    channels: [ChannelConfig; 2],
+    transcript: Option<Transcript>,
+    redactor: Redactor,
+    training: Option<Training>,
+    response_rules: ResponseRules,
+    quirks: Quirks,
+    /// Firmware of the last `identity()`, used for quirk gating.
+    firmware: Option<FirmwareVersion>,
+    /// Forward the link runs through; kept open for reconnects.
+    #[cfg(feature = "ssh")]
+    tunnel: Option<SshTunnel>,
+    link_established_at: SystemTime,
+    counters: CommandCounters,
+    /// Whether `DISPlay:TEXT` works; `None` until probed.
+    display_text: Option<bool>,
+    undo: Option<UndoStack>,
+    notes: NoteLog,
+    conflict: ConflictState,
+    channel_policy: ChannelPolicy,
+    middleware: Vec<Arc<dyn Middleware>>,
+    clock: Arc<dyn Clock>,
 }
 
 impl Spd3303x {
@@ -209,36 +770,58 @@ impl Spd3303x {
     /// - disables timers on CH1/CH2
     /// - disables waveform display on CH1/CH2
     /// - resets CH1/CH2 set voltage/current to 0 V / 0 A
+    ///
+    /// Channels denied by the [channel policy](Self::set_channel_policy)
+    /// are skipped, and track mode is left alone unless CH1 and CH2 are
+    /// both allowed.
     pub async fn soft_reset(&mut self) -> Result<()> {
+        operation::run("soft_reset", self.soft_reset_steps()).await
+    }
+
+    async fn soft_reset_steps(&mut self) -> Result<()> {
+        let controlled: Vec<Channel> = Channel::all()
+            .filter(|ch| self.channel_policy.permits(*ch))
+            .collect();
         debug!("soft_reset: turning all outputs OFF");
-        self.set_output(Channel::Ch1, OutputState::Off).await?;
-        self.set_output(Channel::Ch2, OutputState::Off).await?;
-        self.set_output(Channel::Ch3, OutputState::Off).await?;
+        for &channel in &controlled {
+            self.set_output(channel, OutputState::Off).await?;
+        }
 
-        debug!("soft_reset: setting track mode to Independent");
-        self.set_track_mode(TrackMode::Independent).await?;
+        if self.ensure_permitted(&[Channel::Ch1, Channel::Ch2]).is_ok() {
+            debug!("soft_reset: setting track mode to Independent");
+            self.set_track_mode(TrackMode::Independent).await?;
+        }
 
+        let programmable: Vec<Channel> = Channel::programmable()
+            .filter(|ch| controlled.contains(ch))
+            .collect();
         debug!("soft_reset: disabling timers on CH1/CH2");
-        self.timer_state(Channel::Ch1, TimerState::Off).await?;
-        self.timer_state(Channel::Ch2, TimerState::Off).await?;
+        for &channel in &programmable {
+            self.timer_state(channel, TimerState::Off).await?;
+        }
 
         debug!("soft_reset: disabling waveform display on CH1/CH2");
-        self.set_wave_display(Channel::Ch1, OutputState::Off).await?;
-        self.set_wave_display(Channel::Ch2, OutputState::Off).await?;
+        for &channel in &programmable {
+            self.set_wave_display(channel, OutputState::Off).await?;
+        }
 
         debug!("soft_reset: resetting CH1/CH2 setpoints to 0 V / 0 A");
-        self.set_voltage(Channel::Ch1, 0.0).await?;
-        self.set_current(Channel::Ch1, 0.0).await?;
-        self.set_voltage(Channel::Ch2, 0.0).await?;
-        self.set_current(Channel::Ch2, 0.0).await?;
+        for &channel in &programmable {
+            self.set_voltage(channel, 0.0).await?;
+            self.set_current(channel, 0.0).await?;
+        }
 
         debug!("soft_reset: complete");
         Ok(())
     }
 
     pub async fn connect(host: &str, resource: &str) -> Result<Self> {
-        let inner = DeviceClient::connect(host, resource).await?;
-        Ok(Self { inner })
+        Self::open(Endpoint::Vxi11 {
+            host: host.to_string(),
+            resource: resource.to_string(),
+            timeout: None,
+        })
+        .await
     }
 
     pub async fn connect_with_timeout(
@@ -246,8 +829,120 @@ impl Spd3303x {
         resource: &str,
         timeout: Duration,
     ) -> Result<Self> {
-        let inner = DeviceClient::connect_with_timeout(host, resource, timeout).await?;
-        Ok(Self { inner })
+        Self::open(Endpoint::Vxi11 {
+            host: host.to_string(),
+            resource: resource.to_string(),
+            timeout: Some(timeout),
+        })
+        .await
+    }
+
+    /// Connect over the instrument's raw SCPI socket instead of VXI-11,
+    /// usually port [`DEVICE_PORT`](crate::sniff::DEVICE_PORT) (5025).
+    /// Everything else behaves as with [`connect`](Self::connect).
+    pub async fn connect_tcp(addr: impl tokio::net::ToSocketAddrs) -> Result<Self> {
+        let addrs = resolve(addr, "instrument").await?;
+        Self::open(Endpoint::Socket(addrs)).await
+    }
+
+    /// Connect through an `spd3303xd` broker instead of directly.
+    ///
+    /// All methods behave as with a direct connection; the broker
+    /// serializes this client's commands with those of other clients.
+    pub async fn connect_broker(addr: impl tokio::net::ToSocketAddrs) -> Result<Self> {
+        let addrs = resolve(addr, "broker").await?;
+        Self::open(Endpoint::BrokerTcp(addrs)).await
+    }
+
+    /// Connect through an `spd3303xd` broker listening on a Unix socket.
+    #[cfg(unix)]
+    pub async fn connect_broker_unix(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        Self::open(Endpoint::BrokerUnix(path.as_ref().to_path_buf())).await
+    }
+
+    /// Connect through a Prologix GPIB-Ethernet adapter to the supply at
+    /// `gpib_address` on its bus. The adapter listens on
+    /// [`prologix::DEFAULT_PORT`](crate::prologix::DEFAULT_PORT).
+    pub async fn connect_prologix(
+        addr: impl tokio::net::ToSocketAddrs,
+        gpib_address: u8,
+    ) -> Result<Self> {
+        let addrs = resolve(addr, "Prologix adapter").await?;
+        Self::open(Endpoint::Prologix {
+            addrs,
+            gpib_address,
+        })
+        .await
+    }
+
+    /// Connect over a serial port with the given line settings.
+    #[cfg(feature = "serial")]
+    pub async fn connect_serial(config: &SerialConfig) -> Result<Self> {
+        Self::open(Endpoint::Serial(config.clone())).await
+    }
+
+    /// Connect over USB through the `usbtmc` driver's device node, e.g.
+    /// `/dev/usbtmc0`; see [`usbtmc`](crate::usbtmc).
+    #[cfg(target_os = "linux")]
+    pub async fn connect_usbtmc(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        Self::open(Endpoint::Usbtmc(path.as_ref().to_path_buf())).await
+    }
+
+    /// Connect to a simulated instrument, see [`mock`](crate::mock).
+    pub fn mock(device: &MockDevice) -> Self {
+        let mut inst = Self::from_transport(device.clone());
+        inst.endpoint = Some(Endpoint::Mock(device.clone()));
+        inst
+    }
+
+    /// Use an already open [`Transport`]. Such a handle cannot
+    /// [`reconnect`](Self::reconnect), as it does not know how the link
+    /// was opened.
+    pub fn from_transport(transport: impl Transport + 'static) -> Self {
+        Self::from_link(Box::new(transport))
+    }
+
+    async fn open(endpoint: Endpoint) -> Result<Self> {
+        let mut inst = Self::from_link(endpoint.open().await?);
+        inst.endpoint = Some(endpoint);
+        Ok(inst)
+    }
+
+    fn from_link(inner: Box<dyn Transport>) -> Self {
+        Self {
+            inner,
+            endpoint: None,
+            escalation: None,
+            timeouts: CommandTimeouts::none(),
+            auto_reconnect: None,
+            retry: None,
+            healthy: true,
+            capabilities: Capabilities::spd3303x(),
+            strict: false,
+            channels: Default::default(),
+            transcript: None,
+            redactor: Redactor::new(),
+            training: None,
+            response_rules: ResponseRules::spd3303x(),
+            quirks: Quirks::known(),
+            firmware: None,
+            #[cfg(feature = "ssh")]
+            tunnel: None,
+            link_established_at: SystemTime::now(),
+            counters: CommandCounters::default(),
+            display_text: None,
+            undo: None,
+            notes: NoteLog::new(),
+            conflict: ConflictState::default(),
+            channel_policy: ChannelPolicy::allow_all(),
+            middleware: Vec::new(),
+            clock: Arc::new(TokioClock),
+        }
+    }
+
+    #[cfg(feature = "ssh")]
+    pub(crate) fn set_tunnel(&mut self, tunnel: SshTunnel) {
+        self.tunnel = Some(tunnel);
     }
 
     pub async fn close(&mut self) -> Result<()> {
@@ -255,16 +950,154 @@ impl Spd3303x {
         Ok(())
     }
 
+    /// Install or remove the timeout escalation ladder, see
+    /// [`escalation`](crate::escalation). Off by default.
+    pub fn set_escalation(&mut self, policy: Option<EscalationPolicy>) {
+        self.escalation = policy;
+    }
+
+    pub fn escalation(&self) -> Option<&EscalationPolicy> {
+        self.escalation.as_ref()
+    }
+
+    /// Deadlines of each write and query while no escalation ladder is
+    /// installed (the ladder has its own). None by default.
+    pub fn set_command_timeouts(&mut self, timeouts: CommandTimeouts) {
+        self.timeouts = timeouts;
+    }
+
+    pub fn command_timeouts(&self) -> CommandTimeouts {
+        self.timeouts
+    }
+
+    /// Run `operation` with `timeouts` in place of the handle's, e.g. a
+    /// longer deadline for one slow query:
+    ///
+    /// ```ignore
+    /// let timeouts = CommandTimeouts::uniform(Duration::from_secs(10));
+    /// let reply = inst.with_command_timeouts(timeouts, |inst| Box::pin(inst.query_raw("*TST?")));
+    /// ```
+    pub async fn with_command_timeouts<T>(
+        &mut self,
+        timeouts: CommandTimeouts,
+        operation: impl for<'a> FnOnce(
+            &'a mut Spd3303x,
+        ) -> Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>,
+    ) -> Result<T> {
+        let saved = std::mem::replace(&mut self.timeouts, timeouts);
+        let result = operation(self).await;
+        self.timeouts = saved;
+        result
+    }
+
+    /// Reconnect and resend when a command fails on the link, see
+    /// [`reconnect`](crate::reconnect). Off by default.
+    pub fn set_auto_reconnect(&mut self, policy: Option<ReconnectPolicy>) {
+        self.auto_reconnect = policy;
+    }
+
+    pub fn auto_reconnect(&self) -> Option<&ReconnectPolicy> {
+        self.auto_reconnect.as_ref()
+    }
+
+    /// Install or remove the retry policy for transient command failures,
+    /// see [`retry`](crate::retry). Off by default.
+    pub fn set_retry_policy(&mut self, policy: Option<RetryPolicy>) {
+        self.retry = policy;
+    }
+
+    pub fn retry_policy(&self) -> Option<&RetryPolicy> {
+        self.retry.as_ref()
+    }
+
+    /// Replace the time source of sequences, settling, control loops,
+    /// monitors and timed outputs on this handle, see
+    /// [`clock`](crate::clock).
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    pub fn clock(&self) -> Arc<dyn Clock> {
+        self.clock.clone()
+    }
+
+    /// `false` once a command has timed out on every rung of the
+    /// escalation ladder, or timed out under the
+    /// [command timeouts](Self::set_command_timeouts) without the link
+    /// being cleared, until the next successful clear or
+    /// [`reconnect`](Self::reconnect).
+    pub fn is_healthy(&self) -> bool {
+        self.healthy
+    }
+
+    /// Drop the link and open it again where it was first connected.
+    /// Host-side settings (limits, pipelines, rules) are kept.
+    pub async fn reconnect(&mut self) -> Result<()> {
+        let endpoint = self
+            .endpoint
+            .clone()
+            .ok_or_else(|| anyhow!("this connection cannot be reopened"))?;
+        match tokio::time::timeout(CLOSE_TIMEOUT, self.inner.close()).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => debug!("reconnect: closing the old link failed: {e:#}"),
+            Err(_) => debug!("reconnect: closing the old link timed out"),
+        }
+        self.inner = endpoint.open().await.context("failed to reconnect")?;
+        self.healthy = true;
+        self.link_established_at = SystemTime::now();
+        self.counters.reconnects += 1;
+        debug!("reconnected");
+        Ok(())
+    }
+
+    /// When the current link was opened; reset by reconnects.
+    pub fn link_established_at(&self) -> SystemTime {
+        self.link_established_at
+    }
+
+    /// Commands sent since this handle was created, across reconnects.
+    pub fn command_counters(&self) -> CommandCounters {
+        self.counters
+    }
+
     pub async fn idn(&mut self) -> Result<String> {
         self.query("*IDN?\n").await
     }
 
+    /// Query and parse `*IDN?`. Also remembers the firmware version, which
+    /// enables gating of features with known quirks.
+    pub async fn identity(&mut self) -> Result<Identity> {
+        let identity = Identity::parse(&self.idn().await?)?;
+        self.firmware = match identity.firmware_version() {
+            Ok(firmware) => Some(firmware),
+            Err(e) => {
+                warn!("{e:#}; features with known quirks are not gated");
+                None
+            }
+        };
+        Ok(identity)
+    }
+
+    /// Firmware version seen by the last `identity()` call.
+    pub fn firmware(&self) -> Option<FirmwareVersion> {
+        self.firmware
+    }
+
+    /// Treat `quirk.feature` as broken on firmware older than
+    /// `quirk.fixed_in`, in addition to the quirks known to this crate.
+    pub fn add_quirk(&mut self, quirk: Quirk) {
+        self.quirks.add(quirk);
+    }
+
     pub async fn save_state(&mut self, slot: u8) -> Result<()> {
+        self.gate(Feature::SaveRecall)?;
         ensure_slot(slot)?;
         self.write(&format!("*SAV {}\n", slot)).await
     }
 
+    /// Restores CH1 and CH2, so both must be allowed by the channel policy.
     pub async fn recall_state(&mut self, slot: u8) -> Result<()> {
+        self.gate(Feature::SaveRecall)?;
         ensure_slot(slot)?;
         self.write(&format!("*RCL {}\n", slot)).await
     }
@@ -284,7 +1117,9 @@ impl Spd3303x {
             .await
     }
 
-    pub async fn query_voltage(&mut self, channel: Channel) -> Result<f64> {
+    /// Programmed voltage of `channel` (`CHn:VOLT?`), not what the output
+    /// actually delivers; see [`measured_voltage`](Self::measured_voltage).
+    pub async fn setpoint_voltage(&mut self, channel: Channel) -> Result<f64> {
         guard_programmable(channel)?;
         let resp = self
             .query(&format!("{}:VOLT?\n", channel.as_scpi()))
@@ -292,13 +1127,20 @@ impl Spd3303x {
         parse_f64(&resp)
     }
 
+    #[deprecated(note = "use `setpoint_voltage`, or `measured_voltage` for the output reading")]
+    pub async fn query_voltage(&mut self, channel: Channel) -> Result<f64> {
+        self.setpoint_voltage(channel).await
+    }
+
     pub async fn set_current(&mut self, channel: Channel, amps: f64) -> Result<()> {
         guard_programmable(channel)?;
         self.write(&format!("{}:CURR {:.6}\n", channel.as_scpi(), amps))
             .await
     }
 
-    pub async fn query_current(&mut self, channel: Channel) -> Result<f64> {
+    /// Programmed current limit of `channel` (`CHn:CURR?`), not the
+    /// current drawn; see [`measured_current`](Self::measured_current).
+    pub async fn setpoint_current(&mut self, channel: Channel) -> Result<f64> {
         guard_programmable(channel)?;
         let resp = self
             .query(&format!("{}:CURR?\n", channel.as_scpi()))
@@ -306,11 +1148,41 @@ impl Spd3303x {
         parse_f64(&resp)
     }
 
+    #[deprecated(note = "use `setpoint_current`, or `measured_current` for the output reading")]
+    pub async fn query_current(&mut self, channel: Channel) -> Result<f64> {
+        self.setpoint_current(channel).await
+    }
+
+    /// Enforces the enable dependencies of the channel policy: a channel
+    /// whose prerequisite is off is not switched on, and the channels that
+    /// depend on one are switched off before it.
     pub async fn set_output(&mut self, channel: Channel, state: OutputState) -> Result<()> {
+        self.ensure_permitted(&[channel])?;
+        self.ensure_dependencies(channel, state).await?;
         self.write(&format!("OUTPut {},{}\n", channel.as_scpi(), state.as_str()))
             .await
     }
 
+    /// Switch `channel` off past every gate: the channel policy, the enable
+    /// dependencies, observer-only mode, training and the middleware. For
+    /// safety shutoffs, which must not be refused.
+    pub async fn force_output_off(&mut self, channel: Channel) -> Result<()> {
+        self.send_write(&format!("OUTPut {},OFF\n", channel.as_scpi()), false)
+            .await
+    }
+
+    /// Switch the output of every selected channel, in channel order.
+    pub async fn set_outputs(
+        &mut self,
+        channels: impl Into<ChannelSelector>,
+        state: OutputState,
+    ) -> Result<()> {
+        for channel in channels.into().channels() {
+            self.set_output(channel, state).await?;
+        }
+        Ok(())
+    }
+
     pub async fn query_output(&mut self, channel: Channel) -> Result<bool> {
         match channel {
             Channel::Ch1 | Channel::Ch2 => {
@@ -332,11 +1204,14 @@ impl Spd3303x {
         }
     }
 
+    /// Couples CH1 and CH2, so both must be allowed by the channel policy.
     pub async fn set_track_mode(&mut self, mode: TrackMode) -> Result<()> {
+        self.gate(Feature::TrackMode)?;
         self.write(&format!("OUTP:TRACK {}\n", mode.as_value())).await
     }
 
     pub async fn query_track_mode(&mut self) -> Result<TrackMode> {
+        self.gate(Feature::TrackMode)?;
         let resp = self.query("OUTP:TRACK?\n").await?;
         let value = resp.trim().parse::<u8>()?;
         TrackMode::from_value(value)
@@ -344,48 +1219,331 @@ impl Spd3303x {
 
     pub async fn set_wave_display(&mut self, channel: Channel, state: OutputState) -> Result<()> {
         guard_programmable(channel)?;
+        self.gate(Feature::WaveDisplay)?;
         self.write(&format!("OUTP:WAVE {},{}\n", channel.as_scpi(), state.as_str()))
             .await
     }
 
-    pub async fn measure_voltage(&mut self, channel: Option<Channel>) -> Result<f64> {
-        if let Some(ch) = channel {
-            guard_programmable(ch)?;
+    /// Whether the waveform display of CH1/CH2 is on, from the status word
+    /// bits.
+    pub async fn query_wave_display(&mut self, channel: Channel) -> Result<bool> {
+        guard_programmable(channel)?;
+        let status = self.system_status().await?;
+        Ok(status.wave_display_on(channel).unwrap_or(false))
+    }
+
+    /// Install the measurement post-processing pipeline for a channel.
+    ///
+    /// The pipeline is applied to `measure_*` readings that name the channel
+    /// explicitly (including `channel_status`); readings of the currently
+    /// selected channel (`None`) are returned unprocessed.
+    pub fn set_pipeline(&mut self, channel: Channel, pipeline: SamplePipeline) -> Result<()> {
+        guard_programmable(channel)?;
+        self.channel_config_mut(channel).pipeline = pipeline;
+        Ok(())
+    }
+
+    pub fn pipeline(&self, channel: Channel) -> Option<&SamplePipeline> {
+        match channel {
+            Channel::Ch1 | Channel::Ch2 => Some(&self.channel_config(channel).pipeline),
+            Channel::Ch3 => None,
         }
-        let suffix = match channel { Some(ch) => format!(" {}", ch.as_scpi()), None => String::new() };
-        let resp = self.query(&format!("MEAS:VOLT?{}\n", suffix)).await?;
-        parse_f64(&resp)
     }
 
-    pub async fn measure_current(&mut self, channel: Option<Channel>) -> Result<f64> {
-        if let Some(ch) = channel {
-            guard_programmable(ch)?;
+    /// In strict mode setpoints finer than the setting resolution are
+    /// rejected with [`Violation::TooPrecise`](crate::validate::Violation)
+    /// instead of being rounded by the instrument. Off by default.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    /// Restrict the setpoints accepted for a channel below its rated range.
+    ///
+    /// Enforced on every voltage and current write, typed or raw, and by
+    /// `timer_set`; see [`validate`] for the exact rules.
+    pub fn set_limits(&mut self, channel: Channel, limits: Limits) -> Result<()> {
+        guard_programmable(channel)?;
+        validate::ensure(validate::check_limits(&limits))?;
+        self.channel_config_mut(channel).limits = limits;
+        Ok(())
+    }
+
+    /// Restrict which channels this handle may control. Denied channels
+    /// can still be read; every setter, timer and raw write naming them
+    /// fails with [`Violation::ChannelForbidden`].
+    pub fn set_channel_policy(&mut self, policy: ChannelPolicy) {
+        self.channel_policy = policy;
+    }
+
+    pub fn channel_policy(&self) -> &ChannelPolicy {
+        &self.channel_policy
+    }
+
+    /// Let `channel` be on only while `requires` is on; see
+    /// [`ChannelPolicy::require_on`].
+    pub fn require_on(&mut self, channel: Channel, requires: Channel) {
+        self.channel_policy =
+            std::mem::take(&mut self.channel_policy).require_on(channel, requires);
+    }
+
+    /// Deny control of `channel`, e.g. a CH3 rail switched by hand.
+    pub fn forbid(&mut self, channel: Channel) {
+        self.channel_policy = std::mem::take(&mut self.channel_policy).forbid(channel);
+    }
+
+    fn ensure_permitted(&self, channels: &[Channel]) -> Result<()> {
+        for &channel in channels {
+            validate::ensure(validate::check_channel_policy(channel, &self.channel_policy))?;
         }
-        let suffix = match channel { Some(ch) => format!(" {}", ch.as_scpi()), None => String::new() };
-        let resp = self.query(&format!("MEAS:CURR?{}\n", suffix)).await?;
-        parse_f64(&resp)
+        Ok(())
+    }
+
+    /// Check the enable dependencies before switching `channel`, switching
+    /// its dependents off first when it goes off.
+    ///
+    /// Switching off is never refused: the dependents are switched off on a
+    /// best-effort basis, all of them if the status word cannot be read, and
+    /// failures are only logged.
+    async fn ensure_dependencies(&mut self, channel: Channel, state: OutputState) -> Result<()> {
+        match state {
+            OutputState::On => {
+                let prerequisites: Vec<Channel> =
+                    self.channel_policy.prerequisites(channel).collect();
+                if prerequisites.is_empty() {
+                    return Ok(());
+                }
+                let status = self.system_status().await?;
+                for requires in prerequisites {
+                    let on = match requires {
+                        Channel::Ch1 => status.ch1_output_on,
+                        Channel::Ch2 => status.ch2_output_on,
+                        Channel::Ch3 => {
+                            return Err(anyhow!(
+                                "CH3 cannot be a prerequisite of {}: its output state \
+                                 cannot be read back",
+                                channel.label()
+                            ));
+                        }
+                    };
+                    if !on {
+                        return Err(Violation::DependencyOff { channel, requires }.into());
+                    }
+                }
+            }
+            OutputState::Off => {
+                let dependents = self.channel_policy.dependents(channel);
+                if dependents.is_empty() {
+                    return Ok(());
+                }
+                let status = match self.system_status().await {
+                    Ok(status) => Some(status),
+                    Err(e) => {
+                        warn!(
+                            "policy: reading the output states failed ({e:#}); \
+                             switching off every dependent of {}",
+                            channel.label()
+                        );
+                        None
+                    }
+                };
+                for dependent in dependents {
+                    let on = status
+                        .as_ref()
+                        .and_then(|status| status.output_on(dependent))
+                        .unwrap_or(true);
+                    if on {
+                        info!(
+                            "policy: switching {} off before {}",
+                            dependent.label(),
+                            channel.label()
+                        );
+                        if let Err(e) = self.force_output_off(dependent).await {
+                            warn!("policy: switching {} off failed: {e:#}", dependent.label());
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn limits(&self, channel: Channel) -> Option<Limits> {
+        match channel {
+            Channel::Ch1 | Channel::Ch2 => Some(self.channel_config(channel).limits),
+            Channel::Ch3 => None,
+        }
+    }
+
+    /// Record the total series resistance of the leads between a channel's
+    /// terminals and the DUT, used by `set_load_voltage`.
+    ///
+    /// The SPD3303X has no remote sense terminals, so long or thin leads cause
+    /// a voltage error of `I·R` at the load.
+    pub fn set_wire_resistance(&mut self, channel: Channel, ohms: f64) -> Result<()> {
+        guard_programmable(channel)?;
+        if !ohms.is_finite() || ohms < 0.0 {
+            return Err(anyhow!("wire resistance must be a finite, non-negative value"));
+        }
+        self.channel_config_mut(channel).wire_resistance_ohms = ohms;
+        Ok(())
+    }
+
+    pub fn wire_resistance(&self, channel: Channel) -> Option<f64> {
+        match channel {
+            Channel::Ch1 | Channel::Ch2 => Some(self.channel_config(channel).wire_resistance_ohms),
+            Channel::Ch3 => None,
+        }
+    }
+
+    /// Program `volts` as the voltage at the DUT, raising the terminal
+    /// setpoint by the measured `I·R` drop across the configured wire
+    /// resistance.
+    ///
+    /// Compensation is iterative (the load current changes with the
+    /// setpoint) and the setpoint is never raised above the channel's rated
+    /// maximum or configured voltage limit. Returns the terminal setpoint
+    /// finally programmed. The drop depends on the load, so call this again
+    /// when the load changes.
+    pub async fn set_load_voltage(&mut self, channel: Channel, volts: f64) -> Result<f64> {
+        operation::run("set_load_voltage", self.compensate_load_voltage(channel, volts)).await
+    }
+
+    async fn compensate_load_voltage(&mut self, channel: Channel, volts: f64) -> Result<f64> {
+        guard_programmable(channel)?;
+        let ohms = self.channel_config(channel).wire_resistance_ohms;
+        let max_v = self
+            .channel_limits(channel)
+            .effective_max_voltage(&self.capabilities);
+        // Owned, so the closure does not borrow `self` across the writes.
+        let capabilities = self.capabilities.clone();
+        let quantize =
+            |volts: f64| capabilities.quantize(Quantity::Voltage, volts.clamp(0.0, max_v));
+        let mut setpoint = quantize(volts);
+        self.set_voltage(channel, setpoint).await?;
+        if ohms == 0.0 {
+            return Ok(setpoint);
+        }
+
+        for _ in 0..COMPENSATION_MAX_ITERATIONS {
+            let current = self.measure_raw(Quantity::Current, Some(channel)).await?;
+            let next = quantize(volts + current * ohms);
+            if (next - setpoint).abs() < COMPENSATION_TOLERANCE_V {
+                break;
+            }
+            debug!(
+                "set_load_voltage: {} {:.3} A through {:.4} ohm, setpoint {:.3} V -> {:.3} V",
+                channel.as_scpi(),
+                current,
+                ohms,
+                setpoint,
+                next
+            );
+            setpoint = next;
+            self.set_voltage(channel, setpoint).await?;
+        }
+        Ok(setpoint)
+    }
+
+    /// Voltage read back at the output (`MEAS:VOLT?`), through the
+    /// channel's measurement pipeline; see
+    /// [`setpoint_voltage`](Self::setpoint_voltage) for the programmed one.
+    pub async fn measured_voltage(&mut self, channel: Option<Channel>) -> Result<f64> {
+        self.measure(Quantity::Voltage, channel).await
+    }
+
+    /// Current read back at the output (`MEAS:CURR?`); see
+    /// [`setpoint_current`](Self::setpoint_current) for the limit.
+    pub async fn measured_current(&mut self, channel: Option<Channel>) -> Result<f64> {
+        self.measure(Quantity::Current, channel).await
+    }
+
+    pub async fn measured_power(&mut self, channel: Option<Channel>) -> Result<f64> {
+        self.measure(Quantity::Power, channel).await
+    }
+
+    #[deprecated(note = "renamed to `measured_voltage`")]
+    pub async fn measure_voltage(&mut self, channel: Option<Channel>) -> Result<f64> {
+        self.measured_voltage(channel).await
     }
 
+    #[deprecated(note = "renamed to `measured_current`")]
+    pub async fn measure_current(&mut self, channel: Option<Channel>) -> Result<f64> {
+        self.measured_current(channel).await
+    }
+
+    #[deprecated(note = "renamed to `measured_power`")]
     pub async fn measure_power(&mut self, channel: Option<Channel>) -> Result<f64> {
+        self.measured_power(channel).await
+    }
+
+    async fn measure(&mut self, quantity: Quantity, channel: Option<Channel>) -> Result<f64> {
+        let value = self.measure_raw(quantity, channel).await?;
+        let Some(ch) = channel else {
+            return Ok(value);
+        };
+        let pipeline = self.channel_config(ch).pipeline.clone();
+        if pipeline.is_empty() {
+            return Ok(value);
+        }
+        let current_a = match quantity {
+            Quantity::Current => Some(value),
+            _ if pipeline.needs_current() => {
+                Some(self.measure_raw(Quantity::Current, channel).await?)
+            }
+            _ => None,
+        };
+        Ok(pipeline.apply(Sample {
+            channel: ch,
+            quantity,
+            value,
+            current_a,
+        }))
+    }
+
+    /// A reading without the channel's pipeline applied.
+    pub(crate) async fn measure_raw(
+        &mut self,
+        quantity: Quantity,
+        channel: Option<Channel>,
+    ) -> Result<f64> {
         if let Some(ch) = channel {
             guard_programmable(ch)?;
         }
+        if quantity == Quantity::Power {
+            self.gate(Feature::MeasurePower)?;
+        }
         let suffix = match channel { Some(ch) => format!(" {}", ch.as_scpi()), None => String::new() };
-        // According to the SPD3303X/3303X-E manual, the SCPI command is
+        // According to the SPD3303X/3303X-E manual, the power query is
         // `MEASure: POWEr? [{CH1|CH2}]`. Use the full mnemonic `POWEr`
         // here, as some firmware revisions appear not to respond to the
         // abbreviated `POW?` form.
-        let resp = self.query(&format!("MEAS:POWEr?{}\n", suffix)).await?;
+        let mnemonic = match quantity {
+            Quantity::Voltage => "VOLT",
+            Quantity::Current => "CURR",
+            Quantity::Power => "POWEr",
+        };
+        let resp = self
+            .query(&format!("MEAS:{}?{}\n", mnemonic, suffix))
+            .await?;
         parse_f64(&resp)
     }
 
     pub async fn channel_status(&mut self, channel: Channel) -> Result<ChannelStatus> {
+        let setpoint_voltage_v = self.setpoint_voltage(channel).await?;
+        let setpoint_current_a = self.setpoint_current(channel).await?;
+        #[allow(deprecated)]
         Ok(ChannelStatus {
-            set_voltage_v: self.query_voltage(channel).await?,
-            set_current_a: self.query_current(channel).await?,
-            measured_voltage_v: self.measure_voltage(Some(channel)).await?,
-            measured_current_a: self.measure_current(Some(channel)).await?,
-            measured_power_w: self.measure_power(Some(channel)).await?,
+            setpoint_voltage_v,
+            setpoint_current_a,
+            measured_voltage_v: self.measured_voltage(Some(channel)).await?,
+            measured_current_a: self.measured_current(Some(channel)).await?,
+            measured_power_w: self.measured_power(Some(channel)).await?,
+            set_voltage_v: setpoint_voltage_v,
+            set_current_a: setpoint_current_a,
         })
     }
 
@@ -395,19 +1553,34 @@ impl Spd3303x {
         group: u8,
         voltage: f64,
         current: f64,
-        seconds: f64,
+        duration: Duration,
     ) -> Result<()> {
-        guard_programmable(channel)?;
+        self.gate(Feature::Timer)?;
+        validate::ensure(validate::check_setpoint(
+            channel,
+            voltage,
+            current,
+            &self.capabilities,
+            &self.channel_limits(channel),
+        ))?;
+        self.ensure_precision(Quantity::Voltage, voltage)?;
+        self.ensure_precision(Quantity::Current, current)?;
         ensure_group(group)?;
+        validate::ensure(validate::check_timer_duration(duration))?;
         self.write(&format!(
-            "TIMER:SET {},{},{:.6},{:.6},{:.6}\n",
-            channel.as_scpi(), group, voltage, current, seconds
+            "TIMER:SET {},{},{:.6},{:.6},{:.3}\n",
+            channel.as_scpi(), group, voltage, current, duration.as_secs_f64()
         ))
-        .await
+        .await?;
+        if matches!(channel, Channel::Ch1 | Channel::Ch2) {
+            self.channel_config_mut(channel).timer_groups = None;
+        }
+        Ok(())
     }
 
     pub async fn timer_query(&mut self, channel: Channel, group: u8) -> Result<TimerEntry> {
         guard_programmable(channel)?;
+        self.gate(Feature::Timer)?;
         ensure_group(group)?;
         let resp = self
             .query(&format!("TIMER:SET? {},{}\n", channel.as_scpi(), group))
@@ -415,10 +1588,68 @@ impl Spd3303x {
         parse_timer_response(group, &resp)
     }
 
+    /// Read every timer group of `channel`.
+    ///
+    /// A group whose reply is missing or garbled is retried once and then
+    /// recorded in [`TimerGroups::errors`] while the remaining groups are
+    /// still read, so one bad response does not lose the whole table.
+    pub async fn read_all_timers(&mut self, channel: Channel) -> Result<TimerGroups> {
+        guard_programmable(channel)?;
+        operation::run("read_all_timers", self.read_timer_groups(channel)).await
+    }
+
+    async fn read_timer_groups(&mut self, channel: Channel) -> Result<TimerGroups> {
+        let mut groups = TimerGroups {
+            channel,
+            entries: Default::default(),
+            errors: Vec::new(),
+        };
+        for group in 1..=TIMER_GROUPS {
+            let mut result = self.timer_query(channel, group).await;
+            if let Err(e) = &result {
+                debug!("read_all_timers: group {group} failed ({e:#}), retrying");
+                result = self.timer_query(channel, group).await;
+            }
+            match result {
+                Ok(entry) => groups.entries[usize::from(group - 1)] = Some(entry),
+                Err(e) => groups.errors.push((group, format!("{e:#}"))),
+            }
+        }
+        Ok(groups)
+    }
+
     pub async fn timer_state(&mut self, channel: Channel, state: TimerState) -> Result<()> {
         guard_programmable(channel)?;
+        self.gate(Feature::Timer)?;
         self.write(&format!("TIMER {},{}\n", channel.as_scpi(), state.as_str()))
-            .await
+            .await?;
+        let started = matches!(state, TimerState::On).then(|| self.clock.now());
+        self.channel_config_mut(channel).timer_started = started;
+        Ok(())
+    }
+
+    /// When this handle switched the timer of `channel` on, if it is on as
+    /// far as the handle knows.
+    pub(crate) fn timer_started(&self, channel: Channel) -> Option<Instant> {
+        match channel {
+            Channel::Ch1 | Channel::Ch2 => self.channel_config(channel).timer_started,
+            Channel::Ch3 => None,
+        }
+    }
+
+    /// The timer groups of `channel`, read once and then kept until the
+    /// next `timer_set`.
+    pub(crate) async fn programmed_timer_groups(
+        &mut self,
+        channel: Channel,
+    ) -> Result<[TimerEntry; TIMER_GROUPS as usize]> {
+        guard_programmable(channel)?;
+        if let Some(groups) = &self.channel_config(channel).timer_groups {
+            return Ok(groups.clone());
+        }
+        let groups = self.read_all_timers(channel).await?.into_complete()?;
+        self.channel_config_mut(channel).timer_groups = Some(groups.clone());
+        Ok(groups)
     }
 
     pub async fn system_error(&mut self) -> Result<String> {
@@ -429,42 +1660,54 @@ impl Spd3303x {
         self.query("SYST:VERS?\n").await
     }
 
+    /// `SYST:VERS?` parsed for comparison.
+    pub async fn firmware_version(&mut self) -> Result<FirmwareVersion> {
+        self.system_version().await?.parse()
+    }
+
     pub async fn system_status(&mut self) -> Result<SystemStatus> {
         let resp = self.query("SYST:STAT?\n").await?;
-        let trimmed = resp.trim_start_matches("0x").trim();
-        let word = u32::from_str_radix(trimmed, 16)?;
-        Ok(SystemStatus::from_word(word))
+        parse::system_status(resp.as_bytes())
+            .with_context(|| format!("invalid status reply {resp:?}"))
     }
 
     pub async fn set_ip(&mut self, ip: &str) -> Result<()> {
+        self.gate(Feature::Network)?;
         self.write(&format!("IPaddr {}\n", ip)).await
     }
 
     pub async fn set_mask(&mut self, mask: &str) -> Result<()> {
+        self.gate(Feature::Network)?;
         self.write(&format!("MASKaddr {}\n", mask)).await
     }
 
     pub async fn set_gateway(&mut self, gateway: &str) -> Result<()> {
+        self.gate(Feature::Network)?;
         self.write(&format!("GATEaddr {}\n", gateway)).await
     }
 
     pub async fn query_ip(&mut self) -> Result<String> {
+        self.gate(Feature::Network)?;
         self.query("IPaddr?\n").await
     }
 
     pub async fn query_mask(&mut self) -> Result<String> {
+        self.gate(Feature::Network)?;
         self.query("MASKaddr?\n").await
     }
 
     pub async fn query_gateway(&mut self) -> Result<String> {
+        self.gate(Feature::Network)?;
         self.query("GATEaddr?\n").await
     }
 
     pub async fn set_dhcp(&mut self, state: DhcpState) -> Result<()> {
+        self.gate(Feature::Network)?;
         self.write(&format!("DHCP {}\n", state.as_str())).await
     }
 
     pub async fn query_dhcp(&mut self) -> Result<DhcpState> {
+        self.gate(Feature::Network)?;
         let resp = self.query("DHCP?\n").await?;
         if resp.to_uppercase().contains("ON") {
             Ok(DhcpState::On)
@@ -482,45 +1725,561 @@ impl Spd3303x {
         })
     }
 
-    async fn write(&mut self, command: &str) -> Result<()> {
-        debug!("SCPI write  -> {}", command.trim_end_matches('\n'));
-        self.inner
-            .write(command.as_bytes())
-            .await
-            .with_context(|| format!("failed to send {command:?}"))?;
+    /// Start recording the SCPI traffic, keeping the last `capacity`
+    /// exchanges. Replaces any transcript recorded so far.
+    pub fn enable_transcript(&mut self, capacity: usize) {
+        self.transcript = Some(Transcript::new(capacity));
+    }
+
+    pub fn disable_transcript(&mut self) -> Option<Transcript> {
+        self.transcript.take()
+    }
+
+    pub fn transcript(&self) -> Option<&Transcript> {
+        self.transcript.as_ref()
+    }
+
+    /// Never log, record or report `secret` in clear text; see
+    /// [`crate::redact`].
+    pub fn add_redacted_secret(&mut self, secret: impl Into<String>) {
+        self.redactor.add_secret(secret);
+    }
+
+    /// Pass every logged or recorded command, reply and error through
+    /// `hook`, after registered secrets are replaced.
+    pub fn set_redaction_hook(&mut self, hook: impl Fn(&str) -> String + Send + Sync + 'static) {
+        self.redactor.set_hook(hook);
+    }
+
+    /// Run `middleware` around every command, after those added before;
+    /// see [`middleware`](crate::middleware).
+    pub fn add_middleware(&mut self, middleware: Arc<dyn Middleware>) {
+        self.middleware.push(middleware);
+    }
+
+    pub fn clear_middleware(&mut self) {
+        self.middleware.clear();
+    }
+
+    /// Enter or leave training mode, see [`training`](crate::training).
+    pub fn set_training(&mut self, training: Option<Training>) {
+        self.training = training;
+    }
+
+    /// Require replies to queries starting with `header` (e.g. `"DHCP?"`)
+    /// to have `shape`; others fail with
+    /// [`UnexpectedResponse`](crate::response::UnexpectedResponse). Replaces
+    /// the built-in shape for that header, if any.
+    pub fn expect_response(&mut self, header: &str, shape: ResponseShape) {
+        self.response_rules.set(header, shape);
+    }
+
+    /// Stop checking replies to `header`.
+    pub fn ignore_response_shape(&mut self, header: &str) {
+        self.response_rules.remove(header);
+    }
+
+    /// Send an arbitrary SCPI command, bypassing all validation except the
+    /// channel policy and the limits: a command naming a denied channel is
+    /// rejected, `CHn:VOLT`/`CHn:CURR` are checked as in
+    /// [`set_voltage`](Self::set_voltage), and `OUTPut CHn,ON|OFF` obeys
+    /// the enable dependencies as in [`set_output`](Self::set_output).
+    ///
+    /// A setpoint whose value is not a plain number, e.g. `MAX` or `30V`,
+    /// is rejected, and so is a bare `VOLT`/`CURR` above the limits of
+    /// either channel, since it applies to whichever one is selected. While
+    /// limits are installed, compound commands (`;`) are rejected too, as
+    /// their parts cannot be checked one by one.
+    ///
+    /// A trailing newline is added if missing.
+    pub async fn write_raw(&mut self, command: &str) -> Result<()> {
+        if let Some((channel, state)) = output_change(command) {
+            self.ensure_permitted(&[channel])?;
+            self.ensure_dependencies(channel, state).await?;
+        }
+        self.write(&terminated(command)).await
+    }
+
+    /// Send an arbitrary SCPI query and return the trimmed response,
+    /// bypassing all validation.
+    pub async fn query_raw(&mut self, command: &str) -> Result<String> {
+        self.query(&terminated(command)).await
+    }
+
+    fn channel_config(&self, channel: Channel) -> &ChannelConfig {
+        &self.channels[config_index(channel)]
+    }
+
+    fn channel_config_mut(&mut self, channel: Channel) -> &mut ChannelConfig {
+        &mut self.channels[config_index(channel)]
+    }
+
+    fn channel_limits(&self, channel: Channel) -> Limits {
+        self.limits(channel).unwrap_or_default()
+    }
+
+    fn ensure_precision(&self, quantity: Quantity, value: f64) -> Result<()> {
+        if !self.strict {
+            return Ok(());
+        }
+        validate::ensure(validate::check_precision(quantity, value, &self.capabilities))
+    }
+
+    pub(crate) fn display_support(&self) -> Option<bool> {
+        self.display_text
+    }
+
+    pub(crate) fn set_display_support(&mut self, supported: bool) {
+        self.display_text = Some(supported);
+    }
+
+    pub(crate) fn conflict_state(&self) -> &ConflictState {
+        &self.conflict
+    }
+
+    pub(crate) fn conflict_state_mut(&mut self) -> &mut ConflictState {
+        &mut self.conflict
+    }
+
+    pub(crate) fn notes(&self) -> &NoteLog {
+        &self.notes
+    }
+
+    pub(crate) fn undo_stack_ref(&self) -> Option<&UndoStack> {
+        self.undo.as_ref()
+    }
+
+    pub(crate) fn undo_stack_mut(&mut self) -> Option<&mut UndoStack> {
+        self.undo.as_mut()
+    }
+
+    pub(crate) fn set_undo_stack(&mut self, stack: Option<UndoStack>) {
+        self.undo = stack;
+    }
+
+    /// Fail with `FirmwareUnsupported` if `feature` has a quirk on the
+    /// connected firmware. Passes while the firmware is unknown.
+    pub(crate) fn gate(&self, feature: Feature) -> Result<()> {
+        if let Some(firmware) = self.firmware {
+            self.quirks.check(feature, firmware)?;
+        }
         Ok(())
     }
 
+    async fn write(&mut self, command: &str) -> Result<()> {
+        self.send_write(command, true).await
+    }
+
+    /// The channel policy, limits and strict mode of this handle as the
+    /// middleware every gated write passes first.
+    fn safety_layer(&self) -> SafetyLayer<'_> {
+        SafetyLayer {
+            capabilities: &self.capabilities,
+            limits: [
+                self.channel_limits(Channel::Ch1),
+                self.channel_limits(Channel::Ch2),
+            ],
+            policy: &self.channel_policy,
+            strict: self.strict,
+        }
+    }
+
+    /// Write `command`; without `gated` the safety layer, the training
+    /// confirmation, the observer-only check and the middleware cannot
+    /// refuse it.
+    async fn send_write(&mut self, command: &str, gated: bool) -> Result<()> {
+        let shown = self.redactor.redact(command);
+        debug!("SCPI write  -> {}", shown.trim_end_matches('\n'));
+        let chain = self.middleware.clone();
+        let line = shown.trim_end_matches('\n');
+        let allowed = if gated {
+            let safe = self.safety_layer().before_write(line).await;
+            let confirmed = match (safe, &mut self.training) {
+                (Err(e), _) => Err(e),
+                (Ok(()), Some(training)) => training.confirm(&shown).await,
+                (Ok(()), None) => Ok(()),
+            };
+            // Switching an output off is let through in observer-only mode,
+            // so that failsafes and watchdogs still work.
+            let switching_off = matches!(output_change(command), Some((_, OutputState::Off)));
+            let mut allowed = confirmed.and_then(|()| {
+                if switching_off {
+                    Ok(())
+                } else {
+                    self.ensure_in_control()
+                }
+            });
+            if allowed.is_ok() {
+                allowed = middleware::before_write(&chain, line).await;
+            }
+            allowed
+        } else {
+            Ok(())
+        };
+        let result = match allowed {
+            Ok(()) => {
+                let mut attempt = 1;
+                let result = loop {
+                    let result = self.exchange(command, &shown, false).await;
+                    match self.retry_delay(&shown, attempt, &result) {
+                        Some(delay) => self.clock.sleep(delay).await,
+                        None => break result,
+                    }
+                    attempt += 1;
+                };
+                if let Err(e) = &result {
+                    self.observe_command_error(&shown, e);
+                }
+                result
+                    .map(|_| ())
+                    .with_context(|| format!("failed to send {shown:?}"))
+            }
+            Err(e) => Err(e),
+        };
+        for middleware in &chain {
+            middleware.after_write(line, &result);
+        }
+        self.record(Direction::Write, command, &result);
+        result
+    }
+
     async fn query(&mut self, command: &str) -> Result<String> {
-        debug!("SCPI query  -> {}", command.trim_end_matches('\n'));
-        self.write(command).await?;
-        let resp = self.inner.read(MAX_READ).await?;
-        let raw = String::from_utf8(resp)?;
+        let shown = self.redactor.redact(command);
+        debug!("SCPI query  -> {}", shown.trim_end_matches('\n'));
+        let chain = self.middleware.clone();
+        let line = shown.trim_end_matches('\n');
+        let result = match middleware::before_query(&chain, line).await {
+            Ok(()) => {
+                let mut attempt = 1;
+                loop {
+                    let result = self.query_trimmed(command, &shown).await;
+                    let Some(delay) = self.retry_delay(&shown, attempt, &result) else {
+                        break result;
+                    };
+                    self.clock.sleep(delay).await;
+                    // A late or shifted reply to the failed attempt would be
+                    // read as the answer to the resent query; only retry on
+                    // a link that is back in step.
+                    if let Err(e) = self.resync_link(RETRY_CLEAR_TIMEOUT).await {
+                        warn!("clearing the link before retrying failed: {e:#}");
+                        break result;
+                    }
+                    attempt += 1;
+                }
+            }
+            Err(e) => Err(e),
+        };
+        for middleware in &chain {
+            middleware.after_query(line, &result);
+        }
+        self.record(Direction::Query, command, &result);
+        result
+    }
+
+    /// `shown` is the redacted form of `command`, used in messages.
+    async fn query_trimmed(&mut self, command: &str, shown: &str) -> Result<String> {
+        let raw = self
+            .exchange(command, shown, true)
+            .await
+            .with_context(|| format!("failed to query {shown:?}"))?
+            .unwrap_or_default();
         let trimmed = raw.trim_matches(char::from(0)).trim().to_string();
 
-        debug!("SCPI result <- {}", trimmed);
+        debug!("SCPI result <- {}", self.redactor.redact(&trimmed));
 
         if trimmed.is_empty() {
-            return Err(anyhow!("empty response from device for command {command:?}"));
+            return Err(EmptyResponse {
+                command: shown.trim_end_matches('\n').to_string(),
+            }
+            .into());
         }
+        self.response_rules.check(shown, &trimmed, raw.as_bytes())?;
 
         Ok(trimmed)
     }
-}
 
-fn ensure_slot(slot: u8) -> Result<()> {
-    if (1..=5).contains(&slot) {
+    /// Pause before sending attempt `attempt + 1` of `shown`, if the retry
+    /// policy covers how attempt `attempt` failed.
+    fn retry_delay<T>(
+        &mut self,
+        shown: &str,
+        attempt: u32,
+        result: &Result<T>,
+    ) -> Option<Duration> {
+        let Err(e) = result else {
+            return None;
+        };
+        let policy = self.retry.as_ref()?;
+        if !policy.retries(attempt, e) {
+            return None;
+        }
+        warn!(
+            "SCPI {:?} failed ({e:#}); retrying (attempt {} of {})",
+            shown.trim_end(),
+            attempt + 1,
+            policy.attempts
+        );
+        self.counters.retries += 1;
+        Some(policy.delay)
+    }
+
+    /// Run one write or query on the link, climbing the escalation ladder
+    /// on timeouts if a policy is installed.
+    async fn exchange(
+        &mut self,
+        command: &str,
+        shown: &str,
+        reply: bool,
+    ) -> Result<Option<String>> {
+        if reply {
+            self.counters.queries += 1;
+        } else {
+            self.counters.writes += 1;
+        }
+        let mut result = self.exchange_escalated(command, shown, reply).await;
+        if let Err(e) = &result
+            && self.endpoint.is_some()
+            && let Some(policy) = self.auto_reconnect.clone()
+        {
+            warn!("SCPI {:?} failed on the link ({e:#}); reconnecting", shown.trim_end());
+            result = match self.reconnect_with_backoff(&policy).await {
+                Ok(_) => self.exchange_escalated(command, shown, reply).await,
+                Err(reconnect) => Err(reconnect.context(format!("{e:#}"))),
+            };
+        }
+        if let Err(e) = &result {
+            self.counters.errors += 1;
+            self.conflict.own_errors += 1;
+            if e.is::<CommandTimedOut>() {
+                self.counters.timeouts += 1;
+            }
+        }
+        result
+    }
+
+    async fn exchange_escalated(
+        &mut self,
+        command: &str,
+        shown: &str,
+        reply: bool,
+    ) -> Result<Option<String>> {
+        let Some(policy) = self.escalation.clone() else {
+            let Some(deadline) = self.timeouts.deadline(reply) else {
+                return transport::exchange(&mut *self.inner, command, reply).await;
+            };
+            if !self.healthy {
+                // An earlier timeout could not be cleared; its reply may
+                // still be on the link.
+                self.resync_link(deadline).await.map_err(|e| {
+                    e.context("connection is out of step after a timeout; reconnect first")
+                })?;
+            }
+            let exchange = transport::exchange(&mut *self.inner, command, reply);
+            return match tokio::time::timeout(deadline, exchange).await {
+                Ok(result) => result,
+                Err(_) => {
+                    // The abandoned command may still be answered; clear the
+                    // link so that the reply is not taken for the next one's.
+                    if let Err(e) = self.resync_link(deadline).await {
+                        warn!("clearing the link after a timeout failed: {e:#}");
+                    }
+                    Err(CommandTimedOut {
+                        command: shown.trim_end_matches('\n').to_string(),
+                        attempts: 1,
+                        waited: deadline,
+                    }
+                    .into())
+                }
+            };
+        };
+        if !self.healthy {
+            if !policy.reconnect {
+                return Err(anyhow!(
+                    "connection is unhealthy after repeated timeouts; reconnect first"
+                ));
+            }
+            self.reconnect().await?;
+        }
+
+        let shown = shown.trim_end_matches('\n');
+        let deadlines = policy.deadlines();
+        let mut waited = Duration::ZERO;
+        let mut attempts = 0;
+        for (attempt, deadline) in deadlines.into_iter().enumerate() {
+            attempts += 1;
+            let exchange = transport::exchange(&mut *self.inner, command, reply);
+            if let Ok(result) = tokio::time::timeout(deadline, exchange).await {
+                return result;
+            }
+            waited += deadline;
+            warn!(
+                "SCPI {shown:?} timed out after {deadline:?} (attempt {})",
+                attempt + 1
+            );
+            if attempt + 1 == deadlines.len() {
+                break;
+            }
+            // The abandoned attempt may still be answered; a retry on the
+            // same link would read that reply as its own.
+            if policy.clear {
+                if let Err(e) = self.clear_link(policy.retry_timeout).await {
+                    warn!("clearing the link failed: {e:#}");
+                    break;
+                }
+            } else if reply {
+                break;
+            }
+        }
+
+        self.healthy = false;
+        if policy.reconnect
+            && let Err(e) = self.reconnect().await
+        {
+            warn!("{e:#}");
+        }
+        Err(CommandTimedOut {
+            command: shown.to_string(),
+            attempts,
+            waited,
+        }
+        .into())
+    }
+
+    /// [`clear_link`](Self::clear_link), marking the connection unhealthy
+    /// until it succeeds.
+    async fn resync_link(&mut self, deadline: Duration) -> Result<()> {
+        self.healthy = false;
+        self.clear_link(deadline).await?;
+        self.healthy = true;
         Ok(())
-    } else {
-        Err(anyhow!("slot must be 1..=5"))
     }
-}
 
-fn ensure_group(group: u8) -> Result<()> {
-    if (1..=5).contains(&group) {
+    /// Device clear if the link has one, otherwise reopen the link.
+    async fn clear_link(&mut self, deadline: Duration) -> Result<()> {
+        let cleared = tokio::time::timeout(deadline, self.inner.clear())
+            .await
+            .map_err(|_| CommandTimedOut::once("device clear", deadline))??;
+        if !cleared {
+            self.reconnect().await?;
+        }
         Ok(())
+    }
+
+    fn record<T: TranscriptResponse>(
+        &mut self,
+        direction: Direction,
+        command: &str,
+        result: &Result<T>,
+    ) {
+        let Some(transcript) = &mut self.transcript else {
+            return;
+        };
+        let redactor = &self.redactor;
+        let (response, error) = match result {
+            Ok(value) => (value.response().map(|r| redactor.redact(&r)), None),
+            Err(e) => (None, Some(redactor.redact(&format!("{e:#}")))),
+        };
+        transcript.push(TranscriptEntry {
+            at: SystemTime::now(),
+            operation: operation::current(),
+            direction,
+            command: redactor.redact(command.trim_end_matches('\n')),
+            response,
+            error,
+        });
+    }
+}
+
+/// What a write/query result contributes to the transcript.
+trait TranscriptResponse {
+    fn response(&self) -> Option<String>;
+}
+
+impl TranscriptResponse for () {
+    fn response(&self) -> Option<String> {
+        None
+    }
+}
+
+impl TranscriptResponse for String {
+    fn response(&self) -> Option<String> {
+        Some(self.clone())
+    }
+}
+
+async fn resolve(addr: impl tokio::net::ToSocketAddrs, what: &str) -> Result<Vec<SocketAddr>> {
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host(addr)
+        .await
+        .with_context(|| format!("failed to resolve the {what} address"))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(anyhow!("the {what} address resolved to nothing"));
+    }
+    Ok(addrs)
+}
+
+fn terminated(command: &str) -> String {
+    format!("{}\n", command.trim_end_matches('\n'))
+}
+
+/// Channel and state switched by a raw `OUTPut CHn,ON|OFF` command.
+pub(crate) fn output_change(command: &str) -> Option<(Channel, OutputState)> {
+    let (header, args) = command.trim().split_once(char::is_whitespace)?;
+    let header = header.trim_start_matches(':').to_ascii_uppercase();
+    if !matches!(header.as_str(), "OUTP" | "OUTPUT") {
+        return None;
+    }
+    let (channel, state) = args.split_once(',')?;
+    let channel = Channel::all().find(|ch| ch.label().eq_ignore_ascii_case(channel.trim()))?;
+    let state = match state.trim().to_ascii_uppercase().as_str() {
+        "ON" | "1" => OutputState::On,
+        "OFF" | "0" => OutputState::Off,
+        _ => return None,
+    };
+    Some((channel, state))
+}
+
+/// Channel, quantity and value of a raw `[CHn:]VOLT <v>` or
+/// `[CHn:]CURR <a>` write. The channel is `None` for the bare form, which
+/// applies to the selected channel; the value is `None` if it is not a
+/// plain number.
+pub(crate) fn setpoint_change(command: &str) -> Option<(Option<Channel>, Quantity, Option<f64>)> {
+    let command = command.trim();
+    let (header, value) = command
+        .split_once(char::is_whitespace)
+        .unwrap_or((command, ""));
+    let header = header.trim_start_matches(':').to_ascii_uppercase();
+    let (channel, quantity) = match header.split_once(':') {
+        Some((channel, quantity)) => {
+            let channel =
+                Channel::programmable().find(|ch| ch.label().eq_ignore_ascii_case(channel))?;
+            (Some(channel), quantity)
+        }
+        None => (None, header.as_str()),
+    };
+    let quantity = if quantity.starts_with("VOLT") {
+        Quantity::Voltage
+    } else if quantity.starts_with("CURR") {
+        Quantity::Current
     } else {
-        Err(anyhow!("timer group must be 1..=5"))
+        return None;
+    };
+    Some((channel, quantity, value.trim().parse().ok()))
+}
+
+fn ensure_slot(slot: u8) -> Result<()> {
+    validate::ensure(validate::check_slot(slot))
+}
+
+fn ensure_group(group: u8) -> Result<()> {
+    validate::ensure(validate::check_group(group))
+}
+
+fn config_index(channel: Channel) -> usize {
+    match channel {
+        Channel::Ch1 => 0,
+        Channel::Ch2 => 1,
+        Channel::Ch3 => unreachable!("CH3 has no programmable configuration"),
     }
 }
 
@@ -528,48 +2287,23 @@ fn guard_programmable(channel: Channel) -> Result<()> {
     if matches!(channel, Channel::Ch1 | Channel::Ch2) {
         Ok(())
     } else {
-        Err(anyhow!("channel {} does not support this command", channel.as_scpi()))
+        Err(Violation::UnsupportedChannel(channel).into())
     }
 }
 
 fn parse_channel(value: &str) -> Result<Channel> {
-    match value.trim().to_uppercase().as_str() {
-        "CH1" => Ok(Channel::Ch1),
-        "CH2" => Ok(Channel::Ch2),
-        "CH3" => Ok(Channel::Ch3),
-        other => Err(anyhow!("unknown channel {other}")),
-    }
+    parse::channel(value.as_bytes()).map_err(|_| anyhow!("unknown channel {}", value.trim()))
 }
 
 fn parse_f64(input: &str) -> Result<f64> {
-    input
-        .trim()
-        .parse::<f64>()
-        .map_err(|e| anyhow!("failed to parse float from {input:?}: {e}"))
+    parse::number(input.as_bytes()).with_context(|| format!("failed to parse float from {input:?}"))
 }
 
-fn parse_on_off(value: &str) -> bool {
-    value.trim().eq_ignore_ascii_case("ON") || value.trim() == "1"
+fn on_off(on: bool) -> &'static str {
+    if on { "ON" } else { "OFF" }
 }
 
 fn parse_timer_response(group: u8, resp: &str) -> Result<TimerEntry> {
-    let mut parts = resp.trim().split(',');
-    let voltage = parts
-        .next()
-        .ok_or_else(|| anyhow!("missing voltage in timer response"))?
-        .parse::<f64>()?;
-    let current = parts
-        .next()
-        .ok_or_else(|| anyhow!("missing current in timer response"))?
-        .parse::<f64>()?;
-    let duration = parts
-        .next()
-        .ok_or_else(|| anyhow!("missing duration in timer response"))?
-        .parse::<f64>()?;
-    Ok(TimerEntry {
-        group,
-        voltage_v: voltage,
-        current_a: current,
-        duration_s: duration,
-    })
+    parse::timer_entry(group, resp.as_bytes())
+        .with_context(|| format!("invalid timer response {resp:?}"))
 }
@@ -0,0 +1,211 @@
+//! Discovery of SPD3303X supplies on a subnet, for asset tracking.
+//!
+//! Every host of the subnet is probed on the portmapper port; hosts that
+//! answer are asked for `*IDN?` over VXI-11 and kept if they report an
+//! SPD3303X model. MAC addresses come from the host's ARP table (Linux
+//! only), which is populated by the probe itself.
+
+use std::fmt;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use tokio::net::TcpStream;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tracing::debug;
+
+use crate::instrument::{Identity, Spd3303x};
+use crate::table::Table;
+
+/// ONC RPC portmapper, which every VXI-11 instrument listens on.
+const PORTMAPPER_PORT: u16 = 111;
+/// Smallest prefix accepted, so a typo cannot start a scan of millions of
+/// addresses.
+const MIN_PREFIX: u8 = 16;
+
+/// An IPv4 network in CIDR notation, e.g. `192.168.0.0/24`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subnet {
+    network: Ipv4Addr,
+    prefix: u8,
+}
+
+impl Subnet {
+    pub fn new(address: Ipv4Addr, prefix: u8) -> Result<Self> {
+        if !(MIN_PREFIX..=32).contains(&prefix) {
+            return Err(anyhow!("subnet prefix must be {MIN_PREFIX}..=32, got /{prefix}"));
+        }
+        let mask = u32::MAX << (32 - u32::from(prefix));
+        Ok(Self {
+            network: Ipv4Addr::from(u32::from(address) & mask),
+            prefix,
+        })
+    }
+
+    /// Host addresses, excluding the network and broadcast addresses where
+    /// the subnet has them.
+    pub fn hosts(&self) -> impl Iterator<Item = Ipv4Addr> {
+        let first = u32::from(self.network);
+        let size = 1u64 << (32 - u32::from(self.prefix));
+        let (start, end) = if size > 2 {
+            (u64::from(first) + 1, u64::from(first) + size - 1)
+        } else {
+            (u64::from(first), u64::from(first) + size)
+        };
+        (start..end).map(|addr| Ipv4Addr::from(addr as u32))
+    }
+}
+
+impl FromStr for Subnet {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (address, prefix) = s
+            .split_once('/')
+            .ok_or_else(|| anyhow!("expected a subnet like 192.168.0.0/24, got {s:?}"))?;
+        let address = address
+            .parse::<Ipv4Addr>()
+            .map_err(|e| anyhow!("invalid subnet address {address:?}: {e}"))?;
+        let prefix = prefix
+            .parse::<u8>()
+            .map_err(|e| anyhow!("invalid subnet prefix {prefix:?}: {e}"))?;
+        Self::new(address, prefix)
+    }
+}
+
+impl fmt::Display for Subnet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InventoryOptions {
+    /// VXI-11 device name.
+    pub resource: String,
+    /// Limit for the probe and for the identity query of each host.
+    pub timeout: Duration,
+    /// Hosts probed at the same time.
+    pub concurrency: usize,
+}
+
+impl Default for InventoryOptions {
+    fn default() -> Self {
+        Self {
+            resource: "inst0".to_string(),
+            timeout: Duration::from_millis(500),
+            concurrency: 64,
+        }
+    }
+}
+
+/// One supply found on the network.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InventoryEntry {
+    pub ip: Ipv4Addr,
+    pub mac: Option<String>,
+    pub identity: Identity,
+}
+
+/// Probe every host of `subnet` and return the supplies found, ordered by
+/// address.
+pub async fn scan(subnet: Subnet, options: &InventoryOptions) -> Vec<InventoryEntry> {
+    let permits = Arc::new(Semaphore::new(options.concurrency.max(1)));
+    let mut probes = JoinSet::new();
+    for ip in subnet.hosts() {
+        let permits = permits.clone();
+        let options = options.clone();
+        probes.spawn(async move {
+            let _permit = permits.acquire_owned().await.ok()?;
+            probe(ip, &options).await
+        });
+    }
+
+    let mut entries = Vec::new();
+    while let Some(result) = probes.join_next().await {
+        if let Ok(Some(entry)) = result {
+            entries.push(entry);
+        }
+    }
+    entries.sort_by_key(|entry| entry.ip);
+    entries
+}
+
+/// Identify the supply at `ip`, if there is one.
+pub async fn probe(ip: Ipv4Addr, options: &InventoryOptions) -> Option<InventoryEntry> {
+    let portmapper = SocketAddr::from((ip, PORTMAPPER_PORT));
+    tokio::time::timeout(options.timeout, TcpStream::connect(portmapper))
+        .await
+        .ok()?
+        .ok()?;
+
+    let host = ip.to_string();
+    let identity = tokio::time::timeout(options.timeout * 4, async {
+        let mut inst =
+            Spd3303x::connect_with_timeout(&host, &options.resource, options.timeout).await?;
+        let identity = inst.identity().await;
+        let _ = inst.close().await;
+        identity
+    })
+    .await;
+    let identity = match identity {
+        Ok(Ok(identity)) => identity,
+        Ok(Err(e)) => {
+            debug!("inventory: {ip} answered but is not usable: {e:#}");
+            return None;
+        }
+        Err(_) => {
+            debug!("inventory: {ip} timed out");
+            return None;
+        }
+    };
+    if !identity.model.to_uppercase().starts_with("SPD3303") {
+        debug!("inventory: {ip} is a {}, skipping", identity.model);
+        return None;
+    }
+    Some(InventoryEntry {
+        ip,
+        mac: arp_mac(ip),
+        identity,
+    })
+}
+
+pub fn render_table(entries: &[InventoryEntry]) -> Table {
+    let mut table = Table::new(["IP", "MAC", "Model", "Serial", "Firmware", "Hardware"]);
+    for entry in entries {
+        table.push_row([
+            entry.ip.to_string(),
+            entry.mac.clone().unwrap_or_else(|| "?".to_string()),
+            entry.identity.model.clone(),
+            entry.identity.serial.clone(),
+            entry.identity.firmware.clone(),
+            entry.identity.hardware.clone(),
+        ]);
+    }
+    table
+}
+
+#[cfg(target_os = "linux")]
+fn arp_mac(ip: Ipv4Addr) -> Option<String> {
+    // Columns: IP address, HW type, Flags, HW address, Mask, Device.
+    let table = std::fs::read_to_string("/proc/net/arp").ok()?;
+    let ip = ip.to_string();
+    table.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            [addr, _, _, mac, ..] if *addr == ip && *mac != "00:00:00:00:00:00" => {
+                Some(mac.to_string())
+            }
+            _ => None,
+        }
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn arp_mac(_ip: Ipv4Addr) -> Option<String> {
+    None
+}
@@ -1,5 +1,79 @@
+pub mod alarms;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod broker;
+pub mod builder;
+pub mod checkpoint;
+pub mod clock;
+pub mod combined;
+pub mod conflict;
+pub mod control;
+pub mod current_limit;
+pub mod daemon;
+pub mod display;
+pub mod error;
+pub mod escalation;
+pub mod events;
+pub mod exit;
+pub mod failsafe;
+pub mod frame;
+pub mod hil;
+pub mod inrush;
 pub mod instrument;
+pub mod inventory;
+pub mod metrics;
+pub mod middleware;
+pub mod mock;
+pub mod monitor;
+pub mod network;
+pub mod notes;
+pub mod operation;
+pub mod parse;
+pub mod pipeline;
+pub mod presets;
+pub mod profile;
+pub mod prologix;
+pub mod quirks;
+pub mod ramp;
+pub mod reconnect;
+pub mod redact;
+pub mod response;
+pub mod retry;
+#[cfg(feature = "sqlite")]
+pub mod runs;
+pub mod safety;
+pub mod script;
+pub mod sequence;
+#[cfg(feature = "serial")]
+pub mod serial;
+pub mod settle;
+pub mod slew;
+pub mod snapshot;
+pub mod sniff;
+pub mod socket;
+pub mod source_sink;
+pub mod sweep;
+pub mod table;
+pub mod tasks;
+pub mod throttle;
+pub mod timer_capture;
+pub mod training;
+pub mod transaction;
+pub mod transcript;
+pub mod transport;
+pub mod trend;
+#[cfg(feature = "ssh")]
+pub mod tunnel;
+pub mod undo;
+pub mod unit_settings;
+pub mod units;
+pub mod uptime;
+#[cfg(target_os = "linux")]
+pub mod usbtmc;
+pub mod validate;
 
 // Re-export the primary types so users can depend on the crate
 // without knowing the internal module layout, mirroring sdg2000x_control.
+pub use builder::Spd3303xBuilder;
 pub use instrument::*;
+pub use table::Table;
@@ -0,0 +1,169 @@
+//! Monitor samples as Prometheus metrics, and a Grafana dashboard for them.
+//!
+//! [`render`] writes the text exposition format for any number of
+//! instruments; serve it from whatever HTTP endpoint the lab already runs.
+//! Every series carries an `instrument` label, chosen by the caller, and a
+//! `channel` label (`CH1`, `CH2`). With the `json` feature,
+//! [`grafana_dashboard`] builds a dashboard over exactly these names that
+//! imports as is.
+
+use std::fmt::Write;
+
+use crate::instrument::{Channel, RegulationMode};
+use crate::monitor::MonitorSample;
+
+pub const MEASURED_VOLTAGE: &str = "spd3303x_measured_voltage_volts";
+pub const MEASURED_CURRENT: &str = "spd3303x_measured_current_amperes";
+pub const MEASURED_POWER: &str = "spd3303x_measured_power_watts";
+pub const SET_VOLTAGE: &str = "spd3303x_set_voltage_volts";
+pub const SET_CURRENT: &str = "spd3303x_set_current_amperes";
+pub const OUTPUT_ON: &str = "spd3303x_output_on";
+pub const CONSTANT_CURRENT: &str = "spd3303x_constant_current";
+pub const TRACKING_ERROR: &str = "spd3303x_tracking_error_volts";
+
+/// Name and help text of every gauge [`render`] writes.
+pub const METRICS: [(&str, &str); 8] = [
+    (MEASURED_VOLTAGE, "Measured output voltage."),
+    (MEASURED_CURRENT, "Measured output current."),
+    (MEASURED_POWER, "Measured output power."),
+    (SET_VOLTAGE, "Programmed voltage setpoint."),
+    (SET_CURRENT, "Programmed current limit."),
+    (OUTPUT_ON, "1 while the output is on."),
+    (CONSTANT_CURRENT, "1 while the channel regulates current (CC)."),
+    (
+        TRACKING_ERROR,
+        "Set minus measured voltage while the output is on in CV.",
+    ),
+];
+
+/// Value of `metric` for one channel of a sample, if it has one.
+fn value(metric: &str, sample: &MonitorSample, channel: Channel) -> Option<f64> {
+    let status = sample.channel(channel)?;
+    let flag = |on: bool| if on { 1.0 } else { 0.0 };
+    match metric {
+        MEASURED_VOLTAGE => Some(status.measured_voltage_v),
+        MEASURED_CURRENT => Some(status.measured_current_a),
+        MEASURED_POWER => Some(status.measured_power_w),
+        SET_VOLTAGE => Some(status.setpoint_voltage_v),
+        SET_CURRENT => Some(status.setpoint_current_a),
+        OUTPUT_ON => sample.system.output_on(channel).map(flag),
+        CONSTANT_CURRENT => sample
+            .system
+            .regulation_mode(channel)
+            .map(|mode| flag(mode == RegulationMode::ConstantCurrent)),
+        TRACKING_ERROR => sample.tracking_error(channel),
+        _ => None,
+    }
+}
+
+/// Render the latest sample of each instrument, keyed by the value of its
+/// `instrument` label, in the Prometheus text format.
+pub fn render<'a>(samples: impl IntoIterator<Item = (&'a str, &'a MonitorSample)>) -> String {
+    let samples: Vec<_> = samples.into_iter().collect();
+    let mut out = String::new();
+    for (metric, help) in METRICS {
+        let _ = writeln!(out, "# HELP {metric} {help}");
+        let _ = writeln!(out, "# TYPE {metric} gauge");
+        for (instrument, sample) in &samples {
+            for (channel, _) in &sample.channels {
+                if let Some(value) = value(metric, sample, *channel) {
+                    let _ = writeln!(
+                        out,
+                        "{metric}{{instrument=\"{}\",channel=\"{}\"}} {value}",
+                        escape_label(instrument),
+                        channel.label()
+                    );
+                }
+            }
+        }
+    }
+    out
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// A Grafana dashboard over the metrics of [`render`], with an
+/// `instrument` variable offering `instruments` and a Prometheus data
+/// source variable, as JSON ready for Dashboards > Import.
+#[cfg(feature = "json")]
+pub fn grafana_dashboard<'a>(instruments: impl IntoIterator<Item = &'a str>) -> String {
+    use serde_json::{json, Value};
+
+    let instruments: Vec<&str> = instruments.into_iter().collect();
+    let datasource = json!({ "type": "prometheus", "uid": "${datasource}" });
+    let panel = |id: u32, title: &str, metric: &str, unit: &str, y: u32, x: u32| -> Value {
+        json!({
+            "id": id,
+            "type": "timeseries",
+            "title": title,
+            "datasource": datasource,
+            "gridPos": { "h": 8, "w": 12, "x": x, "y": y },
+            "fieldConfig": { "defaults": { "unit": unit }, "overrides": [] },
+            "targets": [{
+                "refId": "A",
+                "datasource": datasource,
+                "expr": format!("{metric}{{instrument=~\"$instrument\"}}"),
+                "legendFormat": "{{instrument}} {{channel}}",
+            }],
+        })
+    };
+    let state_panel = |id: u32, title: &str, metric: &str, y: u32, x: u32| -> Value {
+        let mut panel = panel(id, title, metric, "none", y, x);
+        panel["type"] = json!("state-timeline");
+        panel["fieldConfig"]["defaults"]["mappings"] = json!([{
+            "type": "value",
+            "options": {
+                "0": { "text": "off", "color": "transparent" },
+                "1": { "text": "on", "color": "green" },
+            },
+        }]);
+        panel
+    };
+    let options: Vec<Value> = instruments
+        .iter()
+        .map(|name| json!({ "text": name, "value": name, "selected": false }))
+        .collect();
+
+    let dashboard = json!({
+        "title": "SPD3303X",
+        "uid": "spd3303x",
+        "tags": ["spd3303x"],
+        "schemaVersion": 39,
+        "time": { "from": "now-1h", "to": "now" },
+        "refresh": "10s",
+        "templating": { "list": [
+            {
+                "name": "datasource",
+                "type": "datasource",
+                "query": "prometheus",
+                "label": "Data source",
+            },
+            {
+                "name": "instrument",
+                "type": "custom",
+                "label": "Instrument",
+                "query": instruments.join(","),
+                "options": options,
+                "multi": true,
+                "includeAll": true,
+                "current": { "text": "All", "value": "$__all" },
+            },
+        ]},
+        "panels": [
+            panel(1, "Voltage", MEASURED_VOLTAGE, "volt", 0, 0),
+            panel(2, "Current", MEASURED_CURRENT, "amp", 0, 12),
+            panel(3, "Power", MEASURED_POWER, "watt", 8, 0),
+            panel(4, "Tracking error", TRACKING_ERROR, "volt", 8, 12),
+            panel(5, "Voltage setpoint", SET_VOLTAGE, "volt", 16, 0),
+            panel(6, "Current limit", SET_CURRENT, "amp", 16, 12),
+            state_panel(7, "Output", OUTPUT_ON, 24, 0),
+            state_panel(8, "Constant current", CONSTANT_CURRENT, 24, 12),
+        ],
+    });
+    serde_json::to_string_pretty(&dashboard).unwrap_or_default()
+}
@@ -0,0 +1,162 @@
+//! Hooks around every command sent by a handle.
+//!
+//! A [`Middleware`] installed with `Spd3303x::add_middleware` sees each
+//! write and query before the link. Writes first pass the handle's own
+//! safety layer, the channel policy and limits of [`validate`] run as a
+//! middleware, then training and the observer-only check, then the
+//! installed chain:
+//! a `before_*` hook can delay the command or fail it, an `after_*` hook
+//! observes the result. Hooks run in the order they were added. Commands
+//! are passed with secrets redacted and without the line terminator.
+//!
+//! For one-off policies, [`Spd3303x::on_before_write`] and
+//! [`Spd3303x::on_after_query`] take closures. [`AuditTrail`] and
+//! [`Pacing`] are middleware shipped with the crate.
+//!
+//! [`validate`]: crate::validate
+//! [`Pacing`]: crate::throttle::Pacing
+//! [`Spd3303x::on_before_write`]: crate::instrument::Spd3303x::on_before_write
+//! [`Spd3303x::on_after_query`]: crate::instrument::Spd3303x::on_after_query
+
+use std::fs::{File, OpenOptions};
+use std::future::Future;
+use std::io::Write;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use tracing::{info, warn};
+
+use crate::instrument::Spd3303x;
+
+pub type HookFuture<'a> = Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>;
+
+pub trait Middleware: Send + Sync {
+    /// Runs before a write is sent; an error fails the write unsent.
+    fn before_write<'a>(&'a self, _command: &'a str) -> HookFuture<'a> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn after_write(&self, _command: &str, _result: &Result<()>) {}
+
+    /// Runs before a query is sent; an error fails the query unsent.
+    fn before_query<'a>(&'a self, _command: &'a str) -> HookFuture<'a> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn after_query(&self, _command: &str, _result: &Result<String>) {}
+}
+
+/// Logs every write and its outcome at info level on the `audit` target,
+/// so state changes can be routed to their own log, and appends it to a
+/// file if one is given. `spd3303xd` writes its audit log through this.
+#[derive(Debug, Clone, Default)]
+pub struct AuditTrail {
+    file: Option<Arc<Mutex<File>>>,
+}
+
+impl AuditTrail {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also append to the file at `path`, creating it if needed.
+    pub fn to_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("failed to open audit log {}", path.display()))?;
+        Ok(Self {
+            file: Some(Arc::new(Mutex::new(file))),
+        })
+    }
+
+    /// Append `entry` to the file, if any, as one line behind a Unix
+    /// timestamp.
+    pub fn record(&self, entry: &str) -> Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        let at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+        writeln!(file, "{at:.3} {entry}")?;
+        Ok(())
+    }
+}
+
+impl Middleware for AuditTrail {
+    fn after_write(&self, command: &str, result: &Result<()>) {
+        let entry = match result {
+            Ok(()) => {
+                info!(target: "audit", command, "sent");
+                format!("write {command:?}")
+            }
+            Err(e) => {
+                info!(target: "audit", command, error = %format!("{e:#}"), "failed");
+                format!("write {command:?} failed: {e:#}")
+            }
+        };
+        if let Err(e) = self.record(&entry) {
+            warn!("audit: appending to the log failed: {e:#}");
+        }
+    }
+}
+
+type WriteCheck = Box<dyn Fn(&str) -> Result<()> + Send + Sync>;
+type QueryObserver = Box<dyn Fn(&str, &Result<String>) + Send + Sync>;
+
+struct BeforeWrite(WriteCheck);
+
+impl Middleware for BeforeWrite {
+    fn before_write<'a>(&'a self, command: &'a str) -> HookFuture<'a> {
+        let result = (self.0)(command);
+        Box::pin(async move { result })
+    }
+}
+
+struct AfterQuery(QueryObserver);
+
+impl Middleware for AfterQuery {
+    fn after_query(&self, command: &str, result: &Result<String>) {
+        (self.0)(command, result);
+    }
+}
+
+impl Spd3303x {
+    /// Run `check` before every write; an error fails the write unsent.
+    pub fn on_before_write(
+        &mut self,
+        check: impl Fn(&str) -> Result<()> + Send + Sync + 'static,
+    ) {
+        self.add_middleware(Arc::new(BeforeWrite(Box::new(check))));
+    }
+
+    /// Call `observe` with every query and its result.
+    pub fn on_after_query(
+        &mut self,
+        observe: impl Fn(&str, &Result<String>) + Send + Sync + 'static,
+    ) {
+        self.add_middleware(Arc::new(AfterQuery(Box::new(observe))));
+    }
+}
+
+pub(crate) async fn before_write(chain: &[Arc<dyn Middleware>], command: &str) -> Result<()> {
+    for middleware in chain {
+        middleware.before_write(command).await?;
+    }
+    Ok(())
+}
+
+pub(crate) async fn before_query(chain: &[Arc<dyn Middleware>], command: &str) -> Result<()> {
+    for middleware in chain {
+        middleware.before_query(command).await?;
+    }
+    Ok(())
+}
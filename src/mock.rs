@@ -0,0 +1,827 @@
+//! Simulated SPD3303X for running code without a bench supply.
+//!
+//! [`MockDevice`] answers the SCPI subset this crate sends (identity,
+//! channel selection, setpoints, measurements, outputs, track mode, the
+//! status word, timer groups, waveform display, save/recall slots and the
+//! network settings) from in-memory state, and `Spd3303x::mock` connects to
+//! it. Setpoints, timer groups and slots are range-checked like on the
+//! instrument, and CH3 only takes `OUTPut`. Timer groups are stored and
+//! their state shows in the status word, but running timers do not step
+//! through the groups.
+//! Outputs can be given a resistive load so measurements and the CC/CV
+//! bits behave like on the bench. A [`LatencyModel`] delays every exchange
+//! like a real link would, which keeps timing-sensitive code and benchmarks
+//! meaningful offline.
+//!
+//! [`MockDevice::serve`] puts the simulator on a TCP socket, as the
+//! `spd3303x-sim` binary does for demos and CI. It speaks raw SCPI like the
+//! instrument's port 5025, for `Spd3303x::connect_tcp`, and also understands
+//! the `++` commands of a Prologix adapter, so `Spd3303x::connect_prologix`
+//! works against it.
+//!
+//! Invalid commands leave entries in the error queue read by
+//! `SYSTem:ERRor?`, as listed by [`SimError`].
+//!
+//! With [`MockDevice::record_wire`] on, the device keeps the exact bytes of
+//! every command it receives. [`MockDevice::check_golden`] compares them
+//! with a golden file, so a test can pin down what an operation sends;
+//! set `SPD3303X_BLESS=1` to write the files instead.
+
+use std::collections::VecDeque;
+use std::net::Ipv4Addr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tracing::{debug, info, warn};
+
+use crate::instrument::{
+    Channel, NetworkConfig, TrackMode, MAX_CURRENT_A, MAX_TIMER_DURATION, MAX_VOLTAGE_V,
+    TIMER_GROUPS,
+};
+use crate::transport::{Transport, TransportFuture};
+
+const IDENTITY: &str = "Siglent Technologies,SPD3303X-E,SPD3XMOCK000001,1.01.01.02.05,V3.0";
+const FIRMWARE: &str = "1.01.01.02.05";
+/// Depth of the simulated error queue.
+pub const ERROR_QUEUE_DEPTH: usize = 20;
+
+/// Errors the simulator queues, with the SCPI-99 codes and texts the
+/// instrument reports them under. `SYSTem:ERRor?` returns them as
+/// `<code>, <text>` and `0, No Error` once the queue is empty.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SimError {
+    /// A query or setter got an argument it does not take.
+    ParameterNotAllowed,
+    /// A setter without its argument, e.g. `CH1:VOLT`.
+    MissingParameter,
+    /// Unknown command or channel keyword.
+    UndefinedHeader,
+    /// A setpoint that is not a number.
+    NumericDataError,
+    /// A setpoint beyond the rated range.
+    DataOutOfRange,
+    /// A keyword argument that is not one of the accepted ones.
+    IllegalParameterValue,
+    /// Errors were dropped because the queue was full; replaces the last
+    /// entry, as SCPI requires.
+    QueueOverflow,
+}
+
+impl SimError {
+    pub fn code(self) -> i32 {
+        match self {
+            SimError::ParameterNotAllowed => -108,
+            SimError::MissingParameter => -109,
+            SimError::UndefinedHeader => -113,
+            SimError::NumericDataError => -120,
+            SimError::DataOutOfRange => -222,
+            SimError::IllegalParameterValue => -224,
+            SimError::QueueOverflow => -350,
+        }
+    }
+
+    pub fn text(self) -> &'static str {
+        match self {
+            SimError::ParameterNotAllowed => "Parameter not allowed",
+            SimError::MissingParameter => "Missing parameter",
+            SimError::UndefinedHeader => "Undefined header",
+            SimError::NumericDataError => "Numeric data error",
+            SimError::DataOutOfRange => "Data out of range",
+            SimError::IllegalParameterValue => "Illegal parameter value",
+            SimError::QueueOverflow => "Queue overflow",
+        }
+    }
+
+    /// The reply to `SYSTem:ERRor?` for this error.
+    pub fn entry(self) -> String {
+        format!("{}, {}", self.code(), self.text())
+    }
+}
+
+/// Delay of one simulated exchange.
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LatencyModel {
+    /// Fixed cost of every write or query.
+    pub round_trip: Duration,
+    /// Added per byte of command and reply.
+    pub per_byte: Duration,
+}
+
+impl LatencyModel {
+    /// No delay at all.
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    /// A VXI-11 exchange on a quiet LAN, as a starting point; measure the
+    /// real figure with [`measure_latency`](crate::sequence::measure_latency).
+    pub fn lan() -> Self {
+        Self {
+            round_trip: Duration::from_millis(3),
+            per_byte: Duration::from_micros(1),
+        }
+    }
+
+    pub fn delay(&self, bytes: usize) -> Duration {
+        self.round_trip + self.per_byte * bytes as u32
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct MockChannel {
+    set_voltage_v: f64,
+    set_current_a: f64,
+    /// Resistive load on the output; `None` is an open circuit.
+    load_ohms: Option<f64>,
+}
+
+impl MockChannel {
+    /// Output voltage and current, and whether the channel is in CC.
+    fn operating_point(&self, on: bool) -> (f64, f64, bool) {
+        match self.load_ohms {
+            Some(ohms) if on && ohms > 0.0 => {
+                let current = self.set_voltage_v / ohms;
+                if current > self.set_current_a {
+                    (self.set_current_a * ohms, self.set_current_a, true)
+                } else {
+                    (self.set_voltage_v, current, false)
+                }
+            }
+            _ if on => (self.set_voltage_v, 0.0, false),
+            _ => (0.0, 0.0, false),
+        }
+    }
+}
+
+/// One `TIMER:SET` group: voltage, current and seconds.
+#[derive(Debug, Clone, Copy, Default)]
+struct MockTimerGroup {
+    voltage_v: f64,
+    current_a: f64,
+    seconds: f64,
+}
+
+#[derive(Debug)]
+struct MockState {
+    selected: Channel,
+    channels: [MockChannel; 2],
+    outputs: [bool; 3],
+    track_mode: TrackMode,
+    timers: [[MockTimerGroup; TIMER_GROUPS as usize]; 2],
+    timers_on: [bool; 2],
+    wave_display: [bool; 2],
+    /// CH1/CH2 setpoints stored by `*SAV 1..5`.
+    slots: [Option<[(f64, f64); 2]>; 5],
+    network: NetworkConfig,
+    errors: VecDeque<SimError>,
+    /// Bytes received while recording is on.
+    wire: Option<Vec<u8>>,
+}
+
+impl Default for MockState {
+    fn default() -> Self {
+        Self {
+            selected: Channel::Ch1,
+            channels: Default::default(),
+            outputs: [false; 3],
+            track_mode: TrackMode::Independent,
+            timers: Default::default(),
+            timers_on: [false; 2],
+            wave_display: [false; 2],
+            slots: [None; 5],
+            network: NetworkConfig {
+                ip: "192.168.1.100".to_string(),
+                mask: "255.255.255.0".to_string(),
+                gateway: "192.168.1.1".to_string(),
+                dhcp: false,
+            },
+            errors: VecDeque::new(),
+            wire: None,
+        }
+    }
+}
+
+/// Handle to a simulated instrument; clones share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct MockDevice {
+    state: Arc<Mutex<MockState>>,
+    latency: LatencyModel,
+}
+
+impl MockDevice {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_latency(mut self, latency: LatencyModel) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    pub fn latency(&self) -> LatencyModel {
+        self.latency
+    }
+
+    /// Put a resistive load on CH1/CH2, or remove it with `None`.
+    pub fn set_load(&self, channel: Channel, ohms: Option<f64>) -> Result<()> {
+        let index = index(channel).ok_or_else(|| anyhow!("CH3 has no simulated load"))?;
+        self.lock().channels[index].load_ohms = ohms;
+        Ok(())
+    }
+
+    /// Start or stop recording received commands; starting discards what
+    /// was recorded before.
+    pub fn record_wire(&self, enabled: bool) {
+        self.lock().wire = enabled.then(Vec::new);
+    }
+
+    /// Bytes received since recording started or the last call, each
+    /// command as sent with its terminator.
+    pub fn take_wire(&self) -> Vec<u8> {
+        self.lock().wire.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Compare the recorded bytes with the golden file at `path` and clear
+    /// them. With `SPD3303X_BLESS` set, the file is written instead.
+    pub fn check_golden(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let actual = self.take_wire();
+        if std::env::var_os("SPD3303X_BLESS").is_some() {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            std::fs::write(path, &actual)?;
+            return Ok(());
+        }
+        let expected = std::fs::read(path)
+            .map_err(|e| anyhow!("cannot read golden file {}: {e}", path.display()))?;
+        if actual == expected {
+            return Ok(());
+        }
+        let show = |bytes: &[u8]| String::from_utf8_lossy(bytes).escape_debug().to_string();
+        Err(anyhow!(
+            "wire bytes differ from {}:\nexpected: {}\n  actual: {}",
+            path.display(),
+            show(&expected),
+            show(&actual)
+        ))
+    }
+
+    /// Network settings as last set over SCPI.
+    pub fn network(&self) -> NetworkConfig {
+        self.lock().network.clone()
+    }
+
+    /// Queue `error` as if a command had caused it.
+    pub fn inject_error(&self, error: SimError) {
+        self.lock().error(error);
+    }
+
+    /// Errors currently queued, oldest first.
+    pub fn queued_errors(&self) -> Vec<SimError> {
+        self.lock().errors.iter().copied().collect()
+    }
+
+    /// Handle one command; queries return their reply. Unknown commands
+    /// and bad values are queued as [`SimError`]s, and invalid queries get
+    /// no reply, as on the instrument.
+    pub fn handle(&self, command: &str) -> Result<Option<String>> {
+        let command = command.trim();
+        let (header, args) = match command.split_once(char::is_whitespace) {
+            Some((header, args)) => (header, args.trim()),
+            None => (command, ""),
+        };
+        let query = header.ends_with('?');
+        let keywords: Vec<String> = header
+            .trim_end_matches('?')
+            .split(':')
+            .map(mnemonic)
+            .collect();
+        let keywords: Vec<&str> = keywords.iter().map(String::as_str).collect();
+
+        let mut state = self.lock();
+        let bare_query = query
+            && matches!(
+                keywords.as_slice(),
+                ["*IDN"]
+                    | ["SYST", _]
+                    | ["INST"]
+                    | ["OUTP", "TRAC"]
+                    | ["IPAD" | "MASK" | "GAT" | "DHCP"]
+                    | [_, "VOLT" | "CURR"]
+                    if keywords[0] != "MEAS"
+            );
+        if bare_query && !args.is_empty() {
+            state.error(SimError::ParameterNotAllowed);
+            return Err(no_reply(command));
+        }
+        let reply = match (keywords.as_slice(), query) {
+            (["*IDN"], true) => Some(IDENTITY.to_string()),
+            (["*CLS"], false) => {
+                state.errors.clear();
+                None
+            }
+            (["SYST", "VERS"], true) => Some(FIRMWARE.to_string()),
+            (["SYST", "ERR"], true) => Some(
+                state
+                    .errors
+                    .pop_front()
+                    .map_or_else(|| "0, No Error".to_string(), SimError::entry),
+            ),
+            (["SYST", "STAT"], true) => Some(format!("0x{:X}", state.status_word())),
+            (["INST"], true) => Some(state.selected.label().to_string()),
+            (["INST"], false) => {
+                match parse_channel(args) {
+                    Some(channel) => state.selected = channel,
+                    None if args.is_empty() => state.error(SimError::MissingParameter),
+                    None => state.error(SimError::IllegalParameterValue),
+                }
+                None
+            }
+            ([ch, quantity @ ("VOLT" | "CURR")], false) => {
+                state.set_setpoint(ch, quantity, args);
+                None
+            }
+            ([ch, quantity @ ("VOLT" | "CURR")], true) => {
+                let Some(index) = parse_channel(ch).and_then(index) else {
+                    state.error(SimError::UndefinedHeader);
+                    return Err(no_reply(command));
+                };
+                let channel = &state.channels[index];
+                let value = match *quantity {
+                    "VOLT" => channel.set_voltage_v,
+                    _ => channel.set_current_a,
+                };
+                Some(format!("{value:.3}"))
+            }
+            (["MEAS", quantity @ ("VOLT" | "CURR" | "POW")], true) => {
+                let channel = if args.is_empty() {
+                    Some(state.selected)
+                } else {
+                    parse_channel(args)
+                };
+                let Some(index) = channel.and_then(index) else {
+                    state.error(SimError::IllegalParameterValue);
+                    return Err(no_reply(command));
+                };
+                let (volts, amps, _) = state.channels[index].operating_point(state.outputs[index]);
+                let value = match *quantity {
+                    "VOLT" => volts,
+                    "CURR" => amps,
+                    _ => volts * amps,
+                };
+                Some(format!("{value:.3}"))
+            }
+            (["OUTP"], false) => {
+                state.set_output(args);
+                None
+            }
+            (["OUTP", "TRAC"], false) => {
+                match args {
+                    "0" => state.track_mode = TrackMode::Independent,
+                    "1" => state.track_mode = TrackMode::Series,
+                    "2" => state.track_mode = TrackMode::Parallel,
+                    "" => state.error(SimError::MissingParameter),
+                    _ => state.error(SimError::IllegalParameterValue),
+                }
+                None
+            }
+            (["OUTP", "TRAC"], true) => Some(
+                match state.track_mode {
+                    TrackMode::Independent => "0",
+                    TrackMode::Series => "1",
+                    TrackMode::Parallel => "2",
+                }
+                .to_string(),
+            ),
+            (["OUTP", "WAV"], false) => {
+                state.set_wave_display(args);
+                None
+            }
+            (["*SAV"], false) => {
+                if let Some(slot) = state.slot(args) {
+                    let setpoints = state.channels.map(|ch| (ch.set_voltage_v, ch.set_current_a));
+                    state.slots[slot] = Some(setpoints);
+                }
+                None
+            }
+            (["*RCL"], false) => {
+                if let Some(slot) = state.slot(args) {
+                    // An empty slot recalls the power-on defaults.
+                    let setpoints = state.slots[slot].unwrap_or_default();
+                    for (channel, (volts, amps)) in state.channels.iter_mut().zip(setpoints) {
+                        channel.set_voltage_v = volts;
+                        channel.set_current_a = amps;
+                    }
+                }
+                None
+            }
+            (["TIM", "SET"], false) => {
+                state.set_timer_group(args);
+                None
+            }
+            (["TIM", "SET"], true) => {
+                let Some((index, group)) = state.timer_group(args) else {
+                    return Err(no_reply(command));
+                };
+                let entry = state.timers[index][group];
+                Some(format!(
+                    "{:.3},{:.3},{:.3}",
+                    entry.voltage_v, entry.current_a, entry.seconds
+                ))
+            }
+            (["TIM"], false) => {
+                state.set_timer(args);
+                None
+            }
+            ([field @ ("IPAD" | "MASK" | "GAT")], false) => {
+                if args.is_empty() {
+                    state.error(SimError::MissingParameter);
+                } else if args.parse::<Ipv4Addr>().is_err() {
+                    state.error(SimError::IllegalParameterValue);
+                } else {
+                    let address = args.to_string();
+                    match *field {
+                        "IPAD" => state.network.ip = address,
+                        "MASK" => state.network.mask = address,
+                        _ => state.network.gateway = address,
+                    }
+                }
+                None
+            }
+            ([field @ ("IPAD" | "MASK" | "GAT")], true) => Some(match *field {
+                "IPAD" => state.network.ip.clone(),
+                "MASK" => state.network.mask.clone(),
+                _ => state.network.gateway.clone(),
+            }),
+            (["DHCP"], false) => {
+                match parse_switch(args) {
+                    Some(on) => state.network.dhcp = on,
+                    None if args.is_empty() => state.error(SimError::MissingParameter),
+                    None => state.error(SimError::IllegalParameterValue),
+                }
+                None
+            }
+            (["DHCP"], true) => Some(format!(
+                "DHCP:{}",
+                if state.network.dhcp { "ON" } else { "OFF" }
+            )),
+            (_, true) => {
+                state.error(SimError::UndefinedHeader);
+                return Err(no_reply(command));
+            }
+            (_, false) => {
+                state.error(SimError::UndefinedHeader);
+                None
+            }
+        };
+        Ok(reply)
+    }
+
+    pub(crate) async fn write(&self, command: &str) -> Result<()> {
+        self.record(command);
+        tokio::time::sleep(self.latency.delay(command.len())).await;
+        self.handle(command)?;
+        Ok(())
+    }
+
+    pub(crate) async fn query(&self, command: &str) -> Result<String> {
+        self.record(command);
+        let reply = self
+            .handle(command)?
+            .ok_or_else(|| anyhow!("simulated instrument sent no reply to {command:?}"))?;
+        tokio::time::sleep(self.latency.delay(command.len() + reply.len())).await;
+        Ok(format!("{reply}\n"))
+    }
+
+    /// Accept clients forever; all of them share this device's state.
+    pub async fn serve(&self, listener: TcpListener) -> Result<()> {
+        info!("sim: listening on {}", listener.local_addr()?);
+        loop {
+            let (client, peer) = listener.accept().await?;
+            client.set_nodelay(true)?;
+            let device = self.clone();
+            tokio::spawn(async move {
+                match device.serve_client(client).await {
+                    Ok(()) => debug!("sim: {peer} disconnected"),
+                    Err(e) => warn!("sim: {peer} dropped: {e:#}"),
+                }
+            });
+        }
+    }
+
+    /// Answer one client until it disconnects. Replies are sent right away,
+    /// or on `++read` once the client has sent any Prologix command.
+    pub async fn serve_client<S>(&self, stream: S) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut stream = BufReader::new(stream);
+        let mut prologix = false;
+        let mut pending: Option<String> = None;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if stream.read_line(&mut line).await? == 0 {
+                return Ok(());
+            }
+            let command = line.trim_end_matches(['\r', '\n']);
+            if let Some(controller) = command.strip_prefix("++") {
+                prologix = true;
+                if controller.starts_with("read") {
+                    if let Some(reply) = pending.take() {
+                        stream.get_mut().write_all(reply.as_bytes()).await?;
+                    }
+                }
+                continue;
+            }
+            // Handled and recorded with the terminator it came with.
+            let terminator = &line[command.len()..];
+            let command = format!("{}{terminator}", unescape(command));
+            if command.trim().is_empty() {
+                continue;
+            }
+            let header = command.split_whitespace().next().unwrap_or_default();
+            let reply = if header.ends_with('?') {
+                match self.query(&command).await {
+                    Ok(reply) => Some(reply),
+                    Err(e) => {
+                        debug!("sim: {e:#}");
+                        None
+                    }
+                }
+            } else {
+                self.write(&command).await?;
+                None
+            };
+            match reply {
+                Some(reply) if prologix => pending = Some(reply),
+                Some(reply) => stream.get_mut().write_all(reply.as_bytes()).await?,
+                None => {}
+            }
+            stream.get_mut().flush().await?;
+        }
+    }
+
+    fn record(&self, command: &str) {
+        if let Some(wire) = self.lock().wire.as_mut() {
+            wire.extend_from_slice(command.as_bytes());
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MockState> {
+        // The state stays consistent even if a holder panicked.
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Transport for MockDevice {
+    fn write<'a>(&'a mut self, command: &'a str) -> TransportFuture<'a, ()> {
+        Box::pin(MockDevice::write(self, command))
+    }
+
+    fn query<'a>(&'a mut self, command: &'a str) -> TransportFuture<'a, String> {
+        Box::pin(MockDevice::query(self, command))
+    }
+
+    /// Replies are produced when read, so an abandoned query leaves
+    /// nothing behind to clear.
+    fn clear(&mut self) -> TransportFuture<'_, bool> {
+        Box::pin(async { Ok(true) })
+    }
+}
+
+impl MockState {
+    fn error(&mut self, error: SimError) {
+        if self.errors.len() >= ERROR_QUEUE_DEPTH {
+            if let Some(last) = self.errors.back_mut() {
+                *last = SimError::QueueOverflow;
+            }
+            return;
+        }
+        self.errors.push_back(error);
+    }
+
+    fn set_setpoint(&mut self, ch: &str, quantity: &str, value: &str) {
+        let Some(index) = parse_channel(ch).and_then(index) else {
+            return self.error(SimError::UndefinedHeader);
+        };
+        let (max, setpoint) = match quantity {
+            "VOLT" => (MAX_VOLTAGE_V, &mut self.channels[index].set_voltage_v),
+            _ => (MAX_CURRENT_A, &mut self.channels[index].set_current_a),
+        };
+        if value.is_empty() {
+            return self.error(SimError::MissingParameter);
+        }
+        match value.parse::<f64>() {
+            Ok(value) if (0.0..=max).contains(&value) => *setpoint = value,
+            Ok(_) => self.error(SimError::DataOutOfRange),
+            Err(_) => self.error(SimError::NumericDataError),
+        }
+    }
+
+    fn set_output(&mut self, args: &str) {
+        let Some((ch, state)) = args.split_once(',') else {
+            return self.error(SimError::MissingParameter);
+        };
+        let Some(channel) = parse_channel(ch) else {
+            return self.error(SimError::IllegalParameterValue);
+        };
+        let Some(on) = parse_switch(state) else {
+            return self.error(SimError::IllegalParameterValue);
+        };
+        let slot = match channel {
+            Channel::Ch1 => 0,
+            Channel::Ch2 => 1,
+            Channel::Ch3 => 2,
+        };
+        self.outputs[slot] = on;
+    }
+
+    /// `CHn,ON|OFF` for a CH1/CH2 setting, as the channel index and state.
+    fn channel_switch(&mut self, args: &str) -> Option<(usize, bool)> {
+        let Some((ch, state)) = args.split_once(',') else {
+            self.error(SimError::MissingParameter);
+            return None;
+        };
+        match (parse_channel(ch).and_then(index), parse_switch(state)) {
+            (Some(index), Some(on)) => Some((index, on)),
+            _ => {
+                self.error(SimError::IllegalParameterValue);
+                None
+            }
+        }
+    }
+
+    fn set_wave_display(&mut self, args: &str) {
+        if let Some((index, on)) = self.channel_switch(args) {
+            self.wave_display[index] = on;
+        }
+    }
+
+    fn set_timer(&mut self, args: &str) {
+        if let Some((index, on)) = self.channel_switch(args) {
+            self.timers_on[index] = on;
+        }
+    }
+
+    /// `CHn,<group>` as the channel index and group index.
+    fn timer_group(&mut self, args: &str) -> Option<(usize, usize)> {
+        let Some((ch, group)) = args.split_once(',') else {
+            self.error(SimError::MissingParameter);
+            return None;
+        };
+        let Some(index) = parse_channel(ch).and_then(index) else {
+            self.error(SimError::IllegalParameterValue);
+            return None;
+        };
+        match group.trim().parse::<u8>() {
+            Ok(group) if (1..=TIMER_GROUPS).contains(&group) => {
+                Some((index, usize::from(group - 1)))
+            }
+            Ok(_) => {
+                self.error(SimError::DataOutOfRange);
+                None
+            }
+            Err(_) => {
+                self.error(SimError::NumericDataError);
+                None
+            }
+        }
+    }
+
+    /// `CHn,<group>,<volts>,<amps>,<seconds>`.
+    fn set_timer_group(&mut self, args: &str) {
+        let fields: Vec<&str> = args.splitn(3, ',').collect();
+        let [ch, group, values] = fields[..] else {
+            return self.error(SimError::MissingParameter);
+        };
+        let Some((index, group)) = self.timer_group(&format!("{ch},{group}")) else {
+            return;
+        };
+        let values: Result<Vec<f64>, _> = values.split(',').map(|v| v.trim().parse()).collect();
+        let Ok(values) = values else {
+            return self.error(SimError::NumericDataError);
+        };
+        let [voltage_v, current_a, seconds] = values[..] else {
+            return self.error(SimError::MissingParameter);
+        };
+        let in_range = (0.0..=MAX_VOLTAGE_V).contains(&voltage_v)
+            && (0.0..=MAX_CURRENT_A).contains(&current_a)
+            && (0.0..=MAX_TIMER_DURATION.as_secs_f64()).contains(&seconds);
+        if !in_range {
+            return self.error(SimError::DataOutOfRange);
+        }
+        self.timers[index][group] = MockTimerGroup {
+            voltage_v,
+            current_a,
+            seconds,
+        };
+    }
+
+    /// Index of a `*SAV`/`*RCL` slot, 1 to 5 on the instrument.
+    fn slot(&mut self, args: &str) -> Option<usize> {
+        match args.parse::<usize>() {
+            Ok(slot) if (1..=self.slots.len()).contains(&slot) => Some(slot - 1),
+            Ok(_) => {
+                self.error(SimError::DataOutOfRange);
+                None
+            }
+            Err(_) if args.is_empty() => {
+                self.error(SimError::MissingParameter);
+                None
+            }
+            Err(_) => {
+                self.error(SimError::NumericDataError);
+                None
+            }
+        }
+    }
+
+    /// Bit layout as decoded by `SystemStatus`.
+    fn status_word(&self) -> u32 {
+        let mut word = 0;
+        for (index, channel) in self.channels.iter().enumerate() {
+            let (_, _, cc) = channel.operating_point(self.outputs[index]);
+            if cc {
+                word |= 1 << index;
+            }
+            if self.outputs[index] {
+                word |= 1 << (4 + index);
+            }
+            if self.timers_on[index] {
+                word |= 1 << (6 + index);
+            }
+            if self.wave_display[index] {
+                word |= 1 << (8 + index);
+            }
+        }
+        word |= match self.track_mode {
+            TrackMode::Independent => 0b01,
+            TrackMode::Series => 0b11,
+            TrackMode::Parallel => 0b10,
+        } << 2;
+        if self.track_mode == TrackMode::Parallel {
+            word |= 1 << 10;
+        }
+        word
+    }
+}
+
+/// SCPI short form of a keyword: the first four letters, or three if the
+/// fourth is a vowel (`VOLTage` -> `VOLT`, `ERRor` -> `ERR`).
+fn mnemonic(keyword: &str) -> String {
+    let upper = keyword.trim().to_ascii_uppercase();
+    if upper.starts_with('*') || upper.starts_with("CH") {
+        return upper;
+    }
+    let mut short: String = upper.chars().take(4).collect();
+    if short.len() == 4 && short.ends_with(['A', 'E', 'I', 'O', 'U']) {
+        short.pop();
+    }
+    short
+}
+
+fn parse_channel(text: &str) -> Option<Channel> {
+    Channel::all().find(|channel| channel.label().eq_ignore_ascii_case(text.trim()))
+}
+
+fn parse_switch(text: &str) -> Option<bool> {
+    match text.trim().to_ascii_uppercase().as_str() {
+        "ON" => Some(true),
+        "OFF" => Some(false),
+        _ => None,
+    }
+}
+
+/// State index of CH1/CH2.
+fn index(channel: Channel) -> Option<usize> {
+    match channel {
+        Channel::Ch1 => Some(0),
+        Channel::Ch2 => Some(1),
+        Channel::Ch3 => None,
+    }
+}
+
+/// Undo the ESC escaping of a Prologix adapter.
+fn unescape(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => out.extend(chars.next()),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn no_reply(command: &str) -> anyhow::Error {
+    anyhow!("simulated instrument does not answer {command:?}")
+}
@@ -0,0 +1,750 @@
+//! Background polling of the instrument state.
+//!
+//! When polls fail or take longer than [`MonitorConfig::slow_poll`], e.g.
+//! because the instrument is busy, the poll interval doubles up to
+//! [`MonitorConfig::max_interval`] and [`Event::Degraded`] is published. After
+//! a few healthy polls in a row the configured rate is restored and
+//! [`Event::Recovered`] follows.
+//!
+//! By default setpoints are re-read only every few polls, see
+//! [`PollStrategy::Cached`]. That takes a two-channel poll from 11 queries
+//! to 7.8 on average without delaying readbacks; `benches/polling.rs`
+//! compares the strategies against the simulator.
+//!
+//! Firmware sometimes flips a status bit for a single poll, e.g. the CC/CV
+//! bit during a load transient. [`Debounce`] makes the monitor publish a
+//! CC/CV or output transition only once the new state has been seen in
+//! that many consecutive polls.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use tokio::sync::{watch, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+use crate::alarms::Alarms;
+use crate::clock::{Clock, Ticker};
+use crate::events::{Event, EventBus, EventFilter, EventStream};
+use crate::instrument::{Channel, ChannelStatus, RegulationMode, Spd3303x, SystemStatus};
+use crate::safety::SafetyLimits;
+use crate::timer_capture::TimerProgress;
+use crate::trend::{TrendPoint, TrendRecorder};
+use crate::uptime::UptimeTracker;
+
+/// Healthy polls in a row needed to return to the configured interval.
+const RECOVERY_POLLS: u32 = 3;
+
+#[derive(Debug, Clone)]
+pub struct MonitorConfig {
+    pub interval: Duration,
+    /// Channels whose setpoints and readbacks are polled.
+    pub channels: Vec<Channel>,
+    /// A poll taking longer than this counts as a sign of overload.
+    pub slow_poll: Duration,
+    /// Upper bound of the interval while backing off.
+    pub max_interval: Duration,
+    pub strategy: PollStrategy,
+    /// Polls kept per channel for [`Monitor::wave_trend`]; 0 disables it.
+    pub trend_points: usize,
+    /// Publish [`Event::TrackingErrorExceeded`] when a channel's
+    /// [tracking error](MonitorSample::tracking_error) rises above this.
+    pub tracking_error_threshold_v: Option<f64>,
+    pub debounce: Debounce,
+    /// Switch off channels whose readings exceed these limits, if their
+    /// [`trip_on_measurement`](SafetyLimits::trip_on_measurement) is set.
+    pub safety_limits: Option<SafetyLimits>,
+}
+
+/// Consecutive polls a status bit must hold its new value for before the
+/// transition is published; 1 publishes on the first poll.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Debounce {
+    /// For [`Event::RegulationChanged`].
+    pub regulation_polls: u32,
+    /// For [`Event::OutputChanged`].
+    pub output_polls: u32,
+}
+
+impl Default for Debounce {
+    fn default() -> Self {
+        Self {
+            regulation_polls: 1,
+            output_polls: 1,
+        }
+    }
+}
+
+impl Default for MonitorConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            channels: Channel::programmable().collect(),
+            slow_poll: Duration::from_secs(2),
+            max_interval: Duration::from_secs(30),
+            strategy: PollStrategy::Cached { setpoint_every: 5 },
+            trend_points: 600,
+            tracking_error_threshold_v: None,
+            debounce: Debounce::default(),
+            safety_limits: None,
+        }
+    }
+}
+
+/// How the queries of a poll are issued.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PollStrategy {
+    /// Lock the instrument for each query, so other users of the shared
+    /// instrument get in between at the cost of a longer poll.
+    Sequential,
+    /// Issue all queries of a poll under one lock.
+    Batched,
+    /// Like `Batched`, but re-read setpoints only every `setpoint_every`
+    /// polls. Setpoints change only when written, so a change from the
+    /// front panel or another client can show up that many polls late;
+    /// readbacks and the status word are read every poll.
+    Cached { setpoint_every: u32 },
+}
+
+/// Runs polls with a [`PollStrategy`]. [`Monitor`] polls through this; it
+/// is public for benchmarking and custom loops.
+#[derive(Debug, Clone)]
+pub struct Poller {
+    strategy: PollStrategy,
+    channels: Vec<Channel>,
+    /// Setpoints of the last refresh, one per channel, for `Cached`.
+    setpoints: Vec<(f64, f64)>,
+    polls: u32,
+}
+
+impl Poller {
+    pub fn new(strategy: PollStrategy, channels: Vec<Channel>) -> Self {
+        Self {
+            strategy,
+            channels,
+            setpoints: Vec::new(),
+            polls: 0,
+        }
+    }
+
+    pub async fn poll(&mut self, inst: &Mutex<Spd3303x>) -> Result<MonitorSample> {
+        let (system, statuses) = match self.strategy {
+            PollStrategy::Sequential => self.poll_sequential(inst).await?,
+            PollStrategy::Batched => {
+                let mut inst = inst.lock().await;
+                let system = inst.system_status().await?;
+                let mut statuses = Vec::with_capacity(self.channels.len());
+                for &channel in &self.channels {
+                    statuses.push((channel, inst.channel_status(channel).await?));
+                }
+                (system, statuses)
+            }
+            PollStrategy::Cached { setpoint_every } => {
+                let refresh = self.setpoints.len() != self.channels.len()
+                    || self.polls % setpoint_every.max(1) == 0;
+                let mut inst = inst.lock().await;
+                let system = inst.system_status().await?;
+                let mut statuses = Vec::with_capacity(self.channels.len());
+                for (index, &channel) in self.channels.iter().enumerate() {
+                    let (setpoint_voltage_v, setpoint_current_a) = if refresh {
+                        (
+                            inst.setpoint_voltage(channel).await?,
+                            inst.setpoint_current(channel).await?,
+                        )
+                    } else {
+                        self.setpoints[index]
+                    };
+                    #[allow(deprecated)]
+                    let status = ChannelStatus {
+                        setpoint_voltage_v,
+                        setpoint_current_a,
+                        measured_voltage_v: inst.measured_voltage(Some(channel)).await?,
+                        measured_current_a: inst.measured_current(Some(channel)).await?,
+                        measured_power_w: inst.measured_power(Some(channel)).await?,
+                        set_voltage_v: setpoint_voltage_v,
+                        set_current_a: setpoint_current_a,
+                    };
+                    statuses.push((channel, status));
+                }
+                if refresh {
+                    self.setpoints = statuses
+                        .iter()
+                        .map(|(_, status)| (status.setpoint_voltage_v, status.setpoint_current_a))
+                        .collect();
+                }
+                (system, statuses)
+            }
+        };
+        self.polls = self.polls.wrapping_add(1);
+        let timers = self.timer_progress(inst, &system).await;
+        let clock = inst.lock().await.clock();
+        Ok(MonitorSample {
+            at: clock.now(),
+            system,
+            channels: statuses,
+            timers,
+            clock,
+        })
+    }
+
+    /// Progress of the timers on the polled channels that the status word
+    /// reports as on. A failure to read the programmed groups only leaves
+    /// that timer out.
+    async fn timer_progress(
+        &self,
+        inst: &Mutex<Spd3303x>,
+        system: &SystemStatus,
+    ) -> Vec<TimerProgress> {
+        let mut timers = Vec::new();
+        for &channel in &self.channels {
+            if system.timer_on(channel) != Some(true) {
+                continue;
+            }
+            match inst.lock().await.timer_progress(channel).await {
+                Ok(Some(progress)) => timers.push(progress),
+                Ok(None) => {}
+                Err(e) => debug!("monitor: no progress for the {} timer: {e:#}", channel.label()),
+            }
+        }
+        timers
+    }
+
+    async fn poll_sequential(
+        &self,
+        inst: &Mutex<Spd3303x>,
+    ) -> Result<(SystemStatus, Vec<(Channel, ChannelStatus)>)> {
+        let system = inst.lock().await.system_status().await?;
+        let mut statuses = Vec::with_capacity(self.channels.len());
+        for &channel in &self.channels {
+            let setpoint_voltage_v = inst.lock().await.setpoint_voltage(channel).await?;
+            let setpoint_current_a = inst.lock().await.setpoint_current(channel).await?;
+            #[allow(deprecated)]
+            let status = ChannelStatus {
+                setpoint_voltage_v,
+                setpoint_current_a,
+                measured_voltage_v: inst.lock().await.measured_voltage(Some(channel)).await?,
+                measured_current_a: inst.lock().await.measured_current(Some(channel)).await?,
+                measured_power_w: inst.lock().await.measured_power(Some(channel)).await?,
+                set_voltage_v: setpoint_voltage_v,
+                set_current_a: setpoint_current_a,
+            };
+            statuses.push((channel, status));
+        }
+        Ok((system, statuses))
+    }
+}
+
+/// One poll of the instrument. Readings pass through the channel's
+/// measurement pipeline like any other `channel_status` call.
+#[derive(Debug, Clone)]
+pub struct MonitorSample {
+    /// When the poll finished, on the clock of the polled handle.
+    pub at: Instant,
+    pub system: SystemStatus,
+    pub channels: Vec<(Channel, ChannelStatus)>,
+    /// Running timers that the polled handle started, see
+    /// [`Spd3303x::timer_progress`].
+    pub timers: Vec<TimerProgress>,
+    clock: Arc<dyn Clock>,
+}
+
+impl MonitorSample {
+    /// Time since this sample was taken, on the clock it was taken by.
+    pub fn age(&self) -> Duration {
+        self.clock.elapsed(self.at)
+    }
+
+    pub fn channel(&self, channel: Channel) -> Option<&ChannelStatus> {
+        self.channels
+            .iter()
+            .find(|(ch, _)| *ch == channel)
+            .map(|(_, status)| status)
+    }
+
+    pub fn timer_progress(&self, channel: Channel) -> Option<&TimerProgress> {
+        self.timers.iter().find(|progress| progress.channel == channel)
+    }
+
+    /// Set minus measured voltage of `channel`, while its output is on and
+    /// in CV. A growing value points at IR drop in the leads or connectors;
+    /// in CC the output voltage is below the setpoint by design, so there
+    /// is none.
+    pub fn tracking_error(&self, channel: Channel) -> Option<f64> {
+        let status = self.channel(channel)?;
+        let cv = self.system.regulation_mode(channel)? == RegulationMode::ConstantVoltage;
+        (self.system.output_on(channel)? && cv)
+            .then(|| status.setpoint_voltage_v - status.measured_voltage_v)
+    }
+}
+
+/// Cached state is older than the caller accepts, typically because polls
+/// have been failing.
+#[derive(Debug, Clone)]
+pub struct StaleData {
+    /// Age of the newest sample; `None` if no poll has succeeded yet.
+    pub age: Option<Duration>,
+    pub max_age: Duration,
+    /// Error of the most recent failed poll, if the last poll failed.
+    pub last_error: Option<String>,
+}
+
+impl fmt::Display for StaleData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.age {
+            Some(age) => write!(
+                f,
+                "monitor data is {age:?} old, older than the accepted {:?}",
+                self.max_age
+            )?,
+            None => write!(f, "monitor has no data yet")?,
+        }
+        if let Some(error) = &self.last_error {
+            write!(f, "; last poll failed: {error}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for StaleData {}
+
+/// Polls a shared instrument at a fixed interval and publishes the results
+/// and derived transitions on an [`EventBus`].
+pub struct Monitor {
+    bus: EventBus,
+    alarms: Alarms,
+    latest: watch::Receiver<Option<Arc<MonitorSample>>>,
+    last_error: watch::Receiver<Option<String>>,
+    trend: Arc<std::sync::Mutex<TrendRecorder>>,
+    uptime: watch::Receiver<UptimeTracker>,
+    shutdown: watch::Sender<bool>,
+    task: Option<JoinHandle<()>>,
+    /// Feeds `alarms` from `bus`, which outlives the polling task.
+    alarm_task: JoinHandle<()>,
+}
+
+impl Monitor {
+    pub fn start(inst: Arc<Mutex<Spd3303x>>, config: MonitorConfig) -> Self {
+        Self::start_with_bus(inst, config, EventBus::default())
+    }
+
+    /// Like [`start`](Self::start), publishing on an existing bus.
+    pub fn start_with_bus(inst: Arc<Mutex<Spd3303x>>, config: MonitorConfig, bus: EventBus) -> Self {
+        let (latest_tx, latest) = watch::channel(None);
+        let (last_error_tx, last_error) = watch::channel(None);
+        let (shutdown, shutdown_rx) = watch::channel(false);
+        let trend = Arc::new(std::sync::Mutex::new(TrendRecorder::new(config.trend_points)));
+        let (uptime_tx, uptime) = watch::channel(UptimeTracker::new());
+        let state = PollState {
+            latest: latest_tx,
+            last_error: last_error_tx,
+            trend: trend.clone(),
+            uptime: uptime_tx,
+        };
+        let alarms = Alarms::new();
+        let alarm_task = alarms.track(&bus);
+        let task = tokio::spawn(run(inst, config, bus.clone(), state, shutdown_rx));
+        Self {
+            bus,
+            alarms,
+            latest,
+            last_error,
+            trend,
+            uptime,
+            shutdown,
+            task: Some(task),
+            alarm_task,
+        }
+    }
+
+    /// Subscribe to the events matching `filter`.
+    pub fn events(&self, filter: EventFilter) -> EventStream {
+        self.bus.subscribe(filter)
+    }
+
+    pub fn bus(&self) -> &EventBus {
+        &self.bus
+    }
+
+    /// Alarms derived from this monitor's events.
+    pub fn alarms(&self) -> &Alarms {
+        &self.alarms
+    }
+
+    /// Most recent successful poll, if any, however old.
+    pub fn latest(&self) -> Option<Arc<MonitorSample>> {
+        self.latest.borrow().clone()
+    }
+
+    /// Most recent successful poll, or [`StaleData`] if it is older than
+    /// `max_age` or there is none.
+    pub fn latest_within(&self, max_age: Duration) -> Result<Arc<MonitorSample>> {
+        let latest = self.latest();
+        match &latest {
+            Some(sample) if sample.age() <= max_age => Ok(sample.clone()),
+            _ => Err(StaleData {
+                age: latest.as_ref().map(|sample| sample.age()),
+                max_age,
+                last_error: self.last_error.borrow().clone(),
+            }
+            .into()),
+        }
+    }
+
+    /// Output state of `channel` from a sample no older than `max_age`.
+    pub fn output_on(&self, channel: Channel, max_age: Duration) -> Result<bool> {
+        self.latest_within(max_age)?
+            .system
+            .output_on(channel)
+            .ok_or_else(|| {
+                anyhow!(
+                    "output state of {} is not in the status word",
+                    channel.label()
+                )
+            })
+    }
+
+    /// Readbacks of `channel` over the last
+    /// [`trend_points`](MonitorConfig::trend_points) polls, oldest first,
+    /// as the front-panel waveform display would plot them.
+    pub fn wave_trend(&self, channel: Channel) -> Vec<TrendPoint> {
+        self.trend
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .points(channel)
+    }
+
+    /// How long the instrument has been up at least: since the first
+    /// successful poll or the last [`Event::PowerCycleSuspected`].
+    pub fn uptime(&self) -> Option<Duration> {
+        self.uptime.borrow().uptime()
+    }
+
+    /// Tracking error of `channel` in the most recent poll; see
+    /// [`MonitorSample::tracking_error`].
+    pub fn tracking_error(&self, channel: Channel) -> Option<f64> {
+        self.latest()?.tracking_error(channel)
+    }
+
+    /// Timer progress of `channel` in the most recent poll, for a progress
+    /// bar; `None` if its timer was not running.
+    pub fn timer_progress(&self, channel: Channel) -> Option<TimerProgress> {
+        self.latest()?.timer_progress(channel).cloned()
+    }
+
+    /// Stop polling after the poll in progress, if any, has completed.
+    pub async fn stop(mut self) -> Result<()> {
+        self.shutdown.send_replace(true);
+        if let Some(task) = self.task.take() {
+            task.await?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        self.shutdown.send_replace(true);
+        self.alarm_task.abort();
+    }
+}
+
+/// Cached results shared with the [`Monitor`] handle.
+struct PollState {
+    latest: watch::Sender<Option<Arc<MonitorSample>>>,
+    last_error: watch::Sender<Option<String>>,
+    trend: Arc<std::sync::Mutex<TrendRecorder>>,
+    uptime: watch::Sender<UptimeTracker>,
+}
+
+async fn run(
+    inst: Arc<Mutex<Spd3303x>>,
+    config: MonitorConfig,
+    bus: EventBus,
+    state: PollState,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let clock = inst.lock().await.clock();
+    *state.trend.lock().unwrap_or_else(|e| e.into_inner()) =
+        TrendRecorder::starting_at(config.trend_points, clock.now());
+    state.uptime.send_replace(UptimeTracker::with_clock(clock.clone()));
+    let mut ticker = Ticker::new(clock.clone(), config.interval);
+    let mut transitions = Transitions::new(config.debounce);
+    let mut backoff = Backoff::new(config.interval, config.max_interval);
+    let mut poller = Poller::new(config.strategy, config.channels.clone());
+    let mut tracking_alarms: Vec<Channel> = Vec::new();
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = shutdown.changed() => break,
+        }
+        if *shutdown.borrow() {
+            break;
+        }
+
+        let started = clock.now();
+        let result = poller.poll(&inst).await;
+        let elapsed = clock.elapsed(started);
+        let change = match &result {
+            Ok(_) if elapsed <= config.slow_poll => backoff.healthy(),
+            Ok(_) => backoff.strained(format!("poll took {elapsed:?}")),
+            Err(e) => backoff.strained(format!("{e:#}")),
+        };
+        if let Some(event) = change {
+            let interval = backoff.current;
+            debug!("monitor: poll interval now {interval:?}");
+            ticker = Ticker::starting_at(clock.clone(), clock.now() + interval, interval);
+            bus.publish(event);
+        }
+
+        match result {
+            Ok(sample) => {
+                transitions.observe(&bus, &sample.system);
+                let mut rebooted = false;
+                state
+                    .uptime
+                    .send_modify(|tracker| rebooted = tracker.observe(&sample.system));
+                if rebooted {
+                    warn!("monitor: outputs and timers went off across failed polls; power cycle?");
+                    bus.publish(Event::PowerCycleSuspected);
+                }
+                if let Some(limits) = &config.safety_limits
+                    && limits.trip_on_measurement
+                {
+                    enforce_safety_limits(&inst, &bus, &sample, limits).await;
+                }
+                if let Some(threshold_v) = config.tracking_error_threshold_v {
+                    publish_tracking(&bus, &sample, threshold_v, &mut tracking_alarms);
+                }
+                if config.trend_points > 0 {
+                    state
+                        .trend
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .record(&sample);
+                }
+                let sample = Arc::new(sample);
+                state.latest.send_replace(Some(sample.clone()));
+                state.last_error.send_replace(None);
+                for progress in &sample.timers {
+                    bus.publish(Event::TimerProgress(progress.clone()));
+                }
+                bus.publish(Event::Sample(sample));
+            }
+            Err(e) => {
+                warn!("monitor: poll failed: {e:#}");
+                let error = format!("{e:#}");
+                state.last_error.send_replace(Some(error.clone()));
+                state.uptime.send_modify(UptimeTracker::poll_failed);
+                bus.publish(Event::PollFailed { error });
+            }
+        }
+    }
+    debug!("monitor: stopped");
+}
+
+/// Poll interval adaptation, see the module docs.
+struct Backoff {
+    base: Duration,
+    max: Duration,
+    current: Duration,
+    healthy_streak: u32,
+}
+
+impl Backoff {
+    fn new(base: Duration, max: Duration) -> Self {
+        Self {
+            base,
+            max: max.max(base),
+            current: base,
+            healthy_streak: 0,
+        }
+    }
+
+    /// Slow down; returns the event to publish if the interval changed.
+    fn strained(&mut self, reason: String) -> Option<Event> {
+        self.healthy_streak = 0;
+        let next = (self.current * 2).min(self.max);
+        if next == self.current {
+            return None;
+        }
+        self.current = next;
+        warn!("monitor: backing off to {next:?}: {reason}");
+        Some(Event::Degraded {
+            interval: next,
+            reason,
+        })
+    }
+
+    fn healthy(&mut self) -> Option<Event> {
+        if self.current == self.base {
+            return None;
+        }
+        self.healthy_streak += 1;
+        if self.healthy_streak < RECOVERY_POLLS {
+            return None;
+        }
+        self.current = self.base;
+        self.healthy_streak = 0;
+        Some(Event::Recovered {
+            interval: self.base,
+        })
+    }
+}
+
+/// Switch off every channel of `sample` measuring above `limits`.
+async fn enforce_safety_limits(
+    inst: &Mutex<Spd3303x>,
+    bus: &EventBus,
+    sample: &MonitorSample,
+    limits: &SafetyLimits,
+) {
+    let mut tripped: Vec<Channel> = Vec::new();
+    for exceeded in limits.exceeded(sample) {
+        warn!("monitor: {exceeded}; switching the output off");
+        let channel = exceeded.channel;
+        let switched_off = tripped.contains(&channel)
+            || match inst.lock().await.force_output_off(channel).await {
+                Ok(()) => {
+                    tripped.push(channel);
+                    true
+                }
+                Err(e) => {
+                    warn!("monitor: switching {} off failed: {e:#}", channel.label());
+                    false
+                }
+            };
+        bus.publish(Event::SafetyLimitExceeded {
+            exceeded,
+            switched_off,
+        });
+    }
+}
+
+/// Publish threshold crossings of the tracking error; `alarms` holds the
+/// channels currently above the threshold.
+fn publish_tracking(
+    bus: &EventBus,
+    sample: &MonitorSample,
+    threshold_v: f64,
+    alarms: &mut Vec<Channel>,
+) {
+    for (channel, _) in &sample.channels {
+        let channel = *channel;
+        let error_v = sample.tracking_error(channel);
+        let alarmed = alarms.contains(&channel);
+        match error_v {
+            Some(error_v) if error_v > threshold_v && !alarmed => {
+                warn!(
+                    "monitor: {} tracking error {error_v:.3} V exceeds {threshold_v} V",
+                    channel.label()
+                );
+                alarms.push(channel);
+                bus.publish(Event::TrackingErrorExceeded {
+                    channel,
+                    error_v,
+                    threshold_v,
+                });
+            }
+            Some(error_v) if error_v <= threshold_v && alarmed => {
+                alarms.retain(|&ch| ch != channel);
+                bus.publish(Event::TrackingErrorCleared {
+                    channel,
+                    error_v: Some(error_v),
+                });
+            }
+            None if alarmed => {
+                alarms.retain(|&ch| ch != channel);
+                bus.publish(Event::TrackingErrorCleared {
+                    channel,
+                    error_v: None,
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A debounced status bit: the value last published and a new value
+/// waiting to be confirmed.
+#[derive(Debug, Clone, Copy)]
+struct DebouncedBit<T> {
+    published: T,
+    pending: Option<(T, u32)>,
+}
+
+impl<T: Copy + PartialEq> DebouncedBit<T> {
+    fn new(value: T) -> Self {
+        Self {
+            published: value,
+            pending: None,
+        }
+    }
+
+    /// Returns the new value once it has held for `polls` polls in a row.
+    fn observe(&mut self, value: T, polls: u32) -> Option<T> {
+        if value == self.published {
+            self.pending = None;
+            return None;
+        }
+        let seen = match self.pending {
+            Some((pending, seen)) if pending == value => seen + 1,
+            _ => 1,
+        };
+        if seen < polls.max(1) {
+            self.pending = Some((value, seen));
+            return None;
+        }
+        self.published = value;
+        self.pending = None;
+        Some(value)
+    }
+}
+
+/// CC/CV and output transitions of CH1/CH2, debounced.
+struct Transitions {
+    debounce: Debounce,
+    /// Per programmable channel; empty until the first sample.
+    channels: Vec<(Channel, DebouncedBit<RegulationMode>, DebouncedBit<bool>)>,
+}
+
+impl Transitions {
+    fn new(debounce: Debounce) -> Self {
+        Self {
+            debounce,
+            channels: Vec::new(),
+        }
+    }
+
+    fn observe(&mut self, bus: &EventBus, status: &SystemStatus) {
+        if self.channels.is_empty() {
+            self.channels = Channel::programmable()
+                .filter_map(|channel| {
+                    let mode = status.regulation_mode(channel)?;
+                    let on = status.output_on(channel)?;
+                    Some((channel, DebouncedBit::new(mode), DebouncedBit::new(on)))
+                })
+                .collect();
+            return;
+        }
+        for (channel, regulation, output) in &mut self.channels {
+            let channel = *channel;
+            if let Some(mode) = status.regulation_mode(channel)
+                && let Some(mode) = regulation.observe(mode, self.debounce.regulation_polls)
+            {
+                bus.publish(Event::RegulationChanged { channel, mode });
+            }
+            if let Some(on) = status.output_on(channel)
+                && let Some(on) = output.observe(on, self.debounce.output_polls)
+            {
+                bus.publish(Event::OutputChanged { channel, on });
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Provisioning the instrument's LAN settings.
+//!
+//! The settings interact: `IPaddr` is rejected while DHCP is on, so a
+//! static plan turns DHCP off before writing the address, mask and
+//! gateway. Some units only take new settings over once they have been
+//! confirmed on the front panel (Utility > LAN); until then the queries
+//! still return the old values. [`Spd3303x::provision_network`] therefore
+//! re-sends and re-checks a few times and reports what is still missing.
+//!
+//! Changing the address of the unit this handle is connected to over LAN
+//! ends the connection; provision over USB/serial or GPIB, or expect the
+//! verification to fail and reconnect to the new address.
+
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use tracing::{debug, warn};
+
+use crate::instrument::{DhcpState, NetworkConfig, Spd3303x};
+use crate::operation;
+
+const PROVISION_ATTEMPTS: u32 = 3;
+/// Time for the firmware to apply the settings before they are re-read.
+const PROVISION_SETTLE: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NetworkPlan {
+    Static {
+        ip: Ipv4Addr,
+        mask: Ipv4Addr,
+        gateway: Ipv4Addr,
+    },
+    Dhcp,
+}
+
+impl NetworkPlan {
+    /// Settings of `config` that differ from this plan, by name.
+    pub fn mismatches(&self, config: &NetworkConfig) -> Vec<&'static str> {
+        let mut mismatches = Vec::new();
+        let is = |text: &str, expected: Ipv4Addr| {
+            text.trim().parse::<Ipv4Addr>().ok() == Some(expected)
+        };
+        match *self {
+            NetworkPlan::Static { ip, mask, gateway } => {
+                if config.dhcp {
+                    mismatches.push("DHCP");
+                }
+                if !is(&config.ip, ip) {
+                    mismatches.push("IP");
+                }
+                if !is(&config.mask, mask) {
+                    mismatches.push("mask");
+                }
+                if !is(&config.gateway, gateway) {
+                    mismatches.push("gateway");
+                }
+            }
+            NetworkPlan::Dhcp => {
+                if !config.dhcp {
+                    mismatches.push("DHCP");
+                }
+            }
+        }
+        mismatches
+    }
+}
+
+impl Spd3303x {
+    /// Apply `plan` and verify it by re-querying; see the
+    /// [module docs](crate::network) for ordering and front-panel
+    /// confirmation. Returns the settings read back.
+    pub async fn provision_network(&mut self, plan: NetworkPlan) -> Result<NetworkConfig> {
+        operation::run("provision_network", async {
+            let mut mismatches = Vec::new();
+            for attempt in 1..=PROVISION_ATTEMPTS {
+                self.send_network_plan(plan).await?;
+                self.clock().sleep(PROVISION_SETTLE).await;
+                let config = self.network_config().await?;
+                mismatches = plan.mismatches(&config);
+                if mismatches.is_empty() {
+                    debug!("network: {plan:?} applied on attempt {attempt}");
+                    return Ok(config);
+                }
+                warn!(
+                    "network: {} not applied after attempt {attempt} of {PROVISION_ATTEMPTS}",
+                    mismatches.join(", ")
+                );
+            }
+            Err(anyhow!(
+                "{} still differ from the plan after {PROVISION_ATTEMPTS} attempts; \
+                 the unit may need the LAN settings confirmed on its front panel",
+                mismatches.join(", ")
+            ))
+        })
+        .await
+    }
+
+    async fn send_network_plan(&mut self, plan: NetworkPlan) -> Result<()> {
+        match plan {
+            NetworkPlan::Static { ip, mask, gateway } => {
+                self.set_dhcp(DhcpState::Off).await?;
+                self.set_ip(&ip.to_string()).await?;
+                self.set_mask(&mask.to_string()).await?;
+                self.set_gateway(&gateway.to_string()).await
+            }
+            NetworkPlan::Dhcp => self.set_dhcp(DhcpState::On).await,
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//! Operator notes on a running session.
+//!
+//! Swapping a DUT or re-seating a cable leaves a step in the data that is
+//! hard to explain afterwards. [`Spd3303x::annotate`] records a timestamped
+//! note, logs it, and sequence reports carry the notes taken while they
+//! ran. A runner holds the instrument mutably, so notes from another task
+//! go through a [`NoteLog`] handle taken beforehand:
+//!
+//! ```ignore
+//! let notes = inst.note_log();
+//! tokio::spawn(async move { notes.annotate("swapped DUT #4") });
+//! let report = runner.run(&mut inst, &sequence).await?;
+//! ```
+
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use tracing::info;
+
+use crate::instrument::Spd3303x;
+use crate::operation::{self, OperationId};
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Note {
+    pub at: SystemTime,
+    /// Operation running in the annotating task, see [`crate::operation`].
+    pub operation: Option<OperationId>,
+    pub text: String,
+}
+
+/// Shared list of the notes taken on one instrument handle.
+#[derive(Debug, Clone, Default)]
+pub struct NoteLog(Arc<Mutex<Vec<Note>>>);
+
+impl NoteLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn annotate(&self, text: impl Into<String>) {
+        let note = Note {
+            at: SystemTime::now(),
+            operation: operation::current(),
+            text: text.into(),
+        };
+        info!(note = %note.text, "operator note");
+        self.lock().push(note);
+    }
+
+    pub fn notes(&self) -> Vec<Note> {
+        self.lock().clone()
+    }
+
+    /// Notes taken at or after `at`.
+    pub fn since(&self, at: SystemTime) -> Vec<Note> {
+        self.lock()
+            .iter()
+            .filter(|note| note.at >= at)
+            .cloned()
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Note>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Spd3303x {
+    /// Record a timestamped note, e.g. "swapped DUT #4".
+    pub fn annotate(&self, text: impl Into<String>) {
+        self.note_log().annotate(text);
+    }
+
+    /// Handle for taking notes from other tasks.
+    pub fn note_log(&self) -> NoteLog {
+        self.notes().clone()
+    }
+}
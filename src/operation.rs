@@ -0,0 +1,53 @@
+//! Correlation IDs for high-level operations.
+//!
+//! Every operation run through [`run`] gets a fresh [`OperationId`], is
+//! wrapped in an `operation` tracing span carrying that ID, and tags the
+//! SCPI transcript entries it causes, so a wire command can be traced back
+//! to the ramp, sequence step or reset that issued it.
+
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tracing::{info_span, Instrument};
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+tokio::task_local! {
+    static CURRENT: OperationId;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OperationId(u64);
+
+impl OperationId {
+    fn next() -> Self {
+        Self(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    pub fn value(self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for OperationId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "op-{}", self.0)
+    }
+}
+
+/// The innermost operation the current task is running, if any.
+pub fn current() -> Option<OperationId> {
+    CURRENT.try_with(|id| *id).ok()
+}
+
+/// Run `fut` as a named operation with a new ID.
+///
+/// Operations may nest; commands are attributed to the innermost one and
+/// the spans show the nesting.
+pub async fn run<F: Future>(name: &'static str, fut: F) -> F::Output {
+    let id = OperationId::next();
+    let span = info_span!("operation", op = %id, name);
+    CURRENT.scope(id, fut.instrument(span)).await
+}
@@ -0,0 +1,203 @@
+//! Allocation-free decoding of SPD3303X replies.
+//!
+//! The functions here borrow the reply (`&[u8]` as read from the link,
+//! trailing NULs and whitespace allowed) and fail with the `Copy`
+//! [`ParseError`], so a gateway running many pollers can decode into its
+//! own buffers without touching the heap. `Spd3303x` decodes through the
+//! same functions. With the `heapless` feature, [`IdentityBuf`] keeps an
+//! identity in fixed-capacity strings.
+
+use std::fmt;
+use std::time::Duration;
+
+use crate::instrument::{Channel, FirmwareVersion, Identity, SystemStatus, TimerEntry};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    NotUtf8,
+    Empty,
+    InvalidNumber,
+    InvalidChannel,
+    InvalidStatusWord,
+    InvalidDuration,
+    InvalidFirmwareVersion,
+    MissingField(&'static str),
+    /// A field does not fit a fixed-capacity buffer.
+    TooLong(&'static str),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::NotUtf8 => write!(f, "reply is not UTF-8"),
+            ParseError::Empty => write!(f, "reply is empty"),
+            ParseError::InvalidNumber => write!(f, "invalid number"),
+            ParseError::InvalidChannel => write!(f, "unknown channel"),
+            ParseError::InvalidStatusWord => write!(f, "invalid status word"),
+            ParseError::InvalidDuration => write!(f, "invalid duration"),
+            ParseError::InvalidFirmwareVersion => write!(f, "invalid firmware version"),
+            ParseError::MissingField(name) => write!(f, "missing {name}"),
+            ParseError::TooLong(name) => write!(f, "{name} too long"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// The reply as text, without NUL padding and surrounding whitespace.
+pub fn text(reply: &[u8]) -> Result<&str, ParseError> {
+    let text = std::str::from_utf8(reply)
+        .map_err(|_| ParseError::NotUtf8)?
+        .trim_matches(char::from(0))
+        .trim();
+    if text.is_empty() {
+        return Err(ParseError::Empty);
+    }
+    Ok(text)
+}
+
+/// `CHn:VOLT?`, `MEAS:CURR?` and the like.
+pub fn number(reply: &[u8]) -> Result<f64, ParseError> {
+    text(reply)?.parse().map_err(|_| ParseError::InvalidNumber)
+}
+
+/// `INST?`.
+pub fn channel(reply: &[u8]) -> Result<Channel, ParseError> {
+    let text = text(reply)?;
+    Channel::all()
+        .find(|channel| channel.label().eq_ignore_ascii_case(text))
+        .ok_or(ParseError::InvalidChannel)
+}
+
+/// `SYST:STAT?`, hexadecimal with or without `0x`.
+pub fn status_word(reply: &[u8]) -> Result<u32, ParseError> {
+    let text = text(reply)?;
+    let digits = text.strip_prefix("0x").unwrap_or(text).trim();
+    u32::from_str_radix(digits, 16).map_err(|_| ParseError::InvalidStatusWord)
+}
+
+pub fn system_status(reply: &[u8]) -> Result<SystemStatus, ParseError> {
+    status_word(reply).map(SystemStatus::from_word)
+}
+
+/// `TIMER:SET? CHn,<group>`: `voltage,current,seconds`.
+pub fn timer_entry(group: u8, reply: &[u8]) -> Result<TimerEntry, ParseError> {
+    let mut parts = text(reply)?.split(',').map(str::trim);
+    let mut next = |name| {
+        parts
+            .next()
+            .ok_or(ParseError::MissingField(name))?
+            .parse::<f64>()
+            .map_err(|_| ParseError::InvalidNumber)
+    };
+    let voltage_v = next("voltage")?;
+    let current_a = next("current")?;
+    let seconds = next("duration")?;
+    let duration =
+        Duration::try_from_secs_f64(seconds).map_err(|_| ParseError::InvalidDuration)?;
+    Ok(TimerEntry {
+        group,
+        voltage_v,
+        current_a,
+        duration,
+    })
+}
+
+/// Dotted firmware version; missing trailing fields count as 0, and a
+/// letter suffix such as the `R2` of `07R2` is ignored.
+pub fn firmware_version(reply: &[u8]) -> Result<FirmwareVersion, ParseError> {
+    let mut fields = [0u16; 5];
+    let mut parts = text(reply)?.split('.');
+    for field in fields.iter_mut() {
+        match parts.next() {
+            Some(part) => *field = version_field(part.trim())?,
+            None => break,
+        }
+    }
+    if parts.next().is_some() {
+        return Err(ParseError::InvalidFirmwareVersion);
+    }
+    let [major, minor, patch, build, revision] = fields;
+    Ok(FirmwareVersion::new(major, minor, patch, build, revision))
+}
+
+/// Leading digits of one version field, followed by an optional suffix
+/// that starts with a letter.
+fn version_field(part: &str) -> Result<u16, ParseError> {
+    let digits = part.find(|c: char| !c.is_ascii_digit()).unwrap_or(part.len());
+    let (number, suffix) = part.split_at(digits);
+    let suffix_ok = suffix.is_empty()
+        || (suffix.starts_with(|c: char| c.is_ascii_alphabetic())
+            && suffix.chars().all(|c| c.is_ascii_alphanumeric()));
+    if !suffix_ok {
+        return Err(ParseError::InvalidFirmwareVersion);
+    }
+    number.parse().map_err(|_| ParseError::InvalidFirmwareVersion)
+}
+
+/// `*IDN?` fields borrowed from the reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdentityRef<'a> {
+    pub manufacturer: &'a str,
+    pub model: &'a str,
+    pub serial: &'a str,
+    pub firmware: &'a str,
+    /// Empty on older firmware, which omits the hardware version.
+    pub hardware: &'a str,
+}
+
+impl IdentityRef<'_> {
+    pub fn to_identity(&self) -> Identity {
+        Identity {
+            manufacturer: self.manufacturer.to_string(),
+            model: self.model.to_string(),
+            serial: self.serial.to_string(),
+            firmware: self.firmware.to_string(),
+            hardware: self.hardware.to_string(),
+        }
+    }
+}
+
+pub fn identity(reply: &[u8]) -> Result<IdentityRef<'_>, ParseError> {
+    let mut fields = text(reply)?.split(',').map(str::trim);
+    let mut next = |name| fields.next().ok_or(ParseError::MissingField(name));
+    Ok(IdentityRef {
+        manufacturer: next("manufacturer")?,
+        model: next("model")?,
+        serial: next("serial number")?,
+        firmware: next("firmware version")?,
+        hardware: next("hardware version").unwrap_or_default(),
+    })
+}
+
+/// [`Identity`] in fixed-capacity strings.
+#[cfg(feature = "heapless")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdentityBuf {
+    pub manufacturer: heapless::String<32>,
+    pub model: heapless::String<16>,
+    pub serial: heapless::String<32>,
+    pub firmware: heapless::String<24>,
+    pub hardware: heapless::String<16>,
+}
+
+#[cfg(feature = "heapless")]
+impl TryFrom<IdentityRef<'_>> for IdentityBuf {
+    type Error = ParseError;
+
+    fn try_from(identity: IdentityRef<'_>) -> Result<Self, ParseError> {
+        fn field<const N: usize>(
+            value: &str,
+            name: &'static str,
+        ) -> Result<heapless::String<N>, ParseError> {
+            heapless::String::try_from(value).map_err(|_| ParseError::TooLong(name))
+        }
+        Ok(Self {
+            manufacturer: field(identity.manufacturer, "manufacturer")?,
+            model: field(identity.model, "model")?,
+            serial: field(identity.serial, "serial number")?,
+            firmware: field(identity.firmware, "firmware version")?,
+            hardware: field(identity.hardware, "hardware version")?,
+        })
+    }
+}
@@ -0,0 +1,181 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::instrument::Channel;
+
+/// Which reading a [`Sample`] carries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Quantity {
+    Voltage,
+    Current,
+    Power,
+}
+
+impl Quantity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Quantity::Voltage => "voltage",
+            Quantity::Current => "current",
+            Quantity::Power => "power",
+        }
+    }
+
+    pub fn unit(self) -> &'static str {
+        match self {
+            Quantity::Voltage => "V",
+            Quantity::Current => "A",
+            Quantity::Power => "W",
+        }
+    }
+}
+
+/// A single reading as it passes through a [`SamplePipeline`].
+#[derive(Debug, Clone, Copy)]
+pub struct Sample {
+    pub channel: Channel,
+    pub quantity: Quantity,
+    pub value: f64,
+    /// Raw output current of the same channel, if it was read alongside this
+    /// sample. Always present for processors that report `needs_current()`.
+    pub current_a: Option<f64>,
+}
+
+/// A user-installable step that rewrites a measurement value.
+pub trait SampleProcessor: Send + Sync {
+    fn process(&self, sample: &Sample) -> f64;
+
+    /// Whether this processor needs [`Sample::current_a`] for voltage/power
+    /// readings. The instrument performs an extra `MEAS:CURR?` when it does.
+    fn needs_current(&self) -> bool {
+        false
+    }
+}
+
+impl<F> SampleProcessor for F
+where
+    F: Fn(&Sample) -> f64 + Send + Sync,
+{
+    fn process(&self, sample: &Sample) -> f64 {
+        self(sample)
+    }
+}
+
+/// Ordered list of processors applied to every reading of one channel.
+///
+/// Cloning is cheap; processors are shared.
+#[derive(Clone, Default)]
+pub struct SamplePipeline {
+    processors: Vec<Arc<dyn SampleProcessor>>,
+}
+
+impl SamplePipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, processor: impl SampleProcessor + 'static) -> Self {
+        self.push(processor);
+        self
+    }
+
+    pub fn push(&mut self, processor: impl SampleProcessor + 'static) {
+        self.processors.push(Arc::new(processor));
+    }
+
+    /// Append the processors of `other`, after those already present.
+    pub fn extend(&mut self, other: &SamplePipeline) {
+        self.processors.extend(other.processors.iter().cloned());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.processors.is_empty()
+    }
+
+    pub fn needs_current(&self) -> bool {
+        self.processors.iter().any(|p| p.needs_current())
+    }
+
+    /// Run the sample through every processor in order and return the result.
+    pub fn apply(&self, mut sample: Sample) -> f64 {
+        for processor in &self.processors {
+            sample.value = processor.process(&sample);
+        }
+        sample.value
+    }
+}
+
+impl fmt::Debug for SamplePipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SamplePipeline")
+            .field("processors", &self.processors.len())
+            .finish()
+    }
+}
+
+/// Linear correction `value * gain + offset` for one quantity.
+///
+/// Also covers unit conversion, e.g. [`Scale::milli`] to report mV/mA/mW.
+#[derive(Debug, Clone, Copy)]
+pub struct Scale {
+    pub quantity: Quantity,
+    pub gain: f64,
+    pub offset: f64,
+}
+
+impl Scale {
+    pub fn new(quantity: Quantity, gain: f64, offset: f64) -> Self {
+        Self {
+            quantity,
+            gain,
+            offset,
+        }
+    }
+
+    pub fn milli(quantity: Quantity) -> Self {
+        Self::new(quantity, 1000.0, 0.0)
+    }
+}
+
+impl SampleProcessor for Scale {
+    fn process(&self, sample: &Sample) -> f64 {
+        if sample.quantity == self.quantity {
+            sample.value * self.gain + self.offset
+        } else {
+            sample.value
+        }
+    }
+}
+
+/// Subtract the drop across a series resistance (leads, shunt, connector)
+/// so voltage and power readings approximate the values at the DUT.
+///
+/// The SPD3303X has no remote sense terminals, so this is the host-side
+/// equivalent: `V_dut = V - I·R`, `P_dut = P - I²·R`.
+#[derive(Debug, Clone, Copy)]
+pub struct SeriesResistance {
+    pub ohms: f64,
+}
+
+impl SeriesResistance {
+    pub fn new(ohms: f64) -> Self {
+        Self { ohms }
+    }
+}
+
+impl SampleProcessor for SeriesResistance {
+    fn process(&self, sample: &Sample) -> f64 {
+        let Some(current) = sample.current_a else {
+            return sample.value;
+        };
+        match sample.quantity {
+            Quantity::Voltage => sample.value - current * self.ohms,
+            Quantity::Power => sample.value - current * current * self.ohms,
+            Quantity::Current => sample.value,
+        }
+    }
+
+    fn needs_current(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,190 @@
+//! Named presets layered over the five `*SAV` / `*RCL` slots.
+//!
+//! The instrument only knows slot numbers, so a [`PresetManifest`] kept on
+//! the host records which name lives in which slot, with a description and
+//! a hash of the state that was saved, e.g.:
+//!
+//! ```toml
+//! [[presets]]
+//! name = "usb-5v"
+//! slot = 1
+//! description = "5 V / 0.5 A for USB devices"
+//! hash = "9f3c1a2b4d5e6f70"
+//! ```
+//!
+//! Slots can be overwritten from the front panel or by another tool; when
+//! a recalled slot no longer hashes to what the manifest recorded, the
+//! preset has drifted and a warning is logged.
+
+use anyhow::{anyhow, Result};
+use tracing::{debug, warn};
+
+use crate::instrument::{Channel, Spd3303x, TrackMode};
+use crate::operation;
+use crate::snapshot::InstrumentSnapshot;
+use crate::validate::{self, check_slot};
+
+/// FNV-1a, stable across Rust versions unlike `DefaultHasher`, so hashes
+/// stay comparable in a manifest kept for years.
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Preset {
+    pub name: String,
+    /// `*SAV` / `*RCL` slot, 1..=5.
+    pub slot: u8,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub description: String,
+    /// [`state_hash`] of the state saved to the slot, in hex.
+    pub hash: String,
+}
+
+impl Preset {
+    /// Whether `snapshot` is the state this preset was saved with.
+    pub fn matches(&self, snapshot: &InstrumentSnapshot) -> bool {
+        self.hash == state_hash(snapshot)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct PresetManifest {
+    pub presets: Vec<Preset>,
+}
+
+impl PresetManifest {
+    /// A missing file is an empty manifest, so the first save creates it.
+    #[cfg(feature = "config")]
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        use anyhow::Context;
+
+        let path = path.as_ref();
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("failed to read preset manifest {}", path.display()))
+            }
+        };
+        toml::from_str(&text)
+            .with_context(|| format!("failed to parse preset manifest {}", path.display()))
+    }
+
+    #[cfg(feature = "config")]
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        use anyhow::Context;
+
+        let path = path.as_ref();
+        let text = toml::to_string_pretty(self).context("failed to serialize preset manifest")?;
+        std::fs::write(path, text)
+            .with_context(|| format!("failed to write preset manifest {}", path.display()))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Preset> {
+        self.presets.iter().find(|preset| preset.name == name)
+    }
+
+    pub fn by_slot(&self, slot: u8) -> Option<&Preset> {
+        self.presets.iter().find(|preset| preset.slot == slot)
+    }
+
+    /// Add `preset`, replacing any preset of the same name and whatever was
+    /// recorded for its slot, which the save has overwritten.
+    pub fn insert(&mut self, preset: Preset) {
+        self.presets
+            .retain(|existing| existing.name != preset.name && existing.slot != preset.slot);
+        self.presets.push(preset);
+        self.presets.sort_by_key(|preset| preset.slot);
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Preset> {
+        let index = self.presets.iter().position(|preset| preset.name == name)?;
+        Some(self.presets.remove(index))
+    }
+}
+
+/// Hash of the parts of a snapshot a slot stores: track mode and the CH1/CH2
+/// setpoints, in whole mV / mA. Output flags and readbacks are left out;
+/// whether outputs are recalled depends on the firmware.
+pub fn state_hash(snapshot: &InstrumentSnapshot) -> String {
+    let track = match snapshot.system.track_mode {
+        Some(TrackMode::Independent) => 0,
+        Some(TrackMode::Series) => 1,
+        Some(TrackMode::Parallel) => 2,
+        None => 3,
+    };
+    let mut hash = FNV_OFFSET;
+    let mut feed = |value: i64| {
+        for byte in value.to_le_bytes() {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    };
+    feed(track);
+    for channel in Channel::programmable() {
+        let status = snapshot.channel(channel).expect("CH1/CH2 are in every snapshot");
+        feed((status.setpoint_voltage_v * 1000.0).round() as i64);
+        feed((status.setpoint_current_a * 1000.0).round() as i64);
+    }
+    format!("{hash:016x}")
+}
+
+impl Spd3303x {
+    /// `*SAV` the current state to `slot` under `name` and record it in
+    /// `manifest`. Save the manifest afterwards to keep the entry.
+    pub async fn save_preset(
+        &mut self,
+        manifest: &mut PresetManifest,
+        name: &str,
+        slot: u8,
+        description: &str,
+    ) -> Result<()> {
+        validate::ensure(check_slot(slot))?;
+        if name.trim().is_empty() {
+            return Err(anyhow!("preset name must not be empty"));
+        }
+        let snapshot = operation::run("save_preset", async {
+            self.save_state(slot).await?;
+            self.snapshot().await
+        })
+        .await?;
+        if let Some(previous) = manifest.by_slot(slot).filter(|previous| previous.name != name) {
+            debug!("preset: {name:?} replaces {:?} in slot {slot}", previous.name);
+        }
+        manifest.insert(Preset {
+            name: name.to_string(),
+            slot,
+            description: description.to_string(),
+            hash: state_hash(&snapshot),
+        });
+        Ok(())
+    }
+
+    /// Recall the slot of preset `name` and return what the instrument is
+    /// now doing (see [`recall_and_verify`](Self::recall_and_verify)). A
+    /// slot that no longer matches the manifest is logged as drifted.
+    pub async fn recall_preset(
+        &mut self,
+        manifest: &PresetManifest,
+        name: &str,
+    ) -> Result<InstrumentSnapshot> {
+        let preset = manifest
+            .get(name)
+            .ok_or_else(|| anyhow!("no preset named {name:?}"))?;
+        let snapshot = self.recall_and_verify(preset.slot).await?;
+        if !preset.matches(&snapshot) {
+            warn!(
+                "preset: slot {} no longer holds {name:?} (hash {} in the manifest, {} now); \
+                 it was overwritten outside this manifest",
+                preset.slot,
+                preset.hash,
+                state_hash(&snapshot)
+            );
+        }
+        Ok(snapshot)
+    }
+}
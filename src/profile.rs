@@ -0,0 +1,255 @@
+//! Power profiles: a channel's setpoints over time, written as a chain of
+//! segments and compiled to a [`Sequence`].
+//!
+//! ```ignore
+//! let sequence = PowerProfile::new(Channel::Ch1)
+//!     .set(3.3, 1.0)
+//!     .hold(Duration::from_secs(5))
+//!     .ramp_to(5.0, Duration::from_secs(2))
+//!     .hold(Duration::from_secs(10))
+//!     .off()
+//!     .into_sequence();
+//! ```
+//!
+//! The same profile as text, e.g. from a test's source or a config file:
+//!
+//! ```ignore
+//! let profile = power_profile!(
+//!     Channel::Ch1,
+//!     3.3V@1A for 5s -> ramp to 5V over 2s -> hold 10s -> off
+//! )?;
+//! SequenceRunner::new().run(&mut inst, &profile.into_sequence()).await?;
+//! ```
+//!
+//! Segments are separated by `->`:
+//!
+//! - `3.3V@1A` sets voltage and current and switches the output on;
+//!   `3.3V` sets the voltage only. Either may be followed by `for 5s`.
+//! - `ramp to 5V over 2s` steps the voltage linearly from the previous
+//!   setpoint (0 V if none was set) in steps of
+//!   [`ramp_step`](PowerProfile::ramp_step), at most [`MAX_RAMP_STEPS`] of
+//!   them.
+//! - `hold 10s` keeps the present setpoints.
+//! - `on` and `off` switch the output.
+//!
+//! Quantities are read by [`units`](crate::units), so `3v3`, `500mA` and
+//! `1m30s` work too.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::instrument::{Channel, OutputState};
+use crate::sequence::{Sequence, Step};
+use crate::units::{parse_current, parse_duration, parse_voltage};
+
+/// Time between the setpoints of a ramp unless set otherwise.
+const DEFAULT_RAMP_STEP: Duration = Duration::from_millis(100);
+/// Most setpoints one ramp is split into; longer ramps take longer steps.
+pub const MAX_RAMP_STEPS: u32 = 10_000;
+
+/// A profile of one channel; see the module docs.
+#[derive(Debug, Clone)]
+pub struct PowerProfile {
+    channel: Channel,
+    steps: Vec<Step>,
+    ramp_step: Duration,
+    /// Voltage set by the segments so far.
+    voltage: Option<f64>,
+    output_on: bool,
+}
+
+impl PowerProfile {
+    pub fn new(channel: Channel) -> Self {
+        Self {
+            channel,
+            steps: Vec::new(),
+            ramp_step: DEFAULT_RAMP_STEP,
+            voltage: None,
+            output_on: false,
+        }
+    }
+
+    /// Parse a profile written as in the module docs.
+    pub fn parse(channel: Channel, text: &str) -> Result<Self> {
+        let mut profile = Self::new(channel);
+        for segment in text.split("->") {
+            profile = profile
+                .segment(segment)
+                .with_context(|| format!("invalid profile segment {:?}", segment.trim()))?;
+        }
+        Ok(profile)
+    }
+
+    pub fn channel(&self) -> Channel {
+        self.channel
+    }
+
+    /// Time between the setpoints of the ramps added after this call.
+    pub fn ramp_step(mut self, step: Duration) -> Self {
+        self.ramp_step = step;
+        self
+    }
+
+    /// Set voltage and current, switching the output on if it is not.
+    pub fn set(mut self, volts: f64, amps: f64) -> Self {
+        let mut step = Step::new()
+            .label(format!("{volts:.3} V @ {amps:.3} A"))
+            .voltage(self.channel, volts)
+            .current(self.channel, amps);
+        if !self.output_on {
+            step = step.output(self.channel, OutputState::On);
+            self.output_on = true;
+        }
+        self.voltage = Some(volts);
+        self.steps.push(step);
+        self
+    }
+
+    pub fn voltage(mut self, volts: f64) -> Self {
+        self.voltage = Some(volts);
+        self.steps.push(
+            Step::new()
+                .label(format!("{volts:.3} V"))
+                .voltage(self.channel, volts),
+        );
+        self
+    }
+
+    pub fn current(mut self, amps: f64) -> Self {
+        self.steps.push(
+            Step::new()
+                .label(format!("{amps:.3} A"))
+                .current(self.channel, amps),
+        );
+        self
+    }
+
+    /// Keep the present setpoints for `duration`. Directly after a setpoint
+    /// segment this is that segment's hold, as in `3.3V@1A for 5s`.
+    pub fn hold(mut self, duration: Duration) -> Self {
+        match self.steps.last_mut() {
+            Some(step) if step.hold.is_zero() && !step.actions.is_empty() => {
+                step.hold = duration;
+            }
+            _ => self.steps.push(Step::new().label("hold").hold(duration)),
+        }
+        self
+    }
+
+    /// Step the voltage linearly from the previous setpoint to `volts`
+    /// over `duration`, ending at `volts`.
+    ///
+    /// A ramp has at most [`MAX_RAMP_STEPS`] setpoints, so over `1000h` the
+    /// steps are spaced further apart than [`ramp_step`](Self::ramp_step).
+    pub fn ramp_to(mut self, volts: f64, duration: Duration) -> Self {
+        let from = self.voltage.unwrap_or(0.0);
+        let steps = if self.ramp_step.is_zero() {
+            1
+        } else {
+            (duration.as_secs_f64() / self.ramp_step.as_secs_f64())
+                .ceil()
+                .clamp(1.0, f64::from(MAX_RAMP_STEPS)) as u32
+        };
+        let hold = duration / steps;
+        for index in 1..=steps {
+            let fraction = f64::from(index) / f64::from(steps);
+            let step_volts = from + (volts - from) * fraction;
+            self.steps.push(
+                Step::new()
+                    .label(format!("ramp to {volts:.3} V ({index}/{steps})"))
+                    .voltage(self.channel, step_volts)
+                    .hold(hold),
+            );
+        }
+        self.voltage = Some(volts);
+        self
+    }
+
+    pub fn on(mut self) -> Self {
+        self.output_on = true;
+        self.steps.push(
+            Step::new()
+                .label("on")
+                .output(self.channel, OutputState::On),
+        );
+        self
+    }
+
+    pub fn off(mut self) -> Self {
+        self.output_on = false;
+        self.steps.push(
+            Step::new()
+                .label("off")
+                .output(self.channel, OutputState::Off),
+        );
+        self
+    }
+
+    /// Sum of the holds and ramps.
+    pub fn duration(&self) -> Duration {
+        self.steps.iter().map(|step| step.hold).sum()
+    }
+
+    pub fn into_sequence(self) -> Sequence {
+        Sequence { steps: self.steps }
+    }
+
+    fn segment(self, segment: &str) -> Result<Self> {
+        let spaced = segment.replace('@', " @ ");
+        let words: Vec<&str> = spaced.split_whitespace().collect();
+        let lower: Vec<String> = words.iter().map(|word| word.to_ascii_lowercase()).collect();
+        let lower: Vec<&str> = lower.iter().map(String::as_str).collect();
+        match lower.as_slice() {
+            [] => Err(anyhow!("empty segment")),
+            ["on"] => Ok(self.on()),
+            ["off"] => Ok(self.off()),
+            ["hold", ..] => Ok(self.hold(parse_duration(&words[1..].concat())?)),
+            ["ramp", "to", ..] => {
+                let over = lower
+                    .iter()
+                    .position(|word| *word == "over")
+                    .ok_or_else(|| anyhow!("expected e.g. `ramp to 5V over 2s`"))?;
+                let volts = parse_voltage(&words[2..over].concat())?;
+                Ok(self.ramp_to(volts, parse_duration(&words[over + 1..].concat())?))
+            }
+            _ => {
+                let end = lower
+                    .iter()
+                    .position(|word| *word == "for")
+                    .unwrap_or(words.len());
+                let setpoint = words[..end].concat();
+                let profile = match setpoint.split_once('@') {
+                    Some((volts, amps)) => self.set(parse_voltage(volts)?, parse_current(amps)?),
+                    None => self.voltage(parse_voltage(&setpoint)?),
+                };
+                if end == words.len() {
+                    return Ok(profile);
+                }
+                Ok(profile.hold(parse_duration(&words[end + 1..].concat())?))
+            }
+        }
+    }
+}
+
+impl From<PowerProfile> for Sequence {
+    fn from(profile: PowerProfile) -> Self {
+        profile.into_sequence()
+    }
+}
+
+/// Build a [`PowerProfile`] from the profile syntax of the
+/// [`profile`](crate::profile) module, written inline:
+///
+/// ```ignore
+/// let profile = power_profile!(Channel::Ch1, 3.3V@1A for 5s -> hold 10s -> off)?;
+/// ```
+///
+/// Evaluates to `anyhow::Result<PowerProfile>`; the text is parsed at run
+/// time, so a malformed profile is reported when the test runs.
+#[macro_export]
+macro_rules! power_profile {
+    ($channel:expr, $($profile:tt)+) => {
+        $crate::profile::PowerProfile::parse($channel, stringify!($($profile)+))
+    };
+}
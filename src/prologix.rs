@@ -0,0 +1,136 @@
+//! Prologix GPIB-Ethernet adapter link.
+//!
+//! The adapter bridges a TCP socket (port 1234) to the GPIB bus. Lines
+//! starting with `++` configure the adapter itself; everything else is
+//! forwarded to the addressed instrument. Only the Ethernet model is
+//! handled here.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tracing::debug;
+
+use crate::escalation::CommandTimedOut;
+use crate::transport::{Transport, TransportFuture};
+
+pub const DEFAULT_PORT: u16 = 1234;
+
+/// Highest primary GPIB address.
+const MAX_GPIB_ADDRESS: u8 = 30;
+/// Bus read timeout programmed into the adapter (its maximum), and the
+/// host-side limit on waiting for the reply line.
+const ADAPTER_READ_TIMEOUT_MS: u32 = 3000;
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub(crate) struct PrologixClient {
+    stream: BufReader<TcpStream>,
+}
+
+impl PrologixClient {
+    /// Connect to the adapter and address the instrument at
+    /// `gpib_address`.
+    pub(crate) async fn connect(addr: impl ToSocketAddrs, gpib_address: u8) -> Result<Self> {
+        if gpib_address > MAX_GPIB_ADDRESS {
+            return Err(anyhow!(
+                "GPIB address {gpib_address} out of range (0..={MAX_GPIB_ADDRESS})"
+            ));
+        }
+        let stream = TcpStream::connect(addr)
+            .await
+            .context("failed to connect to Prologix adapter")?;
+        stream.set_nodelay(true)?;
+        let mut client = Self {
+            stream: BufReader::new(stream),
+        };
+
+        // Controller mode, no automatic read-after-write (replies are
+        // fetched explicitly with ++read), assert EOI with the last byte
+        // and terminate commands with LF, which is what the SPD3303X expects.
+        for preamble in [
+            "++mode 1".to_string(),
+            format!("++addr {gpib_address}"),
+            "++auto 0".to_string(),
+            "++eoi 1".to_string(),
+            "++eos 2".to_string(),
+            format!("++read_tmo_ms {ADAPTER_READ_TIMEOUT_MS}"),
+        ] {
+            client.send_line(&preamble).await?;
+        }
+        debug!("prologix: addressed GPIB {gpib_address}");
+        Ok(client)
+    }
+
+    pub(crate) async fn write(&mut self, command: &str) -> Result<()> {
+        let escaped = escape(command.trim_end_matches(['\r', '\n']));
+        self.send_line(&escaped).await
+    }
+
+    pub(crate) async fn query(&mut self, command: &str) -> Result<String> {
+        self.write(command).await?;
+        self.send_line("++read eoi").await?;
+        let mut line = String::new();
+        let read = tokio::time::timeout(READ_TIMEOUT, self.stream.read_line(&mut line))
+            .await
+            .map_err(|_| CommandTimedOut::once(command, READ_TIMEOUT))??;
+        if read == 0 {
+            return Err(anyhow!("Prologix adapter closed the connection"));
+        }
+        Ok(line)
+    }
+
+    /// Send Selected Device Clear to the addressed instrument.
+    pub(crate) async fn clear(&mut self) -> Result<()> {
+        self.send_line("++clr").await
+    }
+
+    pub(crate) async fn close(&mut self) -> Result<()> {
+        // Return the instrument to front-panel control before hanging up.
+        self.send_line("++loc").await?;
+        self.stream.shutdown().await?;
+        Ok(())
+    }
+
+    async fn send_line(&mut self, line: &str) -> Result<()> {
+        let stream = self.stream.get_mut();
+        stream.write_all(line.as_bytes()).await?;
+        stream.write_all(b"\n").await?;
+        stream.flush().await?;
+        Ok(())
+    }
+}
+
+impl Transport for PrologixClient {
+    fn write<'a>(&'a mut self, command: &'a str) -> TransportFuture<'a, ()> {
+        Box::pin(PrologixClient::write(self, command))
+    }
+
+    fn query<'a>(&'a mut self, command: &'a str) -> TransportFuture<'a, String> {
+        Box::pin(PrologixClient::query(self, command))
+    }
+
+    fn clear(&mut self) -> TransportFuture<'_, bool> {
+        Box::pin(async move {
+            PrologixClient::clear(self).await?;
+            Ok(true)
+        })
+    }
+
+    fn close(&mut self) -> TransportFuture<'_, ()> {
+        Box::pin(PrologixClient::close(self))
+    }
+}
+
+/// Escape the bytes the adapter would otherwise interpret (CR, LF, ESC and
+/// `+`) so they reach the instrument as data.
+fn escape(command: &str) -> String {
+    let mut out = String::with_capacity(command.len());
+    for c in command.chars() {
+        if matches!(c, '\r' | '\n' | '\x1b' | '+') {
+            out.push('\x1b');
+        }
+        out.push(c);
+    }
+    out
+}
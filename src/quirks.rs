@@ -0,0 +1,138 @@
+//! Firmware-specific defects and gating of the affected features.
+//!
+//! A [`Quirk`] records that a feature misbehaves on firmware older than
+//! `fixed_in`. Once the connected firmware is known (from
+//! `Spd3303x::identity`), methods using an affected feature fail early with
+//! [`FirmwareUnsupported`], which names the first good firmware and the
+//! workaround, instead of timing out on the wire.
+
+use std::fmt;
+
+use crate::instrument::FirmwareVersion;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Feature {
+    /// `MEAS:POWE?`.
+    MeasurePower,
+    /// `TIMER` and `TIMER:SET`.
+    Timer,
+    /// `OUTP:WAVE`.
+    WaveDisplay,
+    /// `OUTP:TRACK`.
+    TrackMode,
+    /// `*SAV` / `*RCL`.
+    SaveRecall,
+    /// `IPaddr`, `MASKaddr`, `GATEaddr`, `DHCP`.
+    Network,
+    /// `DISPlay:TEXT`, undocumented; see [`display`](crate::display).
+    DisplayText,
+}
+
+impl Feature {
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::MeasurePower => "power measurement",
+            Feature::Timer => "timer",
+            Feature::WaveDisplay => "waveform display",
+            Feature::TrackMode => "track mode",
+            Feature::SaveRecall => "save/recall",
+            Feature::Network => "network configuration",
+            Feature::DisplayText => "display text",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Quirk {
+    pub feature: Feature,
+    /// First firmware version on which the feature works.
+    pub fixed_in: FirmwareVersion,
+    /// What to do instead, e.g. a link to the vendor's release notes.
+    pub workaround: String,
+}
+
+/// Known quirks, consulted by every gated method.
+#[derive(Debug, Clone, Default)]
+pub struct Quirks {
+    entries: Vec<Quirk>,
+}
+
+impl Quirks {
+    /// No quirks.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The quirks shipped with this crate; applications can register
+    /// their own with [`add`](Self::add).
+    pub fn known() -> Self {
+        let mut quirks = Self::new();
+        quirks.add(Quirk {
+            feature: Feature::MeasurePower,
+            fixed_in: FirmwareVersion::new(1, 1, 1, 2, 5),
+            workaround: "multiply `MEAS:VOLT?` by `MEAS:CURR?`".to_string(),
+        });
+        quirks.add(Quirk {
+            feature: Feature::Timer,
+            fixed_in: FirmwareVersion::new(1, 1, 1, 2, 5),
+            workaround: "program the timer groups from the front panel".to_string(),
+        });
+        quirks
+    }
+
+    pub fn add(&mut self, quirk: Quirk) {
+        self.entries.push(quirk);
+    }
+
+    pub fn entries(&self) -> &[Quirk] {
+        &self.entries
+    }
+
+    /// Fail if `feature` is known to be broken on `firmware`.
+    pub fn check(
+        &self,
+        feature: Feature,
+        firmware: FirmwareVersion,
+    ) -> Result<(), FirmwareUnsupported> {
+        let broken = self
+            .entries
+            .iter()
+            .find(|quirk| quirk.feature == feature && firmware < quirk.fixed_in);
+        match broken {
+            Some(quirk) => Err(FirmwareUnsupported {
+                feature,
+                firmware,
+                fixed_in: quirk.fixed_in,
+                workaround: quirk.workaround.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A feature was requested that is known to be broken on the connected
+/// firmware.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FirmwareUnsupported {
+    pub feature: Feature,
+    pub firmware: FirmwareVersion,
+    pub fixed_in: FirmwareVersion,
+    pub workaround: String,
+}
+
+impl fmt::Display for FirmwareUnsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} is known not to work on firmware {}; upgrade to {} or later ({})",
+            self.feature.name(),
+            self.firmware,
+            self.fixed_in,
+            self.workaround
+        )
+    }
+}
+
+impl std::error::Error for FirmwareUnsupported {}
@@ -0,0 +1,266 @@
+//! Slew-rate limited setpoint changes.
+//!
+//! [`Spd3303x::ramp_voltage`] moves a channel's voltage setpoint to a
+//! target in small steps at a given rate instead of in one jump, for DUTs
+//! whose input capacitors or protection circuits do not take a 0→24 V
+//! step well. [`Spd3303x::ramp_current`] does the same for the current
+//! limit, so that a load with a large inrush can be brought up under a low
+//! limit that is raised once it has settled, instead of tripping a limit
+//! set high from the start. A ramp starts from the programmed setpoint, not
+//! from the measured output.
+//!
+//! Steps are spaced evenly on the handle's [clock](crate::clock) so the
+//! average rate holds even when a write is slow. A ramp can be stopped
+//! between steps through a [`CancelRamp`] handle; the setpoint is then left
+//! where the last step put it and the report says so.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use tokio::sync::watch;
+use tracing::debug;
+
+use crate::clock::Ticker;
+use crate::instrument::{Channel, Spd3303x};
+use crate::operation;
+use crate::pipeline::Quantity;
+use crate::validate;
+
+/// Voltage step used unless set otherwise.
+pub const DEFAULT_VOLTAGE_STEP_V: f64 = 0.1;
+
+/// Current step used unless set otherwise.
+pub const DEFAULT_CURRENT_STEP_A: f64 = 0.01;
+
+#[derive(Debug, Clone, Default)]
+pub struct RampOptions {
+    /// Largest setpoint change per step, in the unit of the ramped
+    /// quantity; [`DEFAULT_VOLTAGE_STEP_V`] or [`DEFAULT_CURRENT_STEP_A`] if
+    /// not set.
+    pub step: Option<f64>,
+    cancel: Option<watch::Receiver<bool>>,
+}
+
+impl RampOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn step(mut self, step: f64) -> Self {
+        self.step = Some(step);
+        self
+    }
+
+    /// Handle that stops ramps run with these options from then on.
+    /// Replaces the handle of an earlier call.
+    pub fn cancel_handle(&mut self) -> CancelRamp {
+        let (tx, rx) = watch::channel(false);
+        self.cancel = Some(rx);
+        CancelRamp(Arc::new(tx))
+    }
+}
+
+/// Stops a ramp before its next step, see [`RampOptions::cancel_handle`].
+#[derive(Debug, Clone)]
+pub struct CancelRamp(Arc<watch::Sender<bool>>);
+
+impl CancelRamp {
+    pub fn cancel(&self) {
+        self.0.send_replace(true);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.0.borrow()
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RampReport {
+    pub channel: Channel,
+    pub quantity: Quantity,
+    /// Setpoint the ramp started from.
+    pub from: f64,
+    pub target: f64,
+    /// Setpoint programmed last; `target` unless cancelled.
+    pub reached: f64,
+    /// Steps written.
+    pub steps: u32,
+    pub elapsed: Duration,
+    pub cancelled: bool,
+}
+
+impl Spd3303x {
+    /// Move the voltage setpoint of `channel` to `target_v` at
+    /// `rate_v_per_s`, in steps of [`DEFAULT_VOLTAGE_STEP_V`].
+    pub async fn ramp_voltage(
+        &mut self,
+        channel: Channel,
+        target_v: f64,
+        rate_v_per_s: f64,
+    ) -> Result<RampReport> {
+        self.ramp_voltage_with(channel, target_v, rate_v_per_s, &RampOptions::default())
+            .await
+    }
+
+    /// [`ramp_voltage`](Self::ramp_voltage) with a chosen step size and
+    /// cancellation.
+    pub async fn ramp_voltage_with(
+        &mut self,
+        channel: Channel,
+        target_v: f64,
+        rate_v_per_s: f64,
+        options: &RampOptions,
+    ) -> Result<RampReport> {
+        validate::ensure(validate::check_voltage(
+            channel,
+            target_v,
+            self.capabilities(),
+            &self.limits(channel).unwrap_or_default(),
+        ))?;
+        let step = options.step.unwrap_or(DEFAULT_VOLTAGE_STEP_V);
+        let ramp = Ramp {
+            channel,
+            quantity: Quantity::Voltage,
+            target: target_v,
+            rate: rate_v_per_s,
+            step,
+        };
+        operation::run("ramp_voltage", ramp.run(self, options.cancel.clone())).await
+    }
+
+    /// Move the current limit of `channel` to `target_a` at
+    /// `rate_a_per_s`, in steps of [`DEFAULT_CURRENT_STEP_A`].
+    pub async fn ramp_current(
+        &mut self,
+        channel: Channel,
+        target_a: f64,
+        rate_a_per_s: f64,
+    ) -> Result<RampReport> {
+        self.ramp_current_with(channel, target_a, rate_a_per_s, &RampOptions::default())
+            .await
+    }
+
+    /// [`ramp_current`](Self::ramp_current) with a chosen step size and
+    /// cancellation.
+    pub async fn ramp_current_with(
+        &mut self,
+        channel: Channel,
+        target_a: f64,
+        rate_a_per_s: f64,
+        options: &RampOptions,
+    ) -> Result<RampReport> {
+        validate::ensure(validate::check_current(
+            channel,
+            target_a,
+            self.capabilities(),
+            &self.limits(channel).unwrap_or_default(),
+        ))?;
+        let step = options.step.unwrap_or(DEFAULT_CURRENT_STEP_A);
+        let ramp = Ramp {
+            channel,
+            quantity: Quantity::Current,
+            target: target_a,
+            rate: rate_a_per_s,
+            step,
+        };
+        operation::run("ramp_current", ramp.run(self, options.cancel.clone())).await
+    }
+}
+
+struct Ramp {
+    channel: Channel,
+    quantity: Quantity,
+    target: f64,
+    rate: f64,
+    step: f64,
+}
+
+impl Ramp {
+    async fn run(
+        self,
+        inst: &mut Spd3303x,
+        mut cancel: Option<watch::Receiver<bool>>,
+    ) -> Result<RampReport> {
+        let unit = self.quantity.unit();
+        if !(self.rate.is_finite() && self.rate > 0.0) {
+            return Err(anyhow!("ramp rate must be above 0 {unit}/s, got {}", self.rate));
+        }
+        if !(self.step.is_finite() && self.step > 0.0) {
+            return Err(anyhow!("ramp step must be above 0 {unit}, got {}", self.step));
+        }
+
+        let clock = inst.clock();
+        let started = clock.now();
+        let from = self.setpoint(inst).await?;
+        let delta = self.target - from;
+        let steps = (delta.abs() / self.step).ceil().max(1.0) as u32;
+        let period = Duration::try_from_secs_f64(delta.abs() / self.rate / f64::from(steps))
+            .map_err(|_| anyhow!("ramp rate {} {unit}/s is too low", self.rate))?;
+        debug!(
+            "{} ramp on {}: {from} -> {} {unit} in {steps} steps of {period:?}",
+            self.quantity.name(),
+            self.channel.label(),
+            self.target
+        );
+
+        let mut report = RampReport {
+            channel: self.channel,
+            quantity: self.quantity,
+            from,
+            target: self.target,
+            reached: from,
+            steps: 0,
+            elapsed: Duration::ZERO,
+            cancelled: false,
+        };
+        let mut ticker = Ticker::starting_at(clock.clone(), started + period, period);
+        for index in 1..=steps {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                () = cancelled(&mut cancel) => {}
+            }
+            if cancel.as_ref().is_some_and(|cancel| *cancel.borrow()) {
+                debug!("{} ramp on {}: cancelled", self.quantity.name(), self.channel.label());
+                report.cancelled = true;
+                break;
+            }
+            let value = if index == steps {
+                self.target
+            } else {
+                // Intermediate setpoints on the setting resolution, so that
+                // strict mode accepts them.
+                let value = from + delta * f64::from(index) / f64::from(steps);
+                inst.capabilities().quantize(self.quantity, value)
+            };
+            self.write(inst, value).await?;
+            report.reached = value;
+            report.steps = index;
+        }
+        report.elapsed = clock.elapsed(started);
+        Ok(report)
+    }
+
+    async fn setpoint(&self, inst: &mut Spd3303x) -> Result<f64> {
+        match self.quantity {
+            Quantity::Current => inst.setpoint_current(self.channel).await,
+            _ => inst.setpoint_voltage(self.channel).await,
+        }
+    }
+
+    async fn write(&self, inst: &mut Spd3303x, value: f64) -> Result<()> {
+        match self.quantity {
+            Quantity::Current => inst.set_current(self.channel, value).await,
+            _ => inst.set_voltage(self.channel, value).await,
+        }
+    }
+}
+
+/// Resolves once `cancel` is set; never without a receiver.
+async fn cancelled(cancel: &mut Option<watch::Receiver<bool>>) {
+    match cancel {
+        Some(cancel) if cancel.wait_for(|cancelled| *cancelled).await.is_ok() => {}
+        _ => std::future::pending().await,
+    }
+}
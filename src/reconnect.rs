@@ -0,0 +1,141 @@
+//! Reconnecting with exponential backoff after the link drops.
+//!
+//! A supply that reboots or a network blip breaks the link, and every
+//! command after it fails until the handle is reconnected. With a
+//! [`ReconnectPolicy`] installed through `Spd3303x::set_auto_reconnect`, a
+//! command that fails on the link reopens it, waiting
+//! [`initial_backoff`](ReconnectPolicy::initial_backoff) before the first
+//! attempt and `multiplier` times longer before each further one, up to
+//! [`max_backoff`](ReconnectPolicy::max_backoff). Once the link is back the
+//! command is sent again and the caller sees its result; only when every
+//! attempt fails does it get the error, with a [`ReconnectFailed`] under
+//! it. Long-running loggers and monitors so ride out the outage.
+//!
+//! Backoff waits go through the handle's [`clock`](crate::clock). Host-side
+//! settings are kept across the reconnect; instrument state is whatever
+//! the supply has after the outage, so check it if the supply may have
+//! rebooted. Handles that cannot [`reconnect`](crate::instrument::Spd3303x::reconnect),
+//! such as those made from a transport, fail as before.
+
+use std::fmt;
+use std::time::Duration;
+
+use anyhow::Result;
+use tracing::{info, warn};
+
+use crate::instrument::Spd3303x;
+
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    /// Wait before the first attempt.
+    pub initial_backoff: Duration,
+    /// Longest wait between attempts.
+    pub max_backoff: Duration,
+    /// Factor the wait grows by after each failed attempt.
+    pub multiplier: f64,
+    /// Attempts before giving up; `None` keeps trying.
+    pub max_attempts: Option<u32>,
+    /// Deadline of one attempt to open the link.
+    pub attempt_timeout: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            multiplier: 2.0,
+            max_attempts: Some(10),
+            attempt_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    pub fn max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    pub fn max_attempts(mut self, attempts: Option<u32>) -> Self {
+        self.max_attempts = attempts;
+        self
+    }
+
+    pub fn attempt_timeout(mut self, timeout: Duration) -> Self {
+        self.attempt_timeout = timeout;
+        self
+    }
+
+    /// Wait before attempt `attempt`, counted from 0.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = self.multiplier.max(1.0).powi(attempt.min(64) as i32);
+        Duration::try_from_secs_f64(self.initial_backoff.as_secs_f64() * factor)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+}
+
+/// Every reconnect attempt of a [`ReconnectPolicy`] failed.
+#[derive(Debug, Clone)]
+pub struct ReconnectFailed {
+    pub attempts: u32,
+    /// Error of the last attempt.
+    pub last_error: String,
+}
+
+impl fmt::Display for ReconnectFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "could not reconnect after {} attempts: {}",
+            self.attempts, self.last_error
+        )
+    }
+}
+
+impl std::error::Error for ReconnectFailed {}
+
+impl Spd3303x {
+    /// Reconnect, backing off between attempts as `policy` says. Returns the
+    /// number of attempts it took.
+    pub async fn reconnect_with_backoff(&mut self, policy: &ReconnectPolicy) -> Result<u32> {
+        let clock = self.clock();
+        let mut attempt = 0;
+        loop {
+            clock.sleep(policy.backoff(attempt)).await;
+            attempt += 1;
+            let error = match tokio::time::timeout(policy.attempt_timeout, self.reconnect()).await
+            {
+                Ok(Ok(())) => {
+                    info!("reconnect: link back after {attempt} attempt(s)");
+                    return Ok(attempt);
+                }
+                Ok(Err(e)) => format!("{e:#}"),
+                Err(_) => format!("timed out after {:?}", policy.attempt_timeout),
+            };
+            warn!("reconnect: attempt {attempt} failed: {error}");
+            if policy.max_attempts.is_some_and(|max| attempt >= max) {
+                return Err(ReconnectFailed {
+                    attempts: attempt,
+                    last_error: error,
+                }
+                .into());
+            }
+        }
+    }
+}
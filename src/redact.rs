@@ -0,0 +1,68 @@
+//! Scrubbing of sensitive strings before SCPI traffic is logged or
+//! recorded.
+//!
+//! Registered secrets are held in zeroizing buffers and replaced by
+//! [`REDACTED`] in tracing output, error messages and the transcript. A hook
+//! can redact further, e.g. by pattern, once firmware grows commands that
+//! carry credentials.
+
+use std::fmt;
+
+use zeroize::Zeroizing;
+
+pub const REDACTED: &str = "<redacted>";
+
+type Hook = Box<dyn Fn(&str) -> String + Send + Sync>;
+
+#[derive(Default)]
+pub struct Redactor {
+    secrets: Vec<Zeroizing<String>>,
+    hook: Option<Hook>,
+}
+
+impl Redactor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace every occurrence of `secret` from now on.
+    pub fn add_secret(&mut self, secret: impl Into<String>) {
+        let secret = Zeroizing::new(secret.into());
+        if !secret.is_empty() {
+            self.secrets.push(secret);
+        }
+    }
+
+    /// Run `hook` on every string after the registered secrets are replaced.
+    pub fn set_hook(&mut self, hook: impl Fn(&str) -> String + Send + Sync + 'static) {
+        self.hook = Some(Box::new(hook));
+    }
+
+    /// Forget all secrets (wiping their buffers) and the hook.
+    pub fn clear(&mut self) {
+        self.secrets.clear();
+        self.hook = None;
+    }
+
+    pub fn redact(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for secret in &self.secrets {
+            if text.contains(secret.as_str()) {
+                text = text.replace(secret.as_str(), REDACTED);
+            }
+        }
+        match &self.hook {
+            Some(hook) => hook(&text),
+            None => text,
+        }
+    }
+}
+
+impl fmt::Debug for Redactor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Redactor")
+            .field("secrets", &self.secrets.len())
+            .field("hook", &self.hook.is_some())
+            .finish()
+    }
+}
@@ -0,0 +1,165 @@
+//! Expected shapes of query responses.
+//!
+//! Each query header (the command up to the first space, e.g. `MEAS:VOLT?`)
+//! can have a [`ResponseShape`]. A reply that does not match fails the query
+//! with [`UnexpectedResponse`], carrying the raw bytes, instead of
+//! surfacing later as an unrelated parse error.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use regex::Regex;
+
+#[derive(Debug, Clone)]
+pub enum ResponseShape {
+    /// A single decimal number.
+    Number,
+    /// Exactly this many comma-separated decimal numbers.
+    Numbers(usize),
+    /// One of these words, compared case-insensitively.
+    OneOf(Vec<String>),
+    Pattern(Regex),
+}
+
+impl ResponseShape {
+    pub fn one_of<S: Into<String>>(words: impl IntoIterator<Item = S>) -> Self {
+        ResponseShape::OneOf(words.into_iter().map(Into::into).collect())
+    }
+
+    /// Panics if `pattern` is not a valid regular expression.
+    pub fn pattern(pattern: &str) -> Self {
+        ResponseShape::Pattern(Regex::new(pattern).expect("invalid response pattern"))
+    }
+
+    pub fn matches(&self, response: &str) -> bool {
+        match self {
+            ResponseShape::Number => is_number(response),
+            ResponseShape::Numbers(count) => {
+                let fields: Vec<&str> = response.split(',').collect();
+                fields.len() == *count && fields.iter().all(|f| is_number(f))
+            }
+            ResponseShape::OneOf(words) => {
+                words.iter().any(|w| w.eq_ignore_ascii_case(response.trim()))
+            }
+            ResponseShape::Pattern(regex) => regex.is_match(response),
+        }
+    }
+}
+
+impl fmt::Display for ResponseShape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResponseShape::Number => write!(f, "a number"),
+            ResponseShape::Numbers(count) => write!(f, "{count} comma-separated numbers"),
+            ResponseShape::OneOf(words) => write!(f, "one of {}", words.join("|")),
+            ResponseShape::Pattern(regex) => write!(f, "a match for /{}/", regex.as_str()),
+        }
+    }
+}
+
+/// A query reply that does not have the registered shape.
+#[derive(Debug, Clone)]
+pub struct UnexpectedResponse {
+    pub command: String,
+    pub expected: String,
+    /// Trimmed reply.
+    pub got: String,
+    /// Reply as received.
+    pub raw: Vec<u8>,
+}
+
+impl fmt::Display for UnexpectedResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unexpected response to {}: expected {}, got {:?} (raw {:02X?})",
+            self.command, self.expected, self.got, self.raw
+        )
+    }
+}
+
+impl std::error::Error for UnexpectedResponse {}
+
+/// A query answered with nothing but whitespace or NULs.
+#[derive(Debug, Clone)]
+pub struct EmptyResponse {
+    pub command: String,
+}
+
+impl fmt::Display for EmptyResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "empty response from device for command {:?}", self.command)
+    }
+}
+
+impl std::error::Error for EmptyResponse {}
+
+/// Shapes registered per query header.
+#[derive(Debug, Clone, Default)]
+pub struct ResponseRules {
+    shapes: HashMap<String, ResponseShape>,
+}
+
+impl ResponseRules {
+    /// No rules.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Shapes of the queries this crate issues, as documented in the manual.
+    pub fn spd3303x() -> Self {
+        let dotted_quad = r"^\d{1,3}(\.\d{1,3}){3}$";
+        let mut rules = Self::new();
+        rules.set("*IDN?", ResponseShape::pattern(r"^[^,]+,[^,]+,[^,]+,[^,]+(,.*)?$"));
+        rules.set("INST?", ResponseShape::one_of(["CH1", "CH2", "CH3"]));
+        for header in [
+            "CH1:VOLT?",
+            "CH2:VOLT?",
+            "CH1:CURR?",
+            "CH2:CURR?",
+            "MEAS:VOLT?",
+            "MEAS:CURR?",
+            "MEAS:POWER?",
+        ] {
+            rules.set(header, ResponseShape::Number);
+        }
+        rules.set("OUTP:TRACK?", ResponseShape::one_of(["0", "1", "2"]));
+        rules.set("SYST:STAT?", ResponseShape::pattern(r"^(0x)?[0-9A-Fa-f]+$"));
+        rules.set("TIMER:SET?", ResponseShape::Numbers(3));
+        rules.set("DHCP?", ResponseShape::pattern(r"(?i)^(DHCP:)?(ON|OFF)$"));
+        for header in ["IPADDR?", "MASKADDR?", "GATEADDR?"] {
+            rules.set(header, ResponseShape::pattern(dotted_quad));
+        }
+        rules
+    }
+
+    /// Register `shape` for queries starting with `header`, replacing any
+    /// previous shape. Headers are compared case-insensitively.
+    pub fn set(&mut self, header: &str, shape: ResponseShape) {
+        self.shapes.insert(header.to_ascii_uppercase(), shape);
+    }
+
+    pub fn remove(&mut self, header: &str) -> Option<ResponseShape> {
+        self.shapes.remove(&header.to_ascii_uppercase())
+    }
+
+    /// Check the trimmed reply `got` to `command`; `raw` is attached to
+    /// the error.
+    pub fn check(&self, command: &str, got: &str, raw: &[u8]) -> Result<(), UnexpectedResponse> {
+        let command = command.trim_end_matches(['\r', '\n']);
+        let header = command.split(' ').next().unwrap_or(command);
+        match self.shapes.get(&header.to_ascii_uppercase()) {
+            Some(shape) if !shape.matches(got) => Err(UnexpectedResponse {
+                command: command.to_string(),
+                expected: shape.to_string(),
+                got: got.to_string(),
+                raw: raw.to_vec(),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn is_number(text: &str) -> bool {
+    text.trim().parse::<f64>().is_ok_and(f64::is_finite)
+}
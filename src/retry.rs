@@ -0,0 +1,116 @@
+//! Retrying commands that fail transiently.
+//!
+//! The SPD3303X now and then drops the reply to a query that follows
+//! another command closely, which shows up as a timeout, an empty reply or
+//! a reply of the wrong shape. With a [`RetryPolicy`] installed through
+//! `Spd3303x::set_retry_policy` or `Spd3303xBuilder::retry`, a write or
+//! query failing with one of the policy's [`Retriable`] errors is sent
+//! again after [`delay`](RetryPolicy::delay), up to
+//! [`attempts`](RetryPolicy::attempts) times in all.
+//!
+//! Errors raised before anything is sent (validation, control conflicts,
+//! middleware) are never retried. Before a query is sent again the link is
+//! cleared or reopened, so that a late reply to the failed attempt cannot
+//! be taken for the answer to the next; if that fails, the query is not
+//! retried. Each attempt goes through the
+//! [escalation ladder](crate::escalation) and the auto-reconnect of the
+//! handle on its own. As with the ladder, a retried write is sent again,
+//! which is safe for the SPD3303X commands.
+
+use std::time::Duration;
+
+use crate::escalation::CommandTimedOut;
+use crate::response::{EmptyResponse, UnexpectedResponse};
+
+/// Errors a [`RetryPolicy`] can retry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Retriable {
+    /// The command ran into its deadline, see [`CommandTimedOut`].
+    Timeout,
+    /// A query was answered with nothing but whitespace.
+    EmptyResponse,
+    /// A reply did not have its registered shape.
+    UnexpectedResponse,
+    /// The link failed, e.g. a reset connection.
+    Link,
+}
+
+impl Retriable {
+    /// Which kind of retriable error `error` carries, if any.
+    pub fn classify(error: &anyhow::Error) -> Option<Self> {
+        error.chain().find_map(|cause| {
+            if cause.is::<CommandTimedOut>() || cause.is::<tokio::time::error::Elapsed>() {
+                Some(Retriable::Timeout)
+            } else if cause.is::<EmptyResponse>() {
+                Some(Retriable::EmptyResponse)
+            } else if cause.is::<UnexpectedResponse>() {
+                Some(Retriable::UnexpectedResponse)
+            } else if cause.is::<std::io::Error>() {
+                Some(Retriable::Link)
+            } else {
+                None
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Attempts in all, the first one included.
+    pub attempts: u32,
+    /// Pause before each retry.
+    pub delay: Duration,
+    pub retry_on: Vec<Retriable>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            delay: Duration::from_millis(50),
+            retry_on: vec![
+                Retriable::Timeout,
+                Retriable::EmptyResponse,
+                Retriable::UnexpectedResponse,
+            ],
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn attempts(mut self, attempts: u32) -> Self {
+        self.attempts = attempts;
+        self
+    }
+
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Retry only the given errors.
+    pub fn retry_on(mut self, retry_on: impl IntoIterator<Item = Retriable>) -> Self {
+        self.retry_on = retry_on.into_iter().collect();
+        self
+    }
+
+    /// Retry `retriable` too.
+    pub fn also_retry(mut self, retriable: Retriable) -> Self {
+        if !self.retry_on.contains(&retriable) {
+            self.retry_on.push(retriable);
+        }
+        self
+    }
+
+    /// Whether attempt number `attempt` (from 1), having failed with
+    /// `error`, is followed by another.
+    pub fn retries(&self, attempt: u32, error: &anyhow::Error) -> bool {
+        attempt < self.attempts
+            && Retriable::classify(error).is_some_and(|kind| self.retry_on.contains(&kind))
+    }
+}
@@ -0,0 +1,368 @@
+//! Local database of test runs.
+//!
+//! Each run is stored with its kind (`sequence`, `matrix`, or anything the
+//! caller uses, e.g. `soak`), a name, the instrument, its start, duration,
+//! outcome, notes and named summary metrics, in a SQLite file that needs no
+//! server. [`RunRecord::sequence`] and [`RunRecord::matrix`] summarize the
+//! crate's own reports; [`list`] searches the file with a [`RunFilter`].
+//!
+//! The calls block on file I/O; from async code, keep the database small
+//! or run them in `tokio::task::spawn_blocking`.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+use crate::sequence::SequenceReport;
+use crate::sweep::MatrixReport;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    kind TEXT NOT NULL,
+    name TEXT NOT NULL,
+    instrument TEXT,
+    started_at_ms INTEGER NOT NULL,
+    duration_ms INTEGER NOT NULL,
+    passed INTEGER
+);
+CREATE INDEX IF NOT EXISTS runs_started ON runs (started_at_ms);
+CREATE TABLE IF NOT EXISTS run_metrics (
+    run_id INTEGER NOT NULL REFERENCES runs (id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    value REAL,
+    PRIMARY KEY (run_id, name)
+);
+CREATE TABLE IF NOT EXISTS run_notes (
+    run_id INTEGER NOT NULL REFERENCES runs (id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    text TEXT NOT NULL,
+    PRIMARY KEY (run_id, position)
+);
+";
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RunRecord {
+    /// Assigned when the run is stored.
+    pub id: Option<i64>,
+    pub kind: String,
+    pub name: String,
+    pub instrument: Option<String>,
+    pub started_at: SystemTime,
+    pub duration: Duration,
+    /// `None` for runs without a verdict.
+    pub passed: Option<bool>,
+    /// A NaN value is stored as SQL `NULL` and read back as NaN.
+    pub metrics: BTreeMap<String, f64>,
+    /// In the order they were added; a note may span several lines.
+    pub notes: Vec<String>,
+}
+
+impl RunRecord {
+    /// A run starting now.
+    pub fn new(kind: impl Into<String>, name: impl Into<String>) -> Self {
+        Self {
+            id: None,
+            kind: kind.into(),
+            name: name.into(),
+            instrument: None,
+            started_at: SystemTime::now(),
+            duration: Duration::ZERO,
+            passed: None,
+            metrics: BTreeMap::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    /// Summary of a finished sequence run: step count, worst timing error
+    /// and pause time. It passes unless aborted, detached or a step's
+    /// condition timed out.
+    pub fn sequence(name: impl Into<String>, report: &SequenceReport) -> Self {
+        let duration = report.steps.iter().map(|step| step.actual).sum::<Duration>()
+            + report.paused;
+        let worst_error = report
+            .steps
+            .iter()
+            .map(|step| step.error_s().abs())
+            .fold(0.0, f64::max);
+        let conditions_met = report
+            .steps
+            .iter()
+            .all(|step| step.condition_met != Some(false));
+        let mut record = Self::new("sequence", name)
+            .started_at(SystemTime::now() - duration)
+            .duration(duration)
+            .passed(!report.aborted && !report.detached && conditions_met)
+            .metric("steps", report.steps.len() as f64)
+            .metric("max_step_error_s", worst_error)
+            .metric("paused_s", report.paused.as_secs_f64());
+        record.notes = report.notes.iter().map(|note| note.text.clone()).collect();
+        record
+    }
+
+    /// Summary of a finished corner matrix: corners run, passed and failed.
+    pub fn matrix(name: impl Into<String>, report: &MatrixReport) -> Self {
+        Self::new("matrix", name)
+            .passed(!report.aborted && report.failed() == 0)
+            .metric("corners", report.corners.len() as f64)
+            .metric("passed", report.passed() as f64)
+            .metric("failed", report.failed() as f64)
+    }
+
+    pub fn instrument(mut self, instrument: impl Into<String>) -> Self {
+        self.instrument = Some(instrument.into());
+        self
+    }
+
+    pub fn started_at(mut self, started_at: SystemTime) -> Self {
+        self.started_at = started_at;
+        self
+    }
+
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    pub fn passed(mut self, passed: bool) -> Self {
+        self.passed = Some(passed);
+        self
+    }
+
+    pub fn metric(mut self, name: impl Into<String>, value: f64) -> Self {
+        self.metrics.insert(name.into(), value);
+        self
+    }
+
+    pub fn note(mut self, text: impl Into<String>) -> Self {
+        self.notes.push(text.into());
+        self
+    }
+}
+
+/// Which runs [`list`] returns; every field left unset matches all runs.
+#[derive(Debug, Clone, Default)]
+pub struct RunFilter {
+    pub kind: Option<String>,
+    /// Substring of the run name.
+    pub name_contains: Option<String>,
+    pub instrument: Option<String>,
+    pub since: Option<SystemTime>,
+    pub until: Option<SystemTime>,
+    pub passed: Option<bool>,
+    pub limit: Option<usize>,
+}
+
+impl RunFilter {
+    pub fn kind(mut self, kind: impl Into<String>) -> Self {
+        self.kind = Some(kind.into());
+        self
+    }
+
+    pub fn name_contains(mut self, text: impl Into<String>) -> Self {
+        self.name_contains = Some(text.into());
+        self
+    }
+
+    pub fn instrument(mut self, instrument: impl Into<String>) -> Self {
+        self.instrument = Some(instrument.into());
+        self
+    }
+
+    pub fn since(mut self, since: SystemTime) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    pub fn until(mut self, until: SystemTime) -> Self {
+        self.until = Some(until);
+        self
+    }
+
+    pub fn passed(mut self, passed: bool) -> Self {
+        self.passed = Some(passed);
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+}
+
+/// An open run database.
+pub struct RunDb {
+    conn: Connection,
+}
+
+impl RunDb {
+    /// Open or create the database at `path`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let conn = Connection::open(path)
+            .with_context(|| format!("opening run database {}", path.display()))?;
+        Self::init(conn)
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    /// Store `record` and return its id.
+    pub fn insert(&mut self, record: &RunRecord) -> Result<i64> {
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO runs (kind, name, instrument, started_at_ms, duration_ms, passed)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                record.kind,
+                record.name,
+                record.instrument,
+                unix_ms(record.started_at),
+                i64::try_from(record.duration.as_millis()).unwrap_or(i64::MAX),
+                record.passed,
+            ],
+        )?;
+        let id = tx.last_insert_rowid();
+        for (name, value) in &record.metrics {
+            let value = (!value.is_nan()).then_some(*value);
+            tx.execute(
+                "INSERT INTO run_metrics (run_id, name, value) VALUES (?1, ?2, ?3)",
+                params![id, name, value],
+            )?;
+        }
+        for (position, text) in record.notes.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO run_notes (run_id, position, text) VALUES (?1, ?2, ?3)",
+                params![id, position as i64, text],
+            )?;
+        }
+        tx.commit()?;
+        Ok(id)
+    }
+
+    pub fn get(&self, id: i64) -> Result<Option<RunRecord>> {
+        let record = self
+            .conn
+            .query_row("SELECT * FROM runs WHERE id = ?1", [id], from_row)
+            .optional()?;
+        record.map(|record| self.with_details(record)).transpose()
+    }
+
+    /// Runs matching `filter`, newest first.
+    pub fn list(&self, filter: &RunFilter) -> Result<Vec<RunRecord>> {
+        let mut sql = "SELECT * FROM runs WHERE 1 = 1".to_string();
+        let mut args: Vec<rusqlite::types::Value> = Vec::new();
+        // Each condition binds one value, at its `?`.
+        let mut clause = |condition: &str, value: rusqlite::types::Value| {
+            args.push(value);
+            sql.push_str(" AND ");
+            sql.push_str(&condition.replace('?', &format!("?{}", args.len())));
+        };
+        if let Some(kind) = &filter.kind {
+            clause("kind = ?", kind.clone().into());
+        }
+        if let Some(text) = &filter.name_contains {
+            clause("instr(name, ?) > 0", text.clone().into());
+        }
+        if let Some(instrument) = &filter.instrument {
+            clause("instrument = ?", instrument.clone().into());
+        }
+        if let Some(since) = filter.since {
+            clause("started_at_ms >= ?", unix_ms(since).into());
+        }
+        if let Some(until) = filter.until {
+            clause("started_at_ms < ?", unix_ms(until).into());
+        }
+        if let Some(passed) = filter.passed {
+            clause("passed = ?", passed.into());
+        }
+        sql.push_str(" ORDER BY started_at_ms DESC, id DESC");
+        if let Some(limit) = filter.limit {
+            sql.push_str(&format!(" LIMIT {limit}"));
+        }
+
+        let mut statement = self.conn.prepare(&sql)?;
+        let records = statement
+            .query_map(rusqlite::params_from_iter(args), from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        records
+            .into_iter()
+            .map(|record| self.with_details(record))
+            .collect()
+    }
+
+    pub fn delete(&self, id: i64) -> Result<bool> {
+        Ok(self.conn.execute("DELETE FROM runs WHERE id = ?1", [id])? > 0)
+    }
+
+    /// Fill in the metrics and notes of a record read from `runs`.
+    fn with_details(&self, mut record: RunRecord) -> Result<RunRecord> {
+        let mut statement = self
+            .conn
+            .prepare_cached("SELECT name, value FROM run_metrics WHERE run_id = ?1")?;
+        let metrics = statement.query_map([record.id], |row| {
+            let value: Option<f64> = row.get(1)?;
+            Ok((row.get(0)?, value.unwrap_or(f64::NAN)))
+        })?;
+        record.metrics = metrics.collect::<rusqlite::Result<_>>()?;
+
+        let mut statement = self.conn.prepare_cached(
+            "SELECT text FROM run_notes WHERE run_id = ?1 ORDER BY position",
+        )?;
+        let notes = statement.query_map([record.id], |row| row.get(0))?;
+        record.notes = notes.collect::<rusqlite::Result<_>>()?;
+        Ok(record)
+    }
+}
+
+/// Store `record` in the database at `path`, creating it if needed.
+pub fn record(path: impl AsRef<Path>, record: &RunRecord) -> Result<i64> {
+    RunDb::open(path)?.insert(record)
+}
+
+/// Runs in the database at `path` matching `filter`, newest first.
+pub fn list(path: impl AsRef<Path>, filter: &RunFilter) -> Result<Vec<RunRecord>> {
+    RunDb::open(path)?.list(filter)
+}
+
+fn from_row(row: &Row<'_>) -> rusqlite::Result<RunRecord> {
+    let duration_ms: i64 = row.get("duration_ms")?;
+    Ok(RunRecord {
+        id: Some(row.get("id")?),
+        kind: row.get("kind")?,
+        name: row.get("name")?,
+        instrument: row.get("instrument")?,
+        started_at: from_unix_ms(row.get("started_at_ms")?),
+        duration: Duration::from_millis(duration_ms.max(0) as u64),
+        passed: row.get("passed")?,
+        metrics: BTreeMap::new(),
+        notes: Vec::new(),
+    })
+}
+
+fn unix_ms(at: SystemTime) -> i64 {
+    match at.duration_since(UNIX_EPOCH) {
+        Ok(since) => i64::try_from(since.as_millis()).unwrap_or(i64::MAX),
+        Err(e) => -i64::try_from(e.duration().as_millis()).unwrap_or(i64::MAX),
+    }
+}
+
+fn from_unix_ms(ms: i64) -> SystemTime {
+    let offset = Duration::from_millis(ms.unsigned_abs());
+    if ms >= 0 {
+        UNIX_EPOCH + offset
+    } else {
+        UNIX_EPOCH - offset
+    }
+}
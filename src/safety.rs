@@ -0,0 +1,141 @@
+//! Software OVP/OCP: a fence around what scripts may do with a shared
+//! supply.
+//!
+//! [`SafetyLimits`] gives the highest voltage, current and power of each
+//! channel. [`Spd3303x::apply_safety_limits`] installs them as the
+//! channels' [`Limits`], so every setpoint above them, raw writes of
+//! `CHn:VOLT`/`CHn:CURR` included, is refused before it is sent. With
+//! [`trip_on_measurement`](SafetyLimits::trip_on_measurement) set, a
+//! [`Monitor`](crate::monitor::Monitor) given the limits in its
+//! [`MonitorConfig::safety_limits`](crate::monitor::MonitorConfig) also
+//! checks every poll's readings and switches off a channel that measures
+//! above them, publishing
+//! [`Event::SafetyLimitExceeded`](crate::events::Event::SafetyLimitExceeded).
+//!
+//! ```toml
+//! trip_on_measurement = true
+//!
+//! [ch1]
+//! max_voltage_v = 12.0
+//! max_current_a = 1.0
+//! max_power_w = 10.0
+//! ```
+//!
+//! The measured fence trips after the fact, one poll interval late at
+//! worst; it is no replacement for the instrument's own protection or a
+//! fuse.
+
+use std::fmt;
+
+use anyhow::Result;
+
+use crate::instrument::{Channel, Spd3303x};
+use crate::monitor::MonitorSample;
+use crate::pipeline::Quantity;
+use crate::validate::Limits;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct SafetyLimits {
+    pub ch1: Limits,
+    pub ch2: Limits,
+    /// Switch a channel off when its readings exceed the limits.
+    pub trip_on_measurement: bool,
+}
+
+impl SafetyLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the limits of CH1 or CH2; CH3 is fixed and ignored.
+    pub fn channel(mut self, channel: Channel, limits: Limits) -> Self {
+        match channel {
+            Channel::Ch1 => self.ch1 = limits,
+            Channel::Ch2 => self.ch2 = limits,
+            Channel::Ch3 => {}
+        }
+        self
+    }
+
+    pub fn trip_on_measurement(mut self, trip: bool) -> Self {
+        self.trip_on_measurement = trip;
+        self
+    }
+
+    pub fn limits(&self, channel: Channel) -> Option<&Limits> {
+        match channel {
+            Channel::Ch1 => Some(&self.ch1),
+            Channel::Ch2 => Some(&self.ch2),
+            Channel::Ch3 => None,
+        }
+    }
+
+    /// Readings of `sample` above the limits, at most one per channel and
+    /// quantity.
+    pub fn exceeded(&self, sample: &MonitorSample) -> Vec<LimitExceeded> {
+        let mut exceeded = Vec::new();
+        for (channel, status) in &sample.channels {
+            let Some(limits) = self.limits(*channel) else {
+                continue;
+            };
+            for (quantity, measured, limit) in [
+                (Quantity::Voltage, status.measured_voltage_v, limits.max_voltage_v),
+                (Quantity::Current, status.measured_current_a, limits.max_current_a),
+                (Quantity::Power, status.measured_power_w, limits.max_power_w),
+            ] {
+                if let Some(limit) = limit
+                    && measured > limit
+                {
+                    exceeded.push(LimitExceeded {
+                        channel: *channel,
+                        quantity,
+                        measured,
+                        limit,
+                    });
+                }
+            }
+        }
+        exceeded
+    }
+}
+
+/// A reading above its [`SafetyLimits`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LimitExceeded {
+    pub channel: Channel,
+    pub quantity: Quantity,
+    pub measured: f64,
+    pub limit: f64,
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unit = self.quantity.unit();
+        write!(
+            f,
+            "{} {} {:.3} {unit} exceeds the safety limit of {:.3} {unit}",
+            self.channel.label(),
+            self.quantity.name(),
+            self.measured,
+            self.limit
+        )
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+impl Spd3303x {
+    /// Install the limits of CH1 and CH2 with
+    /// [`set_limits`](Self::set_limits). Setpoints already programmed are
+    /// not checked.
+    pub fn apply_safety_limits(&mut self, limits: &SafetyLimits) -> Result<()> {
+        for channel in Channel::programmable() {
+            if let Some(channel_limits) = limits.limits(channel) {
+                self.set_limits(channel, *channel_limits)?;
+            }
+        }
+        Ok(())
+    }
+}
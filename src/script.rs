@@ -0,0 +1,209 @@
+//! Replaying SCPI command lists.
+//!
+//! A script is a text file with one SCPI command per line, as kept by
+//! many older bench tools. Blank lines and lines starting with `#` are
+//! skipped, `${name}` is replaced by the value of variable `name` from
+//! [`ScriptOptions`], and commands ending in `?` (before any arguments)
+//! are sent as queries and their replies logged. Commands go through
+//! `write_raw`/`query_raw`, so they get the handle's retries, transcript
+//! and channel policy like any other command.
+//!
+//! ```text
+//! # bring up the DUT rail
+//! CH1:VOLTage ${vdd}
+//! CH1:CURRent 0.5
+//! OUTPut CH1,ON
+//! MEASure:CURRent? CH1
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use tracing::{info, warn};
+
+use crate::instrument::Spd3303x;
+use crate::operation;
+use crate::table::Table;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ScriptOptions {
+    /// Pause after each command.
+    pub delay_between: Duration,
+    /// Stop at the first failing command; otherwise it is logged and the
+    /// script carries on.
+    pub stop_on_error: bool,
+    /// Values substituted for `${name}`.
+    pub variables: BTreeMap<String, String>,
+}
+
+impl Default for ScriptOptions {
+    fn default() -> Self {
+        Self {
+            delay_between: Duration::ZERO,
+            stop_on_error: true,
+            variables: BTreeMap::new(),
+        }
+    }
+}
+
+impl ScriptOptions {
+    pub fn delay_between(mut self, delay: Duration) -> Self {
+        self.delay_between = delay;
+        self
+    }
+
+    pub fn stop_on_error(mut self, stop: bool) -> Self {
+        self.stop_on_error = stop;
+        self
+    }
+
+    pub fn variable(mut self, name: impl Into<String>, value: impl ToString) -> Self {
+        self.variables.insert(name.into(), value.to_string());
+        self
+    }
+}
+
+/// One command of a script as it was sent.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScriptLine {
+    /// 1-based line in the script.
+    pub line: usize,
+    /// Command after substitution.
+    pub command: String,
+    pub reply: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScriptReport {
+    pub lines: Vec<ScriptLine>,
+    /// Whether the script stopped at a failing command.
+    pub stopped: bool,
+}
+
+impl ScriptReport {
+    pub fn errors(&self) -> impl Iterator<Item = &ScriptLine> {
+        self.lines.iter().filter(|line| line.error.is_some())
+    }
+
+    pub fn render_table(&self) -> Table {
+        let mut table = Table::new(["Line", "Command", "Result"]);
+        for line in &self.lines {
+            let result = match (&line.reply, &line.error) {
+                (_, Some(error)) => format!("error: {error}"),
+                (Some(reply), None) => reply.clone(),
+                (None, None) => "ok".to_string(),
+            };
+            table.push_row([line.line.to_string(), line.command.clone(), result]);
+        }
+        table
+    }
+}
+
+impl Spd3303x {
+    /// Run the SCPI script at `path`; see the [module docs](crate::script).
+    pub async fn run_script(
+        &mut self,
+        path: impl AsRef<Path>,
+        options: &ScriptOptions,
+    ) -> Result<ScriptReport> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read SCPI script {}", path.display()))?;
+        self.run_script_text(&text, options).await
+    }
+
+    /// Run a script given as text.
+    pub async fn run_script_text(
+        &mut self,
+        text: &str,
+        options: &ScriptOptions,
+    ) -> Result<ScriptReport> {
+        operation::run("script", async {
+            let mut report = ScriptReport::default();
+            for (index, raw) in text.lines().enumerate() {
+                let line = index + 1;
+                let raw = raw.trim();
+                if raw.is_empty() || raw.starts_with('#') {
+                    continue;
+                }
+                let (command, outcome) = match substitute(raw, &options.variables) {
+                    Ok(command) => {
+                        let outcome = self.send_script_command(&command).await;
+                        (command, outcome)
+                    }
+                    Err(e) => (raw.to_string(), Err(e)),
+                };
+                let (reply, error) = match outcome {
+                    Ok(reply) => {
+                        info!("script: {line}: {command}{}", reply_suffix(reply.as_deref()));
+                        (reply, None)
+                    }
+                    Err(e) => {
+                        warn!("script: {line}: {command} failed: {e:#}");
+                        (None, Some(format!("{e:#}")))
+                    }
+                };
+                let failed = error.is_some();
+                report.lines.push(ScriptLine {
+                    line,
+                    command,
+                    reply,
+                    error,
+                });
+                if failed && options.stop_on_error {
+                    report.stopped = true;
+                    break;
+                }
+                if !options.delay_between.is_zero() {
+                    self.clock().sleep(options.delay_between).await;
+                }
+            }
+            Ok(report)
+        })
+        .await
+    }
+
+    async fn send_script_command(&mut self, command: &str) -> Result<Option<String>> {
+        let header = command.split_whitespace().next().unwrap_or_default();
+        if header.ends_with('?') {
+            Ok(Some(self.query_raw(command).await?.trim().to_string()))
+        } else {
+            self.write_raw(command).await?;
+            Ok(None)
+        }
+    }
+}
+
+fn reply_suffix(reply: Option<&str>) -> String {
+    reply
+        .map(|reply| format!(" -> {reply}"))
+        .unwrap_or_default()
+}
+
+/// Replace every `${name}` in `line`; an unknown name is an error.
+fn substitute(line: &str, variables: &BTreeMap<String, String>) -> Result<String> {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find("${") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find('}')
+            .ok_or_else(|| anyhow!("unterminated variable in {line:?}"))?;
+        let name = after[..end].trim();
+        let value = variables
+            .get(name)
+            .ok_or_else(|| anyhow!("undefined variable {name:?}"))?;
+        out.push_str(value);
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
@@ -0,0 +1,928 @@
+//! Host-driven setpoint sequences.
+//!
+//! Unlike the instrument's built-in timer (five groups per channel, one
+//! channel at a time), a [`Sequence`] can touch several channels per step
+//! and have any number of steps. Step timing is kept by the host.
+//!
+//! Steps may also drive an electronic load through
+//! [`SourceSink`](crate::source_sink::SourceSink); actions within a step are
+//! applied in the order they were added.
+//!
+//! A running sequence can be stopped between steps through an
+//! [`AbortHandle`], and held and continued through a [`PauseHandle`]. While
+//! paused, the step in progress is held with its outputs as they are or at
+//! a safe voltage (see [`PauseBehavior`]); the remaining hold time resumes
+//! where it stopped.
+//!
+//! A step can also wait for a [`Condition`] before its hold starts, e.g. a
+//! charge current falling below a threshold or a DUT's boot current
+//! settling into CV, with a timeout. A condition that times out is
+//! reported in the step's [`StepReport::condition_met`] and the sequence
+//! carries on, so a following step can still switch off. Waiting for a
+//! reading to [settle](crate::settle) is one such condition.
+//!
+//! A long run can be [checkpointed](crate::checkpoint) to disk, detached
+//! through a [`DetachHandle`] and resumed from another process.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, Result};
+use tokio::sync::watch;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+use crate::checkpoint::{CheckpointPhase, SequenceCheckpoint};
+use crate::instrument::{Channel, OutputState, RegulationMode, Spd3303x};
+use crate::notes::Note;
+use crate::operation;
+use crate::pipeline::Quantity;
+use crate::settle::{self, SettleSpec, SettleWindow};
+use crate::source_sink::{ElectronicLoad, LoadSetting, NoLoad, SourceSink};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Action {
+    SetVoltage(Channel, f64),
+    SetCurrent(Channel, f64),
+    Output(Channel, OutputState),
+    Load(LoadSetting),
+    LoadInput(bool),
+}
+
+impl Action {
+    fn is_load(&self) -> bool {
+        matches!(self, Action::Load(_) | Action::LoadInput(_))
+    }
+}
+
+/// How often a [`Condition`] is re-checked by default.
+const DEFAULT_CONDITION_POLL: Duration = Duration::from_millis(100);
+
+/// A reading a step waits for, checked with one query per poll.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Condition {
+    CurrentBelow(Channel, f64),
+    CurrentAbove(Channel, f64),
+    VoltageBelow(Channel, f64),
+    VoltageAbove(Channel, f64),
+    Regulation(Channel, RegulationMode),
+    /// The reading has stayed within `tolerance` peak to peak for
+    /// `window`; see [`settle`](crate::settle).
+    Settled {
+        channel: Channel,
+        quantity: Quantity,
+        tolerance: f64,
+        window: Duration,
+    },
+}
+
+impl Condition {
+    /// `window` keeps the readings of a [`Condition::Settled`] across polls.
+    async fn holds(&self, inst: &mut Spd3303x, window: &mut SettleWindow) -> Result<bool> {
+        Ok(match *self {
+            Condition::CurrentBelow(channel, amps) => {
+                inst.measured_current(Some(channel)).await? < amps
+            }
+            Condition::CurrentAbove(channel, amps) => {
+                inst.measured_current(Some(channel)).await? > amps
+            }
+            Condition::VoltageBelow(channel, volts) => {
+                inst.measured_voltage(Some(channel)).await? < volts
+            }
+            Condition::VoltageAbove(channel, volts) => {
+                inst.measured_voltage(Some(channel)).await? > volts
+            }
+            Condition::Regulation(channel, mode) => {
+                inst.system_status().await?.regulation_mode(channel) == Some(mode)
+            }
+            Condition::Settled {
+                channel,
+                quantity,
+                tolerance,
+                ..
+            } => {
+                let value = settle::read(inst, channel, quantity).await?;
+                window.push(inst.clock().now(), value);
+                window.is_settled(tolerance)
+            }
+        })
+    }
+
+    fn settle_window(&self) -> Duration {
+        match *self {
+            Condition::Settled { window, .. } => window,
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+/// Wait of a step for its [`Condition`].
+#[derive(Debug, Clone, Copy)]
+pub struct WaitUntil {
+    pub condition: Condition,
+    /// Longest wait, pauses not counted.
+    pub timeout: Duration,
+    pub poll_interval: Duration,
+}
+
+/// Actions applied together, then held for `hold`, after waiting for
+/// `wait` if set.
+#[derive(Debug, Clone, Default)]
+pub struct Step {
+    pub label: Option<String>,
+    pub actions: Vec<Action>,
+    pub wait: Option<WaitUntil>,
+    pub hold: Duration,
+}
+
+impl Step {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn voltage(mut self, channel: Channel, volts: f64) -> Self {
+        self.actions.push(Action::SetVoltage(channel, volts));
+        self
+    }
+
+    pub fn current(mut self, channel: Channel, amps: f64) -> Self {
+        self.actions.push(Action::SetCurrent(channel, amps));
+        self
+    }
+
+    pub fn output(mut self, channel: Channel, state: OutputState) -> Self {
+        self.actions.push(Action::Output(channel, state));
+        self
+    }
+
+    pub fn load(mut self, setting: LoadSetting) -> Self {
+        self.actions.push(Action::Load(setting));
+        self
+    }
+
+    pub fn load_input(mut self, on: bool) -> Self {
+        self.actions.push(Action::LoadInput(on));
+        self
+    }
+
+    pub fn hold(mut self, hold: Duration) -> Self {
+        self.hold = hold;
+        self
+    }
+
+    /// Wait up to `timeout` for `condition` after the actions are applied.
+    pub fn wait_until(mut self, condition: Condition, timeout: Duration) -> Self {
+        self.wait = Some(WaitUntil {
+            condition,
+            timeout,
+            poll_interval: DEFAULT_CONDITION_POLL,
+        });
+        self
+    }
+
+    /// Wait up to `timeout` for `channel` to regulate in CV.
+    pub fn wait_for_cv(self, channel: Channel, timeout: Duration) -> Self {
+        self.wait_until(
+            Condition::Regulation(channel, RegulationMode::ConstantVoltage),
+            timeout,
+        )
+    }
+
+    /// Wait for a reading of `channel` to settle as described by `spec`.
+    pub fn wait_for_settled(self, channel: Channel, spec: SettleSpec) -> Self {
+        let condition = Condition::Settled {
+            channel,
+            quantity: spec.quantity,
+            tolerance: spec.tolerance,
+            window: spec.window,
+        };
+        self.wait_until(condition, spec.timeout)
+            .poll_interval(spec.poll_interval)
+    }
+
+    /// Re-check the condition of [`wait_until`](Self::wait_until) this often.
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        if let Some(wait) = &mut self.wait {
+            wait.poll_interval = interval;
+        }
+        self
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Sequence {
+    pub steps: Vec<Step>,
+}
+
+impl Sequence {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn step(mut self, step: Step) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    /// Whether any step drives an electronic load.
+    pub fn uses_load(&self) -> bool {
+        self.steps
+            .iter()
+            .any(|step| step.actions.iter().any(Action::is_load))
+    }
+
+    /// Sum of all hold times, without waits for conditions.
+    pub fn duration(&self) -> Duration {
+        self.steps.iter().map(|step| step.hold).sum()
+    }
+
+    /// CH1/CH2 whose voltage or output any step sets.
+    fn supply_channels(&self) -> Vec<Channel> {
+        let mut channels = Vec::new();
+        for action in self.steps.iter().flat_map(|step| &step.actions) {
+            if let Action::SetVoltage(channel, _) | Action::Output(channel, _) = *action
+                && channel != Channel::Ch3
+                && !channels.contains(&channel)
+            {
+                channels.push(channel);
+            }
+        }
+        channels
+    }
+}
+
+/// Round-trip time of simple queries, see [`SequenceRunner::calibrate`].
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LatencyEstimate {
+    pub samples: usize,
+    pub mean: Duration,
+    pub min: Duration,
+    pub max: Duration,
+}
+
+impl LatencyEstimate {
+    /// Time from issuing a command until the instrument acts on it,
+    /// approximated as half the mean round trip.
+    pub fn one_way(&self) -> Duration {
+        self.mean / 2
+    }
+}
+
+/// Requested vs achieved timing of one step.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StepReport {
+    pub index: usize,
+    pub label: Option<String>,
+    pub requested: Duration,
+    /// Time from this step's first command to the next step's first
+    /// command (or to the end of the hold for the last step).
+    pub actual: Duration,
+    /// Time spent sending this step's commands.
+    pub command_time: Duration,
+    /// Whether the step's condition held before its timeout; `None`
+    /// without a condition.
+    pub condition_met: Option<bool>,
+    /// Time spent waiting for the condition, pauses not counted.
+    pub waited: Duration,
+}
+
+impl StepReport {
+    /// `actual - requested` in seconds; positive when the step ran long.
+    pub fn error_s(&self) -> f64 {
+        self.actual.as_secs_f64() - self.requested.as_secs_f64()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SequenceReport {
+    pub steps: Vec<StepReport>,
+    /// Whether the sequence was aborted; `steps` then ends with the step
+    /// that was holding at the time.
+    pub aborted: bool,
+    /// Whether the sequence was detached, leaving its checkpoint to be
+    /// resumed; `steps` then ends like on abort.
+    pub detached: bool,
+    /// Step a resumed run continued at; `steps` before it come from the
+    /// checkpoint.
+    pub resumed_from: Option<usize>,
+    /// Time spent paused, not counted in the steps' `actual` times.
+    pub paused: Duration,
+    /// Operator notes taken while the sequence ran, see
+    /// [`Spd3303x::annotate`].
+    pub notes: Vec<Note>,
+}
+
+/// Stops a sequence before its next step, see
+/// [`SequenceRunner::abort_handle`].
+#[derive(Debug, Clone)]
+pub struct AbortHandle(Arc<watch::Sender<bool>>);
+
+impl AbortHandle {
+    pub fn abort(&self) {
+        self.0.send_replace(true);
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        *self.0.borrow()
+    }
+}
+
+/// Stops a sequence like [`AbortHandle`] but keeps its checkpoint, with
+/// the outputs as they are, see [`SequenceRunner::detach_handle`].
+#[derive(Debug, Clone)]
+pub struct DetachHandle(Arc<watch::Sender<bool>>);
+
+impl DetachHandle {
+    pub fn detach(&self) {
+        self.0.send_replace(true);
+    }
+
+    pub fn is_detached(&self) -> bool {
+        *self.0.borrow()
+    }
+}
+
+/// Holds and continues a sequence, see [`SequenceRunner::pause_handle`].
+#[derive(Debug, Clone)]
+pub struct PauseHandle(Arc<watch::Sender<bool>>);
+
+impl PauseHandle {
+    pub fn pause(&self) {
+        self.0.send_replace(true);
+    }
+
+    pub fn resume(&self) {
+        self.0.send_replace(false);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.0.borrow()
+    }
+}
+
+/// What happens to the supply while a sequence is paused.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PauseBehavior {
+    /// Leave setpoints and outputs as they are.
+    #[default]
+    Hold,
+    /// Set the voltage of the channels the sequence drives to `volts`, and
+    /// back to the previous setpoints on resume.
+    SafeVoltage { volts: f64 },
+}
+
+/// Shortest on or off time of [`PulseSpec`]. The output is switched by a
+/// relay whose contacts bounce and wear when cycled faster than this.
+pub const MIN_RELAY_DWELL: Duration = Duration::from_millis(100);
+
+/// Output on/off cycling with fixed setpoints, e.g. for thermal cycling.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PulseSpec {
+    pub on_time: Duration,
+    pub off_time: Duration,
+    pub cycles: u32,
+}
+
+impl PulseSpec {
+    /// The pulses as a sequence. The last off step has no hold, so the
+    /// sequence ends when the output goes off for the last time.
+    pub fn sequence(&self, channel: Channel) -> Result<Sequence> {
+        if self.cycles == 0 {
+            return Err(anyhow!("pulse needs at least one cycle"));
+        }
+        if self.on_time < MIN_RELAY_DWELL || self.off_time < MIN_RELAY_DWELL {
+            return Err(anyhow!(
+                "pulse on/off times must be at least {MIN_RELAY_DWELL:?} for the output relay"
+            ));
+        }
+        let mut sequence = Sequence::new();
+        for cycle in 0..self.cycles {
+            let off_hold = if cycle + 1 == self.cycles {
+                Duration::ZERO
+            } else {
+                self.off_time
+            };
+            sequence = sequence
+                .step(
+                    Step::new()
+                        .label(format!("on {}", cycle + 1))
+                        .output(channel, OutputState::On)
+                        .hold(self.on_time),
+                )
+                .step(
+                    Step::new()
+                        .label(format!("off {}", cycle + 1))
+                        .output(channel, OutputState::Off)
+                        .hold(off_hold),
+                );
+        }
+        Ok(sequence)
+    }
+}
+
+/// Executes [`Sequence`]s against an instrument.
+///
+/// Step deadlines are absolute (relative to the start of the sequence), so
+/// command time does not accumulate into drift. With a latency
+/// compensation set, each step's commands are issued that much before the
+/// deadline so they take effect on time.
+#[derive(Debug, Clone, Default)]
+pub struct SequenceRunner {
+    latency_compensation: Duration,
+    abort: Option<watch::Receiver<bool>>,
+    detach: Option<watch::Receiver<bool>>,
+    pause: Option<watch::Receiver<bool>>,
+    pause_behavior: PauseBehavior,
+    checkpoint: Option<PathBuf>,
+    max_checkpoint_age: Option<Duration>,
+}
+
+impl SequenceRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn latency_compensation(mut self, latency: Duration) -> Self {
+        self.latency_compensation = latency;
+        self
+    }
+
+    /// Handle that aborts sequences run by this runner from then on.
+    /// Replaces the handle of an earlier call.
+    pub fn abort_handle(&mut self) -> AbortHandle {
+        let (tx, rx) = watch::channel(false);
+        self.abort = Some(rx);
+        AbortHandle(Arc::new(tx))
+    }
+
+    /// Handle that detaches sequences run by this runner from then on.
+    /// Replaces the handle of an earlier call.
+    pub fn detach_handle(&mut self) -> DetachHandle {
+        let (tx, rx) = watch::channel(false);
+        self.detach = Some(rx);
+        DetachHandle(Arc::new(tx))
+    }
+
+    /// Handle that pauses and resumes sequences run by this runner from
+    /// then on. Replaces the handle of an earlier call.
+    pub fn pause_handle(&mut self) -> PauseHandle {
+        let (tx, rx) = watch::channel(false);
+        self.pause = Some(rx);
+        PauseHandle(Arc::new(tx))
+    }
+
+    pub fn pause_behavior(mut self, behavior: PauseBehavior) -> Self {
+        self.pause_behavior = behavior;
+        self
+    }
+
+    /// Keep a [`SequenceCheckpoint`] of the running sequence at `path`.
+    #[cfg(feature = "json")]
+    pub fn checkpoint_to(mut self, path: impl Into<PathBuf>) -> Self {
+        self.checkpoint = Some(path.into());
+        self
+    }
+
+    /// Refuse to [`resume`](Self::resume) from checkpoints older than
+    /// `age`. Unlimited by default.
+    pub fn max_checkpoint_age(mut self, age: Duration) -> Self {
+        self.max_checkpoint_age = Some(age);
+        self
+    }
+
+    /// Measure the command latency of `inst` with `samples` status queries
+    /// and use its one-way estimate as compensation.
+    pub async fn calibrate(
+        &mut self,
+        inst: &mut Spd3303x,
+        samples: usize,
+    ) -> Result<LatencyEstimate> {
+        let estimate = measure_latency(inst, samples).await?;
+        debug!(
+            "sequence: latency mean {:?} (min {:?}, max {:?}) over {} samples",
+            estimate.mean, estimate.min, estimate.max, estimate.samples
+        );
+        self.latency_compensation = estimate.one_way();
+        Ok(estimate)
+    }
+
+    /// Run a sequence that only drives the supply.
+    pub async fn run(&self, inst: &mut Spd3303x, sequence: &Sequence) -> Result<SequenceReport> {
+        if sequence.uses_load() {
+            return Err(anyhow!("sequence contains load actions; use run_source_sink"));
+        }
+        let fut = self.run_steps::<NoLoad>(inst, None, sequence, None);
+        operation::run("sequence", fut).await
+    }
+
+    /// Run a sequence that may drive both the supply and the load.
+    pub async fn run_source_sink<L: ElectronicLoad>(
+        &self,
+        pair: &mut SourceSink<'_, L>,
+        sequence: &Sequence,
+    ) -> Result<SequenceReport> {
+        let fut = self.run_steps(pair.source, Some(&mut *pair.sink), sequence, None);
+        operation::run("sequence", fut).await
+    }
+
+    /// Continue a supply-only sequence from `checkpoint`; see
+    /// [`checkpoint`](crate::checkpoint) for what is restored.
+    pub async fn resume(
+        &self,
+        inst: &mut Spd3303x,
+        sequence: &Sequence,
+        checkpoint: &SequenceCheckpoint,
+    ) -> Result<SequenceReport> {
+        if sequence.uses_load() {
+            return Err(anyhow!("sequence contains load actions; use resume_source_sink"));
+        }
+        checkpoint.validate(sequence, self.max_checkpoint_age)?;
+        let fut = self.run_steps::<NoLoad>(inst, None, sequence, Some(checkpoint));
+        operation::run("sequence", fut).await
+    }
+
+    /// Continue a sequence driving the supply and the load from
+    /// `checkpoint`.
+    pub async fn resume_source_sink<L: ElectronicLoad>(
+        &self,
+        pair: &mut SourceSink<'_, L>,
+        sequence: &Sequence,
+        checkpoint: &SequenceCheckpoint,
+    ) -> Result<SequenceReport> {
+        checkpoint.validate(sequence, self.max_checkpoint_age)?;
+        let fut = self.run_steps(pair.source, Some(&mut *pair.sink), sequence, Some(checkpoint));
+        operation::run("sequence", fut).await
+    }
+
+    /// Pulse the output of `channel` as described by `spec`. The output is
+    /// left off when the pulses end, including on abort or error.
+    pub async fn run_pulses(
+        &self,
+        inst: &mut Spd3303x,
+        channel: Channel,
+        spec: &PulseSpec,
+    ) -> Result<SequenceReport> {
+        let sequence = spec.sequence(channel)?;
+        let fut = self.run_steps::<NoLoad>(inst, None, &sequence, None);
+        let result = operation::run("pulse_output", fut).await;
+        let completed = matches!(&result, Ok(report) if !report.aborted);
+        if !completed && let Err(e) = inst.set_output(channel, OutputState::Off).await {
+            warn!("pulse: turning {} off failed: {e:#}", channel.label());
+        }
+        result
+    }
+
+    /// Sleep until `deadline`, plus however long the runner is paused in
+    /// the meantime. Returns the time spent paused, or `None` if aborted
+    /// or detached.
+    async fn wait_until(
+        &self,
+        inst: &mut Spd3303x,
+        channels: &[Channel],
+        mut deadline: Instant,
+    ) -> Result<Option<Duration>> {
+        let clock = inst.clock();
+        let mut paused = Duration::ZERO;
+        loop {
+            let pause = self.pause.clone();
+            let abort = self.abort.clone();
+            let detach = self.detach.clone();
+            tokio::select! {
+                biased;
+                Some(()) = wait_for(abort, true) => return Ok(None),
+                Some(()) = wait_for(detach, true) => return Ok(None),
+                Some(()) = wait_for(pause, true) => {
+                    let started = clock.now();
+                    if !self.hold_paused(inst, channels).await? {
+                        return Ok(None);
+                    }
+                    let held = clock.elapsed(started);
+                    paused += held;
+                    deadline += held;
+                }
+                _ = clock.sleep_until(deadline) => return Ok(Some(paused)),
+            }
+        }
+    }
+
+    /// Poll the condition of `wait` until it holds or times out, pausing
+    /// like a hold does. Returns whether it held, the time waited and the
+    /// time paused, or `None` if aborted or detached.
+    async fn wait_condition(
+        &self,
+        inst: &mut Spd3303x,
+        channels: &[Channel],
+        wait: &WaitUntil,
+    ) -> Result<Option<(bool, Duration, Duration)>> {
+        let clock = inst.clock();
+        let started = clock.now();
+        let mut paused = Duration::ZERO;
+        let mut window = SettleWindow::new(wait.condition.settle_window());
+        loop {
+            let met = wait.condition.holds(inst, &mut window).await?;
+            let waited = clock.elapsed(started).saturating_sub(paused);
+            if met || waited >= wait.timeout {
+                if !met {
+                    warn!(
+                        "sequence: {:?} not met within {:?}",
+                        wait.condition, wait.timeout
+                    );
+                }
+                return Ok(Some((met, waited, paused)));
+            }
+            let next = clock.now() + wait.poll_interval;
+            let Some(time) = self.wait_until(inst, channels, next).await? else {
+                return Ok(None);
+            };
+            paused += time;
+        }
+    }
+
+    /// Hold while paused; `false` if aborted or detached meanwhile. The
+    /// supply is left at the safe voltage then.
+    async fn hold_paused(&self, inst: &mut Spd3303x, channels: &[Channel]) -> Result<bool> {
+        debug!("sequence: paused");
+        let mut restore = Vec::new();
+        if let PauseBehavior::SafeVoltage { volts } = self.pause_behavior {
+            for &channel in channels {
+                restore.push((channel, inst.setpoint_voltage(channel).await?));
+                inst.set_voltage(channel, volts).await?;
+            }
+        }
+        let resumed = tokio::select! {
+            biased;
+            Some(()) = wait_for(self.abort.clone(), true) => false,
+            Some(()) = wait_for(self.detach.clone(), true) => false,
+            _ = wait_for(self.pause.clone(), false) => true,
+        };
+        if !resumed {
+            debug!("sequence: stopped while paused");
+            return Ok(false);
+        }
+        for (channel, volts) in restore {
+            inst.set_voltage(channel, volts).await?;
+        }
+        debug!("sequence: resumed");
+        Ok(true)
+    }
+
+    async fn run_steps<L: ElectronicLoad>(
+        &self,
+        inst: &mut Spd3303x,
+        mut load: Option<&mut L>,
+        sequence: &Sequence,
+        resume: Option<&SequenceCheckpoint>,
+    ) -> Result<SequenceReport> {
+        let clock = inst.clock();
+        let start = clock.now();
+        let started_at = SystemTime::now();
+        let mut deadline = start;
+        let mut report = SequenceReport::default();
+        let fingerprint = sequence.fingerprint();
+        let mut first = 0;
+        if let Some(checkpoint) = resume {
+            debug!(
+                "sequence: resuming at step {} ({:?})",
+                checkpoint.step, checkpoint.phase
+            );
+            first = checkpoint.step;
+            report.steps = checkpoint.completed.clone();
+            report.paused = checkpoint.paused;
+            report.resumed_from = Some(checkpoint.step);
+        }
+        // Issue time, index and time paused while waiting for a condition
+        // of the step in progress.
+        let mut previous: Option<(Instant, usize, Duration)> = None;
+        let channels = sequence.supply_channels();
+
+        for (index, step) in sequence.steps.iter().enumerate().skip(first) {
+            let resumed = resume.filter(|_| index == first);
+            let issue_at = deadline
+                .checked_sub(self.latency_compensation)
+                .unwrap_or(deadline);
+            let Some(paused) = self.wait_until(inst, &channels, issue_at).await? else {
+                self.stopped(&mut report, &format!("before step {index}"));
+                break;
+            };
+            report.paused += paused;
+            deadline += paused;
+            let issued = clock.now();
+            if let Some((prev_issued, prev_index, prev_paused)) = previous {
+                report.steps[prev_index].actual =
+                    (issued - prev_issued).saturating_sub(prev_paused + paused);
+            }
+
+            // A resumed step restores everything the sequence had set up to
+            // it, not just its own actions.
+            let restored;
+            let actions = match resumed {
+                Some(_) => {
+                    restored = sequence.state_through(index);
+                    &restored
+                }
+                None => &step.actions,
+            };
+            let fut = apply_actions(inst, load.as_deref_mut(), actions);
+            operation::run("sequence_step", fut).await?;
+            let command_time = clock.elapsed(issued);
+            debug!(
+                "sequence: step {index} issued at {:?}, commands took {:?}",
+                issued - start,
+                command_time
+            );
+
+            report.steps.push(StepReport {
+                index,
+                label: step.label.clone(),
+                requested: step.hold,
+                actual: Duration::ZERO,
+                command_time,
+                condition_met: None,
+                waited: Duration::ZERO,
+            });
+            previous = Some((issued, index, Duration::ZERO));
+
+            // A step resumed in its hold skips its condition and holds for
+            // what is left.
+            let (wait, hold, since) = match resumed.map(|checkpoint| checkpoint.phase) {
+                Some(CheckpointPhase::Holding { since }) => (
+                    None,
+                    step.hold.saturating_sub(since.elapsed().unwrap_or_default()),
+                    since,
+                ),
+                _ => (step.wait.as_ref(), step.hold, SystemTime::now()),
+            };
+            let phase = match wait {
+                Some(_) => CheckpointPhase::Waiting,
+                None => CheckpointPhase::Holding { since },
+            };
+            self.save_checkpoint(fingerprint, &report, index, phase);
+
+            if let Some(wait) = wait {
+                let Some((met, waited, paused)) =
+                    self.wait_condition(inst, &channels, wait).await?
+                else {
+                    self.stopped(
+                        &mut report,
+                        &format!("waiting for the condition of step {index}"),
+                    );
+                    break;
+                };
+                report.paused += paused;
+                report.steps[index].condition_met = Some(met);
+                report.steps[index].waited = waited;
+                previous = Some((issued, index, paused));
+                deadline = clock.now();
+                let phase = CheckpointPhase::Holding {
+                    since: SystemTime::now(),
+                };
+                self.save_checkpoint(fingerprint, &report, index, phase);
+            }
+            deadline += hold;
+        }
+
+        if let Some((issued, index, step_paused)) = previous {
+            let mut paused = Duration::ZERO;
+            if !report.aborted && !report.detached {
+                match self.wait_until(inst, &channels, deadline).await? {
+                    Some(time) => paused = time,
+                    None => self.stopped(&mut report, "during the last step"),
+                }
+            }
+            report.paused += paused;
+            report.steps[index].actual =
+                clock.elapsed(issued).saturating_sub(step_paused + paused);
+        }
+        if !report.detached {
+            self.remove_checkpoint();
+        }
+        report.notes = inst.note_log().since(started_at);
+        Ok(report)
+    }
+
+    /// Mark `report` as aborted or detached, whichever stopped it.
+    fn stopped(&self, report: &mut SequenceReport, when: &str) {
+        if self.detach.as_ref().is_some_and(|rx| *rx.borrow()) {
+            debug!("sequence: detached {when}");
+            report.detached = true;
+        } else {
+            debug!("sequence: aborted {when}");
+            report.aborted = true;
+        }
+    }
+
+    /// Write the checkpoint of step `step` if the runner keeps one. A
+    /// failed write is logged; the sequence itself carries on.
+    fn save_checkpoint(
+        &self,
+        fingerprint: u64,
+        report: &SequenceReport,
+        step: usize,
+        phase: CheckpointPhase,
+    ) {
+        let Some(path) = &self.checkpoint else {
+            return;
+        };
+        let checkpoint = SequenceCheckpoint {
+            fingerprint,
+            step,
+            phase,
+            completed: report.steps[..step].to_vec(),
+            paused: report.paused,
+            saved_at: SystemTime::now(),
+        };
+        #[cfg(feature = "json")]
+        if let Err(e) = checkpoint.save(path) {
+            warn!("sequence: {e:#}");
+        }
+        #[cfg(not(feature = "json"))]
+        let _ = (path, checkpoint);
+    }
+
+    fn remove_checkpoint(&self) {
+        if let Some(path) = &self.checkpoint
+            && let Err(e) = std::fs::remove_file(path)
+            && e.kind() != std::io::ErrorKind::NotFound
+        {
+            warn!("sequence: failed to remove checkpoint {}: {e}", path.display());
+        }
+    }
+}
+
+impl Spd3303x {
+    /// Pulse the output of `channel`; see [`SequenceRunner::run_pulses`]
+    /// for latency compensation and abort.
+    pub async fn pulse_output(
+        &mut self,
+        channel: Channel,
+        spec: PulseSpec,
+    ) -> Result<SequenceReport> {
+        SequenceRunner::new().run_pulses(self, channel, &spec).await
+    }
+}
+
+/// Time `samples` round trips of `SYST:STAT?`.
+pub async fn measure_latency(inst: &mut Spd3303x, samples: usize) -> Result<LatencyEstimate> {
+    let samples = samples.max(1);
+    let mut total = Duration::ZERO;
+    let mut min = Duration::MAX;
+    let mut max = Duration::ZERO;
+    for _ in 0..samples {
+        let started = Instant::now();
+        inst.system_status().await?;
+        let elapsed = started.elapsed();
+        total += elapsed;
+        min = min.min(elapsed);
+        max = max.max(elapsed);
+    }
+    Ok(LatencyEstimate {
+        samples,
+        mean: total / samples as u32,
+        min,
+        max,
+    })
+}
+
+/// Resolves once `rx` holds `value`; never if there is no channel or its
+/// sender is gone.
+async fn wait_for(rx: Option<watch::Receiver<bool>>, value: bool) -> Option<()> {
+    let mut rx = rx?;
+    match rx.wait_for(|current| *current == value).await {
+        Ok(_) => Some(()),
+        Err(_) => std::future::pending().await,
+    }
+}
+
+async fn apply_actions<L: ElectronicLoad>(
+    inst: &mut Spd3303x,
+    mut load: Option<&mut L>,
+    actions: &[Action],
+) -> Result<()> {
+    for action in actions {
+        match *action {
+            Action::SetVoltage(channel, volts) => inst.set_voltage(channel, volts).await?,
+            Action::SetCurrent(channel, amps) => inst.set_current(channel, amps).await?,
+            Action::Output(channel, state) => inst.set_output(channel, state).await?,
+            Action::Load(setting) => {
+                let load = load.as_deref_mut().ok_or_else(|| anyhow!("no load attached"))?;
+                load.set_load(setting).await?
+            }
+            Action::LoadInput(on) => {
+                let load = load.as_deref_mut().ok_or_else(|| anyhow!("no load attached"))?;
+                load.set_input(on).await?
+            }
+        }
+    }
+    Ok(())
+}
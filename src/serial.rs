@@ -0,0 +1,133 @@
+//! SCPI over a serial line, for setups that reach the supply through a
+//! USB-serial or RS-232 bridge.
+//!
+//! Replies are framed by reading up to the LF terminator.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio_serial::SerialStream;
+
+use crate::escalation::CommandTimedOut;
+use crate::transport::{Transport, TransportFuture};
+
+pub use tokio_serial::{DataBits, FlowControl, Parity, StopBits};
+
+/// Line settings of the serial port; defaults to 9600 8N1 without flow
+/// control.
+#[derive(Debug, Clone)]
+pub struct SerialConfig {
+    pub path: String,
+    pub baud_rate: u32,
+    pub data_bits: DataBits,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+    pub flow_control: FlowControl,
+    /// How long to wait for a complete reply line.
+    pub timeout: Duration,
+}
+
+impl SerialConfig {
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            baud_rate: 9600,
+            data_bits: DataBits::Eight,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            flow_control: FlowControl::None,
+            timeout: Duration::from_secs(2),
+        }
+    }
+
+    pub fn baud_rate(mut self, baud_rate: u32) -> Self {
+        self.baud_rate = baud_rate;
+        self
+    }
+
+    pub fn data_bits(mut self, data_bits: DataBits) -> Self {
+        self.data_bits = data_bits;
+        self
+    }
+
+    pub fn parity(mut self, parity: Parity) -> Self {
+        self.parity = parity;
+        self
+    }
+
+    pub fn stop_bits(mut self, stop_bits: StopBits) -> Self {
+        self.stop_bits = stop_bits;
+        self
+    }
+
+    pub fn flow_control(mut self, flow_control: FlowControl) -> Self {
+        self.flow_control = flow_control;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+pub(crate) struct SerialClient {
+    port: BufReader<SerialStream>,
+    timeout: Duration,
+}
+
+impl SerialClient {
+    pub(crate) fn open(config: &SerialConfig) -> Result<Self> {
+        let builder = tokio_serial::new(&config.path, config.baud_rate)
+            .data_bits(config.data_bits)
+            .parity(config.parity)
+            .stop_bits(config.stop_bits)
+            .flow_control(config.flow_control)
+            .timeout(config.timeout);
+        let port = SerialStream::open(&builder)
+            .with_context(|| format!("failed to open serial port {}", config.path))?;
+        Ok(Self {
+            port: BufReader::new(port),
+            timeout: config.timeout,
+        })
+    }
+
+    pub(crate) async fn write(&mut self, command: &str) -> Result<()> {
+        let port = self.port.get_mut();
+        port.write_all(command.as_bytes()).await?;
+        port.flush().await?;
+        Ok(())
+    }
+
+    pub(crate) async fn query(&mut self, command: &str) -> Result<String> {
+        self.write(command).await?;
+        let mut line = Vec::new();
+        let read = tokio::time::timeout(self.timeout, self.port.read_until(b'\n', &mut line))
+            .await
+            .map_err(|_| CommandTimedOut::once(command, self.timeout))??;
+        if read == 0 {
+            return Err(anyhow!("serial port closed"));
+        }
+        Ok(String::from_utf8(line)?)
+    }
+
+    pub(crate) async fn close(&mut self) -> Result<()> {
+        self.port.get_mut().flush().await?;
+        Ok(())
+    }
+}
+
+impl Transport for SerialClient {
+    fn write<'a>(&'a mut self, command: &'a str) -> TransportFuture<'a, ()> {
+        Box::pin(SerialClient::write(self, command))
+    }
+
+    fn query<'a>(&'a mut self, command: &'a str) -> TransportFuture<'a, String> {
+        Box::pin(SerialClient::query(self, command))
+    }
+
+    fn close(&mut self) -> TransportFuture<'_, ()> {
+        Box::pin(SerialClient::close(self))
+    }
+}
@@ -0,0 +1,189 @@
+//! Waiting for a reading to settle.
+//!
+//! A reading counts as settled once it has stayed within a band of
+//! `tolerance` (peak to peak) for a whole `window`, judged on a moving
+//! window of polls. That adapts to the DUT, unlike a fixed sleep that is
+//! too short for a large capacitor and wastes time on a resistor.
+//! [`Spd3303x::wait_for_settled`] waits on its own; in a sequence,
+//! [`Step::wait_for_settled`](crate::sequence::Step::wait_for_settled) does
+//! the same before the step's hold starts.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+use crate::instrument::{Channel, Spd3303x};
+use crate::pipeline::Quantity;
+
+/// How often the reading is polled by default.
+pub const DEFAULT_SETTLE_POLL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SettleSpec {
+    pub quantity: Quantity,
+    /// Time the reading must stay within `tolerance`.
+    pub window: Duration,
+    /// Allowed peak-to-peak spread within the window, in the quantity's
+    /// unit.
+    pub tolerance: f64,
+    /// Longest wait.
+    pub timeout: Duration,
+    pub poll_interval: Duration,
+}
+
+impl SettleSpec {
+    pub fn voltage(window: Duration, tolerance_v: f64, timeout: Duration) -> Self {
+        Self {
+            quantity: Quantity::Voltage,
+            window,
+            tolerance: tolerance_v,
+            timeout,
+            poll_interval: DEFAULT_SETTLE_POLL,
+        }
+    }
+
+    pub fn current(window: Duration, tolerance_a: f64, timeout: Duration) -> Self {
+        Self {
+            quantity: Quantity::Current,
+            tolerance: tolerance_a,
+            ..Self::voltage(window, 0.0, timeout)
+        }
+    }
+
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+}
+
+/// Result of [`Spd3303x::wait_for_settled`].
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SettleReport {
+    pub settled: bool,
+    pub waited: Duration,
+    /// Mean of the readings in the last window.
+    pub value: f64,
+    /// Peak-to-peak spread of the readings in the last window.
+    pub spread: f64,
+    pub readings: usize,
+}
+
+/// Readings of the trailing window.
+#[derive(Debug, Clone)]
+pub(crate) struct SettleWindow {
+    span: Duration,
+    readings: VecDeque<(Instant, f64)>,
+}
+
+impl SettleWindow {
+    pub(crate) fn new(span: Duration) -> Self {
+        Self {
+            span,
+            readings: VecDeque::new(),
+        }
+    }
+
+    /// Add a reading, dropping those no longer needed to cover the window.
+    pub(crate) fn push(&mut self, at: Instant, value: f64) {
+        self.readings.push_back((at, value));
+        let start = at.checked_sub(self.span).unwrap_or(at);
+        while self.readings.get(1).is_some_and(|&(at, _)| at <= start) {
+            self.readings.pop_front();
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        match (self.readings.front(), self.readings.back()) {
+            (Some(&(first, _)), Some(&(last, _))) => last - first >= self.span,
+            _ => false,
+        }
+    }
+
+    fn spread(&self) -> f64 {
+        let values = self.readings.iter().map(|&(_, value)| value);
+        let max = values.clone().fold(f64::NEG_INFINITY, f64::max);
+        let min = values.fold(f64::INFINITY, f64::min);
+        if self.readings.is_empty() {
+            0.0
+        } else {
+            max - min
+        }
+    }
+
+    fn mean(&self) -> f64 {
+        let sum: f64 = self.readings.iter().map(|&(_, value)| value).sum();
+        sum / self.readings.len().max(1) as f64
+    }
+
+    pub(crate) fn is_settled(&self, tolerance: f64) -> bool {
+        self.is_full() && self.spread() <= tolerance
+    }
+}
+
+/// Read `quantity` of `channel` through its measurement pipeline.
+pub(crate) async fn read(
+    inst: &mut Spd3303x,
+    channel: Channel,
+    quantity: Quantity,
+) -> Result<f64> {
+    match quantity {
+        Quantity::Voltage => inst.measured_voltage(Some(channel)).await,
+        Quantity::Current => inst.measured_current(Some(channel)).await,
+        Quantity::Power => inst.measured_power(Some(channel)).await,
+    }
+}
+
+impl Spd3303x {
+    /// Poll `channel` until its reading has stayed within the tolerance of
+    /// `spec` for its window, or the timeout has passed. A timeout is not
+    /// an error; check [`SettleReport::settled`].
+    pub async fn wait_for_settled(
+        &mut self,
+        channel: Channel,
+        spec: SettleSpec,
+    ) -> Result<SettleReport> {
+        let clock = self.clock();
+        let started = clock.now();
+        let mut window = SettleWindow::new(spec.window);
+        let mut readings = 0;
+        loop {
+            let value = read(self, channel, spec.quantity).await?;
+            window.push(clock.now(), value);
+            readings += 1;
+            let settled = window.is_settled(spec.tolerance);
+            let waited = clock.elapsed(started);
+            if settled || waited >= spec.timeout {
+                let report = SettleReport {
+                    settled,
+                    waited,
+                    value: window.mean(),
+                    spread: window.spread(),
+                    readings,
+                };
+                if settled {
+                    debug!(
+                        "settle: {} {} settled at {:.4} after {waited:?}",
+                        channel.label(),
+                        spec.quantity.name(),
+                        report.value
+                    );
+                } else {
+                    warn!(
+                        "settle: {} {} still moving by {:.4} {} after {waited:?}",
+                        channel.label(),
+                        spec.quantity.name(),
+                        report.spread,
+                        spec.quantity.unit()
+                    );
+                }
+                return Ok(report);
+            }
+            clock.sleep(spec.poll_interval).await;
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! Output slew-rate estimates.
+//!
+//! [`Spd3303x::estimate_slew_rate`] settles a channel at one voltage, steps
+//! it to another and reads the output back to back during the transition.
+//! A least-squares line through the readings between 10% and 90% of the
+//! step gives the slope. Readings are SCPI round trips a few milliseconds
+//! apart, so a fast edge on a light load may be over before the second
+//! reading; the report says how many readings the fit used, and a fit from
+//! fewer than [`MIN_FIT_POINTS`] is not reported.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use tracing::debug;
+
+use crate::instrument::{Channel, Spd3303x};
+use crate::operation;
+use crate::pipeline::Quantity;
+use crate::settle::SettleSpec;
+use crate::table::Table;
+
+/// Readings inside the 10–90% band needed for a slope.
+pub const MIN_FIT_POINTS: usize = 3;
+
+/// Voltage within which the starting point counts as settled.
+const SETTLE_TOLERANCE_V: f64 = 0.01;
+const SETTLE_WINDOW: Duration = Duration::from_millis(300);
+const SETTLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest time the transition is sampled for.
+const TRANSITION_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SlewSample {
+    /// Time since the new setpoint was sent.
+    pub offset: Duration,
+    pub voltage_v: f64,
+}
+
+/// Result of [`Spd3303x::estimate_slew_rate`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SlewReport {
+    pub channel: Channel,
+    pub from_v: f64,
+    pub to_v: f64,
+    pub samples: Vec<SlewSample>,
+    /// Fitted slope in V/ms, negative for a falling step; `None` when too
+    /// few readings fell inside the 10–90% band.
+    pub slew_v_per_ms: Option<f64>,
+    /// Readings the fit used.
+    pub fit_points: usize,
+    /// Time from the output first passing 10% of the step to it first
+    /// passing 90%.
+    pub transition_time: Option<Duration>,
+}
+
+impl SlewReport {
+    pub fn render_table(&self) -> Table {
+        let slew = self
+            .slew_v_per_ms
+            .map_or_else(|| "too fast to fit".to_string(), |slew| format!("{slew:.4} V/ms"));
+        let transition = self.transition_time.map_or_else(
+            || "not reached".to_string(),
+            |time| format!("{:.1} ms", time.as_secs_f64() * 1e3),
+        );
+        Table::new(["Quantity", self.channel.label()])
+            .row([
+                "Step".to_string(),
+                format!("{:.3} V -> {:.3} V", self.from_v, self.to_v),
+            ])
+            .row(["Slew rate".to_string(), slew])
+            .row(["10-90% time".to_string(), transition])
+            .row(["Fit points".to_string(), self.fit_points.to_string()])
+            .row(["Samples".to_string(), self.samples.len().to_string()])
+    }
+}
+
+impl Spd3303x {
+    /// Step the voltage of `channel` from `from_v` to `to_v` and estimate
+    /// the output's slew rate in V/ms.
+    ///
+    /// The output must be on, with the load the figure is wanted for; it
+    /// is left at `to_v`. Readings bypass the channel's measurement
+    /// pipeline, so a filter cannot slow the edge down.
+    pub async fn estimate_slew_rate(
+        &mut self,
+        channel: Channel,
+        from_v: f64,
+        to_v: f64,
+    ) -> Result<SlewReport> {
+        operation::run("estimate_slew_rate", async {
+            if !self.query_output(channel).await? {
+                return Err(anyhow!("{} must be on to measure its slew rate", channel.label()));
+            }
+            let step = to_v - from_v;
+            if step.abs() < 10.0 * SETTLE_TOLERANCE_V {
+                return Err(anyhow!("a {:.3} V step is too small to time", step.abs()));
+            }
+
+            self.set_voltage(channel, from_v).await?;
+            let settle = SettleSpec::voltage(SETTLE_WINDOW, SETTLE_TOLERANCE_V, SETTLE_TIMEOUT);
+            if !self.wait_for_settled(channel, settle).await?.settled {
+                return Err(anyhow!(
+                    "{} did not settle at {from_v:.3} V; is the load too heavy?",
+                    channel.label()
+                ));
+            }
+
+            let clock = self.clock();
+            let started = clock.now();
+            self.set_voltage(channel, to_v).await?;
+            let mut samples = Vec::new();
+            loop {
+                let voltage_v = self.measure_raw(Quantity::Voltage, Some(channel)).await?;
+                let offset = clock.elapsed(started);
+                samples.push(SlewSample { offset, voltage_v });
+                let remaining = (to_v - voltage_v) / step;
+                if remaining < 0.05 || offset >= TRANSITION_TIMEOUT {
+                    break;
+                }
+            }
+            debug!(
+                "slew: {} samples over {:?}",
+                samples.len(),
+                clock.elapsed(started)
+            );
+            Ok(fit(channel, from_v, to_v, samples))
+        })
+        .await
+    }
+}
+
+fn fit(channel: Channel, from_v: f64, to_v: f64, samples: Vec<SlewSample>) -> SlewReport {
+    let step = to_v - from_v;
+    let progress = |sample: &SlewSample| (sample.voltage_v - from_v) / step;
+    let band: Vec<(f64, f64)> = samples
+        .iter()
+        .filter(|sample| (0.1..=0.9).contains(&progress(sample)))
+        .map(|sample| (sample.offset.as_secs_f64() * 1e3, sample.voltage_v))
+        .collect();
+    let slew_v_per_ms = (band.len() >= MIN_FIT_POINTS)
+        .then(|| least_squares_slope(&band))
+        .flatten();
+    let first_at = |fraction: f64| {
+        samples
+            .iter()
+            .find(|sample| progress(sample) >= fraction)
+            .map(|sample| sample.offset)
+    };
+    let transition_time = match (first_at(0.1), first_at(0.9)) {
+        (Some(start), Some(end)) => Some(end.saturating_sub(start)),
+        _ => None,
+    };
+    SlewReport {
+        channel,
+        from_v,
+        to_v,
+        fit_points: band.len(),
+        slew_v_per_ms,
+        transition_time,
+        samples,
+    }
+}
+
+/// Slope of the least-squares line through `points`; `None` if they all
+/// share one x.
+fn least_squares_slope(points: &[(f64, f64)]) -> Option<f64> {
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|&(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|&(_, y)| y).sum::<f64>() / n;
+    let (mut sxy, mut sxx) = (0.0, 0.0);
+    for &(x, y) in points {
+        sxy += (x - mean_x) * (y - mean_y);
+        sxx += (x - mean_x) * (x - mean_x);
+    }
+    (sxx > 0.0).then(|| sxy / sxx)
+}
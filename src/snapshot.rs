@@ -0,0 +1,544 @@
+//! Point-in-time readback of everything the instrument reports.
+//!
+//! Snapshots also serve as golden states for regression checks: a CI job
+//! stores the state a bring-up script should leave behind (with the
+//! `config` feature, [`InstrumentSnapshot::save`]) and later runs fail
+//! with [`StateMismatch`] through [`Spd3303x::assert_state_matches`] when
+//! the script starts leaving something else.
+//!
+//! [`SnapshotDiff`] lists the settings that differ between two states; the
+//! undo stack (see [`undo`](crate::undo)) is built from them.
+
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use tracing::{debug, warn};
+
+use crate::instrument::{Channel, ChannelStatus, Spd3303x, SystemStatus, TimerEntry, TrackMode};
+use crate::operation;
+use crate::quirks::Feature;
+use crate::settle::SettleSpec;
+use crate::table::Table;
+
+/// Before a snapshot is compared or a recalled state read back, the
+/// readbacks of every live output have to hold still this long, within
+/// the tolerances below, or until the timeout.
+const READBACK_SETTLE_WINDOW: Duration = Duration::from_millis(300);
+const READBACK_SETTLE_TOLERANCE_V: f64 = 0.01;
+const READBACK_SETTLE_TOLERANCE_A: f64 = 0.005;
+const READBACK_SETTLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Status word plus setpoints, readbacks and timer groups of CH1 and CH2.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InstrumentSnapshot {
+    pub taken_at: SystemTime,
+    pub system: SystemStatus,
+    pub ch1: ChannelStatus,
+    pub ch2: ChannelStatus,
+    /// `TIMER:SET` groups of CH1 that could be read; see
+    /// [`Spd3303x::read_all_timers`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub ch1_timers: Vec<TimerEntry>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub ch2_timers: Vec<TimerEntry>,
+}
+
+impl InstrumentSnapshot {
+    #[cfg(feature = "config")]
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        use anyhow::Context;
+
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read snapshot {}", path.display()))?;
+        toml::from_str(&text)
+            .with_context(|| format!("failed to parse snapshot {}", path.display()))
+    }
+
+    #[cfg(feature = "config")]
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        use anyhow::Context;
+
+        let path = path.as_ref();
+        let text = toml::to_string_pretty(self).context("failed to serialize snapshot")?;
+        std::fs::write(path, text)
+            .with_context(|| format!("failed to write snapshot {}", path.display()))
+    }
+
+    /// Everything in which `actual` differs from this snapshot beyond
+    /// `tolerance`. The time taken and the raw status word are not compared.
+    pub fn mismatches(
+        &self,
+        actual: &InstrumentSnapshot,
+        tolerance: &Tolerance,
+    ) -> Vec<Mismatch> {
+        let mut mismatches = Vec::new();
+        let mut flag = |field: String, expected: String, actual: String| {
+            if expected != actual {
+                mismatches.push(Mismatch {
+                    field,
+                    expected,
+                    actual,
+                });
+            }
+        };
+        let track = |status: &SystemStatus| format!("{:?}", status.track_mode);
+        flag("track mode".to_string(), track(&self.system), track(&actual.system));
+        for (name, expected, got) in [
+            ("timer 1", self.system.timer1_on, actual.system.timer1_on),
+            ("timer 2", self.system.timer2_on, actual.system.timer2_on),
+        ] {
+            flag(name.to_string(), expected.to_string(), got.to_string());
+        }
+        for channel in Channel::programmable() {
+            let label = channel.label();
+            let on = |status: &SystemStatus| format!("{:?}", status.output_on(channel));
+            flag(format!("{label} output"), on(&self.system), on(&actual.system));
+            let wave = |status: &SystemStatus| format!("{:?}", status.wave_display_on(channel));
+            flag(format!("{label} wave display"), wave(&self.system), wave(&actual.system));
+            if tolerance.regulation_mode {
+                let mode = |status: &SystemStatus| format!("{:?}", status.regulation_mode(channel));
+                flag(format!("{label} mode"), mode(&self.system), mode(&actual.system));
+            }
+        }
+
+        for channel in Channel::programmable() {
+            let (Some(golden), Some(current)) = (self.channel(channel), actual.channel(channel))
+            else {
+                continue;
+            };
+            let label = channel.label();
+            let quantities: [(&str, fn(&ChannelStatus) -> f64, Option<f64>, &str); 5] = [
+                ("set voltage", |s| s.setpoint_voltage_v, tolerance.setpoint_v, "V"),
+                ("set current", |s| s.setpoint_current_a, tolerance.setpoint_a, "A"),
+                ("voltage", |s| s.measured_voltage_v, tolerance.measured_v, "V"),
+                ("current", |s| s.measured_current_a, tolerance.measured_a, "A"),
+                ("power", |s| s.measured_power_w, tolerance.measured_w, "W"),
+            ];
+            for (name, value, allowed, unit) in quantities {
+                let Some(allowed) = allowed else {
+                    continue;
+                };
+                let (expected, got) = (value(golden), value(current));
+                // Written so that NaN readings count as a mismatch.
+                let within = (expected - got).abs() <= allowed;
+                if !within {
+                    mismatches.push(Mismatch {
+                        field: format!("{label} {name}"),
+                        expected: format!("{expected:.3} {unit} ± {allowed}"),
+                        actual: format!("{got:.3} {unit}"),
+                    });
+                }
+            }
+        }
+
+        for channel in Channel::programmable() {
+            for golden in self.timers(channel) {
+                let field = format!("{} timer group {}", channel.label(), golden.group);
+                let current = actual.timer_group(channel, golden.group);
+                let matches = current.is_some_and(|current| {
+                    let close = |allowed: Option<f64>, a: f64, b: f64| {
+                        allowed.is_none_or(|allowed| (a - b).abs() <= allowed)
+                    };
+                    close(tolerance.setpoint_v, golden.voltage_v, current.voltage_v)
+                        && close(tolerance.setpoint_a, golden.current_a, current.current_a)
+                        && golden.duration == current.duration
+                });
+                if !matches {
+                    mismatches.push(Mismatch {
+                        field,
+                        expected: timer_entry(Some(golden)),
+                        actual: timer_entry(current),
+                    });
+                }
+            }
+        }
+        mismatches
+    }
+
+    /// Settings that differ in `after`: setpoints, outputs, waveform
+    /// display, track mode, timer groups and timer state.
+    pub fn diff(&self, after: &InstrumentSnapshot) -> SnapshotDiff {
+        let mut settings = Vec::new();
+        if let Some(mode) = self.system.track_mode {
+            settings.push(Setting::TrackMode(mode));
+        }
+        for channel in Channel::programmable() {
+            if let Some(status) = self.channel(channel) {
+                settings.push(Setting::Voltage(channel, status.setpoint_voltage_v));
+                settings.push(Setting::Current(channel, status.setpoint_current_a));
+            }
+            if let Some(on) = self.system.output_on(channel) {
+                settings.push(Setting::Output(channel, on));
+            }
+            if let Some(on) = self.system.wave_display_on(channel) {
+                settings.push(Setting::WaveDisplay(channel, on));
+            }
+            for entry in self.timers(channel) {
+                settings.push(Setting::TimerGroup(channel, *entry));
+            }
+            if let Some(on) = self.system.timer_on(channel) {
+                settings.push(Setting::Timer(channel, on));
+            }
+        }
+        let changes = settings
+            .into_iter()
+            .filter_map(|before| {
+                let after = before.read(after)?;
+                (after != before).then_some(SettingChange { before, after })
+            })
+            .collect();
+        SnapshotDiff { changes }
+    }
+
+    pub fn channel(&self, channel: Channel) -> Option<&ChannelStatus> {
+        match channel {
+            Channel::Ch1 => Some(&self.ch1),
+            Channel::Ch2 => Some(&self.ch2),
+            Channel::Ch3 => None,
+        }
+    }
+
+    /// Timer groups of `channel` in this snapshot; none for CH3.
+    pub fn timers(&self, channel: Channel) -> &[TimerEntry] {
+        match channel {
+            Channel::Ch1 => &self.ch1_timers,
+            Channel::Ch2 => &self.ch2_timers,
+            Channel::Ch3 => &[],
+        }
+    }
+
+    pub fn timer_group(&self, channel: Channel, group: u8) -> Option<&TimerEntry> {
+        self.timers(channel).iter().find(|entry| entry.group == group)
+    }
+
+    /// Channels whose output is on, as far as the status word reports.
+    pub fn outputs_on(&self) -> Vec<Channel> {
+        Channel::programmable()
+            .filter(|&channel| self.system.output_on(channel) == Some(true))
+            .collect()
+    }
+
+    pub fn render_table(&self) -> Table {
+        let row = |name: &str, value: fn(&ChannelStatus) -> f64, unit: &str| {
+            [
+                name.to_string(),
+                format!("{:.3} {unit}", value(&self.ch1)),
+                format!("{:.3} {unit}", value(&self.ch2)),
+            ]
+        };
+        Table::new(["Setting", "CH1", "CH2"])
+            .row(row("Set voltage", |s| s.setpoint_voltage_v, "V"))
+            .row(row("Set current", |s| s.setpoint_current_a, "A"))
+            .row(row("Voltage", |s| s.measured_voltage_v, "V"))
+            .row(row("Current", |s| s.measured_current_a, "A"))
+            .row(row("Power", |s| s.measured_power_w, "W"))
+    }
+}
+
+/// One instrument setting with its value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Setting {
+    Voltage(Channel, f64),
+    Current(Channel, f64),
+    /// Whether the output is on.
+    Output(Channel, bool),
+    /// Whether the waveform display is on.
+    WaveDisplay(Channel, bool),
+    TrackMode(TrackMode),
+    /// One `TIMER:SET` group; the entry names the group.
+    TimerGroup(Channel, TimerEntry),
+    /// Whether the timer runs.
+    Timer(Channel, bool),
+}
+
+impl Setting {
+    /// Whether `other` is the same setting, whatever its value.
+    pub fn same_target(&self, other: &Setting) -> bool {
+        match (self, other) {
+            (Setting::Voltage(a, _), Setting::Voltage(b, _))
+            | (Setting::Current(a, _), Setting::Current(b, _))
+            | (Setting::Output(a, _), Setting::Output(b, _))
+            | (Setting::WaveDisplay(a, _), Setting::WaveDisplay(b, _))
+            | (Setting::Timer(a, _), Setting::Timer(b, _)) => a == b,
+            (Setting::TimerGroup(a, x), Setting::TimerGroup(b, y)) => a == b && x.group == y.group,
+            (Setting::TrackMode(_), Setting::TrackMode(_)) => true,
+            _ => false,
+        }
+    }
+
+    /// The value of this setting in `snapshot`. CH3's output is not
+    /// reported and reads as off; CH3 setpoints, waveform display and
+    /// timer, an unknown track mode and a timer group that could not be
+    /// read are `None`.
+    pub fn read(&self, snapshot: &InstrumentSnapshot) -> Option<Setting> {
+        Some(match *self {
+            Setting::Voltage(channel, _) => {
+                Setting::Voltage(channel, snapshot.channel(channel)?.setpoint_voltage_v)
+            }
+            Setting::Current(channel, _) => {
+                Setting::Current(channel, snapshot.channel(channel)?.setpoint_current_a)
+            }
+            Setting::Output(channel, _) => {
+                Setting::Output(channel, snapshot.system.output_on(channel).unwrap_or(false))
+            }
+            Setting::WaveDisplay(channel, _) => {
+                Setting::WaveDisplay(channel, snapshot.system.wave_display_on(channel)?)
+            }
+            Setting::TrackMode(_) => Setting::TrackMode(snapshot.system.track_mode?),
+            Setting::TimerGroup(channel, entry) => {
+                Setting::TimerGroup(channel, *snapshot.timer_group(channel, entry.group)?)
+            }
+            Setting::Timer(channel, _) => {
+                Setting::Timer(channel, snapshot.system.timer_on(channel)?)
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SettingChange {
+    pub before: Setting,
+    pub after: Setting,
+}
+
+/// Settings changed between two states.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SnapshotDiff {
+    pub changes: Vec<SettingChange>,
+}
+
+impl SnapshotDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// The diff that takes the state back.
+    pub fn inverse(&self) -> SnapshotDiff {
+        SnapshotDiff {
+            changes: self
+                .changes
+                .iter()
+                .rev()
+                .map(|change| SettingChange {
+                    before: change.after,
+                    after: change.before,
+                })
+                .collect(),
+        }
+    }
+
+    /// Target values in an order that is safe for the DUT: outputs and
+    /// timers going off first, then the track mode and setpoints (timer
+    /// groups among them), outputs and timers coming on last.
+    pub fn ordered_targets(&self) -> Vec<Setting> {
+        let targets = self.changes.iter().map(|change| change.after);
+        let rank = |setting: &Setting| match setting {
+            Setting::Output(_, false) | Setting::Timer(_, false) => 0,
+            Setting::TrackMode(_) | Setting::WaveDisplay(..) => 1,
+            Setting::Voltage(..) | Setting::Current(..) | Setting::TimerGroup(..) => 2,
+            Setting::Output(_, true) | Setting::Timer(_, true) => 3,
+        };
+        let mut targets: Vec<Setting> = targets.collect();
+        targets.sort_by_key(rank);
+        targets
+    }
+}
+
+/// Allowed deviations when comparing snapshots; `None` skips a quantity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Tolerance {
+    pub setpoint_v: Option<f64>,
+    pub setpoint_a: Option<f64>,
+    pub measured_v: Option<f64>,
+    pub measured_a: Option<f64>,
+    pub measured_w: Option<f64>,
+    /// Compare CV/CC, which depends on the load.
+    pub regulation_mode: bool,
+}
+
+impl Tolerance {
+    /// Setpoints to 1 mV / 1 mA, readbacks and regulation mode ignored:
+    /// what a script controls, independent of the DUT.
+    pub fn setpoints() -> Self {
+        Self {
+            setpoint_v: Some(0.001),
+            setpoint_a: Some(0.001),
+            measured_v: None,
+            measured_a: None,
+            measured_w: None,
+            regulation_mode: false,
+        }
+    }
+
+    /// Also compare readbacks, e.g. against the simulator or a fixed load.
+    pub fn with_readbacks(self, volts: f64, amps: f64, watts: f64) -> Self {
+        Self {
+            measured_v: Some(volts),
+            measured_a: Some(amps),
+            measured_w: Some(watts),
+            regulation_mode: true,
+            ..self
+        }
+    }
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self::setpoints()
+    }
+}
+
+/// One field that differs from the golden state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mismatch {
+    pub field: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+/// The instrument is not in the golden state.
+#[derive(Debug, Clone)]
+pub struct StateMismatch {
+    pub mismatches: Vec<Mismatch>,
+    pub actual: InstrumentSnapshot,
+}
+
+impl fmt::Display for StateMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "instrument state differs from the golden state:")?;
+        for mismatch in &self.mismatches {
+            write!(
+                f,
+                "\n  {}: expected {}, got {}",
+                mismatch.field, mismatch.expected, mismatch.actual
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for StateMismatch {}
+
+fn timer_entry(entry: Option<&TimerEntry>) -> String {
+    match entry {
+        Some(entry) => format!(
+            "{:.3} V / {:.3} A for {:?}",
+            entry.voltage_v, entry.current_a, entry.duration
+        ),
+        None => "unreadable".to_string(),
+    }
+}
+
+impl Spd3303x {
+    /// Read a snapshot once the live outputs have settled and fail with
+    /// [`StateMismatch`] if it differs from `golden` beyond `tolerance`;
+    /// otherwise return it.
+    pub async fn assert_state_matches(
+        &mut self,
+        golden: &InstrumentSnapshot,
+        tolerance: Tolerance,
+    ) -> Result<InstrumentSnapshot> {
+        self.settle_readbacks().await?;
+        let actual = self.snapshot().await?;
+        let mismatches = golden.mismatches(&actual, &tolerance);
+        if mismatches.is_empty() {
+            Ok(actual)
+        } else {
+            Err(StateMismatch { mismatches, actual }.into())
+        }
+    }
+
+    /// Read the status word, both programmable channels and their timer
+    /// groups. Timer groups that cannot be read are left out with a
+    /// warning.
+    pub async fn snapshot(&mut self) -> Result<InstrumentSnapshot> {
+        operation::run("snapshot", self.snapshot_steps(true)).await
+    }
+
+    /// A snapshot without the timer groups, for callers that only read
+    /// back what a [`Transaction`](crate::transaction::Transaction) can
+    /// stage.
+    pub(crate) async fn settings_snapshot(&mut self) -> Result<InstrumentSnapshot> {
+        operation::run("snapshot", self.snapshot_steps(false)).await
+    }
+
+    /// `*RCL slot`, then read back what the instrument is doing now.
+    ///
+    /// Recalled states carry the output enable flags on some firmware, so
+    /// outputs may be live afterwards; each one that is gets a warning.
+    pub async fn recall_and_verify(&mut self, slot: u8) -> Result<InstrumentSnapshot> {
+        operation::run("recall_and_verify", async {
+            self.recall_state(slot).await?;
+            self.settle_readbacks().await?;
+            let snapshot = self.snapshot_steps(true).await?;
+            for channel in snapshot.outputs_on() {
+                warn!("recall: slot {slot} left {} output ON", channel.label());
+            }
+            debug!("recall: slot {slot} applied");
+            Ok(snapshot)
+        })
+        .await
+    }
+
+    /// Wait for the voltage and current of each output that is on to
+    /// settle. One that does not is warned about and read back anyway.
+    async fn settle_readbacks(&mut self) -> Result<()> {
+        let system = self.system_status().await?;
+        for channel in Channel::programmable() {
+            if system.output_on(channel) != Some(true) {
+                continue;
+            }
+            for spec in [
+                SettleSpec::voltage(
+                    READBACK_SETTLE_WINDOW,
+                    READBACK_SETTLE_TOLERANCE_V,
+                    READBACK_SETTLE_TIMEOUT,
+                ),
+                SettleSpec::current(
+                    READBACK_SETTLE_WINDOW,
+                    READBACK_SETTLE_TOLERANCE_A,
+                    READBACK_SETTLE_TIMEOUT,
+                ),
+            ] {
+                self.wait_for_settled(channel, spec).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn snapshot_steps(&mut self, timers: bool) -> Result<InstrumentSnapshot> {
+        let mut snapshot = InstrumentSnapshot {
+            taken_at: SystemTime::now(),
+            system: self.system_status().await?,
+            ch1: self.channel_status(Channel::Ch1).await?,
+            ch2: self.channel_status(Channel::Ch2).await?,
+            ch1_timers: Vec::new(),
+            ch2_timers: Vec::new(),
+        };
+        if timers {
+            snapshot.ch1_timers = self.snapshot_timers(Channel::Ch1).await?;
+            snapshot.ch2_timers = self.snapshot_timers(Channel::Ch2).await?;
+        }
+        Ok(snapshot)
+    }
+
+    async fn snapshot_timers(&mut self, channel: Channel) -> Result<Vec<TimerEntry>> {
+        if let Err(e) = self.gate(Feature::Timer) {
+            debug!("snapshot: timer groups left out: {e:#}");
+            return Ok(Vec::new());
+        }
+        let groups = self.read_all_timers(channel).await?;
+        for (group, error) in &groups.errors {
+            warn!("snapshot: {} timer group {group} left out: {error}", channel.label());
+        }
+        Ok(groups.entries.into_iter().flatten().collect())
+    }
+}
@@ -0,0 +1,200 @@
+//! Transparent SCPI proxy that decodes the traffic passing through it.
+//!
+//! Point third-party software at the proxy instead of the supply's raw
+//! socket port; bytes are forwarded unchanged in both directions while every
+//! complete line is reported as a [`Frame`] with its timing.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info, warn};
+
+/// Raw SCPI socket port of the SPD3303X.
+pub const DEVICE_PORT: u16 = 5025;
+
+const CHUNK: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameDirection {
+    ToDevice,
+    FromDevice,
+}
+
+/// One line of SCPI traffic.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// Sequence number of the client connection, starting at 1.
+    pub connection: u64,
+    pub direction: FrameDirection,
+    /// Time since the client connected.
+    pub offset: Duration,
+    /// For device frames, time since the last line sent to the device.
+    pub latency: Option<Duration>,
+    /// The line without its terminator.
+    pub text: String,
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let arrow = match self.direction {
+            FrameDirection::ToDevice => "->",
+            FrameDirection::FromDevice => "<-",
+        };
+        write!(
+            f,
+            "[{:>10.3} ms] #{} {arrow} {}",
+            self.offset.as_secs_f64() * 1000.0,
+            self.connection,
+            self.text
+        )?;
+        if let Some(latency) = self.latency {
+            write!(f, "  ({:.3} ms)", latency.as_secs_f64() * 1000.0)?;
+        }
+        Ok(())
+    }
+}
+
+type FrameSink = Arc<dyn Fn(&Frame) + Send + Sync>;
+
+/// Forwards client connections to one device and reports their frames.
+#[derive(Clone)]
+pub struct Sniffer {
+    device: String,
+    on_frame: FrameSink,
+    connections: Arc<AtomicU64>,
+}
+
+impl Sniffer {
+    /// `device` is the `host:port` of the supply's raw socket.
+    pub fn new(
+        device: impl Into<String>,
+        on_frame: impl Fn(&Frame) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            device: device.into(),
+            on_frame: Arc::new(on_frame),
+            connections: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Accept clients forever, opening a device connection for each.
+    pub async fn serve(&self, listener: TcpListener) -> Result<()> {
+        info!(
+            "sniff: listening on {}, forwarding to {}",
+            listener.local_addr()?,
+            self.device
+        );
+        loop {
+            let (client, peer) = listener.accept().await?;
+            let sniffer = self.clone();
+            tokio::spawn(async move {
+                let device = match TcpStream::connect(&sniffer.device).await {
+                    Ok(device) => device,
+                    Err(e) => {
+                        warn!("sniff: cannot reach {} for {peer}: {e}", sniffer.device);
+                        return;
+                    }
+                };
+                match sniffer.proxy(client, device).await {
+                    Ok(()) => debug!("sniff: {peer} disconnected"),
+                    Err(e) => warn!("sniff: {peer} dropped: {e:#}"),
+                }
+            });
+        }
+    }
+
+    /// Relay between one client and one device connection until either
+    /// side closes.
+    pub async fn proxy<C, D>(&self, client: C, device: D) -> Result<()>
+    where
+        C: AsyncRead + AsyncWrite + Unpin,
+        D: AsyncRead + AsyncWrite + Unpin,
+    {
+        let connection = self.connections.fetch_add(1, Ordering::Relaxed) + 1;
+        let started = Instant::now();
+        let last_request = Mutex::new(None);
+        let (client_rx, client_tx) = tokio::io::split(client);
+        let (device_rx, device_tx) = tokio::io::split(device);
+
+        let pump = |direction: FrameDirection| Pump {
+            sniffer: self,
+            connection,
+            direction,
+            started,
+            last_request: &last_request,
+        };
+        let to_device = pump(FrameDirection::ToDevice);
+        let from_device = pump(FrameDirection::FromDevice);
+        tokio::try_join!(
+            to_device.run(client_rx, device_tx),
+            from_device.run(device_rx, client_tx),
+        )?;
+        Ok(())
+    }
+}
+
+struct Pump<'a> {
+    sniffer: &'a Sniffer,
+    connection: u64,
+    direction: FrameDirection,
+    started: Instant,
+    last_request: &'a Mutex<Option<Instant>>,
+}
+
+impl Pump<'_> {
+    async fn run<R, W>(&self, mut from: R, mut to: W) -> Result<()>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let mut buf = vec![0u8; CHUNK];
+        let mut pending = Vec::new();
+        loop {
+            let n = from.read(&mut buf).await.context("read failed")?;
+            if n == 0 {
+                if !pending.is_empty() {
+                    self.emit(&pending);
+                }
+                to.shutdown().await.ok();
+                return Ok(());
+            }
+            to.write_all(&buf[..n]).await.context("forward failed")?;
+            to.flush().await?;
+
+            pending.extend_from_slice(&buf[..n]);
+            while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = pending.drain(..=end).collect();
+                self.emit(&line);
+            }
+        }
+    }
+
+    fn emit(&self, line: &[u8]) {
+        let now = Instant::now();
+        let mut last_request = self.last_request.lock().unwrap();
+        let latency = match self.direction {
+            FrameDirection::ToDevice => {
+                *last_request = Some(now);
+                None
+            }
+            FrameDirection::FromDevice => last_request.map(|at| now - at),
+        };
+        drop(last_request);
+
+        let text = String::from_utf8_lossy(line)
+            .trim_end_matches(['\r', '\n'])
+            .to_string();
+        (self.sniffer.on_frame)(&Frame {
+            connection: self.connection,
+            direction: self.direction,
+            offset: now - self.started,
+            latency,
+            text,
+        });
+    }
+}
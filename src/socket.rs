@@ -0,0 +1,120 @@
+//! SCPI over the instrument's raw TCP socket.
+//!
+//! The SPD3303X also listens on port 5025
+//! ([`sniff::DEVICE_PORT`](crate::sniff::DEVICE_PORT)), where commands are
+//! plain LF-terminated lines and replies come back the same way. Some
+//! SPD3303X-E firmware handles this more reliably than VXI-11.
+//!
+//! A query given up on before its reply arrived, by [`READ_TIMEOUT`] or by
+//! the caller, leaves that reply to come in later. The next query then
+//! first discards whatever is received, so that it does not read the old
+//! reply as its own.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tracing::debug;
+
+use crate::escalation::CommandTimedOut;
+use crate::transport::{Transport, TransportFuture};
+
+/// How long to wait for a complete reply line.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a drain waits for a late reply still on its way.
+const DRAIN_WAIT: Duration = Duration::from_millis(200);
+
+pub(crate) struct SocketClient {
+    stream: BufReader<TcpStream>,
+    /// A query was sent whose reply has not been read.
+    stale: bool,
+}
+
+impl SocketClient {
+    pub(crate) async fn connect(addr: impl ToSocketAddrs) -> Result<Self> {
+        let stream = TcpStream::connect(addr)
+            .await
+            .context("failed to connect to the SCPI socket")?;
+        stream.set_nodelay(true)?;
+        debug!("socket: connected to {}", stream.peer_addr()?);
+        Ok(Self {
+            stream: BufReader::new(stream),
+            stale: false,
+        })
+    }
+
+    pub(crate) async fn write(&mut self, command: &str) -> Result<()> {
+        let stream = self.stream.get_mut();
+        stream
+            .write_all(command.trim_end_matches(['\r', '\n']).as_bytes())
+            .await?;
+        stream.write_all(b"\n").await?;
+        stream.flush().await?;
+        Ok(())
+    }
+
+    pub(crate) async fn query(&mut self, command: &str) -> Result<String> {
+        if self.stale {
+            self.drain().await?;
+        }
+        self.write(command).await?;
+        // Set until the reply is in, so that a timeout or a cancelled
+        // query leaves the next one to drain.
+        self.stale = true;
+        let mut line = Vec::new();
+        let read = tokio::time::timeout(READ_TIMEOUT, self.stream.read_until(b'\n', &mut line))
+            .await
+            .map_err(|_| CommandTimedOut::once(command, READ_TIMEOUT))??;
+        if read == 0 {
+            return Err(anyhow!("instrument closed the SCPI socket"));
+        }
+        self.stale = false;
+        Ok(String::from_utf8(line)?)
+    }
+
+    /// Discard everything received so far and whatever else arrives within
+    /// [`DRAIN_WAIT`].
+    pub(crate) async fn drain(&mut self) -> Result<()> {
+        let mut discarded = Vec::new();
+        while let Ok(read) =
+            tokio::time::timeout(DRAIN_WAIT, self.stream.read_until(b'\n', &mut discarded)).await
+        {
+            if read? == 0 {
+                return Err(anyhow!("instrument closed the SCPI socket"));
+            }
+        }
+        if !discarded.is_empty() {
+            debug!("socket: discarded {} bytes of stale replies", discarded.len());
+        }
+        self.stale = false;
+        Ok(())
+    }
+
+    pub(crate) async fn close(&mut self) -> Result<()> {
+        self.stream.get_mut().shutdown().await?;
+        Ok(())
+    }
+}
+
+impl Transport for SocketClient {
+    fn write<'a>(&'a mut self, command: &'a str) -> TransportFuture<'a, ()> {
+        Box::pin(SocketClient::write(self, command))
+    }
+
+    fn query<'a>(&'a mut self, command: &'a str) -> TransportFuture<'a, String> {
+        Box::pin(SocketClient::query(self, command))
+    }
+
+    /// The raw socket has no device clear; draining it serves the same end.
+    fn clear(&mut self) -> TransportFuture<'_, bool> {
+        Box::pin(async move {
+            self.drain().await?;
+            Ok(true)
+        })
+    }
+
+    fn close(&mut self) -> TransportFuture<'_, ()> {
+        Box::pin(SocketClient::close(self))
+    }
+}
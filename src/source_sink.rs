@@ -0,0 +1,57 @@
+//! Coordination of this supply with an electronic load.
+//!
+//! The load driver lives outside this crate; it only has to implement
+//! [`ElectronicLoad`]. A [`SourceSink`] pairs it with an [`Spd3303x`] so a
+//! single [`Sequence`](crate::sequence::Sequence) can interleave source
+//! setpoints and load steps, see
+//! [`SequenceRunner::run_source_sink`](crate::sequence::SequenceRunner::run_source_sink).
+
+use std::future::Future;
+
+use anyhow::Result;
+
+use crate::instrument::Spd3303x;
+
+/// Operating mode and setpoint of an electronic load.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LoadSetting {
+    ConstantCurrent(f64),
+    ConstantVoltage(f64),
+    ConstantResistance(f64),
+    ConstantPower(f64),
+}
+
+/// Minimal control surface of an electronic load driver.
+pub trait ElectronicLoad: Send {
+    /// Select the mode and program its setpoint.
+    fn set_load(&mut self, setting: LoadSetting) -> impl Future<Output = Result<()>> + Send;
+
+    /// Switch the load input on or off.
+    fn set_input(&mut self, on: bool) -> impl Future<Output = Result<()>> + Send;
+}
+
+/// Supply and load driven together.
+pub struct SourceSink<'a, L> {
+    pub source: &'a mut Spd3303x,
+    pub sink: &'a mut L,
+}
+
+impl<'a, L: ElectronicLoad> SourceSink<'a, L> {
+    pub fn new(source: &'a mut Spd3303x, sink: &'a mut L) -> Self {
+        Self { source, sink }
+    }
+}
+
+/// Stands in for a load where a sequence only drives the supply.
+pub(crate) enum NoLoad {}
+
+impl ElectronicLoad for NoLoad {
+    fn set_load(&mut self, _setting: LoadSetting) -> impl Future<Output = Result<()>> + Send {
+        std::future::ready(match *self {})
+    }
+
+    fn set_input(&mut self, _on: bool) -> impl Future<Output = Result<()>> + Send {
+        std::future::ready(match *self {})
+    }
+}
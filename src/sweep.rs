@@ -0,0 +1,282 @@
+//! Corner testing: one [`Sequence`] per combination of parameters.
+//!
+//! A [`Matrix`] lists parameters with their values, numeric (a supply
+//! voltage) or tags (a chamber temperature the operator sets). The runner
+//! builds a sequence for every combination with a caller-supplied plan
+//! function, runs them one after another and collects the results in a
+//! single [`MatrixReport`]:
+//!
+//! ```ignore
+//! let matrix = Matrix::new()
+//!     .tags("temperature", ["cold", "room", "hot"])
+//!     .numbers("voltage", [3.0, 3.3, 3.6]);
+//! let report = runner
+//!     .run_matrix(&mut inst, &matrix, |corner| {
+//!         let volts = corner.number("voltage").unwrap_or(3.3);
+//!         let settled = SettleSpec::voltage(window, 0.01, timeout);
+//!         Ok(Sequence::new().step(
+//!             Step::new()
+//!                 .voltage(Channel::Ch1, volts)
+//!                 .wait_for_settled(Channel::Ch1, settled)
+//!                 .hold(hold),
+//!         ))
+//!     })
+//!     .await?;
+//! print!("{}", report.render_table());
+//! ```
+//!
+//! Have each step wait for its readings to settle (see
+//! [`settle`](crate::settle)) rather than hold a fixed time before it
+//! measures, so a slow corner is not read mid-transition.
+//!
+//! The first parameter varies slowest, so tags that take long to change
+//! belong first.
+
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+use tracing::{info, warn};
+
+use crate::instrument::Spd3303x;
+use crate::operation;
+use crate::sequence::{Sequence, SequenceReport, SequenceRunner};
+use crate::table::Table;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ParamValue {
+    Number(f64),
+    Tag(String),
+}
+
+impl fmt::Display for ParamValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParamValue::Number(value) => write!(f, "{value}"),
+            ParamValue::Tag(tag) => write!(f, "{tag}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Matrix {
+    parameters: Vec<(String, Vec<ParamValue>)>,
+}
+
+impl Matrix {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn numbers(self, name: impl Into<String>, values: impl IntoIterator<Item = f64>) -> Self {
+        self.parameter(name, values.into_iter().map(ParamValue::Number))
+    }
+
+    pub fn tags<S: Into<String>>(
+        self,
+        name: impl Into<String>,
+        values: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.parameter(name, values.into_iter().map(|tag| ParamValue::Tag(tag.into())))
+    }
+
+    /// Add a parameter; one of the same name is replaced.
+    pub fn parameter(
+        mut self,
+        name: impl Into<String>,
+        values: impl IntoIterator<Item = ParamValue>,
+    ) -> Self {
+        let name = name.into();
+        let values = values.into_iter().collect();
+        match self.parameters.iter_mut().find(|(existing, _)| *existing == name) {
+            Some((_, existing)) => *existing = values,
+            None => self.parameters.push((name, values)),
+        }
+        self
+    }
+
+    /// Number of combinations; 0 if any parameter has no values.
+    pub fn len(&self) -> usize {
+        if self.parameters.is_empty() {
+            return 0;
+        }
+        self.parameters.iter().map(|(_, values)| values.len()).product()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// All combinations, the last parameter varying fastest.
+    pub fn corners(&self) -> Vec<Corner> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        let mut corners = vec![Corner::default()];
+        for (name, values) in &self.parameters {
+            corners = corners
+                .into_iter()
+                .flat_map(|corner| {
+                    values.iter().map(move |value| {
+                        let mut corner = corner.clone();
+                        corner.values.push((name.clone(), value.clone()));
+                        corner
+                    })
+                })
+                .collect();
+        }
+        corners
+    }
+}
+
+/// One combination of parameter values.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Corner {
+    pub values: Vec<(String, ParamValue)>,
+}
+
+impl Corner {
+    pub fn get(&self, name: &str) -> Option<&ParamValue> {
+        self.values
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value)
+    }
+
+    pub fn number(&self, name: &str) -> Option<f64> {
+        match self.get(name)? {
+            ParamValue::Number(value) => Some(*value),
+            ParamValue::Tag(_) => None,
+        }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        match self.get(name)? {
+            ParamValue::Tag(tag) => Some(tag),
+            ParamValue::Number(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for Corner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, (name, value)) in self.values.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{name}={value}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of one corner: the sequence report, or why it did not complete.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CornerReport {
+    pub corner: Corner,
+    pub report: Option<SequenceReport>,
+    pub error: Option<String>,
+}
+
+impl CornerReport {
+    pub fn passed(&self) -> bool {
+        self.error.is_none() && self.report.as_ref().is_some_and(|report| !report.aborted)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MatrixReport {
+    pub corners: Vec<CornerReport>,
+    /// Whether the runner was aborted; later corners were not run.
+    pub aborted: bool,
+}
+
+impl MatrixReport {
+    pub fn passed(&self) -> usize {
+        self.corners.iter().filter(|corner| corner.passed()).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.corners.len() - self.passed()
+    }
+
+    pub fn render_table(&self) -> Table {
+        let mut table = Table::new(["Corner", "Result", "Steps", "Worst timing error"]);
+        for corner in &self.corners {
+            let (steps, worst) = match &corner.report {
+                Some(report) => (
+                    report.steps.len().to_string(),
+                    report
+                        .steps
+                        .iter()
+                        .map(|step| step.error_s().abs())
+                        .reduce(f64::max)
+                        .map_or_else(
+                            || "-".to_string(),
+                            |error| format!("{:.1} ms", error * 1e3),
+                        ),
+                ),
+                None => ("-".to_string(), "-".to_string()),
+            };
+            let result = match (&corner.error, &corner.report) {
+                (Some(error), _) => format!("FAIL: {error}"),
+                (None, Some(report)) if report.aborted => "ABORTED".to_string(),
+                _ => "PASS".to_string(),
+            };
+            table.push_row([corner.corner.to_string(), result, steps, worst]);
+        }
+        table
+    }
+}
+
+impl SequenceRunner {
+    /// Run the sequence `plan` builds for each corner of `matrix`. A corner
+    /// whose plan or sequence fails is recorded and the next one runs; an
+    /// abort through the runner's [`AbortHandle`](crate::sequence::AbortHandle)
+    /// ends the whole matrix.
+    pub async fn run_matrix(
+        &self,
+        inst: &mut Spd3303x,
+        matrix: &Matrix,
+        mut plan: impl FnMut(&Corner) -> Result<Sequence>,
+    ) -> Result<MatrixReport> {
+        let corners = matrix.corners();
+        if corners.is_empty() {
+            return Err(anyhow!("parameter matrix has no combinations"));
+        }
+        operation::run("sweep_matrix", async {
+            let total = corners.len();
+            let mut report = MatrixReport::default();
+            for (index, corner) in corners.into_iter().enumerate() {
+                info!("sweep: corner {} of {total}: {corner}", index + 1);
+                let result = match plan(&corner) {
+                    Ok(sequence) => self.run(inst, &sequence).await,
+                    Err(e) => Err(e.context("building the sequence failed")),
+                };
+                let (sequence_report, error) = match result {
+                    Ok(sequence_report) => (Some(sequence_report), None),
+                    Err(e) => {
+                        warn!("sweep: corner {corner} failed: {e:#}");
+                        (None, Some(format!("{e:#}")))
+                    }
+                };
+                let aborted = sequence_report.as_ref().is_some_and(|r| r.aborted);
+                report.corners.push(CornerReport {
+                    corner,
+                    report: sequence_report,
+                    error,
+                });
+                if aborted {
+                    report.aborted = true;
+                    break;
+                }
+            }
+            Ok(report)
+        })
+        .await
+    }
+}
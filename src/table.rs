@@ -0,0 +1,95 @@
+use std::fmt;
+
+/// Minimal ASCII table used to render statuses for humans.
+///
+/// ```text
+/// +----------+---------+
+/// | Quantity | Value   |
+/// +----------+---------+
+/// | Voltage  | 5.000 V |
+/// +----------+---------+
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Table {
+    header: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new<I, S>(header: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            header: header.into_iter().map(Into::into).collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn row<I, S>(mut self, cells: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.push_row(cells);
+        self
+    }
+
+    pub fn push_row<I, S>(&mut self, cells: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.rows.push(cells.into_iter().map(Into::into).collect());
+    }
+
+    fn column_widths(&self) -> Vec<usize> {
+        let columns = self
+            .rows
+            .iter()
+            .map(Vec::len)
+            .chain(std::iter::once(self.header.len()))
+            .max()
+            .unwrap_or(0);
+        let mut widths = vec![0; columns];
+        for line in std::iter::once(&self.header).chain(&self.rows) {
+            for (width, cell) in widths.iter_mut().zip(line) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        widths
+    }
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let widths = self.column_widths();
+        let separator = |f: &mut fmt::Formatter<'_>| -> fmt::Result {
+            for width in &widths {
+                write!(f, "+{}", "-".repeat(width + 2))?;
+            }
+            writeln!(f, "+")
+        };
+        let line = |f: &mut fmt::Formatter<'_>, cells: &[String]| -> fmt::Result {
+            for (i, &width) in widths.iter().enumerate() {
+                let cell = cells.get(i).map(String::as_str).unwrap_or("");
+                write!(f, "| {cell:<width$} ")?;
+            }
+            writeln!(f, "|")
+        };
+
+        separator(f)?;
+        if !self.header.is_empty() {
+            line(f, &self.header)?;
+            separator(f)?;
+        }
+        for row in &self.rows {
+            line(f, row)?;
+        }
+        if !self.rows.is_empty() {
+            separator(f)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,259 @@
+//! Ownership of background tasks working on a shared instrument.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use tokio::sync::{oneshot, watch, Mutex};
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+use crate::clock::Ticker;
+use crate::instrument::{Channel, OutputState, Spd3303x};
+use crate::monitor::{Monitor, MonitorConfig};
+
+/// Attempts at switching a timed output off before giving up.
+const OFF_ATTEMPTS: u32 = 3;
+const OFF_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// Handed to tasks spawned through [`Spd3303xTasks::spawn`]; resolves once
+/// shutdown has been requested.
+#[derive(Debug, Clone)]
+pub struct ShutdownSignal(watch::Receiver<bool>);
+
+impl ShutdownSignal {
+    pub fn is_set(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    pub async fn wait(&mut self) {
+        // An error means the sender is gone, which also means shut down.
+        let _ = self.0.wait_for(|stop| *stop).await;
+    }
+}
+
+/// Every background task of an application, so they can be stopped
+/// together and none outlives the instrument.
+///
+/// ```ignore
+/// let mut tasks = Spd3303xTasks::new(Arc::new(Mutex::new(inst)));
+/// let monitor = tasks.start_monitor(MonitorConfig::default());
+/// let mut events = monitor.events(EventFilter::new());
+/// // ...
+/// tasks.shutdown(true).await?;
+/// ```
+pub struct Spd3303xTasks {
+    inst: Arc<Mutex<Spd3303x>>,
+    shutdown: watch::Sender<bool>,
+    tasks: Vec<(String, JoinHandle<()>)>,
+    monitors: Vec<Monitor>,
+}
+
+impl Spd3303xTasks {
+    pub fn new(inst: Arc<Mutex<Spd3303x>>) -> Self {
+        let (shutdown, _) = watch::channel(false);
+        Self {
+            inst,
+            shutdown,
+            tasks: Vec::new(),
+            monitors: Vec::new(),
+        }
+    }
+
+    pub fn instrument(&self) -> &Arc<Mutex<Spd3303x>> {
+        &self.inst
+    }
+
+    pub fn shutdown_signal(&self) -> ShutdownSignal {
+        ShutdownSignal(self.shutdown.subscribe())
+    }
+
+    /// Spawn a task that is expected to return soon after its
+    /// [`ShutdownSignal`] fires.
+    pub fn spawn<F, Fut>(&mut self, name: impl Into<String>, task: F)
+    where
+        F: FnOnce(ShutdownSignal) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let handle = tokio::spawn(task(self.shutdown_signal()));
+        self.tasks.push((name.into(), handle));
+    }
+
+    pub fn start_monitor(&mut self, config: MonitorConfig) -> &Monitor {
+        self.monitors.push(Monitor::start(self.inst.clone(), config));
+        self.monitors.last().expect("monitor was just pushed")
+    }
+
+    /// Query `*IDN?` every `interval` so idle links are not dropped by the
+    /// instrument or intermediate network equipment.
+    pub fn spawn_keep_alive(&mut self, interval: Duration) {
+        let inst = self.inst.clone();
+        self.spawn("keep-alive", move |mut shutdown| async move {
+            let clock = inst.lock().await.clock();
+            let mut ticker = Ticker::new(clock, interval);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {}
+                    _ = shutdown.wait() => break,
+                }
+                if let Err(e) = inst.lock().await.idn().await {
+                    warn!("keep-alive: {e:#}");
+                }
+            }
+        });
+    }
+
+    /// Switch `channel` on and off again after `duration`.
+    ///
+    /// The off command comes from a task of its own, so it is sent even if
+    /// the caller fails or drops the returned handle; shutdown sends it
+    /// early. Only the process exiting can prevent it.
+    pub async fn set_output_for(
+        &mut self,
+        channel: Channel,
+        duration: Duration,
+    ) -> Result<TimedOutput> {
+        let inst = self.inst.clone();
+        let watched = inst.clone();
+        let mut guard = inst.lock().await;
+        let clock = guard.clock();
+        let (on_sent, on_rx) = oneshot::channel();
+        let (end, mut end_rx) = oneshot::channel();
+        let (done_tx, done) = oneshot::channel();
+        // Armed before switching on, so a failure after this point still
+        // ends with the output off. The lock is held until the on command
+        // is through, so the off cannot overtake it, and the timer only
+        // starts once the output is on.
+        self.spawn(
+            format!("timed-output-{}", channel.label()),
+            move |mut shutdown| async move {
+                let on = tokio::select! {
+                    sent = on_rx => sent.is_ok(),
+                    _ = shutdown.wait() => false,
+                };
+                if on {
+                    tokio::select! {
+                        _ = clock.sleep(duration) => {}
+                        Ok(()) = &mut end_rx => {}
+                        _ = shutdown.wait() => {}
+                    }
+                }
+                let _ = done_tx.send(switch_off(&watched, channel).await);
+            },
+        );
+        let output = TimedOutput {
+            end,
+            done,
+        };
+
+        let on = guard.set_output(channel, OutputState::On).await;
+        match on {
+            Ok(()) => {
+                let _ = on_sent.send(());
+                drop(guard);
+                debug!("tasks: {} on for {duration:?}", channel.label());
+                Ok(output)
+            }
+            Err(e) => {
+                // Dropping `on_sent` unsent has the task switch off at once.
+                drop(on_sent);
+                drop(guard);
+                if let Err(off_err) = output.off_now().await {
+                    warn!("tasks: {} may still be on: {off_err:#}", channel.label());
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Stop every task and wait for it to finish, then optionally switch all
+    /// outputs off. All steps run even if one fails; the first error is
+    /// returned.
+    pub async fn shutdown(mut self, outputs_off: bool) -> Result<()> {
+        self.shutdown.send_replace(true);
+        let mut first_error = None;
+
+        for monitor in self.monitors.drain(..) {
+            if let Err(e) = monitor.stop().await {
+                first_error.get_or_insert(e);
+            }
+        }
+        for (name, handle) in self.tasks.drain(..) {
+            debug!("tasks: waiting for {name}");
+            if let Err(e) = handle.await {
+                warn!("tasks: {name} did not finish cleanly: {e}");
+                first_error.get_or_insert(anyhow!("task {name} failed: {e}"));
+            }
+        }
+
+        if outputs_off {
+            let mut inst = self.inst.lock().await;
+            let controlled: Vec<Channel> = Channel::all()
+                .filter(|ch| inst.channel_policy().permits(*ch))
+                .collect();
+            for channel in controlled {
+                if let Err(e) = inst.set_output(channel, OutputState::Off).await {
+                    warn!("tasks: turning {} off failed: {e:#}", channel.label());
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Spd3303xTasks {
+    fn drop(&mut self) {
+        // Without an explicit shutdown the tasks still stop on their own.
+        self.shutdown.send_replace(true);
+    }
+}
+
+/// Handle of [`Spd3303xTasks::set_output_for`]. Dropping it leaves the
+/// timer running.
+#[derive(Debug)]
+pub struct TimedOutput {
+    end: oneshot::Sender<()>,
+    done: oneshot::Receiver<Result<()>>,
+}
+
+impl TimedOutput {
+    /// Wait until the output has been switched off.
+    pub async fn wait(self) -> Result<()> {
+        self.done
+            .await
+            .map_err(|_| anyhow!("timed output task ended without switching off"))?
+    }
+
+    /// Switch the output off now and wait until it is.
+    pub async fn off_now(self) -> Result<()> {
+        let _ = self.end.send(());
+        self.done
+            .await
+            .map_err(|_| anyhow!("timed output task ended without switching off"))?
+    }
+}
+
+async fn switch_off(inst: &Mutex<Spd3303x>, channel: Channel) -> Result<()> {
+    let mut attempt = 1;
+    loop {
+        match inst.lock().await.set_output(channel, OutputState::Off).await {
+            Ok(()) => {
+                debug!("tasks: {} off", channel.label());
+                return Ok(());
+            }
+            Err(e) if attempt < OFF_ATTEMPTS => {
+                warn!("tasks: turning {} off failed, retrying: {e:#}", channel.label());
+            }
+            Err(e) => return Err(e),
+        }
+        attempt += 1;
+        let clock = inst.lock().await.clock();
+        clock.sleep(OFF_RETRY_DELAY).await;
+    }
+}
@@ -0,0 +1,177 @@
+//! Coalescing of rapid setpoint streams (GUI sliders, jog wheels), and
+//! pacing of commands for firmware that drops them back to back.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::sync::{Mutex, Notify};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+use crate::clock::{Clock, TokioClock};
+use crate::instrument::{Channel, Spd3303x};
+use crate::middleware::{HookFuture, Middleware};
+use crate::pipeline::Quantity;
+
+/// Keeps at least `min_gap` between the starts of consecutive commands.
+/// Install it with `Spd3303x::add_middleware` to pace every command of a
+/// handle; [`SetpointThrottle`] paces its batches with one.
+pub struct Pacing {
+    clock: Arc<dyn Clock>,
+    min_gap: Duration,
+    last: StdMutex<Option<Instant>>,
+}
+
+impl Pacing {
+    pub fn new(min_gap: Duration) -> Self {
+        Self::with_clock(Arc::new(TokioClock), min_gap)
+    }
+
+    /// Pace on `clock`, e.g. the handle's own (`Spd3303x::clock`).
+    pub fn with_clock(clock: Arc<dyn Clock>, min_gap: Duration) -> Self {
+        Self {
+            clock,
+            min_gap,
+            last: StdMutex::new(None),
+        }
+    }
+
+    /// Wait until `min_gap` after the previous call started.
+    pub async fn pace(&self) {
+        let wait_until = {
+            let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+            let now = self.clock.now();
+            let start = last.map_or(now, |last| (last + self.min_gap).max(now));
+            *last = Some(start);
+            start
+        };
+        self.clock.sleep_until(wait_until).await;
+    }
+}
+
+impl Middleware for Pacing {
+    fn before_write<'a>(&'a self, _command: &'a str) -> HookFuture<'a> {
+        Box::pin(async {
+            self.pace().await;
+            Ok(())
+        })
+    }
+
+    fn before_query<'a>(&'a self, _command: &'a str) -> HookFuture<'a> {
+        Box::pin(async {
+            self.pace().await;
+            Ok(())
+        })
+    }
+}
+
+/// Forwards only the most recent voltage/current setpoint per channel to
+/// the instrument, at most once per `min_interval`.
+///
+/// `set_voltage`/`set_current` return immediately; a background task sends
+/// whatever value is newest when the link is free, so dragging a slider does
+/// not queue hundreds of writes behind a slow connection. Write errors are
+/// logged and the value is dropped; the next request is sent normally.
+///
+/// Call [`close`](Self::close) to flush outstanding values. Dropping the
+/// throttle also flushes them, in the background.
+pub struct SetpointThrottle {
+    shared: Arc<Shared>,
+    task: Option<JoinHandle<()>>,
+}
+
+#[derive(Default)]
+struct Shared {
+    pending: StdMutex<Vec<(Channel, Quantity, f64)>>,
+    notify: Notify,
+    closed: AtomicBool,
+}
+
+impl SetpointThrottle {
+    pub fn new(inst: Arc<Mutex<Spd3303x>>, min_interval: Duration) -> Self {
+        let shared = Arc::new(Shared::default());
+        let task = tokio::spawn(run(inst, shared.clone(), min_interval));
+        Self {
+            shared,
+            task: Some(task),
+        }
+    }
+
+    pub fn set_voltage(&self, channel: Channel, volts: f64) {
+        self.submit(channel, Quantity::Voltage, volts);
+    }
+
+    pub fn set_current(&self, channel: Channel, amps: f64) {
+        self.submit(channel, Quantity::Current, amps);
+    }
+
+    /// Send any values still pending and stop the background task.
+    pub async fn close(mut self) -> Result<()> {
+        self.shared.closed.store(true, Ordering::Release);
+        self.shared.notify.notify_one();
+        if let Some(task) = self.task.take() {
+            task.await?;
+        }
+        Ok(())
+    }
+
+    fn submit(&self, channel: Channel, quantity: Quantity, value: f64) {
+        {
+            let mut pending = self.shared.pending.lock().unwrap();
+            match pending
+                .iter_mut()
+                .find(|(ch, q, _)| *ch == channel && *q == quantity)
+            {
+                Some(entry) => entry.2 = value,
+                None => pending.push((channel, quantity, value)),
+            }
+        }
+        self.shared.notify.notify_one();
+    }
+}
+
+impl Drop for SetpointThrottle {
+    fn drop(&mut self) {
+        self.shared.closed.store(true, Ordering::Release);
+        self.shared.notify.notify_one();
+    }
+}
+
+async fn run(inst: Arc<Mutex<Spd3303x>>, shared: Arc<Shared>, min_interval: Duration) {
+    let clock = inst.lock().await.clock();
+    let pacing = Pacing::with_clock(clock, min_interval);
+    loop {
+        if shared.pending.lock().unwrap().is_empty() {
+            if shared.closed.load(Ordering::Acquire) {
+                break;
+            }
+            shared.notify.notified().await;
+            continue;
+        }
+
+        // Values submitted while waiting replace the ones pending before.
+        pacing.pace().await;
+        let pending = std::mem::take(&mut *shared.pending.lock().unwrap());
+        {
+            let mut inst = inst.lock().await;
+            for (channel, quantity, value) in pending {
+                debug!("throttle: sending {} {:?} {:.6}", channel.label(), quantity, value);
+                let result = match quantity {
+                    Quantity::Voltage => inst.set_voltage(channel, value).await,
+                    Quantity::Current => inst.set_current(channel, value).await,
+                    Quantity::Power => unreachable!("power is not a setpoint"),
+                };
+                if let Err(e) = result {
+                    warn!(
+                        "throttle: failed to set {} {}: {e:#}",
+                        channel.label(),
+                        quantity.name()
+                    );
+                }
+            }
+        }
+    }
+}
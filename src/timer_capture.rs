@@ -0,0 +1,237 @@
+//! Hardware timer runs with host-side measurements.
+//!
+//! The built-in timer steps a channel through its programmed groups on the
+//! instrument itself, so the timing does not depend on the link, but it
+//! records nothing. [`Spd3303x::run_timer_with_capture`] starts the timer
+//! and polls the channel while it runs. The instrument does not report
+//! which group is active, so each sample is annotated with the group its
+//! offset falls into according to the programmed durations; near a group
+//! boundary the annotation may be off by the link latency.
+//!
+//! [`Spd3303x::timer_progress`] makes the same estimate for a timer started
+//! with `timer_state`, from the time this handle switched it on, so that a
+//! UI can show a progress bar; the [`Monitor`](crate::monitor::Monitor)
+//! includes it in its samples and events while a timer runs.
+
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, Result};
+use tracing::{debug, warn};
+
+use crate::instrument::{
+    Channel, ChannelStatus, OutputState, RegulationMode, Spd3303x, TimerEntry, TimerState,
+    TIMER_GROUPS,
+};
+use crate::clock::Ticker;
+use crate::notes::Note;
+use crate::operation;
+use crate::table::Table;
+
+/// One poll taken while the timer was running.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CaptureSample {
+    /// Time since the timer was started.
+    pub offset: Duration,
+    /// Timer group estimated to be active; `None` once all have elapsed.
+    pub group: Option<u8>,
+    pub regulation_mode: Option<RegulationMode>,
+    pub status: ChannelStatus,
+}
+
+/// Result of [`Spd3303x::run_timer_with_capture`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TimerCapture {
+    pub channel: Channel,
+    /// The groups as programmed when the run started.
+    pub groups: [TimerEntry; TIMER_GROUPS as usize],
+    pub samples: Vec<CaptureSample>,
+    /// Operator notes taken during the run, see [`Spd3303x::annotate`].
+    pub notes: Vec<Note>,
+}
+
+impl TimerCapture {
+    /// Samples annotated with `group`.
+    pub fn samples_in(&self, group: u8) -> impl Iterator<Item = &CaptureSample> {
+        self.samples
+            .iter()
+            .filter(move |sample| sample.group == Some(group))
+    }
+
+    pub fn render_table(&self) -> Table {
+        let mut table = Table::new(["Offset", "Group", "Mode", "Voltage", "Current", "Power"]);
+        for sample in &self.samples {
+            table.push_row([
+                format!("{:.3} s", sample.offset.as_secs_f64()),
+                sample
+                    .group
+                    .map_or_else(|| "-".to_string(), |group| group.to_string()),
+                sample.regulation_mode.map_or("?", RegulationMode::label).to_string(),
+                format!("{:.3} V", sample.status.measured_voltage_v),
+                format!("{:.3} A", sample.status.measured_current_a),
+                format!("{:.3} W", sample.status.measured_power_w),
+            ]);
+        }
+        table
+    }
+}
+
+/// Estimated position of a running timer, see [`Spd3303x::timer_progress`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TimerProgress {
+    pub channel: Channel,
+    /// Timer group estimated to be active; `None` once all have elapsed.
+    pub group: Option<u8>,
+    /// Time since the timer was switched on.
+    pub elapsed: Duration,
+    /// Time left in the active group.
+    pub group_remaining: Duration,
+    /// Time left until the last group has elapsed.
+    pub remaining: Duration,
+    /// Sum of the programmed durations.
+    pub total: Duration,
+}
+
+impl TimerProgress {
+    /// Position `elapsed` into a run of `groups`.
+    pub fn estimate(channel: Channel, groups: &[TimerEntry], elapsed: Duration) -> Self {
+        let total: Duration = groups.iter().map(|entry| entry.duration).sum();
+        let mut end = Duration::ZERO;
+        let mut group = None;
+        for entry in groups {
+            end += entry.duration;
+            if elapsed < end {
+                group = Some(entry.group);
+                break;
+            }
+        }
+        Self {
+            channel,
+            group,
+            elapsed,
+            group_remaining: group.map_or(Duration::ZERO, |_| end - elapsed),
+            remaining: total.saturating_sub(elapsed),
+            total,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.group.is_none()
+    }
+
+    /// Share of the run that has elapsed, from 0.0 to 1.0.
+    pub fn fraction(&self) -> f64 {
+        if self.total.is_zero() {
+            return 1.0;
+        }
+        (self.elapsed.as_secs_f64() / self.total.as_secs_f64()).min(1.0)
+    }
+}
+
+/// Group active `offset` into a run of `groups`, by their programmed
+/// durations.
+fn active_group(groups: &[TimerEntry], offset: Duration) -> Option<u8> {
+    let mut end = Duration::ZERO;
+    for entry in groups {
+        end += entry.duration;
+        if offset < end {
+            return Some(entry.group);
+        }
+    }
+    None
+}
+
+impl Spd3303x {
+    /// Estimated progress of the timer of `channel`, or `None` if this
+    /// handle has not switched it on.
+    ///
+    /// The instrument does not report the active group, so it is derived
+    /// from the programmed durations and the time since `timer_state`
+    /// switched the timer on; a timer started or stopped on the front panel
+    /// is not seen. The groups are read on the first call and kept until
+    /// the next `timer_set`.
+    pub async fn timer_progress(&mut self, channel: Channel) -> Result<Option<TimerProgress>> {
+        let Some(started) = self.timer_started(channel) else {
+            return Ok(None);
+        };
+        let groups = self.programmed_timer_groups(channel).await?;
+        let elapsed = self.clock().elapsed(started);
+        Ok(Some(TimerProgress::estimate(channel, &groups, elapsed)))
+    }
+
+    /// Run the programmed timer groups of `channel` once, polling the
+    /// channel every `capture_interval` until the last group has elapsed.
+    ///
+    /// The timer is enabled and the output switched on; afterwards the
+    /// timer is disabled again and the output left as the timer left it.
+    /// If the run fails part way, the output is switched off as well.
+    pub async fn run_timer_with_capture(
+        &mut self,
+        channel: Channel,
+        capture_interval: Duration,
+    ) -> Result<TimerCapture> {
+        if capture_interval.is_zero() {
+            return Err(anyhow!("capture interval must be longer than zero"));
+        }
+        operation::run("timer_capture", async {
+            let groups = self.read_all_timers(channel).await?.into_complete()?;
+            let total: Duration = groups.iter().map(|entry| entry.duration).sum();
+            debug!("timer_capture: {} groups over {total:?}", groups.len());
+
+            let started_at = SystemTime::now();
+            let result = match self.timer_state(channel, TimerState::On).await {
+                Ok(()) => {
+                    self.capture_timer_run(channel, &groups, total, capture_interval)
+                        .await
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = self.timer_state(channel, TimerState::Off).await {
+                warn!("timer_capture: disabling the timer failed: {e:#}");
+            }
+            if result.is_err()
+                && let Err(e) = self.set_output(channel, OutputState::Off).await
+            {
+                warn!("timer_capture: switching {} off failed: {e:#}", channel.label());
+            }
+            Ok(TimerCapture {
+                channel,
+                groups,
+                samples: result?,
+                notes: self.note_log().since(started_at),
+            })
+        })
+        .await
+    }
+
+    async fn capture_timer_run(
+        &mut self,
+        channel: Channel,
+        groups: &[TimerEntry],
+        total: Duration,
+        capture_interval: Duration,
+    ) -> Result<Vec<CaptureSample>> {
+        self.set_output(channel, OutputState::On).await?;
+        let clock = self.clock();
+        let started = clock.now();
+        let mut ticker = Ticker::new(clock.clone(), capture_interval);
+        let mut samples = Vec::new();
+        loop {
+            ticker.tick().await;
+            let offset = clock.elapsed(started);
+            let system = self.system_status().await?;
+            let status = self.channel_status(channel).await?;
+            samples.push(CaptureSample {
+                offset,
+                group: active_group(groups, offset),
+                regulation_mode: system.regulation_mode(channel),
+                status,
+            });
+            if offset >= total {
+                return Ok(samples);
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! Training mode: confirm every state-changing command before it is sent.
+//!
+//! With [`Training`] installed through `Spd3303x::set_training`, each write
+//! (setpoints, outputs, track mode, timers, ...) is shown first and only
+//! sent once confirmed, so that someone new to the lab can step through a
+//! script and see what it does. Queries only read state and pass through
+//! unconfirmed. A declined command fails with [`Declined`], which stops the
+//! script like any other error.
+
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Stdin};
+
+type Policy = Box<dyn Fn(&str) -> bool + Send + Sync>;
+
+enum Confirmer {
+    Interactive(BufReader<Stdin>),
+    Policy(Policy),
+}
+
+pub struct Training {
+    confirmer: Confirmer,
+}
+
+impl Training {
+    /// Print each command on stderr and ask on the terminal.
+    pub fn interactive() -> Self {
+        Self {
+            confirmer: Confirmer::Interactive(BufReader::new(tokio::io::stdin())),
+        }
+    }
+
+    /// Let `policy` decide; it is given the command with secrets redacted
+    /// and without the line terminator.
+    pub fn with_policy(policy: impl Fn(&str) -> bool + Send + Sync + 'static) -> Self {
+        Self {
+            confirmer: Confirmer::Policy(Box::new(policy)),
+        }
+    }
+
+    pub(crate) async fn confirm(&mut self, command: &str) -> Result<()> {
+        let command = command.trim_end_matches('\n');
+        let confirmed = match &mut self.confirmer {
+            Confirmer::Interactive(stdin) => prompt(stdin, command).await?,
+            Confirmer::Policy(policy) => policy(command),
+        };
+        if confirmed {
+            Ok(())
+        } else {
+            Err(Declined {
+                command: command.to_string(),
+            }
+            .into())
+        }
+    }
+}
+
+impl fmt::Debug for Training {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let confirmer = match self.confirmer {
+            Confirmer::Interactive(_) => "interactive",
+            Confirmer::Policy(_) => "policy",
+        };
+        f.debug_struct("Training")
+            .field("confirmer", &confirmer)
+            .finish()
+    }
+}
+
+/// A command was not confirmed in training mode and was not sent.
+#[derive(Debug, Clone)]
+pub struct Declined {
+    /// The command, redacted.
+    pub command: String,
+}
+
+impl fmt::Display for Declined {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} was declined in training mode", self.command)
+    }
+}
+
+impl std::error::Error for Declined {}
+
+/// Ask until the answer is yes or no; an empty answer means no.
+async fn prompt(stdin: &mut BufReader<Stdin>, command: &str) -> Result<bool> {
+    let mut stderr = tokio::io::stderr();
+    loop {
+        stderr
+            .write_all(format!("about to send: {command}\nsend? [y/N] ").as_bytes())
+            .await?;
+        stderr.flush().await?;
+
+        let mut answer = String::new();
+        if stdin.read_line(&mut answer).await? == 0 {
+            return Err(anyhow!("stdin closed while waiting for confirmation"));
+        }
+        match answer.trim().to_ascii_lowercase().as_str() {
+            "y" | "yes" => return Ok(true),
+            "" | "n" | "no" => return Ok(false),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! All-or-nothing configuration changes.
+//!
+//! [`Spd3303x::transaction`] takes a closure that stages setpoint, output,
+//! waveform display and track mode changes on a [`Transaction`], reads
+//! back the current state, then sends the staged changes in order. If one
+//! fails, everything the transaction had touched is put back as it was, so
+//! the DUT is never left with half of a new configuration:
+//!
+//! ```ignore
+//! inst.transaction(|txn| {
+//!     txn.set_voltage(Channel::Ch1, 5.0);
+//!     txn.set_current(Channel::Ch1, 0.5);
+//!     txn.set_output(Channel::Ch1, OutputState::On);
+//! })
+//! .await?;
+//! ```
+//!
+//! With undo enabled, each committed transaction is recorded as one step,
+//! see [`undo`](crate::undo).
+
+use anyhow::Result;
+use tracing::{debug, warn};
+
+use crate::instrument::{Channel, OutputState, Spd3303x, TimerEntry, TimerState, TrackMode};
+use crate::operation;
+use crate::snapshot::{InstrumentSnapshot, Setting, SettingChange, SnapshotDiff};
+
+/// Changes staged for [`Spd3303x::transaction`].
+#[derive(Debug, Clone, Default)]
+pub struct Transaction {
+    changes: Vec<Setting>,
+}
+
+impl Transaction {
+    pub fn set_voltage(&mut self, channel: Channel, volts: f64) -> &mut Self {
+        self.changes.push(Setting::Voltage(channel, volts));
+        self
+    }
+
+    pub fn set_current(&mut self, channel: Channel, amps: f64) -> &mut Self {
+        self.changes.push(Setting::Current(channel, amps));
+        self
+    }
+
+    /// CH3's output state is not reported by the instrument; on rollback it
+    /// is switched off.
+    pub fn set_output(&mut self, channel: Channel, state: OutputState) -> &mut Self {
+        let on = matches!(state, OutputState::On);
+        self.changes.push(Setting::Output(channel, on));
+        self
+    }
+
+    pub fn set_wave_display(&mut self, channel: Channel, state: OutputState) -> &mut Self {
+        let on = matches!(state, OutputState::On);
+        self.changes.push(Setting::WaveDisplay(channel, on));
+        self
+    }
+
+    pub fn set_track_mode(&mut self, mode: TrackMode) -> &mut Self {
+        self.changes.push(Setting::TrackMode(mode));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl Spd3303x {
+    /// Stage changes with `build` and apply them, rolling back the touched
+    /// settings if any of them fails. The error of the failed change is
+    /// returned either way; rollback failures are logged.
+    pub async fn transaction(&mut self, build: impl FnOnce(&mut Transaction)) -> Result<()> {
+        let mut txn = Transaction::default();
+        build(&mut txn);
+        if txn.is_empty() {
+            return Ok(());
+        }
+        let diff = self.apply_settings(&txn.changes).await?;
+        self.record_undo(diff);
+        Ok(())
+    }
+
+    /// Apply `settings` in order as one transaction; returns what changed.
+    pub(crate) async fn apply_settings(&mut self, settings: &[Setting]) -> Result<SnapshotDiff> {
+        operation::run("transaction", async {
+            let before = self.settings_snapshot().await?;
+            for (index, setting) in settings.iter().enumerate() {
+                if let Err(e) = self.apply_setting(*setting).await {
+                    let failed = format!("change {} of {}", index + 1, settings.len());
+                    warn!("transaction: {failed} failed, rolling back");
+                    self.roll_back(&before, &settings[..=index]).await;
+                    return Err(
+                        e.context(format!("transaction rolled back after {failed} failed"))
+                    );
+                }
+            }
+            debug!("transaction: {} changes applied", settings.len());
+            Ok(touched(&before, settings))
+        })
+        .await
+    }
+
+    async fn apply_setting(&mut self, setting: Setting) -> Result<()> {
+        match setting {
+            Setting::Voltage(channel, volts) => self.set_voltage(channel, volts).await,
+            Setting::Current(channel, amps) => self.set_current(channel, amps).await,
+            Setting::Output(channel, true) => self.set_output(channel, OutputState::On).await,
+            Setting::Output(channel, false) => self.set_output(channel, OutputState::Off).await,
+            Setting::WaveDisplay(channel, true) => {
+                self.set_wave_display(channel, OutputState::On).await
+            }
+            Setting::WaveDisplay(channel, false) => {
+                self.set_wave_display(channel, OutputState::Off).await
+            }
+            Setting::TrackMode(mode) => self.set_track_mode(mode).await,
+            Setting::TimerGroup(channel, entry) => {
+                let TimerEntry {
+                    group,
+                    voltage_v,
+                    current_a,
+                    duration,
+                } = entry;
+                self.timer_set(channel, group, voltage_v, current_a, duration).await
+            }
+            Setting::Timer(channel, true) => self.timer_state(channel, TimerState::On).await,
+            Setting::Timer(channel, false) => self.timer_state(channel, TimerState::Off).await,
+        }
+    }
+
+    /// Restore what `settings` touched, in the order of
+    /// [`SnapshotDiff::ordered_targets`].
+    async fn roll_back(&mut self, before: &InstrumentSnapshot, settings: &[Setting]) {
+        for setting in touched(before, settings).inverse().ordered_targets() {
+            if let Err(e) = self.apply_setting(setting).await {
+                warn!("transaction: rollback of {setting:?} failed: {e:#}");
+            }
+        }
+    }
+}
+
+/// Each setting touched by `settings`, once, from its value in `before` to
+/// the last value it was given; settings left at their value are omitted.
+fn touched(before: &InstrumentSnapshot, settings: &[Setting]) -> SnapshotDiff {
+    let mut changes: Vec<SettingChange> = Vec::new();
+    for setting in settings {
+        match changes
+            .iter_mut()
+            .find(|change| change.after.same_target(setting))
+        {
+            Some(change) => change.after = *setting,
+            None => {
+                if let Some(previous) = setting.read(before) {
+                    changes.push(SettingChange {
+                        before: previous,
+                        after: *setting,
+                    });
+                }
+            }
+        }
+    }
+    changes.retain(|change| change.before != change.after);
+    SnapshotDiff { changes }
+}
@@ -0,0 +1,74 @@
+//! Record of the SCPI traffic exchanged with the instrument.
+
+use std::collections::VecDeque;
+use std::time::SystemTime;
+
+use crate::operation::OperationId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Direction {
+    Write,
+    Query,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TranscriptEntry {
+    pub at: SystemTime,
+    /// Operation that issued the command, see [`crate::operation`].
+    pub operation: Option<OperationId>,
+    pub direction: Direction,
+    /// Command as sent, without the trailing newline.
+    pub command: String,
+    /// Trimmed reply of a successful query.
+    pub response: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Bounded log of the most recent [`TranscriptEntry`]s.
+#[derive(Debug, Clone)]
+pub struct Transcript {
+    entries: VecDeque<TranscriptEntry>,
+    capacity: usize,
+}
+
+impl Transcript {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity.min(1024)),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, entry: TranscriptEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &TranscriptEntry> {
+        self.entries.iter()
+    }
+
+    /// Entries issued by one operation.
+    pub fn for_operation(&self, id: OperationId) -> impl Iterator<Item = &TranscriptEntry> {
+        self.entries.iter().filter(move |e| e.operation == Some(id))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
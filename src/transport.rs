@@ -0,0 +1,96 @@
+//! The link SCPI traffic goes over.
+//!
+//! Every connection of a `Spd3303x` handle is a [`Transport`]: VXI-11, the
+//! raw socket, a broker, a Prologix adapter, a serial port, USBTMC or the
+//! [simulator](crate::mock). Validation, retries, transcripts and the
+//! rest of the instrument logic sit above it and behave the same on all of
+//! them. Other links (a proxy, a test double, another adapter) plug in by
+//! implementing the trait and connecting with
+//! [`Spd3303x::from_transport`](crate::instrument::Spd3303x::from_transport).
+//!
+//! Commands are passed with their line terminator, which a transport
+//! framing messages itself may strip. Replies are returned as read,
+//! terminator included; the handle trims them.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use anyhow::Result;
+use tokio_vxi11::DeviceClient;
+
+const MAX_READ: u32 = 4096;
+
+pub type TransportFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+pub trait Transport: Send {
+    /// Send a command that has no reply.
+    fn write<'a>(&'a mut self, command: &'a str) -> TransportFuture<'a, ()>;
+
+    /// Send a command and read its reply.
+    fn query<'a>(&'a mut self, command: &'a str) -> TransportFuture<'a, String>;
+
+    /// Device clear, used by the [escalation ladder](crate::escalation);
+    /// `false` if the link has none, in which case it is reopened instead.
+    fn clear(&mut self) -> TransportFuture<'_, bool> {
+        Box::pin(async { Ok(false) })
+    }
+
+    fn close(&mut self) -> TransportFuture<'_, ()> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn write<'a>(&'a mut self, command: &'a str) -> TransportFuture<'a, ()> {
+        (**self).write(command)
+    }
+
+    fn query<'a>(&'a mut self, command: &'a str) -> TransportFuture<'a, String> {
+        (**self).query(command)
+    }
+
+    fn clear(&mut self) -> TransportFuture<'_, bool> {
+        (**self).clear()
+    }
+
+    fn close(&mut self) -> TransportFuture<'_, ()> {
+        (**self).close()
+    }
+}
+
+impl Transport for DeviceClient {
+    fn write<'a>(&'a mut self, command: &'a str) -> TransportFuture<'a, ()> {
+        Box::pin(async move {
+            DeviceClient::write(self, command.as_bytes()).await?;
+            Ok(())
+        })
+    }
+
+    fn query<'a>(&'a mut self, command: &'a str) -> TransportFuture<'a, String> {
+        Box::pin(async move {
+            DeviceClient::write(self, command.as_bytes()).await?;
+            let resp = self.read(MAX_READ).await?;
+            Ok(String::from_utf8(resp)?)
+        })
+    }
+
+    fn close(&mut self) -> TransportFuture<'_, ()> {
+        Box::pin(async move {
+            DeviceClient::close(self).await?;
+            Ok(())
+        })
+    }
+}
+
+/// Write `command`, then read the reply if `reply` is set.
+pub(crate) async fn exchange(
+    transport: &mut dyn Transport,
+    command: &str,
+    reply: bool,
+) -> Result<Option<String>> {
+    if reply {
+        transport.query(command).await.map(Some)
+    } else {
+        transport.write(command).await.map(|()| None)
+    }
+}
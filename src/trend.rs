@@ -0,0 +1,110 @@
+//! Host-side capture of the voltage/current trend.
+//!
+//! The front-panel waveform display (`OUTPut:WAVE`) plots the recent output
+//! voltage and current of a channel, but the SCPI set has no command to
+//! read that plot back; only the on/off state of the display is reported
+//! in the status word. To reproduce the plot in a report, [`Monitor`]
+//! keeps the readbacks of its polls in a [`TrendRecorder`] and
+//! [`Monitor::wave_trend`] returns them.
+//!
+//! [`Monitor`]: crate::monitor::Monitor
+//! [`Monitor::wave_trend`]: crate::monitor::Monitor::wave_trend
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::instrument::Channel;
+use crate::monitor::MonitorSample;
+
+/// One point of the trend: readbacks at `offset` since capture started.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrendPoint {
+    pub offset: Duration,
+    pub voltage_v: f64,
+    pub current_a: f64,
+    /// Voltage setpoint at the time, for the tracking error over time.
+    /// Serialized as `set_voltage_v` until the next release; either name
+    /// is read.
+    #[cfg_attr(
+        feature = "serde",
+        serde(rename = "set_voltage_v", alias = "setpoint_voltage_v")
+    )]
+    pub setpoint_voltage_v: f64,
+}
+
+/// The newest `capacity` points of CH1 and CH2.
+#[derive(Debug, Clone)]
+pub struct TrendRecorder {
+    started: Instant,
+    capacity: usize,
+    points: [VecDeque<TrendPoint>; 2],
+}
+
+impl TrendRecorder {
+    pub fn new(capacity: usize) -> Self {
+        Self::starting_at(capacity, Instant::now())
+    }
+
+    /// A recorder whose offsets count from `started`, an instant of the
+    /// clock the recorded samples are stamped by.
+    pub fn starting_at(capacity: usize, started: Instant) -> Self {
+        Self {
+            started,
+            capacity,
+            points: Default::default(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Add the readbacks of the channels polled in `sample`, dropping the
+    /// oldest points beyond the capacity.
+    pub fn record(&mut self, sample: &MonitorSample) {
+        let offset = sample.at.saturating_duration_since(self.started);
+        let capacity = self.capacity;
+        for (channel, status) in &sample.channels {
+            let Some(points) = self.points_mut(*channel) else {
+                continue;
+            };
+            points.push_back(TrendPoint {
+                offset,
+                voltage_v: status.measured_voltage_v,
+                current_a: status.measured_current_a,
+                setpoint_voltage_v: status.setpoint_voltage_v,
+            });
+            while points.len() > capacity {
+                points.pop_front();
+            }
+        }
+    }
+
+    /// Points of `channel`, oldest first; empty for CH3, which has no
+    /// readbacks.
+    pub fn points(&self, channel: Channel) -> Vec<TrendPoint> {
+        match index(channel) {
+            Some(index) => self.points[index].iter().copied().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.points.iter_mut().for_each(VecDeque::clear);
+    }
+
+    fn points_mut(&mut self, channel: Channel) -> Option<&mut VecDeque<TrendPoint>> {
+        index(channel).map(|index| &mut self.points[index])
+    }
+}
+
+fn index(channel: Channel) -> Option<usize> {
+    match channel {
+        Channel::Ch1 => Some(0),
+        Channel::Ch2 => Some(1),
+        Channel::Ch3 => None,
+    }
+}
@@ -0,0 +1,130 @@
+//! Reaching instruments on isolated lab networks through an SSH bastion.
+//!
+//! [`SshTunnel`] runs the system `ssh` client with a local port forward
+//! (`ssh -N -L`) to a TCP service behind the jump host and stays alive as
+//! long as the connection using it. Authentication is whatever `ssh` is
+//! configured for (keys, agent, `~/.ssh/config`); `BatchMode` is set, so a
+//! host that would prompt for a password fails instead of hanging.
+//!
+//! A single forwarded port carries the `spd3303xd` broker protocol or a
+//! Prologix adapter, but not VXI-11, which looks up a dynamic port through
+//! the portmapper. To reach a supply over LAN, run `spd3303xd` on a host of
+//! the lab network and use [`Spd3303x::connect_via_ssh`].
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use tokio::io::AsyncReadExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::process::{Child, Command};
+use tracing::debug;
+
+use crate::instrument::Spd3303x;
+
+/// How long `ssh` may take to log in and open the forward.
+const READY_TIMEOUT: Duration = Duration::from_secs(15);
+const READY_POLL: Duration = Duration::from_millis(100);
+
+/// A running `ssh -L` forward; dropping it ends the `ssh` process.
+#[derive(Debug)]
+pub struct SshTunnel {
+    child: Child,
+    local: SocketAddr,
+}
+
+impl SshTunnel {
+    /// Forward a free local port through `jump_host` (any destination `ssh`
+    /// accepts, e.g. `user@bastion` or `ssh://bastion:2222`) to `target`, a
+    /// `host:port` as seen from the jump host.
+    pub async fn open(jump_host: &str, target: &str) -> Result<Self> {
+        let (host, port) = target
+            .rsplit_once(':')
+            .ok_or_else(|| anyhow!("expected the tunnel target as host:port, got {target:?}"))?;
+        let port: u16 = port
+            .parse()
+            .map_err(|e| anyhow!("invalid tunnel target port {port:?}: {e}"))?;
+        // The port is free now; another process could take it before ssh
+        // binds it, which then fails through ExitOnForwardFailure.
+        let local = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .await
+            .context("failed to find a free local port")?
+            .local_addr()?;
+
+        debug!("tunnel: forwarding {local} to {target} via {jump_host}");
+        let child = Command::new("ssh")
+            .arg("-N")
+            .args(["-o", "BatchMode=yes"])
+            .args(["-o", "ExitOnForwardFailure=yes"])
+            .arg("-L")
+            .arg(format!("{}:{}:{host}:{port}", local.ip(), local.port()))
+            .arg(jump_host)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .context("failed to start ssh")?;
+
+        let mut tunnel = Self { child, local };
+        tunnel.wait_ready().await?;
+        Ok(tunnel)
+    }
+
+    /// Local end of the forward.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local
+    }
+
+    /// End the forward.
+    pub async fn close(mut self) -> Result<()> {
+        self.child.kill().await.context("failed to stop ssh")
+    }
+
+    async fn wait_ready(&mut self) -> Result<()> {
+        let deadline = tokio::time::Instant::now() + READY_TIMEOUT;
+        loop {
+            if let Some(status) = self.child.try_wait()? {
+                let mut stderr = String::new();
+                if let Some(mut pipe) = self.child.stderr.take() {
+                    pipe.read_to_string(&mut stderr).await.ok();
+                }
+                return Err(anyhow!("ssh exited with {status}: {}", stderr.trim()));
+            }
+            if TcpStream::connect(self.local).await.is_ok() {
+                debug!("tunnel: {} is up", self.local);
+                return Ok(());
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Err(anyhow!("ssh forward not up after {READY_TIMEOUT:?}"));
+            }
+            tokio::time::sleep(READY_POLL).await;
+        }
+    }
+}
+
+impl Spd3303x {
+    /// Connect to an `spd3303xd` broker at `broker` (`host:port` as seen
+    /// from `jump_host`) through an [`SshTunnel`] owned by the connection.
+    pub async fn connect_via_ssh(jump_host: &str, broker: &str) -> Result<Self> {
+        let tunnel = SshTunnel::open(jump_host, broker).await?;
+        let mut inst = Self::connect_broker(tunnel.local_addr()).await?;
+        inst.set_tunnel(tunnel);
+        Ok(inst)
+    }
+
+    /// Connect to the supply at `gpib_address` behind the Prologix adapter
+    /// `adapter` (`host:port` as seen from `jump_host`) through an
+    /// [`SshTunnel`] owned by the connection.
+    pub async fn connect_prologix_via_ssh(
+        jump_host: &str,
+        adapter: &str,
+        gpib_address: u8,
+    ) -> Result<Self> {
+        let tunnel = SshTunnel::open(jump_host, adapter).await?;
+        let mut inst = Self::connect_prologix(tunnel.local_addr(), gpib_address).await?;
+        inst.set_tunnel(tunnel);
+        Ok(inst)
+    }
+}
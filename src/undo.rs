@@ -0,0 +1,133 @@
+//! Multi-step undo and redo for interactive tools.
+//!
+//! With [`Spd3303x::enable_undo`], every committed
+//! [`transaction`](Spd3303x::transaction) is recorded as a [`SnapshotDiff`]
+//! on a bounded stack. [`Spd3303x::undo`] applies the inverse of the newest
+//! one (outputs going off first, see [`SnapshotDiff::ordered_targets`]) and
+//! moves it to the redo stack; [`Spd3303x::redo`] applies it again. A new
+//! transaction clears the redo stack. Changes made with the plain setters
+//! are not recorded, so interactive tools route edits through transactions.
+
+use std::collections::VecDeque;
+
+use anyhow::Result;
+use tracing::debug;
+
+use crate::instrument::Spd3303x;
+use crate::snapshot::SnapshotDiff;
+
+#[derive(Debug, Clone)]
+pub struct UndoStack {
+    depth: usize,
+    undo: VecDeque<SnapshotDiff>,
+    redo: Vec<SnapshotDiff>,
+}
+
+impl UndoStack {
+    /// Keep at most `depth` steps; the oldest are dropped first.
+    pub fn new(depth: usize) -> Self {
+        Self {
+            depth,
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+        }
+    }
+
+    /// Record a new step, clearing the redo stack.
+    pub fn push(&mut self, diff: SnapshotDiff) {
+        if diff.is_empty() {
+            return;
+        }
+        self.redo.clear();
+        self.push_undo(diff);
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Steps that can be undone, newest first.
+    pub fn undo_steps(&self) -> impl Iterator<Item = &SnapshotDiff> {
+        self.undo.iter().rev()
+    }
+
+    fn push_undo(&mut self, diff: SnapshotDiff) {
+        self.undo.push_back(diff);
+        while self.undo.len() > self.depth {
+            self.undo.pop_front();
+        }
+    }
+}
+
+impl Spd3303x {
+    /// Record transactions for [`undo`](Self::undo), keeping `depth` steps.
+    /// Replaces any history recorded so far.
+    pub fn enable_undo(&mut self, depth: usize) {
+        self.set_undo_stack(Some(UndoStack::new(depth)));
+    }
+
+    pub fn disable_undo(&mut self) {
+        self.set_undo_stack(None);
+    }
+
+    pub fn undo_stack(&self) -> Option<&UndoStack> {
+        self.undo_stack_ref()
+    }
+
+    /// Revert the newest recorded step and return it; `None` if there is
+    /// nothing to undo. A step that fails to revert is rolled back and
+    /// stays on the undo stack.
+    pub async fn undo(&mut self) -> Result<Option<SnapshotDiff>> {
+        let Some(diff) = self.undo_stack_mut().and_then(|stack| stack.undo.pop_back()) else {
+            return Ok(None);
+        };
+        match self.apply_settings(&diff.inverse().ordered_targets()).await {
+            Ok(_) => {
+                debug!("undo: reverted {} changes", diff.changes.len());
+                if let Some(stack) = self.undo_stack_mut() {
+                    stack.redo.push(diff.clone());
+                }
+                Ok(Some(diff))
+            }
+            Err(e) => {
+                if let Some(stack) = self.undo_stack_mut() {
+                    stack.undo.push_back(diff);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Re-apply the newest undone step and return it; `None` if there is
+    /// nothing to redo.
+    pub async fn redo(&mut self) -> Result<Option<SnapshotDiff>> {
+        let Some(diff) = self.undo_stack_mut().and_then(|stack| stack.redo.pop()) else {
+            return Ok(None);
+        };
+        match self.apply_settings(&diff.ordered_targets()).await {
+            Ok(_) => {
+                debug!("redo: re-applied {} changes", diff.changes.len());
+                if let Some(stack) = self.undo_stack_mut() {
+                    stack.push_undo(diff.clone());
+                }
+                Ok(Some(diff))
+            }
+            Err(e) => {
+                if let Some(stack) = self.undo_stack_mut() {
+                    stack.redo.push(diff);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    pub(crate) fn record_undo(&mut self, diff: SnapshotDiff) {
+        if let Some(stack) = self.undo_stack_mut() {
+            stack.push(diff);
+        }
+    }
+}
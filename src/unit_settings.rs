@@ -0,0 +1,227 @@
+//! Per-unit settings, kept with the serial number they belong to.
+//!
+//! Calibration, lead resistance, limits and firmware quirk overrides belong
+//! to one particular supply, not to the code driving it. A
+//! [`SettingsStore`] keeps them as one TOML file per unit in a directory,
+//! named after the serial number, e.g. `SPD3XIDX000000.toml`:
+//!
+//! ```toml
+//! alias = "bench-3"
+//!
+//! [ch1]
+//! limits = { max_voltage_v = 12.0, max_current_a = 1.0 }
+//! calibration = { voltage_gain = 1.0012, voltage_offset_v = -0.004 }
+//! wire_resistance_ohms = 0.05
+//!
+//! [[quirks]]
+//! feature = "DisplayText"
+//! fixed_in = [1, 1, 1, 3, 0]
+//! workaround = "update the firmware"
+//! ```
+//!
+//! With [`Spd3303xBuilder::settings_store`] the file of the connected unit
+//! is loaded and applied right after its identity is read, so moving a
+//! script to another bench picks up that bench's data. A unit without a
+//! file is used with the defaults.
+//!
+//! [`Spd3303xBuilder::settings_store`]: crate::builder::Spd3303xBuilder::settings_store
+
+use anyhow::Result;
+
+use crate::instrument::{Channel, Spd3303x};
+use crate::pipeline::{Quantity, SamplePipeline, Scale};
+use crate::quirks::Quirk;
+use crate::validate::Limits;
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct UnitSettings {
+    /// Name of the unit on the bench, for finding it by something other
+    /// than its serial.
+    pub alias: Option<String>,
+    pub ch1: UnitChannelSettings,
+    pub ch2: UnitChannelSettings,
+    /// Quirks of this unit in addition to the known ones.
+    pub quirks: Vec<Quirk>,
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct UnitChannelSettings {
+    /// Host-side limits installed with `Spd3303x::set_limits`.
+    pub limits: Option<Limits>,
+    /// Correction of this channel's readings.
+    pub calibration: Option<Calibration>,
+    /// Lead resistance, see `Spd3303x::set_wire_resistance`.
+    pub wire_resistance_ohms: Option<f64>,
+}
+
+/// Linear correction of readings, `reading * gain + offset`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Calibration {
+    pub voltage_gain: f64,
+    pub voltage_offset_v: f64,
+    pub current_gain: f64,
+    pub current_offset_a: f64,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self {
+            voltage_gain: 1.0,
+            voltage_offset_v: 0.0,
+            current_gain: 1.0,
+            current_offset_a: 0.0,
+        }
+    }
+}
+
+impl Calibration {
+    pub fn pipeline(&self) -> SamplePipeline {
+        SamplePipeline::new()
+            .with(Scale::new(Quantity::Voltage, self.voltage_gain, self.voltage_offset_v))
+            .with(Scale::new(Quantity::Current, self.current_gain, self.current_offset_a))
+    }
+}
+
+impl UnitSettings {
+    pub fn channel(&self, channel: Channel) -> Option<&UnitChannelSettings> {
+        match channel {
+            Channel::Ch1 => Some(&self.ch1),
+            Channel::Ch2 => Some(&self.ch2),
+            Channel::Ch3 => None,
+        }
+    }
+}
+
+/// Directory of [`UnitSettings`] files, one per serial number.
+#[cfg(feature = "config")]
+#[derive(Debug, Clone)]
+pub struct SettingsStore {
+    dir: std::path::PathBuf,
+}
+
+#[cfg(feature = "config")]
+impl SettingsStore {
+    pub fn new(dir: impl Into<std::path::PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &std::path::Path {
+        &self.dir
+    }
+
+    /// File holding the settings of `serial`.
+    pub fn path(&self, serial: &str) -> std::path::PathBuf {
+        let name: String = serial
+            .trim()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+            .collect();
+        self.dir.join(format!("{name}.toml"))
+    }
+
+    /// Settings of `serial`, `None` if the store has none.
+    pub fn load(&self, serial: &str) -> Result<Option<UnitSettings>> {
+        use anyhow::Context;
+
+        let path = self.path(serial);
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("failed to read unit settings {}", path.display()));
+            }
+        };
+        toml::from_str(&text)
+            .map(Some)
+            .with_context(|| format!("failed to parse unit settings {}", path.display()))
+    }
+
+    pub fn save(&self, serial: &str, settings: &UnitSettings) -> Result<()> {
+        use anyhow::Context;
+
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("failed to create {}", self.dir.display()))?;
+        let path = self.path(serial);
+        std::fs::write(&path, toml::to_string_pretty(settings)?)
+            .with_context(|| format!("failed to write unit settings {}", path.display()))
+    }
+
+    /// Serial number of the unit with this alias.
+    pub fn serial_for_alias(&self, alias: &str) -> Result<Option<String>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "toml") {
+                continue;
+            }
+            let Some(serial) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            if let Some(settings) = self.load(serial)?
+                && settings.alias.as_deref() == Some(alias)
+            {
+                return Ok(Some(serial.to_string()));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Spd3303x {
+    /// Install the limits, calibration, lead resistance and quirks of
+    /// `settings`. Calibration runs before any pipeline already installed;
+    /// apply the settings once per handle, as a second call calibrates the
+    /// readings twice.
+    pub fn apply_unit_settings(&mut self, settings: &UnitSettings) -> Result<()> {
+        for channel in Channel::programmable() {
+            let Some(unit) = settings.channel(channel) else {
+                continue;
+            };
+            if let Some(limits) = unit.limits {
+                self.set_limits(channel, limits)?;
+            }
+            if let Some(calibration) = unit.calibration {
+                let mut pipeline = calibration.pipeline();
+                if let Some(installed) = self.pipeline(channel) {
+                    pipeline.extend(installed);
+                }
+                self.set_pipeline(channel, pipeline)?;
+            }
+            if let Some(ohms) = unit.wire_resistance_ohms {
+                self.set_wire_resistance(channel, ohms)?;
+            }
+        }
+        for quirk in &settings.quirks {
+            self.add_quirk(quirk.clone());
+        }
+        Ok(())
+    }
+
+    /// Read the identity and apply the store's settings for this unit, if
+    /// it has any.
+    #[cfg(feature = "config")]
+    pub async fn load_unit_settings(
+        &mut self,
+        store: &SettingsStore,
+    ) -> Result<Option<UnitSettings>> {
+        let identity = self.identity().await?;
+        let Some(settings) = store.load(&identity.serial)? else {
+            tracing::debug!("unit settings: none for {}", identity.serial);
+            return Ok(None);
+        };
+        tracing::debug!("unit settings: applying {}", store.path(&identity.serial).display());
+        self.apply_unit_settings(&settings)?;
+        Ok(Some(settings))
+    }
+}
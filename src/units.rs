@@ -0,0 +1,122 @@
+//! Quantities with units, as typed on a command line.
+//!
+//! A bare number is taken in the base unit (V, A, Ω, s). Otherwise the
+//! unit may follow the number with an optional `m`/`u`/`k` prefix
+//! (`500mA`, `1.5V`, `4.7k`), or stand in for the decimal point as on
+//! schematics (`3v3`, `4k7`, `4R7`). Durations are a sequence of parts in
+//! `h`, `m`, `s`, `ms` and `us`, e.g. `2m30s` or `250ms`. Units are
+//! matched case-insensitively, so `m` is always milli, never mega.
+//!
+//! The functions return `anyhow::Result` and can be used directly as clap
+//! value parsers.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+
+/// Volts, e.g. `3v3`, `3.3V`, `500mV` or `3.3`.
+pub fn parse_voltage(text: &str) -> Result<f64> {
+    parse_quantity(text, &["v"], "voltage")
+}
+
+/// Amperes, e.g. `500mA`, `0.5A`, `1a2` or `0.5`.
+pub fn parse_current(text: &str) -> Result<f64> {
+    parse_quantity(text, &["a"], "current")
+}
+
+/// Ohms, e.g. `4k7`, `4R7`, `10ohm`, `10Ω` or `10`.
+pub fn parse_resistance(text: &str) -> Result<f64> {
+    parse_quantity(text, &["ohms", "ohm", "ω", "r"], "resistance")
+}
+
+/// A duration such as `2m30s`, `1h`, `250ms`, `1.5s` or `90` (seconds).
+pub fn parse_duration(text: &str) -> Result<Duration> {
+    let invalid = || anyhow!("invalid duration {text:?}, expected e.g. 2m30s or 250ms");
+    let lower = text.trim().to_ascii_lowercase();
+    if lower.is_empty() {
+        return Err(invalid());
+    }
+    if let Ok(seconds) = lower.parse::<f64>() {
+        return seconds_to_duration(seconds).ok_or_else(invalid);
+    }
+
+    let mut total = 0.0;
+    let mut rest = lower.as_str();
+    while !rest.is_empty() {
+        let split = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .ok_or_else(invalid)?;
+        let (number, tail) = rest.split_at(split);
+        let value: f64 = number.parse().map_err(|_| invalid())?;
+        let unit_len = tail
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_len);
+        let scale = match unit {
+            "h" => 3600.0,
+            "m" | "min" => 60.0,
+            "s" => 1.0,
+            "ms" => 1e-3,
+            "us" | "µs" => 1e-6,
+            _ => return Err(invalid()),
+        };
+        total += value * scale;
+        rest = tail;
+    }
+    seconds_to_duration(total).ok_or_else(invalid)
+}
+
+/// `None` for negative, non-finite or out-of-range values such as `1e30`.
+fn seconds_to_duration(seconds: f64) -> Option<Duration> {
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+/// Parse `text` in the unit spelled by one of `units` (lowercase; `Ω`
+/// lowercases to `ω`).
+fn parse_quantity(text: &str, units: &[&str], name: &str) -> Result<f64> {
+    let invalid = || anyhow!("invalid {name} {text:?}");
+    let lower = text.trim().to_lowercase();
+    let mut number = lower.as_str();
+    for unit in units {
+        if let Some(stripped) = number.strip_suffix(unit) {
+            number = stripped;
+            break;
+        }
+    }
+
+    // `3v3`, `4k7`, `4r7`: the unit or prefix replaces the decimal point.
+    let infix = [units, &["k", "m", "u"][..]]
+        .concat()
+        .into_iter()
+        .find_map(|marker| {
+            let (whole, fraction) = number.split_once(marker)?;
+            let digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+            (digits(whole) && digits(fraction)).then(|| (marker, format!("{whole}.{fraction}")))
+        });
+    let (value, scale) = match &infix {
+        Some((marker, value)) => (value.as_str(), prefix_scale(marker).unwrap_or(1.0)),
+        None => match number
+            .char_indices()
+            .last()
+            .and_then(|(index, c)| Some((index, prefix_scale(&c.to_string())?)))
+        {
+            Some((index, scale)) => (&number[..index], scale),
+            None => (number, 1.0),
+        },
+    };
+    let value: f64 = value.trim().parse().map_err(|_| invalid())?;
+    let value = value * scale;
+    if !value.is_finite() || value < 0.0 {
+        return Err(anyhow!("{name} must be a finite, non-negative value, got {text:?}"));
+    }
+    Ok(value)
+}
+
+fn prefix_scale(prefix: &str) -> Option<f64> {
+    match prefix {
+        "k" => Some(1e3),
+        "m" => Some(1e-3),
+        "u" | "µ" => Some(1e-6),
+        _ => None,
+    }
+}
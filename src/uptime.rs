@@ -0,0 +1,107 @@
+//! Link bookkeeping and instrument uptime estimation.
+//!
+//! The SPD3303X has no clock or uptime query, so long logs cannot ask it
+//! when it last booted. What the host can know is when its link was
+//! opened (`Spd3303x::link_established_at`), how many commands went over
+//! it (`Spd3303x::command_counters`) and, from the [`Monitor`], when the
+//! instrument most likely rebooted: the supply always powers up with its
+//! outputs and timers off, so a poll gap after which outputs or timers that
+//! were on are all off is reported as [`Event::PowerCycleSuspected`]. A
+//! power cycle while everything was off cannot be told apart from no power
+//! cycle.
+//!
+//! [`Monitor`]: crate::monitor::Monitor
+//! [`Event::PowerCycleSuspected`]: crate::events::Event::PowerCycleSuspected
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::clock::{Clock, TokioClock};
+use crate::instrument::SystemStatus;
+
+/// Commands sent over the current handle since it was created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CommandCounters {
+    pub writes: u64,
+    pub queries: u64,
+    /// Writes and queries that failed, timeouts included.
+    pub errors: u64,
+    pub timeouts: u64,
+    pub reconnects: u64,
+    /// Writes and queries sent again under a retry policy.
+    pub retries: u64,
+}
+
+impl CommandCounters {
+    pub fn total(&self) -> u64 {
+        self.writes + self.queries
+    }
+}
+
+/// Lower bound of the instrument's uptime from successive polls.
+#[derive(Debug, Clone)]
+pub struct UptimeTracker {
+    clock: Arc<dyn Clock>,
+    /// When the instrument was first seen up, or last suspected rebooted.
+    since: Option<Instant>,
+    previous: Option<SystemStatus>,
+    /// Polls failed since the last successful one.
+    gap: bool,
+}
+
+impl Default for UptimeTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UptimeTracker {
+    pub fn new() -> Self {
+        Self::with_clock(Arc::new(TokioClock))
+    }
+
+    /// A tracker that reads the time from `clock`, normally the clock of
+    /// the polled handle.
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            clock,
+            since: None,
+            previous: None,
+            gap: false,
+        }
+    }
+
+    /// Note a failed poll; a reboot can only be inferred across one.
+    pub fn poll_failed(&mut self) {
+        self.gap = true;
+    }
+
+    /// Feed a successful poll; `true` if it suggests a power cycle since the
+    /// previous one.
+    pub fn observe(&mut self, status: &SystemStatus) -> bool {
+        let rebooted = self.gap
+            && self
+                .previous
+                .as_ref()
+                .is_some_and(|previous| anything_on(previous) && !anything_on(status));
+        if rebooted || self.since.is_none() {
+            self.since = Some(self.clock.now());
+        }
+        self.previous = Some(status.clone());
+        self.gap = false;
+        rebooted
+    }
+
+    /// Time the instrument has been up at least, as far as polls tell.
+    pub fn uptime(&self) -> Option<Duration> {
+        self.since.map(|since| self.clock.elapsed(since))
+    }
+}
+
+/// Whatever a power cycle is known to switch off.
+fn anything_on(status: &SystemStatus) -> bool {
+    status.ch1_output_on || status.ch2_output_on || status.timer1_on || status.timer2_on
+}
@@ -0,0 +1,131 @@
+//! SCPI over USB through the Linux `usbtmc` kernel driver.
+//!
+//! The supply's USB port is a USBTMC device; the driver exposes it as
+//! `/dev/usbtmcN`, where every write is one command message and every read
+//! returns (part of) one reply. Reads give up after the driver's timeout,
+//! 5 s unless changed. The device node is usually only accessible to root;
+//! a udev rule for Siglent's vendor id (`f4ec`) fixes that, e.g.
+//!
+//! ```text
+//! SUBSYSTEM=="usbmisc", ATTRS{idVendor}=="f4ec", MODE="0666"
+//! ```
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use tracing::debug;
+
+use crate::escalation::CommandTimedOut;
+use crate::response::EmptyResponse;
+use crate::transport::{Transport, TransportFuture};
+
+const MAX_READ: usize = 4096;
+/// The driver's default read timeout, reported when a read times out.
+const DRIVER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The `usbtmc` device nodes present, in name order.
+pub fn devices() -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir("/dev") else {
+        return Vec::new();
+    };
+    let mut devices: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("usbtmc"))
+        .map(|entry| entry.path())
+        .collect();
+    devices.sort();
+    devices
+}
+
+pub(crate) struct UsbtmcClient {
+    /// Taken while a blocking call is in flight.
+    file: Option<File>,
+}
+
+impl UsbtmcClient {
+    pub(crate) fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("failed to open USBTMC device {}", path.display()))?;
+        debug!("usbtmc: opened {}", path.display());
+        Ok(Self { file: Some(file) })
+    }
+
+    pub(crate) async fn write(&mut self, command: &str) -> Result<()> {
+        let message = format!("{}\n", command.trim_end_matches(['\r', '\n']));
+        self.blocking(move |file| file.write_all(message.as_bytes())).await
+    }
+
+    pub(crate) async fn query(&mut self, command: &str) -> Result<String> {
+        self.write(command).await?;
+        let reply = self
+            .blocking(|file| {
+                let mut reply = Vec::new();
+                let mut chunk = [0u8; MAX_READ];
+                loop {
+                    let read = file.read(&mut chunk)?;
+                    reply.extend_from_slice(&chunk[..read]);
+                    if read == 0 || reply.ends_with(b"\n") {
+                        return Ok(reply);
+                    }
+                }
+            })
+            .await
+            .map_err(|e| match e.downcast_ref::<std::io::Error>() {
+                Some(io) if io.kind() == std::io::ErrorKind::TimedOut => {
+                    CommandTimedOut::once(command, DRIVER_TIMEOUT).into()
+                }
+                _ => e.context("no reply from the USBTMC device"),
+            })?;
+        if reply.is_empty() {
+            return Err(EmptyResponse {
+                command: command.trim_end().to_string(),
+            }
+            .into());
+        }
+        Ok(String::from_utf8(reply)?)
+    }
+
+    pub(crate) async fn close(&mut self) -> Result<()> {
+        self.file = None;
+        Ok(())
+    }
+
+    /// Run `io` on the device file off the async runtime, since the driver
+    /// blocks until the instrument answers or times out.
+    async fn blocking<T: Send + 'static>(
+        &mut self,
+        io: impl FnOnce(&mut File) -> std::io::Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let mut file = self
+            .file
+            .take()
+            .ok_or_else(|| anyhow!("USBTMC device is closed"))?;
+        let (file, result) = tokio::task::spawn_blocking(move || {
+            let result = io(&mut file);
+            (file, result)
+        })
+        .await?;
+        self.file = Some(file);
+        Ok(result?)
+    }
+}
+
+impl Transport for UsbtmcClient {
+    fn write<'a>(&'a mut self, command: &'a str) -> TransportFuture<'a, ()> {
+        Box::pin(UsbtmcClient::write(self, command))
+    }
+
+    fn query<'a>(&'a mut self, command: &'a str) -> TransportFuture<'a, String> {
+        Box::pin(UsbtmcClient::query(self, command))
+    }
+
+    fn close(&mut self) -> TransportFuture<'_, ()> {
+        Box::pin(UsbtmcClient::close(self))
+    }
+}
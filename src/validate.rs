@@ -0,0 +1,592 @@
+//! Pure validators for setpoints and command arguments.
+//!
+//! These are the rules the `Spd3303x` setters enforce before anything is
+//! sent, exposed so front-ends can validate input (e.g. while the user is
+//! typing) without talking to the instrument.
+//!
+//! Non-finite and negative values are always rejected. In strict mode
+//! (`Spd3303x::set_strict`) setpoints must also be exact multiples of the
+//! model's setting resolution instead of being rounded by the instrument.
+//!
+//! The same rules run on the wire as the first middleware of every handle
+//! (`SafetyLayer`), so raw writes and every typed setter are held to them
+//! alike.
+
+use std::fmt;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+
+use crate::instrument::{
+    setpoint_change, Channel, MAX_CURRENT_A, MAX_TIMER_DURATION, MAX_VOLTAGE_V,
+    TIMER_RESOLUTION,
+};
+use crate::middleware::{HookFuture, Middleware};
+use crate::pipeline::Quantity;
+
+/// Fraction of a resolution step tolerated by [`check_precision`].
+const PRECISION_TOLERANCE_STEPS: f64 = 1e-6;
+
+/// What the attached model can do.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Capabilities {
+    /// Channels accepting voltage/current setpoints and measurements.
+    pub programmable: Vec<Channel>,
+    pub max_voltage_v: f64,
+    pub max_current_a: f64,
+    /// Setting resolution, enforced in strict mode.
+    pub voltage_resolution_v: f64,
+    pub current_resolution_a: f64,
+}
+
+impl Capabilities {
+    /// SPD3303X: CH1/CH2 0–32 V / 0–3.2 A in 1 mV / 1 mA steps, CH3 fixed.
+    pub fn spd3303x() -> Self {
+        Self {
+            programmable: Channel::programmable().collect(),
+            max_voltage_v: MAX_VOLTAGE_V,
+            max_current_a: MAX_CURRENT_A,
+            voltage_resolution_v: 0.001,
+            current_resolution_a: 0.001,
+        }
+    }
+
+    /// SPD3303X-E: as the SPD3303X, but in 10 mV / 10 mA steps.
+    pub fn spd3303x_e() -> Self {
+        Self {
+            voltage_resolution_v: 0.01,
+            current_resolution_a: 0.01,
+            ..Self::spd3303x()
+        }
+    }
+
+    /// Setting resolution of a voltage or current; `None` for power.
+    pub fn resolution(&self, quantity: Quantity) -> Option<f64> {
+        match quantity {
+            Quantity::Voltage => Some(self.voltage_resolution_v),
+            Quantity::Current => Some(self.current_resolution_a),
+            Quantity::Power => None,
+        }
+    }
+
+    /// Round a computed setpoint to the setting resolution, so it passes
+    /// strict mode.
+    pub fn quantize(&self, quantity: Quantity, value: f64) -> f64 {
+        match self.resolution(quantity) {
+            // Dividing by the reciprocal keeps e.g. 3200 steps of 1 mA at
+            // exactly 3.2, where multiplying would land just above it.
+            Some(step) if step > 0.0 => (value / step).round() / step.recip(),
+            _ => value,
+        }
+    }
+
+    pub fn is_programmable(&self, channel: Channel) -> bool {
+        self.programmable.contains(&channel)
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self::spd3303x()
+    }
+}
+
+/// User-imposed limits for one channel, tighter than the rated range.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Limits {
+    pub max_voltage_v: Option<f64>,
+    pub max_current_a: Option<f64>,
+    /// Applied to `V·I` of a combined setpoint (see [`check_setpoint`]).
+    pub max_power_w: Option<f64>,
+}
+
+impl Limits {
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    /// Highest voltage setpoint allowed by both the model and these limits.
+    pub fn effective_max_voltage(&self, capabilities: &Capabilities) -> f64 {
+        self.max_voltage_v
+            .map_or(capabilities.max_voltage_v, |v| v.min(capabilities.max_voltage_v))
+    }
+
+    /// Highest current setpoint allowed by both the model and these limits.
+    pub fn effective_max_current(&self, capabilities: &Capabilities) -> f64 {
+        self.max_current_a
+            .map_or(capabilities.max_current_a, |a| a.min(capabilities.max_current_a))
+    }
+}
+
+/// `channel` may only be on while `requires` is on, e.g. a DUT's IO rail
+/// that must never be powered without its core rail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EnableDependency {
+    pub channel: Channel,
+    pub requires: Channel,
+}
+
+/// Channels the host may control, e.g. to keep automation off a rail
+/// that is wired and switched by hand, and the order they may be on in.
+/// Reads are always allowed.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ChannelPolicy {
+    pub denied: Vec<Channel>,
+    pub dependencies: Vec<EnableDependency>,
+}
+
+impl ChannelPolicy {
+    pub fn allow_all() -> Self {
+        Self::default()
+    }
+
+    /// Deny every channel but `channels`.
+    pub fn allow_only(channels: impl IntoIterator<Item = Channel>) -> Self {
+        let allowed: Vec<Channel> = channels.into_iter().collect();
+        Self {
+            denied: Channel::all().filter(|ch| !allowed.contains(ch)).collect(),
+            dependencies: Vec::new(),
+        }
+    }
+
+    pub fn forbid(mut self, channel: Channel) -> Self {
+        if !self.denied.contains(&channel) {
+            self.denied.push(channel);
+        }
+        self
+    }
+
+    /// Let `channel` be on only while `requires` is on. The prerequisite
+    /// must be CH1 or CH2, whose output state can be read back.
+    pub fn require_on(mut self, channel: Channel, requires: Channel) -> Self {
+        let dependency = EnableDependency { channel, requires };
+        if !self.dependencies.contains(&dependency) {
+            self.dependencies.push(dependency);
+        }
+        self
+    }
+
+    pub fn permits(&self, channel: Channel) -> bool {
+        !self.denied.contains(&channel)
+    }
+
+    /// Channels that must be on before `channel` is switched on.
+    pub fn prerequisites(&self, channel: Channel) -> impl Iterator<Item = Channel> + '_ {
+        self.dependencies
+            .iter()
+            .filter(move |dep| dep.channel == channel)
+            .map(|dep| dep.requires)
+    }
+
+    /// Channels that have to go off when `channel` does, directly or
+    /// through other dependencies, in the order to switch them off.
+    pub fn dependents(&self, channel: Channel) -> Vec<Channel> {
+        let mut found: Vec<Channel> = Vec::new();
+        let mut next = 0;
+        let mut current = channel;
+        loop {
+            for dep in &self.dependencies {
+                let dependent = dep.channel;
+                if dep.requires == current && dependent != channel && !found.contains(&dependent) {
+                    found.push(dependent);
+                }
+            }
+            let Some(&following) = found.get(next) else {
+                break;
+            };
+            current = following;
+            next += 1;
+        }
+        found.reverse();
+        found
+    }
+
+    /// First denied channel a raw SCPI command affects: the channels it
+    /// names (`CH1`..`CH3`), every channel for `*RST`, and CH1 and CH2 for
+    /// `*RCL`, `OUTP:TRACK` and the bare `VOLT`/`CURR` forms, which apply to
+    /// whichever channel is selected.
+    pub fn denied_in_command(&self, command: &str) -> Option<Channel> {
+        let named = command
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter_map(|word| {
+                Channel::all().find(|ch| ch.label().eq_ignore_ascii_case(word))
+            });
+        let implied = command.split(';').flat_map(|part| {
+            let header = part.split_whitespace().next().unwrap_or_default();
+            let header = header.trim_start_matches(':').to_ascii_uppercase();
+            let channels: Vec<Channel> = if header.starts_with("*RST") {
+                Channel::all().collect()
+            } else if header.starts_with("*RCL")
+                || header.starts_with("OUTP:TRACK")
+                || header.starts_with("OUTPUT:TRACK")
+                || header.starts_with("VOLT")
+                || header.starts_with("CURR")
+            {
+                Channel::programmable().collect()
+            } else {
+                Vec::new()
+            };
+            channels
+        });
+        named.chain(implied).find(|ch| !self.permits(*ch))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Violation {
+    UnsupportedChannel(Channel),
+    ChannelForbidden(Channel),
+    DependencyOff { channel: Channel, requires: Channel },
+    NotFinite { quantity: Quantity, value: f64 },
+    Negative { quantity: Quantity, value: f64 },
+    AboveRated { quantity: Quantity, value: f64, max: f64 },
+    AboveLimit { quantity: Quantity, value: f64, limit: f64 },
+    SlotOutOfRange(u8),
+    GroupOutOfRange(u8),
+    DurationTooLong { duration: Duration, max: Duration },
+    DurationResolution { duration: Duration, resolution: Duration },
+    TooPrecise { quantity: Quantity, value: f64, resolution: f64 },
+    /// A raw command whose effect on the setpoints cannot be checked.
+    UncheckedCommand(String),
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::UnsupportedChannel(ch) => {
+                write!(f, "channel {} does not support this command", ch.label())
+            }
+            Violation::ChannelForbidden(ch) => {
+                write!(f, "control of {} is forbidden by the channel policy", ch.label())
+            }
+            Violation::DependencyOff { channel, requires } => write!(
+                f,
+                "{} may only be on while {} is on",
+                channel.label(),
+                requires.label()
+            ),
+            Violation::NotFinite { quantity, value } => {
+                write!(f, "{} must be a finite number, got {value}", quantity.name())
+            }
+            Violation::Negative { quantity, value } => {
+                write!(f, "{} must not be negative, got {value}", quantity.name())
+            }
+            Violation::AboveRated {
+                quantity,
+                value,
+                max,
+            } => write!(
+                f,
+                "{} {value} {} exceeds the rated maximum of {max} {}",
+                quantity.name(),
+                quantity.unit(),
+                quantity.unit()
+            ),
+            Violation::AboveLimit {
+                quantity,
+                value,
+                limit,
+            } => write!(
+                f,
+                "{} {value} {} exceeds the configured limit of {limit} {}",
+                quantity.name(),
+                quantity.unit(),
+                quantity.unit()
+            ),
+            Violation::SlotOutOfRange(_) => write!(f, "slot must be 1..=5"),
+            Violation::GroupOutOfRange(_) => write!(f, "timer group must be 1..=5"),
+            Violation::DurationTooLong { duration, max } => write!(
+                f,
+                "timer duration {duration:?} exceeds the maximum of {max:?}"
+            ),
+            Violation::DurationResolution {
+                duration,
+                resolution,
+            } => write!(
+                f,
+                "timer duration {duration:?} is not a multiple of {resolution:?}"
+            ),
+            Violation::TooPrecise {
+                quantity,
+                value,
+                resolution,
+            } => write!(
+                f,
+                "{} {value} {} is finer than the setting resolution of {resolution} {}",
+                quantity.name(),
+                quantity.unit(),
+                quantity.unit()
+            ),
+            Violation::UncheckedCommand(command) => write!(
+                f,
+                "{command:?} cannot be checked against the setpoint limits; \
+                 send plain CHn:VOLT/CHn:CURR values, one command at a time"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Violation {}
+
+pub fn check_channel(channel: Channel, capabilities: &Capabilities) -> Vec<Violation> {
+    if capabilities.is_programmable(channel) {
+        Vec::new()
+    } else {
+        vec![Violation::UnsupportedChannel(channel)]
+    }
+}
+
+pub fn check_voltage(
+    channel: Channel,
+    volts: f64,
+    capabilities: &Capabilities,
+    limits: &Limits,
+) -> Vec<Violation> {
+    let mut violations = check_channel(channel, capabilities);
+    check_value(
+        &mut violations,
+        Quantity::Voltage,
+        volts,
+        capabilities.max_voltage_v,
+        limits.max_voltage_v,
+    );
+    violations
+}
+
+pub fn check_current(
+    channel: Channel,
+    amps: f64,
+    capabilities: &Capabilities,
+    limits: &Limits,
+) -> Vec<Violation> {
+    let mut violations = check_channel(channel, capabilities);
+    check_value(
+        &mut violations,
+        Quantity::Current,
+        amps,
+        capabilities.max_current_a,
+        limits.max_current_a,
+    );
+    violations
+}
+
+/// Validate a voltage/current pair, including `V·I` against the power limit.
+pub fn check_setpoint(
+    channel: Channel,
+    volts: f64,
+    amps: f64,
+    capabilities: &Capabilities,
+    limits: &Limits,
+) -> Vec<Violation> {
+    let mut violations = check_channel(channel, capabilities);
+    check_value(
+        &mut violations,
+        Quantity::Voltage,
+        volts,
+        capabilities.max_voltage_v,
+        limits.max_voltage_v,
+    );
+    check_value(
+        &mut violations,
+        Quantity::Current,
+        amps,
+        capabilities.max_current_a,
+        limits.max_current_a,
+    );
+    if let Some(limit) = limits.max_power_w {
+        let watts = volts * amps;
+        if watts > limit {
+            violations.push(Violation::AboveLimit {
+                quantity: Quantity::Power,
+                value: watts,
+                limit,
+            });
+        }
+    }
+    violations
+}
+
+/// Strict-mode check that `value` is a whole number of resolution steps.
+pub fn check_precision(
+    quantity: Quantity,
+    value: f64,
+    capabilities: &Capabilities,
+) -> Vec<Violation> {
+    let Some(resolution) = capabilities.resolution(quantity).filter(|&step| step > 0.0) else {
+        return Vec::new();
+    };
+    let steps = value / resolution;
+    // Allow for binary representation error, e.g. 3.3 / 0.001.
+    if (steps - steps.round()).abs() > PRECISION_TOLERANCE_STEPS {
+        vec![Violation::TooPrecise {
+            quantity,
+            value,
+            resolution,
+        }]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Limits themselves must be finite and non-negative; a NaN limit would
+/// silently never trip.
+pub fn check_limits(limits: &Limits) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    for (quantity, value) in [
+        (Quantity::Voltage, limits.max_voltage_v),
+        (Quantity::Current, limits.max_current_a),
+        (Quantity::Power, limits.max_power_w),
+    ] {
+        match value {
+            Some(value) if !value.is_finite() => {
+                violations.push(Violation::NotFinite { quantity, value })
+            }
+            Some(value) if value < 0.0 => violations.push(Violation::Negative { quantity, value }),
+            _ => {}
+        }
+    }
+    violations
+}
+
+/// `*SAV` / `*RCL` slots are 1..=5.
+pub fn check_slot(slot: u8) -> Vec<Violation> {
+    if (1..=5).contains(&slot) {
+        Vec::new()
+    } else {
+        vec![Violation::SlotOutOfRange(slot)]
+    }
+}
+
+/// Whether `policy` lets the host control `channel`.
+pub fn check_channel_policy(channel: Channel, policy: &ChannelPolicy) -> Vec<Violation> {
+    if policy.permits(channel) {
+        Vec::new()
+    } else {
+        vec![Violation::ChannelForbidden(channel)]
+    }
+}
+
+/// Timer groups are 1..=5.
+pub fn check_group(group: u8) -> Vec<Violation> {
+    if (1..=5).contains(&group) {
+        Vec::new()
+    } else {
+        vec![Violation::GroupOutOfRange(group)]
+    }
+}
+
+/// Timer group durations are limited to 10000 s in 1 ms steps.
+pub fn check_timer_duration(duration: Duration) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    if duration > MAX_TIMER_DURATION {
+        violations.push(Violation::DurationTooLong {
+            duration,
+            max: MAX_TIMER_DURATION,
+        });
+    }
+    if duration.as_nanos() % TIMER_RESOLUTION.as_nanos() != 0 {
+        violations.push(Violation::DurationResolution {
+            duration,
+            resolution: TIMER_RESOLUTION,
+        });
+    }
+    violations
+}
+
+/// The channel policy, limits and precision rules as a [`Middleware`], run
+/// by the handle before every gated write and its own middleware.
+///
+/// A write naming a denied channel is refused. `[CHn:]VOLT` and
+/// `[CHn:]CURR` writes are checked like `set_voltage`/`set_current`, the
+/// bare forms against both channels; compound commands are refused while
+/// limits are installed, since their setpoints cannot be checked.
+pub(crate) struct SafetyLayer<'a> {
+    pub(crate) capabilities: &'a Capabilities,
+    /// CH1 and CH2.
+    pub(crate) limits: [Limits; 2],
+    pub(crate) policy: &'a ChannelPolicy,
+    pub(crate) strict: bool,
+}
+
+impl SafetyLayer<'_> {
+    fn check(&self, command: &str) -> Result<()> {
+        if let Some(channel) = self.policy.denied_in_command(command) {
+            return Err(Violation::ChannelForbidden(channel).into());
+        }
+        let unchecked = || Violation::UncheckedCommand(command.trim().to_string());
+        let limited = self.limits.iter().any(|limits| *limits != Limits::unlimited());
+        if command.contains(';') && limited {
+            return Err(unchecked().into());
+        }
+        let Some((named, quantity, value)) = setpoint_change(command) else {
+            return Ok(());
+        };
+        let value = value.ok_or_else(unchecked)?;
+        for (channel, limits) in Channel::programmable().zip(&self.limits) {
+            if named.is_some_and(|named| named != channel) {
+                continue;
+            }
+            let mut violations = match quantity {
+                Quantity::Current => check_current(channel, value, self.capabilities, limits),
+                _ => check_voltage(channel, value, self.capabilities, limits),
+            };
+            if self.strict {
+                violations.extend(check_precision(quantity, value, self.capabilities));
+            }
+            ensure(violations)?;
+        }
+        Ok(())
+    }
+}
+
+impl Middleware for SafetyLayer<'_> {
+    fn before_write<'a>(&'a self, command: &'a str) -> HookFuture<'a> {
+        let result = self.check(command);
+        Box::pin(async move { result })
+    }
+}
+
+/// Turn the result of a `check_*` call into an error carrying the first
+/// violation, for use in setters.
+pub fn ensure(violations: Vec<Violation>) -> Result<()> {
+    match violations.into_iter().next() {
+        None => Ok(()),
+        Some(violation) => Err(anyhow!(violation)),
+    }
+}
+
+fn check_value(
+    violations: &mut Vec<Violation>,
+    quantity: Quantity,
+    value: f64,
+    rated_max: f64,
+    limit: Option<f64>,
+) {
+    if !value.is_finite() {
+        violations.push(Violation::NotFinite { quantity, value });
+        return;
+    }
+    if value < 0.0 {
+        violations.push(Violation::Negative { quantity, value });
+    }
+    if value > rated_max {
+        violations.push(Violation::AboveRated {
+            quantity,
+            value,
+            max: rated_max,
+        });
+    }
+    if let Some(limit) = limit.filter(|&limit| value > limit) {
+        violations.push(Violation::AboveLimit {
+            quantity,
+            value,
+            limit,
+        });
+    }
+}
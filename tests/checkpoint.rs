@@ -0,0 +1,33 @@
+//! [`Sequence::fingerprint`], which checkpoints written by other builds of
+//! the crate are matched against.
+
+use std::time::Duration;
+
+use spd3303x_control::instrument::{Channel, OutputState};
+use spd3303x_control::sequence::{Condition, Sequence, Step};
+
+fn sequence(volts: f64) -> Sequence {
+    Sequence::new()
+        .step(
+            Step::new()
+                .label("start")
+                .voltage(Channel::Ch1, volts)
+                .output(Channel::Ch1, OutputState::On)
+                .hold(Duration::from_secs(1)),
+        )
+        .step(Step::new().wait_until(
+            Condition::CurrentBelow(Channel::Ch1, 0.1),
+            Duration::from_secs(5),
+        ))
+}
+
+#[test]
+fn fingerprint_is_pinned() {
+    // Changing this value orphans every checkpoint on disk.
+    assert_eq!(sequence(3.3).fingerprint(), 0x95c5_9c35_d422_0c2f);
+}
+
+#[test]
+fn fingerprint_follows_the_setpoints() {
+    assert_ne!(sequence(3.3).fingerprint(), sequence(3.4).fingerprint());
+}
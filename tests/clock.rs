@@ -0,0 +1,95 @@
+//! [`MockClock`] and [`Ticker`], and a handle running on a mock clock.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use spd3303x_control::clock::{Clock, MockClock, Ticker};
+use spd3303x_control::instrument::{Channel, Spd3303x};
+use spd3303x_control::mock::MockDevice;
+use spd3303x_control::monitor::{Monitor, MonitorConfig, StaleData};
+use spd3303x_control::script::ScriptOptions;
+use tokio::sync::Mutex;
+
+#[tokio::test]
+async fn mock_clock_wakes_sleeps_when_advanced_past_them() {
+    let clock = MockClock::new();
+    let started = clock.now();
+    let sleep = tokio::spawn(clock.sleep(Duration::from_secs(10)));
+
+    clock.advance(Duration::from_secs(5)).await;
+    assert!(!sleep.is_finished());
+    assert_eq!(clock.elapsed(started), Duration::from_secs(5));
+    clock.advance(Duration::from_secs(5)).await;
+    sleep.await.unwrap();
+}
+
+#[tokio::test]
+async fn ticker_starts_at_once_and_lets_a_late_tick_push_the_next() {
+    let clock = MockClock::new();
+    let start = clock.now();
+    let second = Duration::from_secs(1);
+    let mut ticker = Ticker::new(Arc::new(clock.clone()), second);
+    assert_eq!(ticker.tick().await, start);
+
+    let next = tokio::spawn(async move { (ticker.tick().await, ticker) });
+    clock.advance(Duration::from_millis(2500)).await;
+    let (due, mut ticker) = next.await.unwrap();
+    assert_eq!(due, start + second);
+
+    // Due a period after the late tick, not on the original grid.
+    let next = tokio::spawn(async move { ticker.tick().await });
+    clock.advance(Duration::from_millis(999)).await;
+    assert!(!next.is_finished());
+    clock.advance(Duration::from_millis(1)).await;
+    assert_eq!(next.await.unwrap(), start + Duration::from_millis(3500));
+}
+
+#[tokio::test]
+async fn handle_waits_on_its_own_clock() {
+    let device = MockDevice::new();
+    let clock = MockClock::new();
+    let mut inst = Spd3303x::mock(&device);
+    inst.set_clock(Arc::new(clock.clone()));
+    let script = tokio::spawn(async move {
+        let options = ScriptOptions::default().delay_between(Duration::from_secs(3600));
+        let report = inst.run_script_text("CH1:VOLT 1\nCH1:VOLT?\n", &options).await;
+        (report, inst)
+    });
+
+    let started = clock.now();
+    clock.advance(Duration::from_secs(3599)).await;
+    assert!(!script.is_finished());
+    while !script.is_finished() {
+        clock.advance(Duration::from_secs(1)).await;
+    }
+    assert!(clock.elapsed(started) >= Duration::from_secs(3600));
+    let (report, mut inst) = script.await.unwrap();
+    assert_eq!(report.unwrap().errors().count(), 0);
+    assert_eq!(inst.setpoint_voltage(Channel::Ch1).await.unwrap(), 1.0);
+}
+
+#[tokio::test]
+async fn monitor_ages_samples_on_the_handle_clock() {
+    let device = MockDevice::new();
+    let clock = MockClock::new();
+    let mut inst = Spd3303x::mock(&device);
+    inst.set_clock(Arc::new(clock.clone()));
+    let config = MonitorConfig {
+        interval: Duration::from_secs(60),
+        ..MonitorConfig::default()
+    };
+    let monitor = Monitor::start(Arc::new(Mutex::new(inst)), config);
+    while monitor.latest().is_none() {
+        tokio::task::yield_now().await;
+    }
+    let sample = monitor.latest_within(Duration::from_secs(1)).unwrap();
+    assert_eq!(sample.age(), Duration::ZERO);
+
+    clock.advance(Duration::from_secs(5)).await;
+    assert_eq!(sample.age(), Duration::from_secs(5));
+    assert_eq!(monitor.uptime(), Some(Duration::from_secs(5)));
+    let error = monitor.latest_within(Duration::from_secs(1)).unwrap_err();
+    let stale = error.downcast_ref::<StaleData>().unwrap();
+    assert_eq!(stale.age, Some(Duration::from_secs(5)));
+    monitor.stop().await.unwrap();
+}
@@ -0,0 +1,30 @@
+//! Control loops on a [`MockDevice`].
+
+use std::time::Duration;
+
+use spd3303x_control::control::{ControlOptions, Feedback, SourceResistance};
+use spd3303x_control::instrument::{Channel, OutputState, Spd3303x};
+use spd3303x_control::mock::MockDevice;
+
+#[tokio::test]
+async fn source_resistance_restores_v0_when_the_loop_fails() {
+    let device = MockDevice::new();
+    device.set_load(Channel::Ch1, Some(10.0)).unwrap();
+    let mut inst = Spd3303x::mock(&device);
+    inst.set_voltage(Channel::Ch1, 5.0).await.unwrap();
+    inst.set_output(Channel::Ch1, OutputState::On).await.unwrap();
+
+    // The first reading sags the setpoint to 4 V, the second fails.
+    let mut readings = vec![Err(anyhow::anyhow!("DMM unplugged")), Ok(0.5)];
+    let mut feedback = Feedback::current(move || std::future::ready(readings.pop().unwrap()));
+    let options = ControlOptions {
+        interval: Duration::from_millis(10),
+        duration: Duration::from_secs(5),
+    };
+    let mut control = SourceResistance::new(2.0);
+    let result = inst
+        .run_control_loop_with_feedback(Channel::Ch1, &mut control, options, &mut feedback)
+        .await;
+    assert!(format!("{:#}", result.unwrap_err()).contains("DMM unplugged"));
+    assert_eq!(inst.setpoint_voltage(Channel::Ch1).await.unwrap(), 5.0);
+}
@@ -0,0 +1,32 @@
+//! [`Spd3303xError`] as a view of the crate's `anyhow` errors.
+
+use anyhow::Context;
+use spd3303x_control::error::Spd3303xError;
+use spd3303x_control::instrument::{Channel, Spd3303x};
+use spd3303x_control::mock::MockDevice;
+use spd3303x_control::validate::Violation;
+
+#[tokio::test]
+async fn classify_keeps_the_context() {
+    let device = MockDevice::new();
+    let mut inst = Spd3303x::mock(&device);
+    inst.forbid(Channel::Ch2);
+    let error = inst
+        .set_voltage(Channel::Ch2, 1.0)
+        .await
+        .context("bring-up failed")
+        .unwrap_err();
+
+    let Some(Spd3303xError::Validation(violation)) = Spd3303xError::classify(&error) else {
+        panic!("not classified as a validation error: {error:#}");
+    };
+    assert_eq!(violation, Violation::ChannelForbidden(Channel::Ch2));
+    assert!(format!("{error:#}").starts_with("bring-up failed: "));
+
+    let wrapped = anyhow::Error::from(Spd3303xError::from(error)).context("outer");
+    assert!(matches!(
+        Spd3303xError::classify(&wrapped),
+        Some(Spd3303xError::Validation(_))
+    ));
+    assert!(Spd3303xError::classify(&anyhow::anyhow!("plain")).is_none());
+}
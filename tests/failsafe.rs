@@ -0,0 +1,55 @@
+//! [`FailsafeConfig`] applied through [`Spd3303x::apply_failsafe_config`].
+
+use spd3303x_control::failsafe::{FailsafeChannel, FailsafeConfig};
+use spd3303x_control::instrument::{Channel, Spd3303x, TrackMode};
+use spd3303x_control::mock::MockDevice;
+use spd3303x_control::validate::Limits;
+
+#[tokio::test]
+async fn failsafe_leaves_denied_channels_alone() {
+    let device = MockDevice::new();
+    let mut inst = Spd3303x::mock(&device);
+    inst.forbid(Channel::Ch2);
+    let config = FailsafeConfig {
+        track_mode: Some(TrackMode::Independent),
+        ch1: FailsafeChannel {
+            voltage_v: 3.3,
+            current_a: 0.1,
+            output: true,
+            limits: None,
+        },
+        ch2: FailsafeChannel {
+            voltage_v: 5.0,
+            output: true,
+            ..FailsafeChannel::default()
+        },
+        ch3_output: false,
+    };
+    inst.apply_failsafe_config(&config).await.unwrap();
+    assert_eq!(inst.setpoint_voltage(Channel::Ch1).await.unwrap(), 3.3);
+    // The skipped track mode keeps both programmable outputs off.
+    let status = inst.system_status().await.unwrap();
+    assert_eq!(status.output_on(Channel::Ch1), Some(false));
+}
+
+#[tokio::test]
+async fn failsafe_setpoints_are_checked_against_installed_limits() {
+    let device = MockDevice::new();
+    let mut inst = Spd3303x::mock(&device);
+    let limits = Limits {
+        max_voltage_v: Some(3.0),
+        ..Limits::unlimited()
+    };
+    inst.set_limits(Channel::Ch1, limits).unwrap();
+    let config = FailsafeConfig {
+        ch1: FailsafeChannel {
+            voltage_v: 5.0,
+            output: true,
+            ..FailsafeChannel::default()
+        },
+        ..FailsafeConfig::default()
+    };
+    assert!(inst.apply_failsafe_config(&config).await.is_err());
+    let status = inst.system_status().await.unwrap();
+    assert_eq!(status.output_on(Channel::Ch1), Some(false));
+}
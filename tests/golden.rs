@@ -0,0 +1,66 @@
+//! Golden transcripts: the exact bytes each typed operation puts on the
+//! wire, recorded by a [`MockDevice`] and compared with the files in
+//! `tests/golden/`.
+//!
+//! After an intended change to what an operation sends, rewrite the files
+//! and review the diff:
+//!
+//! ```text
+//! SPD3303X_BLESS=1 cargo test --test golden
+//! ```
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use spd3303x_control::instrument::{
+    Channel, DhcpState, OutputState, Spd3303x, TimerState, TrackMode,
+};
+use spd3303x_control::mock::MockDevice;
+
+fn golden_file(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{name}.scpi"))
+}
+
+/// A test named after the operation, running `$op($args)` against a fresh
+/// mock and pinned to `tests/golden/$op.scpi`.
+macro_rules! golden {
+    ($op:ident($($arg:expr),* $(,)?)) => {
+        #[tokio::test]
+        async fn $op() {
+            let device = MockDevice::new();
+            device.record_wire(true);
+            let mut inst = Spd3303x::mock(&device);
+            inst.$op($($arg),*).await.unwrap();
+            device.check_golden(golden_file(stringify!($op))).unwrap();
+        }
+    };
+}
+
+golden!(set_voltage(Channel::Ch1, 5.0));
+golden!(set_current(Channel::Ch2, 0.25));
+golden!(set_output(Channel::Ch1, OutputState::On));
+golden!(force_output_off(Channel::Ch3));
+golden!(set_track_mode(TrackMode::Series));
+golden!(set_wave_display(Channel::Ch2, OutputState::On));
+golden!(select_channel(Channel::Ch2));
+golden!(save_state(3));
+golden!(recall_state(3));
+golden!(timer_set(
+    Channel::Ch1,
+    2,
+    3.3,
+    0.5,
+    Duration::from_millis(1500)
+));
+golden!(timer_state(Channel::Ch1, TimerState::On));
+golden!(set_ip("192.168.1.60"));
+golden!(set_dhcp(DhcpState::On));
+golden!(identity());
+golden!(system_status());
+golden!(measured_power(Some(Channel::Ch1)));
+golden!(channel_status(Channel::Ch1));
+golden!(read_all_timers(Channel::Ch2));
+golden!(network_config());
+golden!(soft_reset());
@@ -0,0 +1,92 @@
+//! [`PowerProfile`] text and the [`power_profile!`] macro.
+
+use std::time::Duration;
+
+use spd3303x_control::instrument::{Channel, OutputState};
+use spd3303x_control::power_profile;
+use spd3303x_control::profile::{MAX_RAMP_STEPS, PowerProfile};
+use spd3303x_control::sequence::Action;
+
+fn actions(profile: PowerProfile) -> Vec<(Vec<String>, Duration)> {
+    profile
+        .into_sequence()
+        .steps
+        .into_iter()
+        .map(|step| {
+            let actions = step.actions.iter().map(|action| format!("{action:?}")).collect();
+            (actions, step.hold)
+        })
+        .collect()
+}
+
+#[test]
+fn macro_reads_the_profile_as_written() {
+    let profile = power_profile!(
+        Channel::Ch1,
+        3.3V@1A for 5s -> ramp to 5V over 200ms -> hold 1m30s -> off
+    )
+    .unwrap();
+    assert_eq!(profile.duration(), Duration::from_millis(95_200));
+
+    let steps = actions(profile);
+    assert_eq!(steps.len(), 5);
+    assert_eq!(
+        steps[0],
+        (
+            vec![
+                format!("{:?}", Action::SetVoltage(Channel::Ch1, 3.3)),
+                format!("{:?}", Action::SetCurrent(Channel::Ch1, 1.0)),
+                format!("{:?}", Action::Output(Channel::Ch1, OutputState::On)),
+            ],
+            Duration::from_secs(5)
+        )
+    );
+    assert_eq!(steps[1].1, Duration::from_millis(100));
+    assert_eq!(steps[2].0, vec![format!("{:?}", Action::SetVoltage(Channel::Ch1, 5.0))]);
+    assert_eq!(steps[3], (Vec::new(), Duration::from_secs(90)));
+    assert_eq!(
+        steps[4].0,
+        vec![format!("{:?}", Action::Output(Channel::Ch1, OutputState::Off))]
+    );
+}
+
+#[test]
+fn macro_matches_the_parser() {
+    let from_macro = power_profile!(Channel::Ch2, 3v3 -> on -> 500mA for 2s).unwrap();
+    let parsed = PowerProfile::parse(Channel::Ch2, "3v3 -> ON -> 500 mA for 2 s").unwrap();
+    assert_eq!(actions(from_macro), actions(parsed));
+}
+
+#[test]
+fn parser_names_the_bad_segment() {
+    for (text, segment) in [
+        ("3.3V -> ramp to 5V in 2s", "ramp to 5V in 2s"),
+        ("3.3V -> -> off", ""),
+        ("3.3X@1A", "3.3X@1A"),
+        ("hold forever", "hold forever"),
+    ] {
+        let error = PowerProfile::parse(Channel::Ch1, text).unwrap_err();
+        assert_eq!(error.to_string(), format!("invalid profile segment {segment:?}"));
+    }
+}
+
+#[test]
+fn ramp_starts_from_the_previous_setpoint() {
+    let profile = PowerProfile::new(Channel::Ch1)
+        .voltage(1.0)
+        .ramp_step(Duration::from_secs(1))
+        .ramp_to(3.0, Duration::from_secs(2));
+    let steps = actions(profile);
+    assert_eq!(steps[1].0, vec![format!("{:?}", Action::SetVoltage(Channel::Ch1, 2.0))]);
+    assert_eq!(steps[2].0, vec![format!("{:?}", Action::SetVoltage(Channel::Ch1, 3.0))]);
+}
+
+#[test]
+fn long_ramps_are_capped() {
+    let hours = Duration::from_secs(1000 * 3600);
+    let profile = PowerProfile::parse(Channel::Ch1, "ramp to 5V over 1000h").unwrap();
+    assert_eq!(profile.duration(), hours);
+    let sequence = profile.into_sequence();
+    assert_eq!(sequence.steps.len(), MAX_RAMP_STEPS as usize);
+    assert_eq!(sequence.steps[0].hold, hours / MAX_RAMP_STEPS);
+}
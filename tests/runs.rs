@@ -0,0 +1,37 @@
+//! The run database; needs the `sqlite` feature.
+//!
+//! ```text
+//! cargo test --features sqlite --test runs
+//! ```
+
+#![cfg(feature = "sqlite")]
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use spd3303x_control::runs::{RunDb, RunFilter, RunRecord};
+
+#[test]
+fn runs_round_trip_with_multiline_notes_and_nan_metrics() {
+    let mut db = RunDb::open_in_memory().unwrap();
+    let record = RunRecord::new("soak", "overnight")
+        .started_at(UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+        .duration(Duration::from_secs(3600))
+        .passed(true)
+        .metric("ripple_mv", 12.5)
+        .metric("efficiency", f64::NAN)
+        .note("fan noise at 02:00\nrecovered by 02:05")
+        .note("second note");
+    let id = db.insert(&record).unwrap();
+
+    let stored = db.get(id).unwrap().unwrap();
+    assert_eq!(stored.notes, record.notes);
+    assert_eq!(stored.metrics["ripple_mv"], 12.5);
+    assert!(stored.metrics["efficiency"].is_nan());
+    assert_eq!(stored.started_at, record.started_at);
+
+    let listed = db.list(&RunFilter::default().kind("soak")).unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].notes.len(), 2);
+    assert!(db.delete(id).unwrap());
+    assert!(db.get(id).unwrap().is_none());
+}
@@ -0,0 +1,452 @@
+//! End-to-end tests against the `spd3303x-sim` binary and the `spd3303x`
+//! command-line tool.
+//!
+//! Each test boots its own simulator on a free port and talks to it over
+//! the raw SCPI socket, as a pipeline would with a bench supply. To run
+//! your own tests against the simulator, copy [`Simulator`]:
+//!
+//! ```text
+//! cargo test --test simulator
+//! ```
+
+use std::io::{BufRead, BufReader};
+use std::net::SocketAddr;
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+use spd3303x_control::combined::MAX_SHARE_IMBALANCE;
+use spd3303x_control::instrument::{
+    Channel, FirmwareVersion, OutputState, RegulationMode, Spd3303x, TrackMode,
+};
+use spd3303x_control::mock::MockDevice;
+use spd3303x_control::quirks::{Feature, FirmwareUnsupported, Quirk};
+use spd3303x_control::transcript::Direction;
+use spd3303x_control::validate::{Limits, Violation};
+
+/// A running `spd3303x-sim`, killed on drop.
+struct Simulator {
+    child: Child,
+    addr: SocketAddr,
+}
+
+impl Simulator {
+    /// Start the simulator with extra `args`, e.g. `["--load", "CH1=10"]`,
+    /// and wait until it listens.
+    fn start(args: &[&str]) -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_spd3303x-sim"))
+            .args(["--listen", "127.0.0.1:0"])
+            .args(args)
+            .env("NO_COLOR", "1")
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("failed to start spd3303x-sim");
+        let stdout = child.stdout.take().expect("no simulator stdout");
+        let mut lines = BufReader::new(stdout).lines().map_while(Result::ok);
+        let addr = lines
+            .find_map(|line| {
+                let (_, addr) = line.split_once("listening on ")?;
+                addr.trim().parse().ok()
+            })
+            .expect("simulator exited before listening");
+        // Keep reading so that the simulator's later log lines do not fail.
+        std::thread::spawn(move || lines.for_each(drop));
+        Self { child, addr }
+    }
+
+    async fn connect(&self) -> Spd3303x {
+        Spd3303x::connect_tcp(self.addr)
+            .await
+            .expect("failed to connect to the simulator")
+    }
+}
+
+impl Drop for Simulator {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn cli(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_spd3303x"))
+        .args(args)
+        .output()
+        .expect("failed to run spd3303x")
+}
+
+/// Run the example `name`, built by `cargo test` next to the test binaries,
+/// against `sim`.
+fn example(name: &str, sim: &Simulator) -> std::process::Output {
+    let examples = std::env::current_exe()
+        .expect("no test binary path")
+        .parent()
+        .and_then(|deps| deps.parent())
+        .expect("test binary outside the target directory")
+        .join("examples");
+    let binary = examples.join(format!("{name}{}", std::env::consts::EXE_SUFFIX));
+    Command::new(&binary)
+        .env("SPD3303X_TCP", sim.addr.to_string())
+        .env("NO_COLOR", "1")
+        .output()
+        .unwrap_or_else(|e| panic!("failed to run {}: {e}", binary.display()))
+}
+
+#[tokio::test]
+async fn identity_over_the_socket() {
+    let sim = Simulator::start(&[]);
+    let mut inst = sim.connect().await;
+
+    let identity = inst.identity().await.unwrap();
+    assert_eq!(identity.manufacturer, "Siglent Technologies");
+    assert_eq!(identity.model, "SPD3303X-E");
+    inst.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn setpoints_reach_the_wire_and_read_back() {
+    let sim = Simulator::start(&[]);
+    let mut inst = sim.connect().await;
+    inst.enable_transcript(64);
+
+    inst.set_voltage(Channel::Ch1, 5.0).await.unwrap();
+    inst.set_current(Channel::Ch1, 1.0).await.unwrap();
+    inst.set_output(Channel::Ch1, OutputState::On).await.unwrap();
+    assert_eq!(inst.setpoint_voltage(Channel::Ch1).await.unwrap(), 5.0);
+    assert_eq!(inst.measured_voltage(Some(Channel::Ch1)).await.unwrap(), 5.0);
+
+    let writes: Vec<String> = inst
+        .transcript()
+        .unwrap()
+        .entries()
+        .filter(|entry| matches!(entry.direction, Direction::Write))
+        .map(|entry| entry.command.clone())
+        .collect();
+    assert_eq!(
+        writes,
+        ["CH1:VOLT 5.000000", "CH1:CURR 1.000000", "OUTPut CH1,ON"]
+    );
+    inst.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn typed_and_raw_writes_pass_the_same_safety_layer() {
+    let sim = Simulator::start(&[]);
+    let mut inst = sim.connect().await;
+    let limits = Limits {
+        max_voltage_v: Some(5.0),
+        ..Limits::unlimited()
+    };
+    inst.set_limits(Channel::Ch1, limits).unwrap();
+    inst.forbid(Channel::Ch2);
+    inst.set_strict(true);
+    let violation = |result: anyhow::Result<()>| {
+        result.unwrap_err().downcast::<Violation>().expect("not a violation")
+    };
+
+    let typed = violation(inst.set_voltage(Channel::Ch1, 6.0).await);
+    let raw = violation(inst.write_raw("CH1:VOLT 6").await);
+    assert_eq!(typed, raw);
+    assert!(matches!(raw, Violation::AboveLimit { .. }));
+    let raw = violation(inst.write_raw("CH1:VOLT 1.0001").await);
+    assert!(matches!(raw, Violation::TooPrecise { .. }));
+    let raw = violation(inst.write_raw("OUTP:TRACK 1").await);
+    assert_eq!(raw, Violation::ChannelForbidden(Channel::Ch2));
+    let typed = violation(inst.set_current(Channel::Ch2, 1.0).await);
+    assert_eq!(typed, Violation::ChannelForbidden(Channel::Ch2));
+
+    inst.write_raw("CH1:VOLT 4.5").await.unwrap();
+    assert_eq!(inst.setpoint_voltage(Channel::Ch1).await.unwrap(), 4.5);
+    inst.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn simulator_records_the_bytes_on_the_wire() {
+    let device = MockDevice::new();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = device.clone();
+    tokio::spawn(async move { server.serve(listener).await });
+    device.record_wire(true);
+
+    let mut inst = Spd3303x::connect_tcp(addr).await.unwrap();
+    inst.set_voltage(Channel::Ch1, 5.0).await.unwrap();
+    inst.set_output(Channel::Ch1, OutputState::On).await.unwrap();
+    assert_eq!(inst.setpoint_voltage(Channel::Ch1).await.unwrap(), 5.0);
+    inst.close().await.unwrap();
+
+    let wire = String::from_utf8(device.take_wire()).unwrap();
+    assert_eq!(wire, "CH1:VOLT 5.000000\nOUTPut CH1,ON\nCH1:VOLT?\n");
+}
+
+#[tokio::test]
+async fn identity_gates_features_with_known_quirks() {
+    let version: FirmwareVersion = "1.01.01.02.07R2".parse().unwrap();
+    assert_eq!(version, FirmwareVersion::new(1, 1, 1, 2, 7));
+    assert!("1.01.01.02.07-2".parse::<FirmwareVersion>().is_err());
+
+    let sim = Simulator::start(&[]);
+    let mut inst = sim.connect().await;
+    inst.identity().await.unwrap();
+    // The simulated firmware has the fixes the shipped quirks ask for.
+    inst.measured_power(Some(Channel::Ch1)).await.unwrap();
+    inst.add_quirk(Quirk {
+        feature: Feature::MeasurePower,
+        fixed_in: FirmwareVersion::new(2, 0, 0, 0, 0),
+        workaround: "none".to_string(),
+    });
+    let error = inst.measured_power(Some(Channel::Ch1)).await.unwrap_err();
+    let unsupported = error.downcast::<FirmwareUnsupported>().unwrap();
+    assert_eq!(unsupported.feature, Feature::MeasurePower);
+    inst.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn load_drives_the_channel_into_cc() {
+    let sim = Simulator::start(&["--load", "CH1=10"]);
+    let mut inst = sim.connect().await;
+
+    inst.set_voltage(Channel::Ch1, 10.0).await.unwrap();
+    inst.set_current(Channel::Ch1, 0.5).await.unwrap();
+    inst.set_output(Channel::Ch1, OutputState::On).await.unwrap();
+
+    let current = inst.measured_current(Some(Channel::Ch1)).await.unwrap();
+    assert!((current - 0.5).abs() < 1e-3, "measured {current} A");
+    let status = inst.system_status().await.unwrap();
+    assert_eq!(
+        status.regulation_mode(Channel::Ch1),
+        Some(RegulationMode::ConstantCurrent)
+    );
+    inst.close().await.unwrap();
+}
+
+/// Program 5 V / 2 A on both channels and switch them on.
+async fn both_on(inst: &mut Spd3303x) {
+    for channel in Channel::programmable() {
+        inst.set_voltage(channel, 5.0).await.unwrap();
+        inst.set_current(channel, 2.0).await.unwrap();
+        inst.set_output(channel, OutputState::On).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn combined_measurements_follow_the_track_mode() {
+    let sim = Simulator::start(&["--load", "CH1=10", "--load", "CH2=10"]);
+    let mut inst = sim.connect().await;
+    both_on(&mut inst).await;
+
+    let error = inst.series_measurements().await.unwrap_err();
+    assert_eq!(error.to_string(), "instrument is in Independent mode, not Series mode");
+
+    inst.set_track_mode(TrackMode::Series).await.unwrap();
+    let series = inst.series_measurements().await.unwrap();
+    assert_eq!(series.track_mode, TrackMode::Series);
+    assert!((series.voltage_v - 10.0).abs() < 1e-3, "{series:?}");
+    assert!((series.current_a - 0.5).abs() < 1e-3, "{series:?}");
+    assert!((series.power_w - 5.0).abs() < 1e-2, "{series:?}");
+    assert!(inst.parallel_measurements().await.is_err());
+
+    inst.set_track_mode(TrackMode::Parallel).await.unwrap();
+    let parallel = inst.parallel_measurements().await.unwrap();
+    assert!((parallel.voltage_v - 5.0).abs() < 1e-3, "{parallel:?}");
+    assert!((parallel.current_a - 1.0).abs() < 1e-3, "{parallel:?}");
+    assert!((parallel.power_w - 5.0).abs() < 1e-2, "{parallel:?}");
+    inst.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn parallel_balance_flags_an_uneven_share() {
+    let sim = Simulator::start(&["--load", "CH1=10", "--load", "CH2=50"]);
+    let mut inst = sim.connect().await;
+    both_on(&mut inst).await;
+    assert!(inst.parallel_balance_report(4).await.is_err());
+
+    inst.set_track_mode(TrackMode::Parallel).await.unwrap();
+    let report = inst.parallel_balance_report(4).await.unwrap();
+    assert_eq!(report.samples, 4);
+    assert!((report.total_current_a - 0.6).abs() < 1e-3, "{report:?}");
+    assert!(report.imbalance.unwrap() > MAX_SHARE_IMBALANCE, "{report:?}");
+    assert!((report.limit_ratio(Channel::Ch1).unwrap() - 0.25).abs() < 1e-3);
+    let warnings = report.warnings();
+    assert_eq!(warnings.len(), 1, "{warnings:?}");
+    assert!(warnings[0].starts_with("CH1 carries 83%"), "{warnings:?}");
+    inst.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn simulator_serves_several_clients() {
+    let sim = Simulator::start(&[]);
+    let mut first = sim.connect().await;
+    let mut second = sim.connect().await;
+
+    first.set_voltage(Channel::Ch2, 12.0).await.unwrap();
+    let read = tokio::time::timeout(Duration::from_secs(5), second.setpoint_voltage(Channel::Ch2))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(read, 12.0);
+}
+
+#[test]
+fn cli_help_lists_the_commands() {
+    let output = cli(&["--help"]);
+    assert!(output.status.success());
+    let help = String::from_utf8_lossy(&output.stdout);
+    assert!(help.contains("inventory"), "{help}");
+}
+
+#[test]
+fn cli_scan_of_an_empty_subnet_succeeds() {
+    let output = cli(&["inventory", "--subnet", "127.0.0.1/32", "--timeout-ms", "200"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("0 supplies found"));
+}
+
+#[test]
+fn examples_run_against_the_simulator() {
+    for name in [
+        "idn_and_status",
+        "ch1_10v_1a_toggle",
+        "ch1_ch2_series_mode",
+        "network_show",
+        "timer_sequence_ch1",
+        "poll_strategies",
+    ] {
+        let sim = Simulator::start(&[]);
+        let output = example(name, &sim);
+        assert!(output.status.success(), "{name}: {output:?}");
+    }
+}
+
+#[tokio::test]
+async fn cli_programs_the_simulator() {
+    let sim = Simulator::start(&[]);
+    let addr = sim.addr.to_string();
+    let output = cli(&[
+        "set", "--tcp", &addr, "--channel", "CH2", "--voltage", "3v3", "--current", "250mA",
+    ]);
+    assert!(output.status.success(), "{output:?}");
+    let output = cli(&["output", "--tcp", &addr, "--channel", "CH2", "on"]);
+    assert!(output.status.success(), "{output:?}");
+
+    let output = cli(&["raw", "--tcp", &addr, "CH2:VOLT?"]);
+    assert!(output.status.success(), "{output:?}");
+    let reply: f64 = String::from_utf8_lossy(&output.stdout).trim().parse().unwrap();
+    assert_eq!(reply, 3.3);
+
+    let output = cli(&["status", "--tcp", &addr, "--channel", "CH2"]);
+    assert!(output.status.success(), "{output:?}");
+    let table = String::from_utf8_lossy(&output.stdout);
+    assert!(table.contains("Setpoint") && table.contains("3.300 V"), "{table}");
+
+    let mut inst = sim.connect().await;
+    assert_eq!(inst.setpoint_current(Channel::Ch2).await.unwrap(), 0.25);
+    let status = inst.system_status().await.unwrap();
+    assert_eq!(status.output_on(Channel::Ch2), Some(true));
+    inst.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn cli_addresses_all_channels() {
+    let sim = Simulator::start(&[]);
+    let addr = sim.addr.to_string();
+    let output = cli(&["set", "--tcp", &addr, "--channel", "all", "--voltage", "5V"]);
+    assert!(output.status.success(), "{output:?}");
+    let output = cli(&["output", "--tcp", &addr, "--channel", "all", "on"]);
+    assert!(output.status.success(), "{output:?}");
+
+    let output = cli(&["status", "--tcp", &addr, "--channel", "all"]);
+    assert!(output.status.success(), "{output:?}");
+    let tables = String::from_utf8_lossy(&output.stdout);
+    assert!(tables.contains("CH1:") && tables.contains("CH2:"), "{tables}");
+
+    let mut inst = sim.connect().await;
+    let status = inst.system_status().await.unwrap();
+    for channel in Channel::programmable() {
+        assert_eq!(inst.setpoint_voltage(channel).await.unwrap(), 5.0);
+        assert_eq!(status.output_on(channel), Some(true));
+    }
+    inst.close().await.unwrap();
+}
+
+#[test]
+fn cli_rejects_a_malformed_subnet() {
+    let output = cli(&["inventory", "--subnet", "not-a-subnet"]);
+    assert!(!output.status.success());
+}
+
+fn exit_code(output: &std::process::Output) -> Option<i32> {
+    output.status.code()
+}
+
+#[test]
+fn cli_exits_with_1_on_an_unclassified_failure() {
+    let sim = Simulator::start(&[]);
+    let addr = sim.addr.to_string();
+    let output = cli(&["set", "--tcp", &addr, "--channel", "CH1"]);
+    assert_eq!(exit_code(&output), Some(1), "{output:?}");
+}
+
+#[test]
+fn cli_exits_with_2_on_a_refused_setpoint() {
+    let sim = Simulator::start(&[]);
+    let addr = sim.addr.to_string();
+    let output = cli(&["set", "--tcp", &addr, "--channel", "CH1", "--voltage", "40V"]);
+    assert_eq!(exit_code(&output), Some(2), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("validation"));
+}
+
+#[test]
+fn cli_exits_with_3_when_the_link_fails() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    drop(listener);
+    let output = cli(&["set", "--tcp", &addr, "--channel", "CH1", "--voltage", "5V"]);
+    assert_eq!(exit_code(&output), Some(3), "{output:?}");
+}
+
+#[test]
+fn cli_exits_with_4_on_an_instrument_error() {
+    let sim = Simulator::start(&[]);
+    let addr = sim.addr.to_string();
+    let output = cli(&["raw", "--tcp", &addr, "FOO:BAR 1"]);
+    assert_eq!(exit_code(&output), Some(4), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("-113"));
+}
+
+#[tokio::test]
+async fn cli_exits_with_5_on_a_safety_trip() {
+    let sim = Simulator::start(&["--load", "CH1=10"]);
+    let addr = sim.addr.to_string();
+    let output = cli(&[
+        "set", "--tcp", &addr, "--channel", "CH1", "--voltage", "10V", "--current", "2A",
+    ]);
+    assert!(output.status.success(), "{output:?}");
+
+    let output = cli(&[
+        "output", "--tcp", &addr, "--channel", "CH1", "on", "--time", "30s", "--max-current",
+        "500mA",
+    ]);
+    assert_eq!(exit_code(&output), Some(5), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("safety limit"));
+
+    let mut inst = sim.connect().await;
+    let status = inst.system_status().await.unwrap();
+    assert_eq!(status.output_on(Channel::Ch1), Some(false));
+    inst.close().await.unwrap();
+}
+
+#[test]
+fn cli_error_format_json_needs_the_json_feature() {
+    let sim = Simulator::start(&[]);
+    let addr = sim.addr.to_string();
+    let output = cli(&[
+        "--error-format", "json", "set", "--tcp", &addr, "--channel", "CH1", "--voltage", "40V",
+    ]);
+    assert_eq!(exit_code(&output), Some(2), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if cfg!(feature = "json") {
+        assert!(stderr.contains(r#""kind":"validation""#), "{stderr}");
+    } else {
+        assert!(stderr.contains("`json` feature"), "{stderr}");
+    }
+}
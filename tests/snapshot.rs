@@ -0,0 +1,35 @@
+//! Snapshots and state assertions on a [`MockDevice`].
+
+use std::time::Duration;
+
+use spd3303x_control::instrument::{Channel, Spd3303x, TimerEntry};
+use spd3303x_control::mock::MockDevice;
+use spd3303x_control::snapshot::{Setting, StateMismatch, Tolerance};
+
+#[tokio::test]
+async fn snapshots_carry_the_timer_groups() {
+    let device = MockDevice::new();
+    let mut inst = Spd3303x::mock(&device);
+    let hold = Duration::from_secs(2);
+    inst.timer_set(Channel::Ch1, 2, 3.3, 0.5, hold).await.unwrap();
+    let golden = inst.snapshot().await.unwrap();
+    let group = golden.timer_group(Channel::Ch1, 2).unwrap();
+    assert_eq!((group.voltage_v, group.current_a, group.duration), (3.3, 0.5, hold));
+    assert_eq!(golden.timers(Channel::Ch2).len(), 5);
+
+    inst.timer_set(Channel::Ch1, 2, 5.0, 0.5, hold).await.unwrap();
+    let error = inst.assert_state_matches(&golden, Tolerance::setpoints()).await.unwrap_err();
+    let mismatch = error.downcast::<StateMismatch>().unwrap();
+    assert_eq!(mismatch.mismatches.len(), 1);
+    assert_eq!(mismatch.mismatches[0].field, "CH1 timer group 2");
+
+    // Applying the way back restores the group.
+    let back = mismatch.actual.diff(&golden);
+    assert_eq!(back.changes.len(), 1);
+    let TimerEntry { voltage_v, .. } = match back.changes[0].after {
+        Setting::TimerGroup(Channel::Ch1, entry) => entry,
+        other => panic!("unexpected change {other:?}"),
+    };
+    inst.timer_set(Channel::Ch1, 2, voltage_v, 0.5, hold).await.unwrap();
+    inst.assert_state_matches(&golden, Tolerance::setpoints()).await.unwrap();
+}
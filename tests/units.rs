@@ -0,0 +1,71 @@
+//! Quantities and durations as typed on a command line.
+
+use std::time::Duration;
+
+use spd3303x_control::units::{parse_current, parse_duration, parse_resistance, parse_voltage};
+
+fn assert_close(text: &str, got: f64, want: f64) {
+    assert!((got - want).abs() < 1e-12, "{text:?} parsed as {got}, expected {want}");
+}
+
+#[test]
+fn quantities_in_every_documented_form() {
+    type Parse = fn(&str) -> anyhow::Result<f64>;
+    let cases: [(Parse, &str, f64); 20] = [
+        (parse_voltage, "3.3", 3.3),
+        (parse_voltage, "3.3V", 3.3),
+        (parse_voltage, "3v3", 3.3),
+        (parse_voltage, "3V3", 3.3),
+        (parse_voltage, "500mV", 0.5),
+        (parse_voltage, "500MV", 0.5),
+        (parse_voltage, " 5 V ", 5.0),
+        (parse_current, "0.5", 0.5),
+        (parse_current, "0.5A", 0.5),
+        (parse_current, "500mA", 0.5),
+        (parse_current, "1a2", 1.2),
+        (parse_current, "4m7", 0.0047),
+        (parse_current, "20uA", 20e-6),
+        (parse_resistance, "10", 10.0),
+        (parse_resistance, "10ohm", 10.0),
+        (parse_resistance, "10Ω", 10.0),
+        (parse_resistance, "4k7", 4700.0),
+        (parse_resistance, "4R7", 4.7),
+        (parse_resistance, "4r7", 4.7),
+        (parse_resistance, "1.5k", 1500.0),
+    ];
+    for (parse, text, want) in cases {
+        assert_close(text, parse(text).unwrap(), want);
+    }
+}
+
+#[test]
+fn quantities_reject_what_is_not_a_value() {
+    for text in ["-1V", "-1", "1e400", "m", "2x", "V", ""] {
+        assert!(parse_voltage(text).is_err(), "{text:?} was accepted");
+    }
+    assert!(parse_current("3v3").is_err());
+    assert!(parse_resistance("4k7k").is_err());
+}
+
+#[test]
+fn durations_in_every_documented_form() {
+    for (text, want) in [
+        ("90", Duration::from_secs(90)),
+        ("1.5s", Duration::from_millis(1500)),
+        ("250ms", Duration::from_millis(250)),
+        ("2m30s", Duration::from_secs(150)),
+        ("2M30S", Duration::from_secs(150)),
+        ("1h", Duration::from_secs(3600)),
+        ("1h30min", Duration::from_secs(5400)),
+        ("500us", Duration::from_micros(500)),
+    ] {
+        assert_eq!(parse_duration(text).unwrap(), want, "{text:?}");
+    }
+}
+
+#[test]
+fn durations_reject_what_is_not_a_duration() {
+    for text in ["-1s", "-1", "1e400", "m", "2x", "s2", ""] {
+        assert!(parse_duration(text).is_err(), "{text:?} was accepted");
+    }
+}
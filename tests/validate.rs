@@ -0,0 +1,142 @@
+//! The setpoint, precision and channel-policy rules, without an instrument.
+
+use spd3303x_control::instrument::Channel;
+use spd3303x_control::pipeline::Quantity;
+use spd3303x_control::validate::{
+    check_precision, check_setpoint, Capabilities, ChannelPolicy, Limits, Violation,
+};
+
+#[test]
+fn setpoints_at_and_beyond_the_rated_maximum() {
+    let capabilities = Capabilities::spd3303x();
+    let unlimited = Limits::unlimited();
+    let above = |quantity, value, max| Violation::AboveRated {
+        quantity,
+        value,
+        max,
+    };
+    for (channel, volts, amps, expected) in [
+        (Channel::Ch1, 32.0, 3.2, vec![]),
+        (Channel::Ch2, 0.0, 0.0, vec![]),
+        (Channel::Ch1, 32.001, 3.2, vec![above(Quantity::Voltage, 32.001, 32.0)]),
+        (Channel::Ch1, 32.0, 3.201, vec![above(Quantity::Current, 3.201, 3.2)]),
+        (
+            Channel::Ch1,
+            -0.1,
+            1.0,
+            vec![Violation::Negative {
+                quantity: Quantity::Voltage,
+                value: -0.1,
+            }],
+        ),
+        (
+            Channel::Ch3,
+            5.0,
+            1.0,
+            vec![Violation::UnsupportedChannel(Channel::Ch3)],
+        ),
+    ] {
+        let violations = check_setpoint(channel, volts, amps, &capabilities, &unlimited);
+        assert_eq!(violations, expected, "{} {volts} V {amps} A", channel.label());
+    }
+}
+
+#[test]
+fn non_finite_setpoints_are_rejected() {
+    let capabilities = Capabilities::spd3303x();
+    for (volts, amps) in [(f64::NAN, 1.0), (1.0, f64::NAN), (f64::INFINITY, 1.0)] {
+        let violations =
+            check_setpoint(Channel::Ch1, volts, amps, &capabilities, &Limits::unlimited());
+        assert!(
+            matches!(violations[..], [Violation::NotFinite { .. }]),
+            "{volts} V {amps} A gave {violations:?}"
+        );
+    }
+}
+
+#[test]
+fn power_limit_applies_to_the_product() {
+    let capabilities = Capabilities::spd3303x();
+    let limits = Limits {
+        max_power_w: Some(10.0),
+        ..Limits::unlimited()
+    };
+    assert!(check_setpoint(Channel::Ch1, 5.0, 2.0, &capabilities, &limits).is_empty());
+    let violations = check_setpoint(Channel::Ch1, 5.0, 2.5, &capabilities, &limits);
+    assert_eq!(
+        violations,
+        vec![Violation::AboveLimit {
+            quantity: Quantity::Power,
+            value: 12.5,
+            limit: 10.0,
+        }]
+    );
+}
+
+#[test]
+fn precision_on_the_1_mv_and_10_mv_grids() {
+    let fine = Capabilities::spd3303x();
+    let coarse = Capabilities::spd3303x_e();
+    for (capabilities, quantity, value, accepted) in [
+        // 3.3 / 0.001 and 3.3 / 0.01 are not whole in binary; the
+        // tolerance has to absorb that.
+        (&fine, Quantity::Voltage, 3.3, true),
+        (&coarse, Quantity::Voltage, 3.3, true),
+        (&fine, Quantity::Voltage, 3.305, true),
+        (&coarse, Quantity::Voltage, 3.305, false),
+        (&fine, Quantity::Voltage, 3.3005, false),
+        (&fine, Quantity::Current, 3.2, true),
+        (&coarse, Quantity::Voltage, 32.0, true),
+        (&coarse, Quantity::Current, 3.2, true),
+        (&coarse, Quantity::Current, 0.015, false),
+        (&coarse, Quantity::Power, 3.3005, true),
+    ] {
+        let violations = check_precision(quantity, value, capabilities);
+        assert_eq!(
+            violations.is_empty(),
+            accepted,
+            "{value} {} at {:?}: {violations:?}",
+            quantity.unit(),
+            capabilities.resolution(quantity)
+        );
+    }
+}
+
+#[test]
+fn commands_that_reach_a_denied_channel() {
+    let no_ch2 = ChannelPolicy::allow_all().forbid(Channel::Ch2);
+    let no_ch3 = ChannelPolicy::allow_all().forbid(Channel::Ch3);
+    for (policy, command, denied) in [
+        (&no_ch2, "*RST", Some(Channel::Ch2)),
+        (&no_ch3, "*RST", Some(Channel::Ch3)),
+        (&no_ch2, "VOLT 1", Some(Channel::Ch2)),
+        (&no_ch2, ":curr 0.5", Some(Channel::Ch2)),
+        (&no_ch3, "VOLT 1", None),
+        (&no_ch2, "*RCL 1", Some(Channel::Ch2)),
+        (&no_ch2, "OUTP:TRACK 1", Some(Channel::Ch2)),
+        (&no_ch2, "ch2:volt 1", Some(Channel::Ch2)),
+        (&no_ch2, "OUTPut CH2,ON", Some(Channel::Ch2)),
+        (&no_ch2, "CH1:VOLT 1", None),
+        (&no_ch2, "CH1:VOLT 1;*RST", Some(Channel::Ch2)),
+        (&no_ch2, "MEAS:VOLT? CH1", None),
+        (&no_ch2, "*IDN?", None),
+    ] {
+        assert_eq!(policy.denied_in_command(command), denied, "{command:?}");
+    }
+}
+
+#[test]
+fn dependents_are_found_transitively() {
+    let chain = ChannelPolicy::allow_all()
+        .require_on(Channel::Ch2, Channel::Ch1)
+        .require_on(Channel::Ch3, Channel::Ch2);
+    // Switched off from the far end of the chain inwards.
+    assert_eq!(chain.dependents(Channel::Ch1), vec![Channel::Ch3, Channel::Ch2]);
+    assert_eq!(chain.dependents(Channel::Ch2), vec![Channel::Ch3]);
+    assert!(chain.dependents(Channel::Ch3).is_empty());
+
+    let cycle = ChannelPolicy::allow_all()
+        .require_on(Channel::Ch1, Channel::Ch2)
+        .require_on(Channel::Ch2, Channel::Ch1);
+    assert_eq!(cycle.dependents(Channel::Ch1), vec![Channel::Ch2]);
+}